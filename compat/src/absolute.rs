@@ -0,0 +1,227 @@
+//! Retries scheduled at absolute points in time.
+//!
+//! [`RetryPolicy`] returns how long to sleep for, measured from when the decision is made.
+//! An [`AbsoluteRetryPolicy`] instead returns the [`Instant`] at which the next attempt should start,
+//! which [`retry_at`] passes straight to a `sleep_until` style function. Policies that plan their
+//! schedule ahead of time can then stick to it exactly.
+//!
+//! [`RetryPolicies`](crate::retry_policies::RetryPolicies) is an [`AbsoluteRetryPolicy`] too, but
+//! the `execute_after` time chosen by [`retry_policies`](::retry_policies) is a wall-clock time,
+//! and an [`Instant`] can't be built from one. It's converted by measuring how far it is from the
+//! policy's own `now`, once, at the moment the decision is made.
+
+use std::{
+    future::Future,
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{Clock, StdClock},
+    RetryPolicy,
+};
+
+/// Policy to decide whether a result should be retried, and when
+pub trait AbsoluteRetryPolicy<Res> {
+    /// Determine if the request should be retried under the given policy.
+    /// Return `Break(result)` if we are done retrying
+    /// Else, return `Continue(instant)` to sleep until that instant.
+    fn should_retry_at(&mut self, result: Res) -> ControlFlow<Res, Instant>;
+}
+
+impl<P: AbsoluteRetryPolicy<R>, R> AbsoluteRetryPolicy<R> for &mut P {
+    fn should_retry_at(&mut self, result: R) -> ControlFlow<R, Instant> {
+        P::should_retry_at(self, result)
+    }
+}
+
+/// Adapts a [`RetryPolicy`] into an [`AbsoluteRetryPolicy`], sleeping until its delay from now
+///
+/// The current [`Instant`] is derived from a [`Clock`], so a [`MockClock`](crate::clock::MockClock)
+/// makes the instants deterministic in tests.
+#[derive(Debug, Clone)]
+pub struct Relative<P, K = StdClock> {
+    policy: P,
+    clock: K,
+    /// An instant, and the clock's time at that instant
+    origin: (Instant, Duration),
+}
+
+impl<P> Relative<P> {
+    /// Sleep until the delays of `policy`, measured from when each decision is made
+    pub fn new(policy: P) -> Self {
+        Self::with_clock(policy, StdClock)
+    }
+}
+
+impl<P, K: Clock> Relative<P, K> {
+    /// Like [`Relative::new`], but reads the time from `clock`
+    pub fn with_clock(policy: P, clock: K) -> Self {
+        let origin = (Instant::now(), clock.now());
+        Self {
+            policy,
+            clock,
+            origin,
+        }
+    }
+
+    /// Get the inner policy
+    pub fn into_inner(self) -> P {
+        self.policy
+    }
+}
+
+impl<P: RetryPolicy<R>, K: Clock, R> AbsoluteRetryPolicy<R> for Relative<P, K> {
+    fn should_retry_at(&mut self, result: R) -> ControlFlow<R, Instant> {
+        let duration = self.policy.should_retry(result)?;
+        let (instant, at) = self.origin;
+        let now = instant + self.clock.now().saturating_sub(at);
+        ControlFlow::Continue(now + duration)
+    }
+}
+
+#[cfg(feature = "retry-policies")]
+impl<P, B, N, R> AbsoluteRetryPolicy<R> for crate::retry_policies::RetryPolicies<P, B, N>
+where
+    P: std::borrow::Borrow<B>,
+    B: retry_policies::RetryPolicy + ?Sized,
+    N: Fn() -> chrono::DateTime<chrono::Utc>,
+    R: crate::ShouldRetry,
+{
+    fn should_retry_at(&mut self, result: R) -> ControlFlow<R, Instant> {
+        let execute_after = self.next_retry(result)?;
+        let now = Instant::now();
+        ControlFlow::Continue(now + self.delay_until(execute_after))
+    }
+}
+
+/// Retry a future using the given [retry policy](`AbsoluteRetryPolicy`) and sleep-until function.
+///
+/// ```
+/// use futures_retry_policies::{absolute::{retry_at, Relative}, iter::Iter};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Relative::new(Iter::new([Duration::from_millis(10); 3]));
+///     let sleep_until = |at: std::time::Instant| tokio::time::sleep_until(at.into());
+///     retry_at(policy, sleep_until, make_request).await.unwrap();
+/// }
+/// ```
+pub async fn retry_at<Policy, Sleeper, Sleep, Futures, Fut>(
+    mut policy: Policy,
+    mut sleep_until: Sleeper,
+    mut futures: Futures,
+) -> Fut::Output
+where
+    Policy: AbsoluteRetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Instant) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    loop {
+        match policy.should_retry_at(futures().await) {
+            ControlFlow::Continue(at) => sleep_until(at).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+/// Convenience for sleeping until an [`Instant`] with a relative sleep function
+pub fn sleep_until_with<Sleeper, Sleep>(mut sleeper: Sleeper) -> impl FnMut(Instant) -> Sleep
+where
+    Sleeper: FnMut(Duration) -> Sleep,
+{
+    move |at| sleeper(at.saturating_duration_since(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ops::ControlFlow,
+        time::{Duration, Instant},
+    };
+
+    use super::{retry_at, AbsoluteRetryPolicy, Relative};
+    use crate::{clock::MockClock, iter::Iter};
+
+    /// Retries at fixed offsets from a starting point
+    struct Anchored {
+        start: Instant,
+        offsets: std::vec::IntoIter<Duration>,
+    }
+
+    impl AbsoluteRetryPolicy<Option<()>> for Anchored {
+        fn should_retry_at(&mut self, result: Option<()>) -> ControlFlow<Option<()>, Instant> {
+            match self.offsets.next() {
+                Some(offset) if result.is_none() => ControlFlow::Continue(self.start + offset),
+                _ => ControlFlow::Break(result),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn sleeps_until_instants() {
+        let start = Instant::now();
+        let policy = Anchored {
+            start,
+            offsets: vec![Duration::from_secs(1), Duration::from_secs(3)].into_iter(),
+        };
+
+        let mut sleeps = vec![];
+        let res = retry_at(
+            policy,
+            |at| {
+                sleeps.push(at - start);
+                async {}
+            },
+            || async { None::<()> },
+        )
+        .await;
+
+        assert_eq!(res, None);
+        assert_eq!(sleeps, [Duration::from_secs(1), Duration::from_secs(3)]);
+    }
+
+    #[test]
+    fn relative() {
+        let before = Instant::now();
+        let mut policy = Relative::new(Iter::new([Duration::from_secs(5)]));
+        let ControlFlow::Continue(at) = policy.should_retry_at(None::<()>) else {
+            panic!("should retry")
+        };
+        assert!(at >= before + Duration::from_secs(5));
+        assert_eq!(policy.should_retry_at(None::<()>), ControlFlow::Break(None));
+    }
+
+    #[tokio::test]
+    async fn relative_follows_clock() {
+        let clock = MockClock::new();
+        let policy = Relative::with_clock(Iter::new([Duration::from_secs(1); 2]), clock.clone());
+
+        let mut sleeps = vec![];
+        let res = retry_at(
+            policy,
+            |at| {
+                sleeps.push(at);
+                async {}
+            },
+            || {
+                // each attempt takes 10s
+                clock.advance(Duration::from_secs(10));
+                async { None::<()> }
+            },
+        )
+        .await;
+
+        assert_eq!(res, None);
+        assert_eq!(sleeps[1] - sleeps[0], Duration::from_secs(10));
+    }
+}
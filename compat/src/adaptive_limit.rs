@@ -0,0 +1,299 @@
+#![cfg(feature = "adaptive-limit")]
+#![cfg_attr(docsrs, doc(cfg(feature = "adaptive-limit")))]
+//! Adaptive concurrency limits, shared with retry policies.
+//!
+//! An [`AdaptiveLimit`] learns how many requests a dependency can handle at once from the
+//! latencies it sees, using a latency gradient: while latencies stay close to their long term
+//! average the limit grows, and as they rise above it, or requests are dropped, the limit shrinks.
+//!
+//! The limit is also a good overload signal for retries. [`Limited`] doesn't retry while every
+//! permit of its limit is in use, as a retry would only queue behind the requests already waiting.
+//!
+//! ```
+//! use futures_retry_policies::{adaptive_limit::{AdaptiveLimit, Limited}, iter::Iter, retry};
+//! use std::time::Duration;
+//!
+//! async fn make_request() -> Option<()> {
+//!     // make a request
+//!     # Some(())
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let limit = AdaptiveLimit::new(10, 1, 100);
+//!
+//!     let policy = Limited::new(Iter::new([Duration::from_millis(10); 3]), &limit);
+//!     let result = retry(policy, tokio::time::sleep, || async {
+//!         let Some(permit) = limit.try_acquire() else {
+//!             // overloaded, shed the request
+//!             return None;
+//!         };
+//!         let result = make_request().await;
+//!         match result {
+//!             Some(_) => permit.success(),
+//!             None => permit.dropped(),
+//!         }
+//!         result
+//!     })
+//!     .await;
+//!     assert_eq!(result, Some(()));
+//! }
+//! ```
+
+use std::{
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    clock::{Clock, StdClock},
+    RetryPolicy, ShouldRetry,
+};
+
+/// How many samples the long term latency average is taken over
+const WINDOW: f64 = 100.0;
+/// How much the latency can rise above its long term average before the limit shrinks
+const TOLERANCE: f64 = 1.5;
+/// How much of each new estimate is mixed into the limit
+const SMOOTHING: f64 = 0.2;
+/// How much the limit shrinks when a request is dropped
+const BACKOFF: f64 = 0.9;
+
+#[derive(Debug)]
+struct State {
+    limit: f64,
+    in_flight: usize,
+    long_rtt: Option<f64>,
+}
+
+impl State {
+    fn sample(&mut self, rtt: Duration, in_flight: usize, min: usize, max: usize) {
+        let rtt = rtt.as_secs_f64().max(f64::MIN_POSITIVE);
+        let long_rtt = match self.long_rtt {
+            None => rtt,
+            Some(long_rtt) => long_rtt + (rtt - long_rtt) / WINDOW,
+        };
+        // recover quickly once latencies drop well below a stale average
+        let long_rtt = if long_rtt / rtt > 2.0 {
+            long_rtt * 0.95
+        } else {
+            long_rtt
+        };
+        self.long_rtt = Some(long_rtt);
+
+        // only grow the limit when it's actually being tested
+        if (in_flight as f64) < self.limit / 2.0 {
+            return;
+        }
+        let gradient = (TOLERANCE * long_rtt / rtt).clamp(0.5, 1.0);
+        let estimate = self.limit * gradient + self.limit.sqrt();
+        self.limit =
+            (self.limit * (1.0 - SMOOTHING) + estimate * SMOOTHING).clamp(min as f64, max as f64);
+    }
+}
+
+/// A concurrency limit, adapting to latency, shared between clones.
+///
+/// ```
+/// use futures_retry_policies::{adaptive_limit::AdaptiveLimit, clock::MockClock};
+/// use std::time::Duration;
+///
+/// let clock = MockClock::new();
+/// let limit = AdaptiveLimit::new(2, 1, 10).with_clock(clock.clone());
+///
+/// let a = limit.try_acquire().unwrap();
+/// let b = limit.try_acquire().unwrap();
+/// assert!(limit.try_acquire().is_none());
+/// assert!(limit.is_overloaded());
+///
+/// b.dropped();
+/// a.dropped();
+/// assert_eq!(limit.limit(), 1);
+/// ```
+#[derive(Clone)]
+pub struct AdaptiveLimit {
+    state: Arc<Mutex<State>>,
+    min: usize,
+    max: usize,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl std::fmt::Debug for AdaptiveLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdaptiveLimit")
+            .field("state", &self.state)
+            .field("min", &self.min)
+            .field("max", &self.max)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AdaptiveLimit {
+    /// Start with a limit of `initial` concurrent requests, adapting between `min` and `max`
+    pub fn new(initial: usize, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            state: Arc::new(Mutex::new(State {
+                limit: initial.clamp(min, max) as f64,
+                in_flight: 0,
+                long_rtt: None,
+            })),
+            min,
+            max,
+            clock: Arc::new(StdClock),
+        }
+    }
+
+    /// Read the time from `clock`
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The current limit
+    pub fn limit(&self) -> usize {
+        self.lock().limit as usize
+    }
+
+    /// How many permits are in use
+    pub fn in_flight(&self) -> usize {
+        self.lock().in_flight
+    }
+
+    /// Whether every permit is in use
+    pub fn is_overloaded(&self) -> bool {
+        let state = self.lock();
+        state.in_flight >= state.limit as usize
+    }
+
+    /// Take a permit for a request, unless the limit has been reached
+    pub fn try_acquire(&self) -> Option<Permit> {
+        let mut state = self.lock();
+        if state.in_flight >= state.limit as usize {
+            return None;
+        }
+        state.in_flight += 1;
+        Some(Permit {
+            limit: self.clone(),
+            start: self.clock.now(),
+            in_flight: state.in_flight,
+        })
+    }
+}
+
+/// A permit for one request, taken from an [`AdaptiveLimit`].
+///
+/// Report how the request went with [`success`](Permit::success) or [`dropped`](Permit::dropped).
+/// Permits dropped without a report are released without adapting the limit.
+#[must_use = "a permit is released as soon as it's dropped"]
+#[derive(Debug)]
+pub struct Permit {
+    limit: AdaptiveLimit,
+    start: Duration,
+    in_flight: usize,
+}
+
+impl Permit {
+    /// The request completed, so its latency is used to adapt the limit
+    pub fn success(self) {
+        let rtt = self.limit.clock.now().saturating_sub(self.start);
+        let (min, max) = (self.limit.min, self.limit.max);
+        self.limit.lock().sample(rtt, self.in_flight, min, max);
+    }
+
+    /// The request was dropped or timed out, which shrinks the limit
+    pub fn dropped(self) {
+        let min = self.limit.min as f64;
+        let mut state = self.limit.lock();
+        state.limit = (state.limit * BACKOFF).max(min);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limit.lock().in_flight -= 1;
+    }
+}
+
+/// A [`RetryPolicy`] that doesn't retry while its [`AdaptiveLimit`] is overloaded
+///
+/// First attempts are always made, as only retries are shed.
+#[derive(Debug, Clone)]
+pub struct Limited<P> {
+    policy: P,
+    limit: AdaptiveLimit,
+    attempts: u32,
+}
+
+impl<P> Limited<P> {
+    /// Retry with `policy` while `limit` has permits available
+    pub fn new(policy: P, limit: &AdaptiveLimit) -> Self {
+        Self {
+            policy,
+            limit: limit.clone(),
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, R> RetryPolicy<R> for Limited<P>
+where
+    P: RetryPolicy<R>,
+    R: ShouldRetry,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        if result.should_retry(self.attempts) && self.limit.is_overloaded() {
+            return ControlFlow::Break(result);
+        }
+        self.policy.should_retry(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::{AdaptiveLimit, Limited};
+    use crate::{clock::MockClock, iter::Iter, RetryPolicy};
+
+    fn run(limit: &AdaptiveLimit, clock: &MockClock, latency: Duration) {
+        let permits: Vec<_> = std::iter::from_fn(|| limit.try_acquire()).collect();
+        clock.advance(latency);
+        permits.into_iter().for_each(|permit| permit.success());
+    }
+
+    #[test]
+    fn grows_then_shrinks_when_latency_jumps() {
+        let clock = MockClock::new();
+        let limit = AdaptiveLimit::new(4, 1, 100).with_clock(clock.clone());
+
+        for _ in 0..10 {
+            run(&limit, &clock, Duration::from_millis(10));
+        }
+        let grown = limit.limit();
+        assert!(grown > 4, "{grown}");
+
+        run(&limit, &clock, Duration::from_millis(100));
+        assert!(limit.limit() < grown, "{} >= {grown}", limit.limit());
+        assert_eq!(limit.in_flight(), 0);
+    }
+
+    #[test]
+    fn sheds_retries_when_overloaded() {
+        let limit = AdaptiveLimit::new(1, 1, 1);
+        let mut policy = Limited::new(Iter::new([Duration::ZERO; 3]), &limit);
+
+        assert!(policy.should_retry(None::<()>).is_continue());
+        let permit = limit.try_acquire().unwrap();
+        assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+        drop(permit);
+        assert!(policy.should_retry(None::<()>).is_continue());
+    }
+}
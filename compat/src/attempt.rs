@@ -0,0 +1,152 @@
+//! The current attempt number, readable from anywhere inside the attempt.
+//!
+//! [`retry_tagged`] sets the attempt number while each attempt is being polled, so code deep inside
+//! the attempt, like log formatters or custom `tracing` layers, can read it with [`current`] to
+//! enrich everything it records, without threading the number through every layer.
+//!
+//! Like a task-local, the value is only set while the attempt is being polled, so it works on any
+//! executor. Nested retries see their own attempt number, and the outer number is restored after.
+
+use std::{
+    cell::Cell,
+    future::Future,
+    ops::ControlFlow,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use pin_project::pin_project;
+
+use crate::RetryPolicy;
+
+thread_local! {
+    static CURRENT: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// The number of the attempt being polled, starting at 1, or `None` outside of [`retry_tagged`]
+pub fn current() -> Option<u32> {
+    CURRENT.get()
+}
+
+/// [`Future`] that sets the [`current`] attempt while it is being polled, returned by [`tag`]
+#[pin_project]
+#[derive(Debug)]
+pub struct Tagged<Fut> {
+    attempt: u32,
+    #[pin]
+    fut: Fut,
+}
+
+/// Set the [`current`] attempt to `attempt` while `fut` is being polled
+pub fn tag<Fut: Future>(attempt: u32, fut: Fut) -> Tagged<Fut> {
+    Tagged { attempt, fut }
+}
+
+impl<Fut: Future> Future for Tagged<Fut> {
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        struct Restore(Option<u32>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.set(self.0);
+            }
+        }
+
+        let this = self.project();
+        let _restore = Restore(CURRENT.replace(Some(*this.attempt)));
+        this.fut.poll(cx)
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// setting the [`current`] attempt while each attempt runs.
+///
+/// ```
+/// use futures_retry_policies::{attempt, iter::Iter};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request, logging which attempt this is
+///     println!("attempt {:?}", attempt::current());
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     attempt::retry_tagged(policy, tokio::time::sleep, make_request).await.unwrap();
+/// }
+/// ```
+pub async fn retry_tagged<Policy, Sleeper, Sleep, Futures, Fut>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    let mut attempt = 1u32;
+    loop {
+        match policy.should_retry(tag(attempt, futures()).await) {
+            ControlFlow::Continue(dur) => {
+                sleeper(dur).await;
+                attempt += 1;
+            }
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use super::{current, retry_tagged};
+    use crate::iter::Iter;
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn tags_each_attempt() {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let res = retry_tagged(Iter::new([Duration::ZERO; 2]), sleep, || {
+            let seen = seen.clone();
+            async move {
+                tokio::task::yield_now().await;
+                seen.borrow_mut().push(current());
+                None::<()>
+            }
+        })
+        .await;
+
+        assert_eq!(res, None);
+        assert_eq!(*seen.borrow(), [Some(1), Some(2), Some(3)]);
+        assert_eq!(current(), None);
+    }
+
+    #[tokio::test]
+    async fn nested_retries() {
+        let seen = Rc::new(RefCell::new(vec![]));
+        retry_tagged(Iter::new([Duration::ZERO]), sleep, || {
+            let seen = seen.clone();
+            async move {
+                let inner = retry_tagged(Iter::new([Duration::ZERO; 2]), sleep, || async {
+                    current()
+                })
+                .await;
+                seen.borrow_mut().push((current(), inner));
+                None::<()>
+            }
+        })
+        .await;
+
+        assert_eq!(*seen.borrow(), [(Some(1), Some(1)), (Some(2), Some(1))]);
+    }
+}
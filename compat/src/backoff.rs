@@ -0,0 +1,766 @@
+//! A batteries-included exponential backoff policy.
+//!
+//! Use [`RetryPolicyBuilder`] to configure the backoff, limits and classification
+//! in one place, and get a single concrete [`Backoff`] policy out.
+//!
+//! ```
+//! use futures_retry_policies::{tokio::RetryFutureExt, RetryPolicyBuilder};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! enum Error { Timeout, Invalid }
+//!
+//! async fn make_request() -> Result<(), Error>  {
+//!     // make a request
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err(Error::Timeout) } else { Ok(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let policy = RetryPolicyBuilder::exponential(Duration::from_millis(10))
+//!         .jitter_full()
+//!         .max_retries(5)
+//!         .max_elapsed(Duration::from_secs(30))
+//!         .retry_if(|res: &Result<(), Error>| matches!(res, Err(Error::Timeout)))
+//!         .build();
+//!
+//!     make_request.retry(policy).await
+//! }
+//! ```
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::{
+    clock::{Clock, StdClock},
+    Classify, RetryPolicy, UseShouldRetry,
+};
+
+/// How to randomise the delays of a [`Backoff`].
+///
+/// Randomising delays stops many clients that failed at the same time from
+/// all retrying at the same time too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Jitter {
+    /// Use the delays as they are
+    #[default]
+    None,
+    /// Pick a delay between zero and the computed delay
+    Full,
+    /// Pick a delay between half the computed delay and the computed delay
+    Equal,
+}
+
+impl Jitter {
+    fn apply(self, delay: Duration, rng: &mut JitterRng, retries: u32) -> Duration {
+        match self {
+            Jitter::None => delay,
+            Jitter::Full => delay.mul_f64(rng.unit(retries)),
+            Jitter::Equal => {
+                let half = delay / 2;
+                half + half.mul_f64(rng.unit(retries))
+            }
+        }
+    }
+}
+
+/// What a [`Backoff`] does once its delays grow too large to represent.
+///
+/// Exponential delays overflow a [`Duration`] after enough retries, which long running
+/// reconnect loops can reach. Delays are never allowed to panic or wrap around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Keep retrying, waiting the [`max_delay`](RetryPolicyBuilder::max_delay)
+    #[default]
+    Saturate,
+    /// Stop retrying
+    GiveUp,
+}
+
+/// Where the randomness for [`Jitter`] comes from.
+///
+/// With the `rand` feature, this is a `SmallRng`, seeded from the OS unless
+/// a seed or a generator is given to the builder. Without it, each delay is randomised by mixing
+/// the retry number into a seed, which is random unless set with
+/// [`jitter_seed`](RetryPolicyBuilder::jitter_seed). Either way, seeded policies are reproducible.
+#[derive(Debug, Clone)]
+struct JitterRng {
+    seed: Option<u64>,
+    #[cfg(feature = "rand")]
+    rng: Option<rand::rngs::SmallRng>,
+}
+
+impl JitterRng {
+    const fn new(seed: Option<u64>) -> Self {
+        Self {
+            seed,
+            #[cfg(feature = "rand")]
+            rng: None,
+        }
+    }
+
+    /// A random number in `[0, 1)`
+    #[cfg(feature = "rand")]
+    fn unit(&mut self, _retries: u32) -> f64 {
+        use rand::{Rng, SeedableRng};
+
+        let seed = self.seed;
+        let rng = self.rng.get_or_insert_with(|| match seed {
+            Some(seed) => rand::rngs::SmallRng::seed_from_u64(seed),
+            None => rand::rngs::SmallRng::from_os_rng(),
+        });
+        rng.random()
+    }
+
+    /// A random number in `[0, 1)`, without pulling in a random number generator.
+    #[cfg(not(feature = "rand"))]
+    fn unit(&mut self, retries: u32) -> f64 {
+        use std::{
+            collections::hash_map::RandomState,
+            hash::{BuildHasher, Hasher},
+        };
+
+        let seed = *self
+            .seed
+            .get_or_insert_with(|| RandomState::new().build_hasher().finish());
+        (splitmix64(seed ^ u64::from(retries)) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(not(feature = "rand"))]
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Builder for a [`Backoff`] policy.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct RetryPolicyBuilder<C = UseShouldRetry, K = StdClock> {
+    base: Duration,
+    factor: f64,
+    max_delay: Duration,
+    jitter: Jitter,
+    rng: JitterRng,
+    overflow: Overflow,
+    anchored: bool,
+    max_retries: Option<u32>,
+    max_elapsed: Option<Duration>,
+    classifier: C,
+    clock: K,
+}
+
+impl RetryPolicyBuilder {
+    /// Start with a delay of `base`, doubling after every retry.
+    pub const fn exponential(base: Duration) -> Self {
+        Self {
+            base,
+            factor: 2.0,
+            max_delay: Duration::MAX,
+            jitter: Jitter::None,
+            rng: JitterRng::new(None),
+            overflow: Overflow::Saturate,
+            anchored: false,
+            max_retries: None,
+            max_elapsed: None,
+            classifier: UseShouldRetry,
+            clock: StdClock,
+        }
+    }
+
+    /// Always wait `delay` between retries.
+    pub const fn fixed(delay: Duration) -> Self {
+        Self::exponential(delay).factor(1.0)
+    }
+}
+
+impl<C, K> RetryPolicyBuilder<C, K> {
+    /// Multiply the delay by `factor` after every retry.
+    pub const fn factor(mut self, factor: f64) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// Never wait more than `max_delay` between retries.
+    pub const fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Randomise the delays between retries.
+    pub const fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Shorthand for [`jitter(Jitter::Full)`](Self::jitter).
+    pub const fn jitter_full(self) -> Self {
+        self.jitter(Jitter::Full)
+    }
+
+    /// Seed the randomness of the [`jitter`](Self::jitter), so the delays are reproducible.
+    ///
+    /// ```
+    /// use futures_retry_policies::{RetryPolicy, RetryPolicyBuilder};
+    /// use std::{ops::ControlFlow, time::Duration};
+    ///
+    /// let policy = || {
+    ///     RetryPolicyBuilder::exponential(Duration::from_secs(1))
+    ///         .jitter_full()
+    ///         .jitter_seed(42)
+    ///         .build()
+    /// };
+    ///
+    /// let (mut a, mut b) = (policy(), policy());
+    /// for _ in 0..5 {
+    ///     assert_eq!(a.should_retry(None::<()>), b.should_retry(None::<()>));
+    /// }
+    /// ```
+    pub const fn jitter_seed(mut self, seed: u64) -> Self {
+        self.rng = JitterRng::new(Some(seed));
+        self
+    }
+
+    /// Draw the randomness of the [`jitter`](Self::jitter) from `rng`.
+    #[cfg(feature = "rand")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+    pub fn jitter_rng(mut self, rng: rand::rngs::SmallRng) -> Self {
+        self.rng.rng = Some(rng);
+        self
+    }
+
+    /// Choose what happens once the delays grow too large to represent.
+    ///
+    /// By default, the delays [saturate](Overflow::Saturate) at the [`max_delay`](Self::max_delay).
+    pub const fn on_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Schedule retries at fixed offsets from the first failure, rather than after the previous attempt.
+    ///
+    /// With an exponential backoff starting at 1s, retries start 1s, 3s, 7s, ... after the first
+    /// attempt failed, however long the attempts themselves take. If an attempt runs past the
+    /// time the next one was due, the next one starts straight away.
+    ///
+    /// ```
+    /// use futures_retry_policies::{clock::MockClock, RetryPolicy, RetryPolicyBuilder};
+    /// use std::{ops::ControlFlow, time::Duration};
+    ///
+    /// let clock = MockClock::new();
+    /// let mut policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+    ///     .anchored()
+    ///     .clock(clock.clone())
+    ///     .retry_if(|_: &Result<(), ()>| true)
+    ///     .build();
+    ///
+    /// assert_eq!(policy.should_retry(Err(())), ControlFlow::Continue(Duration::from_secs(1)));
+    /// // the second attempt took half a second, so the third starts at the 3s mark
+    /// clock.advance(Duration::from_millis(1500));
+    /// assert_eq!(policy.should_retry(Err(())), ControlFlow::Continue(Duration::from_millis(1500)));
+    /// ```
+    pub const fn anchored(mut self) -> Self {
+        self.anchored = true;
+        self
+    }
+
+    /// Give up after `max_retries` retries, ie `max_retries + 1` attempts.
+    ///
+    /// Without this, the policy retries until another limit is reached.
+    pub const fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Don't start a retry that would begin more than `max_elapsed` after the first attempt finished.
+    pub const fn max_elapsed(mut self, max_elapsed: Duration) -> Self {
+        self.max_elapsed = Some(max_elapsed);
+        self
+    }
+
+    /// Decide which results are retried with `classifier`.
+    ///
+    /// By default, results are retried according to their [`ShouldRetry`](crate::ShouldRetry) impl.
+    pub fn retry_if<F>(self, classifier: F) -> RetryPolicyBuilder<F, K> {
+        RetryPolicyBuilder {
+            base: self.base,
+            factor: self.factor,
+            max_delay: self.max_delay,
+            jitter: self.jitter,
+            rng: self.rng,
+            overflow: self.overflow,
+            anchored: self.anchored,
+            max_retries: self.max_retries,
+            max_elapsed: self.max_elapsed,
+            classifier,
+            clock: self.clock,
+        }
+    }
+
+    /// Measure the [`max_elapsed`](Self::max_elapsed) limit with `clock`.
+    pub fn clock<K2: Clock>(self, clock: K2) -> RetryPolicyBuilder<C, K2> {
+        RetryPolicyBuilder {
+            base: self.base,
+            factor: self.factor,
+            max_delay: self.max_delay,
+            jitter: self.jitter,
+            rng: self.rng,
+            overflow: self.overflow,
+            anchored: self.anchored,
+            max_retries: self.max_retries,
+            max_elapsed: self.max_elapsed,
+            classifier: self.classifier,
+            clock,
+        }
+    }
+
+    /// Create the policy
+    pub const fn build(self) -> Backoff<C, K> {
+        Backoff {
+            config: self,
+            retries: 0,
+            started: None,
+            offset: Duration::ZERO,
+        }
+    }
+}
+
+/// The settings accepted by [`static_policy!`](crate::static_policy), checked at compile time.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct StaticConfig {
+    pub base: Option<Duration>,
+    pub factor: Option<f64>,
+    pub max_delay: Option<Duration>,
+    pub max_attempts: Option<u32>,
+    pub max_elapsed: Option<Duration>,
+}
+
+impl StaticConfig {
+    pub const EMPTY: Self = Self {
+        base: None,
+        factor: None,
+        max_delay: None,
+        max_attempts: None,
+        max_elapsed: None,
+    };
+
+    pub const fn validate(self) -> Self {
+        let Some(base) = self.base else {
+            panic!("static_policy! requires a `base` delay");
+        };
+        if let Some(factor) = self.factor {
+            assert!(
+                factor >= 1.0,
+                "static_policy! `factor` must be at least 1.0"
+            );
+        }
+        if let Some(max_delay) = self.max_delay {
+            assert!(
+                max_delay.as_nanos() >= base.as_nanos(),
+                "static_policy! `max_delay` must be at least `base`"
+            );
+        }
+        if let Some(max_attempts) = self.max_attempts {
+            assert!(
+                max_attempts > 0,
+                "static_policy! `max_attempts` must be more than 0"
+            );
+        }
+        if let Some(max_elapsed) = self.max_elapsed {
+            assert!(
+                max_elapsed.as_nanos() >= base.as_nanos(),
+                "static_policy! `max_elapsed` must be at least `base`"
+            );
+        }
+        self
+    }
+
+    pub const fn build(self) -> Backoff {
+        let Some(base) = self.base else {
+            panic!("static_policy! requires a `base` delay");
+        };
+        let mut builder = RetryPolicyBuilder::exponential(base);
+        if let Some(factor) = self.factor {
+            builder = builder.factor(factor);
+        }
+        if let Some(max_delay) = self.max_delay {
+            builder = builder.max_delay(max_delay);
+        }
+        if let Some(max_attempts) = self.max_attempts {
+            builder = builder.max_retries(max_attempts - 1);
+        }
+        if let Some(max_elapsed) = self.max_elapsed {
+            builder = builder.max_elapsed(max_elapsed);
+        }
+        builder.build()
+    }
+}
+
+/// An exponential backoff [`RetryPolicy`], created with a [`RetryPolicyBuilder`].
+///
+/// Its `Debug` output includes the retries so far, the time elapsed since the first attempt
+/// finished, and the next delay before jitter.
+#[derive(Clone)]
+pub struct Backoff<C = UseShouldRetry, K = StdClock> {
+    config: RetryPolicyBuilder<C, K>,
+    retries: u32,
+    started: Option<Duration>,
+    /// When the next retry is due, relative to `started`, if [anchored](RetryPolicyBuilder::anchored)
+    offset: Duration,
+}
+
+impl<C, K: Clock> std::fmt::Debug for Backoff<C, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = self
+            .started
+            .map(|started| self.config.clock.now().saturating_sub(started));
+        f.debug_struct("Backoff")
+            .field("retries", &self.retries)
+            .field("elapsed", &elapsed)
+            .field("next_delay", &self.delay_for(self.retries))
+            .field("base", &self.config.base)
+            .field("factor", &self.config.factor)
+            .field("max_delay", &self.config.max_delay)
+            .field("jitter", &self.config.jitter)
+            .field("max_retries", &self.config.max_retries)
+            .field("max_elapsed", &self.config.max_elapsed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, K> Backoff<C, K> {
+    /// Swap in new settings, keeping the retries so far and the time elapsed
+    pub(crate) fn replace_config(&mut self, config: RetryPolicyBuilder<C, K>) {
+        self.config = config;
+    }
+
+    /// The delay before the given retry, before any jitter is applied.
+    ///
+    /// Returns `None` if the delay overflowed and the policy should give up.
+    fn delay_for(&self, retries: u32) -> Option<Duration> {
+        let RetryPolicyBuilder {
+            base,
+            factor,
+            max_delay,
+            overflow,
+            ..
+        } = &self.config;
+        let delay = base.as_secs_f64() * factor.powf(retries as f64);
+        match Duration::try_from_secs_f64(delay) {
+            Ok(delay) => Some(delay.min(*max_delay)),
+            Err(_) if *overflow == Overflow::Saturate => Some(*max_delay),
+            Err(_) => None,
+        }
+    }
+
+    /// The delays planned for up to the next `n` retries, without changing the policy.
+    ///
+    /// The delays are shown before any jitter is applied, and stop early if the policy would run
+    /// out of retries, or give up on [overflow](Overflow::GiveUp). Whether the
+    /// [`max_elapsed`](RetryPolicyBuilder::max_elapsed) limit is hit depends on how long the
+    /// attempts take, so it isn't accounted for. For [anchored](RetryPolicyBuilder::anchored) policies,
+    /// these are the gaps between when each retry is due.
+    ///
+    /// ```
+    /// use futures_retry_policies::RetryPolicyBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1)).max_retries(3).build();
+    /// let schedule: Vec<_> = policy.preview(5).collect();
+    /// assert_eq!(schedule, [1, 2, 4].map(Duration::from_secs));
+    /// ```
+    pub fn preview(&self, n: usize) -> impl Iterator<Item = Duration> + '_ {
+        let end = self.config.max_retries.unwrap_or(u32::MAX);
+        (self.retries..end)
+            .take(n)
+            .map_while(|retries| self.delay_for(retries))
+    }
+}
+
+impl<C, K, R> RetryPolicy<R> for Backoff<C, K>
+where
+    C: Classify<R>,
+    K: Clock,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let attempt = self.retries.saturating_add(1);
+        let flow = self.decide(result);
+        crate::verbose::observe("Backoff", attempt, flow)
+    }
+}
+
+impl<C, K> Backoff<C, K> {
+    fn decide<R>(&mut self, result: R) -> ControlFlow<R, Duration>
+    where
+        C: Classify<R>,
+        K: Clock,
+    {
+        let now = self.config.clock.now();
+        let started = *self.started.get_or_insert(now);
+
+        if self
+            .config
+            .max_retries
+            .is_some_and(|max| self.retries >= max)
+        {
+            return ControlFlow::Break(result);
+        }
+        let attempts = self.retries.saturating_add(1);
+        if !self.config.classifier.classify(&result, attempts) {
+            return ControlFlow::Break(result);
+        }
+
+        let Some(delay) = self.delay_for(self.retries) else {
+            return ControlFlow::Break(result);
+        };
+        let delay = self
+            .config
+            .jitter
+            .apply(delay, &mut self.config.rng, self.retries);
+        let elapsed = now.saturating_sub(started);
+        let offset = self.offset.saturating_add(delay);
+        let delay = if self.config.anchored {
+            offset.saturating_sub(elapsed)
+        } else {
+            delay
+        };
+        if let Some(max_elapsed) = self.config.max_elapsed {
+            if elapsed.saturating_add(delay) > max_elapsed {
+                return ControlFlow::Break(result);
+            }
+        }
+
+        self.retries = attempts;
+        self.offset = offset;
+        ControlFlow::Continue(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Jitter, Overflow, RetryPolicyBuilder};
+    #[cfg(feature = "tokio")]
+    use crate::clock::TokioClock;
+    use crate::{retry, RetryPolicy};
+
+    fn delays<P: RetryPolicy<Result<(), ()>>>(mut policy: P) -> Vec<Duration> {
+        let mut delays = vec![];
+        while let std::ops::ControlFlow::Continue(delay) = policy.should_retry(Err(())) {
+            delays.push(delay);
+        }
+        delays
+    }
+
+    #[derive(Debug)]
+    struct Error;
+    impl crate::ShouldRetry for Error {
+        fn should_retry(&self, _: u32) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn exponential() {
+        let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .max_retries(4)
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        assert_eq!(
+            delays(policy),
+            [1, 2, 4, 8].map(Duration::from_secs).to_vec()
+        );
+    }
+
+    #[test]
+    fn capped() {
+        let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .factor(3.0)
+            .max_delay(Duration::from_secs(5))
+            .max_retries(4)
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        assert_eq!(
+            delays(policy),
+            [1, 3, 5, 5].map(Duration::from_secs).to_vec()
+        );
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        let policy = RetryPolicyBuilder::fixed(Duration::from_secs(1))
+            .jitter_full()
+            .max_retries(100)
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        let delays = delays(policy);
+        assert_eq!(delays.len(), 100);
+        assert!(delays.iter().all(|d| *d <= Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn seeded_jitter_is_reproducible() {
+        let policy = |seed| {
+            RetryPolicyBuilder::fixed(Duration::from_secs(1))
+                .jitter_full()
+                .jitter_seed(seed)
+                .max_retries(10)
+                .retry_if(|_: &Result<(), ()>| true)
+                .build()
+        };
+        assert_eq!(delays(policy(1)), delays(policy(1)));
+        assert_ne!(delays(policy(1)), delays(policy(2)));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn injected_rng() {
+        use rand::SeedableRng;
+
+        let policy = || {
+            RetryPolicyBuilder::fixed(Duration::from_secs(1))
+                .jitter_full()
+                .jitter_rng(rand::rngs::SmallRng::seed_from_u64(7))
+                .max_retries(10)
+                .retry_if(|_: &Result<(), ()>| true)
+                .build()
+        };
+        assert_eq!(delays(policy()), delays(policy()));
+    }
+
+    #[test]
+    fn debug_shows_progress() {
+        let clock = crate::clock::MockClock::new();
+        let mut policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .clock(clock.clone())
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        assert!(format!("{policy:?}")
+            .starts_with("Backoff { retries: 0, elapsed: None, next_delay: Some(1s)"));
+
+        let _ = policy.should_retry(Err(()));
+        clock.advance(Duration::from_secs(3));
+        assert!(format!("{policy:?}")
+            .starts_with("Backoff { retries: 1, elapsed: Some(3s), next_delay: Some(2s)"));
+    }
+
+    #[test]
+    fn classified() {
+        let policy = RetryPolicyBuilder::fixed(Duration::from_secs(1))
+            .max_retries(4)
+            .retry_if(|_: &Result<(), ()>| false)
+            .build();
+        assert!(delays(policy).is_empty());
+    }
+
+    #[test]
+    fn max_elapsed() {
+        let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .max_elapsed(Duration::from_secs(10))
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        // would wait until the 16s mark before the next retry
+        assert_eq!(
+            delays(policy),
+            [1, 2, 4, 8].map(Duration::from_secs).to_vec()
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn max_elapsed_including_attempts() {
+        let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .max_elapsed(Duration::from_secs(10))
+            .clock(TokioClock::new())
+            .build();
+
+        let start = tokio::time::Instant::now();
+        retry(policy, tokio::time::sleep, || async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Err::<(), _>(Error)
+        })
+        .await
+        .unwrap_err();
+        // attempts finish at 1s, 3s, 6s and 11s. The next retry would start 18s after
+        // the first attempt finished, so it gives up after the fourth attempt.
+        assert_eq!(
+            start.elapsed(),
+            Duration::from_secs(1 + 1 + 1 + 2 + 1 + 4 + 1)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_retry_by_default() {
+        let policy = RetryPolicyBuilder::fixed(Duration::from_secs(1))
+            .max_retries(3)
+            .build();
+
+        let start = tokio::time::Instant::now();
+        retry(policy, tokio::time::sleep, || async { Err::<(), _>(Error) })
+            .await
+            .unwrap_err();
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn saturates_at_extreme_retries() {
+        let mut policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .jitter(Jitter::Equal)
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        policy.retries = u32::MAX;
+        assert_eq!(policy.delay_for(u32::MAX), Some(Duration::MAX));
+        assert!(policy.should_retry(Err(())).is_continue());
+        assert_eq!(policy.retries, u32::MAX);
+
+        let mut policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(60))
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        policy.retries = u32::MAX;
+        assert_eq!(
+            policy.should_retry(Err(())),
+            std::ops::ControlFlow::Continue(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn gives_up_on_overflow() {
+        let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .factor(1e10)
+            .on_overflow(Overflow::GiveUp)
+            .retry_if(|_: &Result<(), ()>| true)
+            .build();
+        // 1s, 1e10s, and 1e20s overflows
+        assert_eq!(delays(policy).len(), 2);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn anchored_to_first_failure() {
+        let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .anchored()
+            .max_retries(3)
+            .clock(TokioClock::new())
+            .build();
+
+        let start = tokio::time::Instant::now();
+        let mut started = vec![];
+        retry(policy, tokio::time::sleep, || {
+            started.push(start.elapsed());
+            async {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                Err::<(), _>(Error)
+            }
+        })
+        .await
+        .unwrap_err();
+        // the first attempt fails at 2s, so retries are due at 3s, 5s and 9s. The second
+        // attempt finishes at 5s, so the third starts straight away.
+        assert_eq!(started, [0, 3, 5, 9].map(Duration::from_secs));
+    }
+}
@@ -0,0 +1,50 @@
+//! Blocking retries with a custom sleep function.
+//!
+//! [`retry_blocking`] runs the same policies as the async retry loops on the current thread,
+//! sleeping with whatever function it's given. The [`sync`](crate::sync) module builds its
+//! [`thread::sleep`](std::thread::sleep) based helpers on it, and re-exports it.
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// Blocking retry function, using the given sleep function.
+///
+/// This is the blocking equivalent of [`retry`](crate::retry), so the same policies can drive
+/// both async and sync code paths, while still letting tests skip the sleeps.
+///
+/// ```rust
+/// use futures_retry_policies::{blocking::retry_blocking, iter::Iter};
+/// use std::time::Duration;
+///
+/// fn read_config() -> Option<String> {
+///     // read a file that might not have been written yet
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(String::new()) }
+/// }
+///
+/// fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let mut slept = Duration::ZERO;
+///     let config = retry_blocking(policy, |dur| slept += dur, read_config);
+///     assert!(config.is_some());
+///     assert_eq!(slept, Duration::from_millis(20));
+/// }
+/// ```
+pub fn retry_blocking<Policy, Sleeper, F, Output>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut f: F,
+) -> Output
+where
+    Policy: RetryPolicy<Output>,
+    Sleeper: FnMut(Duration),
+    F: FnMut() -> Output,
+{
+    loop {
+        match policy.should_retry(f()) {
+            ControlFlow::Continue(dur) => sleeper(dur),
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Retry budgets, limiting how many retries a whole client makes.
+//!
+//! Per-call limits like [`max_retries`](crate::RetryPolicyBuilder::max_retries) don't stop a
+//! client that is making many calls from multiplying its load on a struggling dependency.
+//! A [`RetryBudget`] is a pool of retry tokens shared by every call: each retry takes a token,
+//! and each successful attempt puts one back, so retries stop once most attempts are failing.
+//!
+//! When many tenants share a client, a [`FairBudget`] gives each tenant its own sub-budget under
+//! the shared cap, so one misbehaving tenant can't use up the retries of everyone else.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{Classify, RetryPolicy, UseShouldRetry};
+
+/// A pool of retry tokens, as used by [`Budgeted`]
+pub trait Budget {
+    /// Take a token for a retry, returning whether one was available
+    fn withdraw(&self) -> bool;
+
+    /// Return a token after a successful attempt
+    fn deposit(&self);
+}
+
+impl<B: Budget + ?Sized> Budget for &B {
+    fn withdraw(&self) -> bool {
+        B::withdraw(self)
+    }
+
+    fn deposit(&self) {
+        B::deposit(self)
+    }
+}
+
+/// No budget at all, for when a budget is optional: every retry is allowed
+impl<B: Budget> Budget for Option<B> {
+    fn withdraw(&self) -> bool {
+        self.as_ref().is_none_or(B::withdraw)
+    }
+
+    fn deposit(&self) {
+        if let Some(budget) = self {
+            budget.deposit();
+        }
+    }
+}
+
+/// A [`Budget`] of retry tokens, shared between clones
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    tokens: Arc<AtomicU32>,
+    capacity: u32,
+}
+
+impl RetryBudget {
+    /// Allow up to `capacity` retries more than successful attempts. The budget starts full.
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            tokens: Arc::new(AtomicU32::new(capacity)),
+            capacity,
+        }
+    }
+
+    /// How many retries are currently available
+    pub fn available(&self) -> u32 {
+        self.tokens.load(Ordering::Relaxed)
+    }
+}
+
+impl Budget for RetryBudget {
+    fn withdraw(&self) -> bool {
+        self.tokens
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |tokens| {
+                tokens.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    fn deposit(&self) {
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |tokens| {
+                (tokens < self.capacity).then_some(tokens + 1)
+            });
+    }
+}
+
+/// A [`RetryBudget`] split between tenants, shared between clones.
+///
+/// Each tenant gets a sub-budget of `per_tenant` tokens, multiplied by its [weight](FairBudget::weight),
+/// and every retry must also fit within the global budget of `global` tokens.
+///
+/// Sub-budgets are kept for every tenant that has been seen, so keys should come from a bounded set.
+///
+/// ```
+/// use futures_retry_policies::{budget::{Budget, FairBudget}};
+///
+/// let budget = FairBudget::new(10, 2);
+/// let noisy = budget.tenant("noisy");
+/// let quiet = budget.tenant("quiet");
+///
+/// // the noisy tenant runs out of its own retries...
+/// assert!(noisy.withdraw());
+/// assert!(noisy.withdraw());
+/// assert!(!noisy.withdraw());
+/// // ...without using up everyone else's
+/// assert!(quiet.withdraw());
+/// ```
+#[derive(Debug)]
+pub struct FairBudget<K> {
+    inner: Arc<FairState<K>>,
+}
+
+#[derive(Debug)]
+struct FairState<K> {
+    global: RetryBudget,
+    per_tenant: u32,
+    tenants: Mutex<Tenants<K>>,
+}
+
+#[derive(Debug)]
+struct Tenants<K> {
+    weights: HashMap<K, u32>,
+    budgets: HashMap<K, RetryBudget>,
+}
+
+impl<K> Clone for FairBudget<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> FairBudget<K> {
+    /// Allow up to `global` retries across all tenants, and `per_tenant` for each tenant
+    pub fn new(global: u32, per_tenant: u32) -> Self {
+        Self {
+            inner: Arc::new(FairState {
+                global: RetryBudget::new(global),
+                per_tenant,
+                tenants: Mutex::new(Tenants {
+                    weights: HashMap::new(),
+                    budgets: HashMap::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Give `key` a sub-budget `weight` times the size of a normal tenant's. Tenants have a weight of 1 by default.
+    ///
+    /// This only affects tenants that haven't been [used](FairBudget::tenant) yet.
+    #[must_use]
+    pub fn weight(self, key: K, weight: u32) -> Self {
+        self.inner
+            .tenants
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .weights
+            .insert(key, weight);
+        self
+    }
+
+    /// The budget for a single tenant
+    pub fn tenant(&self, key: K) -> TenantBudget {
+        let mut tenants = self.inner.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        let weight = tenants.weights.get(&key).copied().unwrap_or(1);
+        let capacity = self.inner.per_tenant.saturating_mul(weight);
+        let own = tenants
+            .budgets
+            .entry(key)
+            .or_insert_with(|| RetryBudget::new(capacity))
+            .clone();
+        TenantBudget {
+            own,
+            global: self.inner.global.clone(),
+        }
+    }
+
+    /// How many retries are currently available across all tenants
+    pub fn available(&self) -> u32 {
+        self.inner.global.available()
+    }
+}
+
+/// One tenant's share of a [`FairBudget`]
+#[derive(Debug, Clone)]
+pub struct TenantBudget {
+    own: RetryBudget,
+    global: RetryBudget,
+}
+
+impl TenantBudget {
+    /// How many retries are currently available to this tenant
+    pub fn available(&self) -> u32 {
+        self.own.available().min(self.global.available())
+    }
+}
+
+impl Budget for TenantBudget {
+    fn withdraw(&self) -> bool {
+        if !self.own.withdraw() {
+            return false;
+        }
+        if !self.global.withdraw() {
+            self.own.deposit();
+            return false;
+        }
+        true
+    }
+
+    fn deposit(&self) {
+        self.own.deposit();
+        self.global.deposit();
+    }
+}
+
+/// A [`RetryPolicy`] that only retries while its [`Budget`] has tokens left.
+///
+/// Attempts are counted as successful when they shouldn't be retried, according to
+/// [`ShouldRetry`](crate::ShouldRetry) or the classifier set with [`retry_if`](Budgeted::retry_if).
+///
+/// ```
+/// use futures_retry_policies::{budget::{Budgeted, FairBudget}, iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // shared by the whole client
+///     let budget = FairBudget::new(100, 10).weight("batch-jobs", 3);
+///
+///     let policy = Budgeted::new(Iter::new([Duration::from_millis(10); 3]), budget.tenant("web"));
+///     make_request.retry(policy).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Budgeted<P, B, C = UseShouldRetry> {
+    policy: P,
+    budget: B,
+    classifier: C,
+    attempts: u32,
+}
+
+impl<P, B: Budget> Budgeted<P, B> {
+    /// Retry with `policy`, while `budget` has tokens left
+    pub fn new(policy: P, budget: B) -> Self {
+        Self {
+            policy,
+            budget,
+            classifier: UseShouldRetry,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, B, C> Budgeted<P, B, C> {
+    /// Decide which results are retried, and which refill the budget, with `classifier`
+    pub fn retry_if<F>(self, classifier: F) -> Budgeted<P, B, F> {
+        Budgeted {
+            policy: self.policy,
+            budget: self.budget,
+            classifier,
+            attempts: self.attempts,
+        }
+    }
+}
+
+impl<P, B, C, R> RetryPolicy<R> for Budgeted<P, B, C>
+where
+    P: RetryPolicy<R>,
+    B: Budget,
+    C: Classify<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        if !self.classifier.classify(&result, self.attempts) {
+            self.budget.deposit();
+            return ControlFlow::Break(result);
+        }
+        if !self.budget.withdraw() {
+            return ControlFlow::Break(result);
+        }
+        match self.policy.should_retry(result) {
+            ControlFlow::Continue(delay) => ControlFlow::Continue(delay),
+            ControlFlow::Break(result) => {
+                // the retry didn't happen after all
+                self.budget.deposit();
+                ControlFlow::Break(result)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Budget, Budgeted, FairBudget, RetryBudget};
+    use crate::{iter::Iter, RetryPolicy};
+
+    fn retries<B: Budget>(budget: B) -> usize {
+        let mut policy = Budgeted::new(Iter::new(std::iter::repeat(Duration::ZERO)), budget);
+        std::iter::from_fn(|| policy.should_retry(None::<()>).continue_value())
+            .take(100)
+            .count()
+    }
+
+    #[test]
+    fn shared_budget() {
+        let budget = RetryBudget::new(3);
+        assert_eq!(retries(&budget), 3);
+        assert_eq!(retries(&budget), 0);
+
+        let mut policy = Budgeted::new(Iter::new([Duration::ZERO]), &budget);
+        assert!(policy.should_retry(Some(())).is_break());
+        assert_eq!(budget.available(), 1);
+    }
+
+    #[test]
+    fn refunds_when_inner_gives_up() {
+        let budget = RetryBudget::new(3);
+        let mut policy = Budgeted::new(Iter::new([] as [Duration; 0]), &budget);
+        assert!(policy.should_retry(None::<()>).is_break());
+        assert_eq!(budget.available(), 3);
+    }
+
+    #[test]
+    fn fair_between_tenants() {
+        let budget = FairBudget::new(5, 2).weight("big", 2);
+        assert_eq!(retries(budget.tenant("noisy")), 2);
+        assert_eq!(retries(budget.tenant("noisy")), 0);
+        assert_eq!(retries(budget.tenant("big")), 3);
+        assert_eq!(budget.available(), 0);
+        assert_eq!(budget.tenant("quiet").available(), 0);
+
+        budget.tenant("noisy").deposit();
+        assert_eq!(budget.tenant("noisy").available(), 1);
+    }
+}
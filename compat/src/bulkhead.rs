@@ -0,0 +1,87 @@
+//! Shedding retries while the application is overloaded.
+//!
+//! During overload, retries add to the very load that caused the failures. A [`Bulkhead`]
+//! asks the application how saturated it is, eg. how deep a queue is or how many requests
+//! are in flight, and turns retries into immediate failures above a threshold.
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// A [`RetryPolicy`] that doesn't retry while `saturation()` is at or above a threshold.
+///
+/// First attempts are always made, as only retries are shed.
+///
+/// ```
+/// use futures_retry_policies::{bulkhead::Bulkhead, iter::Iter, tokio::RetryFutureExt};
+/// use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+///
+/// // tracked by the application
+/// static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Bulkhead::new(
+///         Iter::new([Duration::from_millis(10); 3]),
+///         || IN_FLIGHT.load(Ordering::Relaxed),
+///         100,
+///     );
+///     make_request.retry(policy).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bulkhead<P, F> {
+    policy: P,
+    saturation: F,
+    threshold: usize,
+}
+
+impl<P, F: FnMut() -> usize> Bulkhead<P, F> {
+    /// Retry with `policy` while `saturation()` is below `threshold`
+    pub fn new(policy: P, saturation: F, threshold: usize) -> Self {
+        Self {
+            policy,
+            saturation,
+            threshold,
+        }
+    }
+}
+
+impl<P, F, R> RetryPolicy<R> for Bulkhead<P, F>
+where
+    P: RetryPolicy<R>,
+    F: FnMut() -> usize,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        if (self.saturation)() >= self.threshold {
+            return ControlFlow::Break(result);
+        }
+        self.policy.should_retry(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, ops::ControlFlow, time::Duration};
+
+    use super::Bulkhead;
+    use crate::{iter::Iter, RetryPolicy};
+
+    #[test]
+    fn sheds_retries_when_saturated() {
+        let depth = Cell::new(0);
+        let mut policy = Bulkhead::new(Iter::new([Duration::ZERO; 3]), || depth.get(), 10);
+
+        assert!(policy.should_retry(None::<()>).is_continue());
+        depth.set(10);
+        assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+        depth.set(9);
+        assert!(policy.should_retry(None::<()>).is_continue());
+    }
+}
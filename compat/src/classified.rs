@@ -0,0 +1,483 @@
+//! Results that carry their own retry classification.
+//!
+//! Sometimes the attempt itself is the best place to decide whether a result
+//! is worth retrying, eg. when the decision depends on response headers that are
+//! only available while handling the request. [`Classified`] lets the attempt record
+//! that decision once, so the policy doesn't need to re-derive it.
+//!
+//! For plain `Result`s, pairing the error with a [`RetryHint`] as `Result<T, (E, RetryHint)>`
+//! does the same job without a wrapper type.
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::{
+    clock::{Clock, StdClock},
+    RetryPolicy, ShouldRetry,
+};
+
+/// A value annotated with whether it should be retried, and optionally how long to wait first.
+///
+/// ```
+/// use futures_retry_policies::{classified::{Classified, Hinted}, iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Classified<Result<(), &'static str>> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # let too_many_requests = COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2;
+///     if too_many_requests {
+///         // the server told us when to come back
+///         Classified::new(Err("slow down"), true).with_delay_hint(Duration::from_millis(50))
+///     } else {
+///         Classified::new(Ok(()), false)
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), &'static str> {
+///     let policy = Hinted(Iter::new([Duration::from_millis(10); 3]));
+///     make_request.retry(policy).await.into_inner()
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Classified<T> {
+    /// The result of the attempt
+    pub value: T,
+    /// Whether the attempt may be retried
+    pub retryable: bool,
+    /// How long to wait before retrying, if the attempt knows better than the policy
+    pub delay_hint: Option<Duration>,
+}
+
+impl<T> Classified<T> {
+    /// Classify a value, without a delay hint
+    pub fn new(value: T, retryable: bool) -> Self {
+        Self {
+            value,
+            retryable,
+            delay_hint: None,
+        }
+    }
+
+    /// Set the delay that should be waited before the next attempt
+    pub fn with_delay_hint(mut self, delay: Duration) -> Self {
+        self.delay_hint = Some(delay);
+        self
+    }
+
+    /// Get the classified value
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> ShouldRetry for Classified<T> {
+    /// Should retry if the attempt classified itself as retryable
+    fn should_retry(&self, _: u32) -> bool {
+        self.retryable
+    }
+}
+
+/// Marks a value as worth retrying.
+///
+/// As an error, this makes a `Result<T, Retryable<E>>` retry every error. It also converts into a
+/// [`Classified`], so attempts can mark their outcome inline.
+///
+/// ```
+/// use futures_retry_policies::{classified::{Classified, Fatal, Retryable}, iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// #[derive(Debug)]
+/// enum Error { Timeout, Invalid }
+///
+/// async fn make_request() -> Classified<Result<(), Error>> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # let res = if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err(Error::Timeout) } else { Ok(()) };
+///     match res {
+///         Err(Error::Invalid) => Fatal(Err(Error::Invalid)).into(),
+///         res @ Err(_) => Retryable(res).into(),
+///         Ok(()) => Fatal(Ok(())).into(),
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     make_request.retry(policy).await.into_inner()
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(transparent)]
+pub struct Retryable<T>(pub T);
+
+/// Marks a value as not worth retrying.
+///
+/// As an error, this makes a `Result<T, Fatal<E>>` never retry. It also converts into a
+/// [`Classified`], so attempts can mark their outcome inline. See [`Retryable`] for an example.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(transparent)]
+pub struct Fatal<T>(pub T);
+
+impl<T> Retryable<T> {
+    /// Get the marked value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Fatal<T> {
+    /// Get the marked value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> ShouldRetry for Retryable<T> {
+    /// Always retry
+    fn should_retry(&self, _: u32) -> bool {
+        true
+    }
+}
+
+impl<T> ShouldRetry for Fatal<T> {
+    /// Never retry
+    fn should_retry(&self, _: u32) -> bool {
+        false
+    }
+}
+
+impl<T> From<Retryable<T>> for Classified<T> {
+    fn from(value: Retryable<T>) -> Self {
+        Classified::new(value.0, true)
+    }
+}
+
+impl<T> From<Fatal<T>> for Classified<T> {
+    fn from(value: Fatal<T>) -> Self {
+        Classified::new(value.0, false)
+    }
+}
+
+/// What an attempt thinks should happen after its error.
+///
+/// Paired with the error as `Result<T, (E, RetryHint)>`, this is a lightweight way for attempts
+/// to steer the policy, without a custom [`ShouldRetry`] type or a classifier closure.
+/// [`Hinted`] honours the hint's delay.
+///
+/// ```
+/// use futures_retry_policies::{classified::{Hinted, RetryHint}, iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Result<(), (&'static str, RetryHint)> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # let too_many_requests = COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2;
+///     if too_many_requests {
+///         // the server told us when to come back
+///         Err(("slow down", RetryHint::After(Duration::from_millis(50))))
+///     } else {
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Hinted(Iter::new([Duration::from_millis(10); 3]));
+///     assert_eq!(make_request.retry(policy).await, Ok(()));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RetryHint {
+    /// Retry, but wait this long first
+    After(Duration),
+    /// Don't retry
+    Never,
+    /// Retry, waiting however long the policy decides
+    #[default]
+    Default,
+}
+
+impl<E> ShouldRetry for (E, RetryHint) {
+    /// Should retry unless hinted otherwise
+    fn should_retry(&self, _: u32) -> bool {
+        self.1 != RetryHint::Never
+    }
+}
+
+/// A [`RetryPolicy`] that prefers the [`delay_hint`](Classified::delay_hint) of a [`Classified`]
+/// result over the delay chosen by the inner policy.
+///
+/// The inner policy still decides whether to retry at all, so retry limits are respected.
+///
+/// It works the same way for a `Result<T, (E, RetryHint)>`, preferring the delay of
+/// [`RetryHint::After`]. Errors hinted with [`RetryHint::Never`] are returned without
+/// consulting the inner policy.
+///
+/// Hints aren't checked against any deadline of the inner policy. Use [`Hinted::within`] to
+/// decide what happens when a hint is longer than the time that is left.
+#[derive(Debug, Clone)]
+pub struct Hinted<P>(pub P);
+
+impl<P> Hinted<P> {
+    /// Only retry within `max_elapsed` of the first attempt finishing, giving up on hints that
+    /// would wait past it. See [`HintedWithin::long_hint`] for other options.
+    pub fn within(self, max_elapsed: Duration) -> HintedWithin<P> {
+        HintedWithin {
+            policy: self.0,
+            max_elapsed,
+            long_hint: LongHint::default(),
+            clock: StdClock,
+            started: None,
+        }
+    }
+}
+
+/// What [`HintedWithin`] does with a delay hint longer than the time left before its deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LongHint {
+    /// Stop retrying, without consulting the inner policy
+    #[default]
+    GiveUp,
+    /// Retry at the deadline instead
+    Clamp,
+    /// Ignore the hint, and wait for the delay chosen by the inner policy
+    Ignore,
+}
+
+/// A [`Hinted`] policy with a deadline, returned by [`Hinted::within`].
+///
+/// ```
+/// use futures_retry_policies::{classified::{Hinted, LongHint, RetryHint}, iter::Iter, RetryPolicy};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// let hinted = Hinted(Iter::new([Duration::from_secs(1); 5]));
+/// let mut policy = hinted.within(Duration::from_secs(30)).long_hint(LongHint::Clamp);
+///
+/// // the server asked us to come back in an hour, but we only have 30 seconds
+/// let res = Err::<(), _>(("slow down", RetryHint::After(Duration::from_secs(3600))));
+/// assert_eq!(policy.should_retry(res), ControlFlow::Continue(Duration::from_secs(30)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct HintedWithin<P, K = StdClock> {
+    policy: P,
+    max_elapsed: Duration,
+    long_hint: LongHint,
+    clock: K,
+    started: Option<Duration>,
+}
+
+impl<P, K> HintedWithin<P, K> {
+    /// Set what happens to hints longer than the time left. [`LongHint::GiveUp`] by default.
+    pub fn long_hint(mut self, long_hint: LongHint) -> Self {
+        self.long_hint = long_hint;
+        self
+    }
+
+    /// Measure the deadline with `clock`
+    pub fn with_clock<K2: Clock>(self, clock: K2) -> HintedWithin<P, K2> {
+        HintedWithin {
+            policy: self.policy,
+            max_elapsed: self.max_elapsed,
+            long_hint: self.long_hint,
+            clock,
+            started: None,
+        }
+    }
+
+    fn decide<R>(&mut self, hint: Option<Duration>, result: R) -> ControlFlow<R, Duration>
+    where
+        P: RetryPolicy<R>,
+        K: Clock,
+    {
+        let now = self.clock.now();
+        let started = *self.started.get_or_insert(now);
+        let remaining = self.max_elapsed.saturating_sub(now.saturating_sub(started));
+        if remaining.is_zero() {
+            return ControlFlow::Break(result);
+        }
+
+        let long = hint.filter(|&hint| hint > remaining);
+        if long.is_some() && self.long_hint == LongHint::GiveUp {
+            return ControlFlow::Break(result);
+        }
+        let duration = self.policy.should_retry(result)?;
+        ControlFlow::Continue(match (hint, long, self.long_hint) {
+            (_, Some(_), LongHint::Clamp) => remaining,
+            (_, Some(_), _) => duration,
+            (hint, None, _) => hint.unwrap_or(duration),
+        })
+    }
+}
+
+impl<T, P, K> RetryPolicy<Classified<T>> for HintedWithin<P, K>
+where
+    P: RetryPolicy<Classified<T>>,
+    K: Clock,
+{
+    fn should_retry(&mut self, result: Classified<T>) -> ControlFlow<Classified<T>, Duration> {
+        self.decide(result.delay_hint, result)
+    }
+}
+
+impl<T, E, P, K> RetryPolicy<Result<T, (E, RetryHint)>> for HintedWithin<P, K>
+where
+    P: RetryPolicy<Result<T, (E, RetryHint)>>,
+    K: Clock,
+{
+    fn should_retry(
+        &mut self,
+        result: Result<T, (E, RetryHint)>,
+    ) -> ControlFlow<Result<T, (E, RetryHint)>, Duration> {
+        let hint = match &result {
+            Err((_, RetryHint::Never)) => return ControlFlow::Break(result),
+            Err((_, RetryHint::After(delay))) => Some(*delay),
+            _ => None,
+        };
+        self.decide(hint, result)
+    }
+}
+
+impl<T, P> RetryPolicy<Classified<T>> for Hinted<P>
+where
+    P: RetryPolicy<Classified<T>>,
+{
+    fn should_retry(&mut self, result: Classified<T>) -> ControlFlow<Classified<T>, Duration> {
+        let hint = result.delay_hint;
+        let duration = self.0.should_retry(result)?;
+        ControlFlow::Continue(hint.unwrap_or(duration))
+    }
+}
+
+impl<T, E, P> RetryPolicy<Result<T, (E, RetryHint)>> for Hinted<P>
+where
+    P: RetryPolicy<Result<T, (E, RetryHint)>>,
+{
+    fn should_retry(
+        &mut self,
+        result: Result<T, (E, RetryHint)>,
+    ) -> ControlFlow<Result<T, (E, RetryHint)>, Duration> {
+        let hint = match &result {
+            Err((_, RetryHint::Never)) => return ControlFlow::Break(result),
+            Err((_, RetryHint::After(delay))) => Some(*delay),
+            _ => None,
+        };
+        let duration = self.0.should_retry(result)?;
+        ControlFlow::Continue(hint.unwrap_or(duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use std::ops::ControlFlow;
+
+    use super::{Classified, Fatal, Hinted, LongHint, RetryHint, Retryable};
+    use crate::{clock::MockClock, iter::Iter, retry, RetryPolicy, ShouldRetry};
+
+    #[tokio::test(start_paused = true)]
+    async fn honours_hints() {
+        let mut attempts = 0;
+        let start = tokio::time::Instant::now();
+        let res = retry(
+            Hinted(Iter::new([Duration::from_secs(1); 3])),
+            tokio::time::sleep,
+            || {
+                attempts += 1;
+                let res = match attempts {
+                    1 => Classified::new(1, true),
+                    2 => Classified::new(2, true).with_delay_hint(Duration::from_secs(5)),
+                    n => Classified::new(n, false),
+                };
+                async move { res }
+            },
+        )
+        .await;
+
+        assert_eq!(res.into_inner(), 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hint_does_not_extend_limits() {
+        let start = tokio::time::Instant::now();
+        let res = retry(
+            Hinted(Iter::new([Duration::from_secs(1); 2])),
+            tokio::time::sleep,
+            || async { Classified::new((), true).with_delay_hint(Duration::from_secs(3)) },
+        )
+        .await;
+
+        assert!(res.retryable);
+        assert_eq!(start.elapsed(), Duration::from_secs(3 + 3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn honours_hint_tuples() {
+        let mut attempts = 0;
+        let start = tokio::time::Instant::now();
+        let res = retry(
+            Hinted(Iter::new([Duration::from_secs(1); 5])),
+            tokio::time::sleep,
+            || {
+                attempts += 1;
+                let hint = match attempts {
+                    1 => RetryHint::Default,
+                    2 => RetryHint::After(Duration::from_secs(5)),
+                    _ => RetryHint::Never,
+                };
+                async move { Err::<(), _>((attempts, hint)) }
+            },
+        )
+        .await;
+
+        assert_eq!(res, Err((3, RetryHint::Never)));
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 5));
+    }
+
+    #[test]
+    fn hints_longer_than_deadline() {
+        let clock = MockClock::new();
+        let policy = |long_hint| {
+            Hinted(Iter::new([Duration::from_secs(1); 5]))
+                .within(Duration::from_secs(10))
+                .long_hint(long_hint)
+                .with_clock(clock.clone())
+        };
+        let hinted = |secs| Classified::new((), true).with_delay_hint(Duration::from_secs(secs));
+
+        let mut give_up = policy(LongHint::GiveUp);
+        let mut clamp = policy(LongHint::Clamp);
+        let mut ignore = policy(LongHint::Ignore);
+        for policy in [&mut give_up, &mut clamp, &mut ignore] {
+            assert_eq!(
+                policy.should_retry(hinted(4)),
+                ControlFlow::Continue(Duration::from_secs(4))
+            );
+        }
+        clock.advance(Duration::from_secs(4));
+
+        assert!(give_up.should_retry(hinted(7)).is_break());
+        assert_eq!(
+            clamp.should_retry(hinted(7)),
+            ControlFlow::Continue(Duration::from_secs(6))
+        );
+        assert_eq!(
+            ignore.should_retry(hinted(7)),
+            ControlFlow::Continue(Duration::from_secs(1))
+        );
+
+        clock.advance(Duration::from_secs(6));
+        assert!(clamp.should_retry(hinted(1)).is_break());
+    }
+
+    #[test]
+    fn marked_errors() {
+        assert!(Err::<(), _>(Retryable("timeout")).should_retry(1));
+        assert!(!Err::<(), _>(Fatal("invalid")).should_retry(1));
+        assert!(!Ok::<_, Retryable<()>>(()).should_retry(1));
+        assert!(Err::<(), _>(("busy", RetryHint::Default)).should_retry(1));
+        assert!(!Err::<(), _>(("invalid", RetryHint::Never)).should_retry(1));
+    }
+}
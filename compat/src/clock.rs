@@ -0,0 +1,191 @@
+//! Monotonic clocks used by the time-based policies.
+//!
+//! Policies that care about elapsed time, like [`Backoff`](crate::backoff::Backoff) with a
+//! [`max_elapsed`](crate::RetryPolicyBuilder::max_elapsed) limit, or [`FixedRate`](crate::rate::FixedRate),
+//! read the time from a [`Clock`]. They use [`StdClock`] by default, but can be given a [`MockClock`]
+//! in tests to control time exactly.
+//!
+//! Policies with deadlines on the calendar, like [`Until`](crate::limits::Until), read the time
+//! from a [`WallClock`] instead. [`SystemClock`] is the default, and [`MockClock`] works as one too.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// A source of monotonic time
+pub trait Clock {
+    /// The time elapsed since some fixed point chosen by the clock.
+    ///
+    /// This must never decrease.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Duration {
+        C::now(self)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Duration {
+        C::now(self)
+    }
+}
+
+/// A source of wall-clock time
+///
+/// Unlike a [`Clock`], this may jump backwards or forwards as the system time is adjusted.
+pub trait WallClock {
+    /// The current time
+    fn now(&self) -> SystemTime;
+}
+
+impl<C: WallClock + ?Sized> WallClock for &C {
+    fn now(&self) -> SystemTime {
+        C::now(self)
+    }
+}
+
+impl<C: WallClock + ?Sized> WallClock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        C::now(self)
+    }
+}
+
+/// A [`WallClock`] using [`SystemTime::now`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl WallClock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A [`Clock`] using [`std::time::Instant`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdClock;
+
+impl Clock for StdClock {
+    fn now(&self) -> Duration {
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+        ORIGIN.get_or_init(Instant::now).elapsed()
+    }
+}
+
+/// A [`Clock`] that only moves when told to.
+///
+/// Clones share the same time, so a test can keep a clone to advance the clock
+/// that a policy is using. As a [`WallClock`], it starts at the [`UNIX_EPOCH`].
+///
+/// ```
+/// use futures_retry_policies::clock::{Clock, MockClock};
+/// use std::time::Duration;
+///
+/// let clock = MockClock::new();
+/// let policy_clock = clock.clone();
+///
+/// clock.advance(Duration::from_secs(5));
+/// assert_eq!(policy_clock.now(), Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// Create a clock starting at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl WallClock for MockClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Clock::now(self)
+    }
+}
+
+/// A [`Clock`] using [`tokio::time::Instant`](::tokio::time::Instant).
+///
+/// This follows tokio's time, so it respects [`tokio::time::pause`](::tokio::time::pause) in tests.
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[derive(Debug, Clone, Copy)]
+pub struct TokioClock {
+    origin: ::tokio::time::Instant,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioClock {
+    /// Create a clock starting at tokio's current time
+    pub fn new() -> Self {
+        Self {
+            origin: ::tokio::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Clock for TokioClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// A high resolution [`Clock`] using [`quanta`](::quanta).
+///
+/// [`quanta::Clock::mock`](::quanta::Clock::mock) can also be used to control time in tests.
+#[cfg(feature = "quanta")]
+#[cfg_attr(docsrs, doc(cfg(feature = "quanta")))]
+#[derive(Debug, Clone)]
+pub struct QuantaClock {
+    clock: quanta::Clock,
+    origin: quanta::Instant,
+}
+
+#[cfg(feature = "quanta")]
+impl QuantaClock {
+    /// Create a clock starting at the current time of `clock`
+    pub fn new(clock: quanta::Clock) -> Self {
+        let origin = clock.now();
+        Self { clock, origin }
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Default for QuantaClock {
+    fn default() -> Self {
+        Self::new(quanta::Clock::new())
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Clock for QuantaClock {
+    fn now(&self) -> Duration {
+        self.clock.now().duration_since(self.origin)
+    }
+}
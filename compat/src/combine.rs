@@ -0,0 +1,112 @@
+//! Combinators to build a [`Classify`] out of smaller ones.
+//!
+//! Conditions like "retry if it's an IO error or a 5xx, but not if the body was too large"
+//! can be written by combining a classifier per condition, without a new type for each combination.
+//! Closures taking a reference to the result are classifiers too.
+//!
+//! ```
+//! use futures_retry_policies::{combine::{all_of, any_of, not}, tokio::RetryFutureExt, RetryPolicyBuilder};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! enum Error { Io, Status(u16), BodyTooLarge }
+//!
+//! type Res = Result<(), Error>;
+//!
+//! async fn make_request() -> Res {
+//!     // make a request
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err(Error::Status(503)) } else { Ok(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Res {
+//!     let io = |res: &Res| matches!(res, Err(Error::Io));
+//!     let server_error = |res: &Res| matches!(res, Err(Error::Status(500..=599)));
+//!     let too_large = |res: &Res| matches!(res, Err(Error::BodyTooLarge));
+//!
+//!     let policy = RetryPolicyBuilder::exponential(Duration::from_millis(10))
+//!         .max_retries(3)
+//!         .retry_if(all_of(any_of(io, server_error), not(too_large)))
+//!         .build();
+//!     make_request.retry(policy).await
+//! }
+//! ```
+
+use crate::Classify;
+
+/// Retries if either classifier would retry. See [`any_of`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnyOf<A, B>(A, B);
+
+/// Retries if both classifiers would retry. See [`all_of`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllOf<A, B>(A, B);
+
+/// Retries if the classifier would not retry. See [`not`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Not<A>(A);
+
+/// Retry if either `a` or `b` would retry.
+///
+/// `b` is only consulted if `a` wouldn't retry. Nest calls to combine more classifiers.
+pub fn any_of<A, B>(a: A, b: B) -> AnyOf<A, B> {
+    AnyOf(a, b)
+}
+
+/// Retry if both `a` and `b` would retry.
+///
+/// `b` is only consulted if `a` would retry. Nest calls to combine more classifiers.
+pub fn all_of<A, B>(a: A, b: B) -> AllOf<A, B> {
+    AllOf(a, b)
+}
+
+/// Retry if `a` would not retry
+pub fn not<A>(a: A) -> Not<A> {
+    Not(a)
+}
+
+impl<R, A: Classify<R>, B: Classify<R>> Classify<R> for AnyOf<A, B> {
+    fn classify(&mut self, result: &R, attempts: u32) -> bool {
+        self.0.classify(result, attempts) || self.1.classify(result, attempts)
+    }
+}
+
+impl<R, A: Classify<R>, B: Classify<R>> Classify<R> for AllOf<A, B> {
+    fn classify(&mut self, result: &R, attempts: u32) -> bool {
+        self.0.classify(result, attempts) && self.1.classify(result, attempts)
+    }
+}
+
+impl<R, A: Classify<R>> Classify<R> for Not<A> {
+    fn classify(&mut self, result: &R, attempts: u32) -> bool {
+        !self.0.classify(result, attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{all_of, any_of, not};
+    use crate::{Classify, UseShouldRetry};
+
+    #[test]
+    fn combinators() {
+        let even = |n: &u32| n.is_multiple_of(2);
+        let big = |n: &u32| *n > 10;
+
+        let mut classifier = all_of(any_of(even, big), not(|n: &u32| *n == 20));
+        let retried: Vec<u32> = (0..25).filter(|n| classifier.classify(n, 1)).collect();
+        assert_eq!(
+            retried,
+            [0, 2, 4, 6, 8, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 21, 22, 23, 24]
+        );
+    }
+
+    #[test]
+    fn with_should_retry() {
+        let mut classifier = any_of(UseShouldRetry, |res: &Option<u32>| *res == Some(0));
+        assert!(classifier.classify(&None, 1));
+        assert!(classifier.classify(&Some(0), 1));
+        assert!(!classifier.classify(&Some(1), 1));
+    }
+}
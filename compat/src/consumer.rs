@@ -0,0 +1,145 @@
+//! Retrying messages from a queue, with retry topics and a dead letter queue.
+//!
+//! Message consumers usually can't sleep between attempts without blocking the rest of the
+//! queue. Instead, a failed message is published to one of a few retry topics, each consumed
+//! after a fixed delay, and eventually to a dead letter queue. [`RetryTiers`] does the bookkeeping
+//! for this: it classifies the processing result, and tells the integration code what to do with
+//! the message as an [`Instruction`].
+//!
+//! The number of attempts made so far has to travel with the message, eg. in a header.
+//!
+//! ```
+//! use futures_retry_policies::consumer::{Instruction, RetryTiers};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! enum Error { Timeout, Invalid }
+//! impl futures_retry_policies::ShouldRetry for Error {
+//!     fn should_retry(&self, _: u32) -> bool { matches!(self, Error::Timeout) }
+//! }
+//!
+//! // topics consumed after 10s, 1m and 10m
+//! let mut tiers = RetryTiers::new([10, 60, 600].map(Duration::from_secs));
+//!
+//! assert_eq!(tiers.decide(&Ok::<_, Error>(()), 1), Instruction::Ack);
+//! assert_eq!(tiers.decide(&Err::<(), _>(Error::Timeout), 1), Instruction::RetryTier(0));
+//! assert_eq!(tiers.decide(&Err::<(), _>(Error::Timeout), 3), Instruction::RetryTier(2));
+//! assert_eq!(tiers.decide(&Err::<(), _>(Error::Timeout), 4), Instruction::DeadLetter);
+//! assert_eq!(tiers.decide(&Err::<(), _>(Error::Invalid), 1), Instruction::DeadLetter);
+//! ```
+
+use std::time::Duration;
+
+use crate::{Classify, UseShouldRetry};
+
+/// What to do with a message after processing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// Processing succeeded, acknowledge the message
+    Ack,
+    /// Publish the message to the retry topic with this index, see [`RetryTiers::delay`]
+    RetryTier(usize),
+    /// Processing failed for good, publish the message to the dead letter queue
+    DeadLetter,
+}
+
+/// Decides how failed messages move through a list of delayed retry topics.
+///
+/// The `n`th retry of a message goes to the `n`th tier, and the last tier is reused if more
+/// attempts are allowed than there are tiers. Errors are retried according to their
+/// [`ShouldRetry`](crate::ShouldRetry) impl by default.
+#[derive(Debug, Clone)]
+pub struct RetryTiers<C = UseShouldRetry> {
+    tiers: Vec<Duration>,
+    max_attempts: u32,
+    classifier: C,
+}
+
+impl RetryTiers {
+    /// Retry through each of the tiers once, in order.
+    ///
+    /// Panics if there are no tiers.
+    pub fn new(tiers: impl IntoIterator<Item = Duration>) -> Self {
+        let tiers: Vec<_> = tiers.into_iter().collect();
+        assert!(!tiers.is_empty(), "RetryTiers needs at least one tier");
+        Self {
+            max_attempts: u32::try_from(tiers.len())
+                .unwrap_or(u32::MAX)
+                .saturating_add(1),
+            tiers,
+            classifier: UseShouldRetry,
+        }
+    }
+}
+
+impl<C> RetryTiers<C> {
+    /// Send messages to the dead letter queue after `max_attempts` attempts, including the first
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Decide which errors are retried with `classifier`
+    pub fn retry_if<F>(self, classifier: F) -> RetryTiers<F> {
+        RetryTiers {
+            tiers: self.tiers,
+            max_attempts: self.max_attempts,
+            classifier,
+        }
+    }
+
+    /// The delay of the retry topic with index `tier`
+    pub fn delay(&self, tier: usize) -> Option<Duration> {
+        self.tiers.get(tier).copied()
+    }
+
+    /// Decide what to do with a message, after `attempts` attempts (starting at 1) ended in `result`
+    pub fn decide<T, E>(&mut self, result: &Result<T, E>, attempts: u32) -> Instruction
+    where
+        C: Classify<E>,
+    {
+        let Err(err) = result else {
+            return Instruction::Ack;
+        };
+        if attempts >= self.max_attempts || !self.classifier.classify(err, attempts) {
+            return Instruction::DeadLetter;
+        }
+        let retry = attempts.saturating_sub(1) as usize;
+        Instruction::RetryTier(retry.min(self.tiers.len() - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Instruction, RetryTiers};
+
+    #[test]
+    fn reuses_last_tier() {
+        let mut tiers = RetryTiers::new([1, 5].map(Duration::from_secs))
+            .max_attempts(5)
+            .retry_if(|_: &()| true);
+        let decisions: Vec<_> = (1..=5)
+            .map(|n| tiers.decide(&Err::<(), _>(()), n))
+            .collect();
+        assert_eq!(
+            decisions,
+            [
+                Instruction::RetryTier(0),
+                Instruction::RetryTier(1),
+                Instruction::RetryTier(1),
+                Instruction::RetryTier(1),
+                Instruction::DeadLetter,
+            ]
+        );
+        assert_eq!(tiers.delay(1), Some(Duration::from_secs(5)));
+        assert_eq!(tiers.delay(2), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn needs_a_tier() {
+        RetryTiers::new([]);
+    }
+}
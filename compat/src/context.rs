@@ -0,0 +1,94 @@
+//! Lending state to every attempt.
+//!
+//! [`retry`](crate::retry) calls an `FnMut() -> Fut`, so the futures it creates can't borrow
+//! anything from the closure itself. State that should be shared across attempts, like metrics
+//! or a reusable buffer, would otherwise need an `Rc<RefCell<_>>` or `Arc<Mutex<_>>`.
+//! [`retry_with_context`] instead lends a `&mut C` to each attempt in turn.
+
+use std::{future::Future, ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// Retry an async closure that borrows `context` mutably, using the given
+/// [retry policy](`RetryPolicy`) and sleep function.
+///
+/// ```
+/// use futures_retry_policies::{context::retry_with_context, iter::Iter};
+/// use std::time::Duration;
+///
+/// async fn read_into(buf: &mut Vec<u8>) -> Option<usize> {
+///     // read some data, which may fail
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return None }
+///     buf.extend_from_slice(b"hello");
+///     Some(5)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     // the same buffer is reused by every attempt
+///     let mut buf = Vec::with_capacity(1024);
+///     let read = retry_with_context(policy, tokio::time::sleep, &mut buf, async |buf| {
+///         buf.clear();
+///         read_into(buf).await
+///     })
+///     .await;
+///
+///     assert_eq!(read, Some(5));
+///     assert_eq!(buf, b"hello");
+/// }
+/// ```
+pub async fn retry_with_context<Policy, Sleeper, Sleep, C, Attempt, R>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    context: &mut C,
+    mut attempt: Attempt,
+) -> R
+where
+    Policy: RetryPolicy<R>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    C: ?Sized,
+    Attempt: AsyncFnMut(&mut C) -> R,
+{
+    loop {
+        match policy.should_retry(attempt(context).await) {
+            ControlFlow::Continue(delay) => sleeper(delay).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_with_context;
+    use crate::iter::Iter;
+
+    #[derive(Default)]
+    struct Metrics {
+        attempts: u32,
+    }
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn context_is_shared_across_attempts() {
+        let mut metrics = Metrics::default();
+        let res = retry_with_context(
+            Iter::new([Duration::ZERO; 5]),
+            sleep,
+            &mut metrics,
+            async |metrics: &mut Metrics| {
+                metrics.attempts += 1;
+                (metrics.attempts >= 3).then_some(())
+            },
+        )
+        .await;
+
+        assert_eq!(res, Some(()));
+        assert_eq!(metrics.attempts, 3);
+    }
+}
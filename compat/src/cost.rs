@@ -0,0 +1,194 @@
+//! Telling policies how long each failed attempt took.
+//!
+//! A connection refused straight away and a request that timed out after 10s are both failures,
+//! but they call for different retries: the first is cheap to retry immediately, while the second
+//! suggests a dependency that is struggling and should be left alone for a while. A [`CostAware`]
+//! policy is told how long each attempt took, and [`retry_with_cost`] measures it.
+
+use std::{future::Future, ops::ControlFlow, time::Duration};
+
+use crate::{
+    clock::{Clock, StdClock},
+    RetryPolicy,
+};
+
+/// A retry policy that also takes the cost of each attempt into account.
+pub trait CostAware<R> {
+    /// Like [`RetryPolicy::should_retry`], where `cost` is how long the attempt took
+    fn should_retry_with_cost(&mut self, result: R, cost: Duration) -> ControlFlow<R, Duration>;
+}
+
+impl<P: CostAware<R> + ?Sized, R> CostAware<R> for &mut P {
+    fn should_retry_with_cost(&mut self, result: R, cost: Duration) -> ControlFlow<R, Duration> {
+        P::should_retry_with_cost(self, result, cost)
+    }
+}
+
+/// A [`CostAware`] policy that adjusts the delays of another policy by how long attempts took.
+///
+/// Attempts that fail faster than the [fast](ByCost::fast_below) threshold are retried straight
+/// away, and the delays after attempts slower than the [slow](ByCost::slow_above) threshold are
+/// multiplied. The inner policy still decides whether to retry at all, so it still bounds how
+/// many fast retries are made.
+///
+/// Used as a plain [`RetryPolicy`], the cost is unknown and the inner policy's delays are kept.
+///
+/// ```
+/// use futures_retry_policies::{cost::{ByCost, CostAware}, iter::Iter};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// let mut policy = ByCost::new(Iter::new([Duration::from_secs(1); 3]))
+///     .fast_below(Duration::from_millis(10))
+///     .slow_above(Duration::from_secs(5), 4.0);
+///
+/// // connection refused
+/// let refused = policy.should_retry_with_cost(None::<()>, Duration::from_millis(1));
+/// assert_eq!(refused, ControlFlow::Continue(Duration::ZERO));
+/// // timed out
+/// let timed_out = policy.should_retry_with_cost(None::<()>, Duration::from_secs(10));
+/// assert_eq!(timed_out, ControlFlow::Continue(Duration::from_secs(4)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ByCost<P> {
+    policy: P,
+    fast: Option<Duration>,
+    slow: Option<(Duration, f64)>,
+}
+
+impl<P> ByCost<P> {
+    /// Retry with the delays of `policy`, until thresholds are set
+    pub fn new(policy: P) -> Self {
+        Self {
+            policy,
+            fast: None,
+            slow: None,
+        }
+    }
+
+    /// Retry immediately after attempts that took less than `threshold`
+    pub fn fast_below(mut self, threshold: Duration) -> Self {
+        self.fast = Some(threshold);
+        self
+    }
+
+    /// Multiply the delay by `factor` after attempts that took at least `threshold`
+    pub fn slow_above(mut self, threshold: Duration, factor: f64) -> Self {
+        self.slow = Some((threshold, factor));
+        self
+    }
+}
+
+impl<P: RetryPolicy<R>, R> CostAware<R> for ByCost<P> {
+    fn should_retry_with_cost(&mut self, result: R, cost: Duration) -> ControlFlow<R, Duration> {
+        let delay = self.policy.should_retry(result)?;
+        if self.fast.is_some_and(|fast| cost < fast) {
+            return ControlFlow::Continue(Duration::ZERO);
+        }
+        match self.slow {
+            Some((slow, factor)) if cost >= slow => ControlFlow::Continue(
+                Duration::try_from_secs_f64(delay.as_secs_f64() * factor).unwrap_or(Duration::MAX),
+            ),
+            _ => ControlFlow::Continue(delay),
+        }
+    }
+}
+
+impl<P: RetryPolicy<R>, R> RetryPolicy<R> for ByCost<P> {
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.policy.should_retry(result)
+    }
+}
+
+/// Retry the futures using the given [`CostAware`] policy and sleep function, measuring how long
+/// each attempt takes.
+///
+/// ```
+/// use futures_retry_policies::{cost::{retry_with_cost, ByCost}, iter::Iter};
+/// use std::time::Duration;
+///
+/// async fn connect() -> Option<()> {
+///     // connect to the server
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = ByCost::new(Iter::new([Duration::from_millis(100); 3]))
+///         .fast_below(Duration::from_millis(5));
+///     retry_with_cost(policy, tokio::time::sleep, connect).await.unwrap();
+/// }
+/// ```
+pub async fn retry_with_cost<Policy, Sleeper, Sleep, Futures, Fut>(
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> Fut::Output
+where
+    Policy: CostAware<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    retry_with_cost_with_clock(StdClock, policy, sleeper, futures).await
+}
+
+/// Like [`retry_with_cost`], but measures the attempts with `clock`
+pub async fn retry_with_cost_with_clock<K, Policy, Sleeper, Sleep, Futures, Fut>(
+    clock: K,
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+) -> Fut::Output
+where
+    K: Clock,
+    Policy: CostAware<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    loop {
+        let start = clock.now();
+        let result = futures().await;
+        let cost = clock.now().saturating_sub(start);
+        match policy.should_retry_with_cost(result, cost) {
+            ControlFlow::Continue(delay) => sleeper(delay).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, time::Duration};
+
+    use super::{retry_with_cost_with_clock, ByCost};
+    use crate::{clock::MockClock, iter::Iter};
+
+    #[tokio::test]
+    async fn slow_attempts_back_off_harder() {
+        let clock = MockClock::new();
+        let delays = RefCell::new(vec![]);
+        let policy = ByCost::new(Iter::new([Duration::from_secs(1); 2]))
+            .slow_above(Duration::from_millis(10), 3.0);
+
+        let result = retry_with_cost_with_clock(
+            clock.clone(),
+            policy,
+            |delay| {
+                delays.borrow_mut().push(delay);
+                async {}
+            },
+            || {
+                clock.advance(Duration::from_millis(20));
+                async { None::<()> }
+            },
+        )
+        .await;
+
+        assert_eq!(result, None);
+        assert_eq!(delays.into_inner(), [Duration::from_secs(3); 2]);
+    }
+}
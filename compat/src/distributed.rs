@@ -0,0 +1,538 @@
+//! Retry budgets and circuits shared by a fleet of instances.
+//!
+//! A [`RetryBudget`](crate::budget::RetryBudget) only limits the retries of one process. When many
+//! instances call the same dependency, the budget needs to live somewhere they can all reach, like
+//! Redis. [`SharedState`] is the interface to such a store, and [`retry_distributed`] takes a token
+//! from it before every retry.
+//!
+//! The store can also hold a circuit for each dependency. Once a call gives up on a result that
+//! should have been retried, it trips the circuit, and while it's open, no instance retries
+//! calls to that dependency. First attempts are still made.
+//!
+//! [`LocalState`] keeps the state in memory, for tests and single instance deployments. With the
+//! `redis` feature, [`RedisState`] keeps it in Redis.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::{ready, Future},
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    budget::{Budget, RetryBudget},
+    clock::{Clock, StdClock},
+    RetryPolicy, ShouldRetry,
+};
+
+/// A store of retry budgets shared between instances.
+///
+/// Budgets and circuits are identified by a key, usually naming the dependency being called.
+/// Implementations must take and return tokens atomically, as many instances use the same budget
+/// at once.
+///
+/// The futures are [`Send`], so retries using the store can be spawned.
+pub trait SharedState {
+    /// The error when the store can't be reached
+    type Error;
+
+    /// Take a token for a retry from the budget `key`, returning whether one was available
+    fn withdraw(&self, key: &str) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+
+    /// Return a token to the budget `key` after a successful attempt
+    fn deposit(&self, key: &str) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Whether the circuit `key` is open, so calls shouldn't be retried
+    fn is_open(&self, key: &str) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+
+    /// Open the circuit `key`, after a call gave up on a result that should have been retried
+    fn trip(&self, key: &str) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+impl<S: SharedState + ?Sized> SharedState for &S {
+    type Error = S::Error;
+
+    fn withdraw(&self, key: &str) -> impl Future<Output = Result<bool, Self::Error>> + Send {
+        S::withdraw(self, key)
+    }
+
+    fn deposit(&self, key: &str) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        S::deposit(self, key)
+    }
+
+    fn is_open(&self, key: &str) -> impl Future<Output = Result<bool, Self::Error>> + Send {
+        S::is_open(self, key)
+    }
+
+    fn trip(&self, key: &str) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        S::trip(self, key)
+    }
+}
+
+/// [`SharedState`] kept in memory, with every budget holding up to `capacity` tokens
+pub struct LocalState {
+    capacity: u32,
+    budgets: Mutex<HashMap<String, RetryBudget>>,
+    open_for: Option<Duration>,
+    /// When each tripped circuit closes again, as read from the clock
+    circuits: Mutex<HashMap<String, Duration>>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl std::fmt::Debug for LocalState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalState")
+            .field("capacity", &self.capacity)
+            .field("open_for", &self.open_for)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalState {
+    /// Allow up to `capacity` retries more than successful attempts for each key.
+    /// Circuits never open, until [`open_for`](LocalState::open_for) is set.
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            budgets: Mutex::new(HashMap::new()),
+            open_for: None,
+            circuits: Mutex::new(HashMap::new()),
+            clock: Arc::new(StdClock),
+        }
+    }
+
+    /// Keep tripped circuits open for `duration`
+    pub fn open_for(mut self, duration: Duration) -> Self {
+        self.open_for = Some(duration);
+        self
+    }
+
+    /// Read the time from `clock`
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn budget(&self, key: &str) -> RetryBudget {
+        let mut budgets = self.budgets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(budget) = budgets.get(key) {
+            return budget.clone();
+        }
+        budgets
+            .entry(key.to_owned())
+            .or_insert_with(|| RetryBudget::new(self.capacity))
+            .clone()
+    }
+}
+
+impl SharedState for LocalState {
+    type Error = Infallible;
+
+    fn withdraw(&self, key: &str) -> impl Future<Output = Result<bool, Infallible>> + Send {
+        ready(Ok(self.budget(key).withdraw()))
+    }
+
+    fn deposit(&self, key: &str) -> impl Future<Output = Result<(), Infallible>> + Send {
+        self.budget(key).deposit();
+        ready(Ok(()))
+    }
+
+    fn is_open(&self, key: &str) -> impl Future<Output = Result<bool, Infallible>> + Send {
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        let open = match circuits.get(key) {
+            Some(&closes) if closes > self.clock.now() => true,
+            Some(_) => {
+                circuits.remove(key);
+                false
+            }
+            None => false,
+        };
+        ready(Ok(open))
+    }
+
+    fn trip(&self, key: &str) -> impl Future<Output = Result<(), Infallible>> + Send {
+        if let Some(open_for) = self.open_for {
+            let closes = self.clock.now().saturating_add(open_for);
+            self.circuits
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.to_owned(), closes);
+        }
+        ready(Ok(()))
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function, while the
+/// shared budget `key` in `state` has tokens left and its circuit is closed.
+///
+/// Successful attempts, according to [`ShouldRetry`], return a token. Giving up on a result that
+/// should be retried trips the circuit. If the store can't be reached, the retry goes ahead, so an
+/// outage of the store doesn't turn into an outage of the dependency.
+///
+/// ```
+/// use futures_retry_policies::{distributed::{retry_distributed, LocalState}, iter::Iter};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // in production, this would be backed by a store shared with the other instances
+///     let state = LocalState::new(100);
+///
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     retry_distributed(policy, tokio::time::sleep, &state, "payments-api", make_request)
+///         .await
+///         .unwrap();
+/// }
+/// ```
+pub async fn retry_distributed<Policy, Sleeper, Sleep, State, Futures, Fut>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    state: State,
+    key: &str,
+    mut futures: Futures,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    State: SharedState,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    Fut::Output: ShouldRetry,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = futures().await;
+        if !result.should_retry(attempts) {
+            let _ = state.deposit(key).await;
+            break result;
+        }
+        if let Ok(true) = state.is_open(key).await {
+            break result;
+        }
+        let withdrew = state.withdraw(key).await;
+        if let Ok(false) = withdrew {
+            let _ = state.trip(key).await;
+            break result;
+        }
+        match policy.should_retry(result) {
+            ControlFlow::Continue(delay) => sleeper(delay).await,
+            ControlFlow::Break(result) => {
+                // the retry didn't happen after all, so return the token if one was taken
+                if let Ok(true) = withdrew {
+                    let _ = state.deposit(key).await;
+                }
+                let _ = state.trip(key).await;
+                break result;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis")))]
+mod redis {
+    use std::{future::Future, time::Duration};
+
+    use redis::{aio::ConnectionManager, RedisError, Script};
+
+    use super::SharedState;
+
+    /// Counts a spent token, unless `ARGV[1]` tokens are already spent. A missing key is a full
+    /// budget, so budgets don't need to be created up front.
+    const WITHDRAW: &str = r"
+        local spent = tonumber(redis.call('GET', KEYS[1]) or '0')
+        if spent < tonumber(ARGV[1]) then
+            redis.call('INCR', KEYS[1])
+            return 1
+        end
+        return 0
+    ";
+
+    /// Returns a spent token, if any were spent
+    const DEPOSIT: &str = r"
+        local spent = tonumber(redis.call('GET', KEYS[1]) or '0')
+        if spent > 0 then
+            redis.call('DECR', KEYS[1])
+        end
+        return 0
+    ";
+
+    /// [`SharedState`] kept in Redis, with every budget holding up to `capacity` tokens.
+    ///
+    /// Tokens are taken and returned by Lua scripts, so they're atomic across instances. A budget
+    /// is stored as the number of tokens spent under `{prefix}{key}:spent`, and a tripped circuit
+    /// as `{prefix}{key}:open`, expiring once it closes.
+    ///
+    /// ```no_run
+    /// use futures_retry_policies::{distributed::{retry_distributed, RedisState}, iter::Iter};
+    /// use std::time::Duration;
+    ///
+    /// async fn make_request() -> Option<()> {
+    ///     // make a request
+    ///     # Some(())
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> redis::RedisResult<()> {
+    ///     let client = redis::Client::open("redis://127.0.0.1/")?;
+    ///     let state = RedisState::new(client.get_connection_manager().await?, 100)
+    ///         .open_for(Duration::from_secs(30));
+    ///
+    ///     let policy = Iter::new([Duration::from_millis(10); 3]);
+    ///     retry_distributed(policy, tokio::time::sleep, &state, "payments-api", make_request).await;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[derive(Clone)]
+    pub struct RedisState {
+        connection: ConnectionManager,
+        capacity: u32,
+        open_for: Option<Duration>,
+        prefix: String,
+        withdraw: Script,
+        deposit: Script,
+    }
+
+    impl std::fmt::Debug for RedisState {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("RedisState")
+                .field("capacity", &self.capacity)
+                .field("open_for", &self.open_for)
+                .field("prefix", &self.prefix)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl RedisState {
+        /// Allow up to `capacity` retries more than successful attempts for each key.
+        /// Circuits never open, until [`open_for`](RedisState::open_for) is set.
+        pub fn new(connection: ConnectionManager, capacity: u32) -> Self {
+            Self {
+                connection,
+                capacity,
+                open_for: None,
+                prefix: "retry:".to_owned(),
+                withdraw: Script::new(WITHDRAW),
+                deposit: Script::new(DEPOSIT),
+            }
+        }
+
+        /// Keep tripped circuits open for `duration`
+        pub fn open_for(mut self, duration: Duration) -> Self {
+            self.open_for = Some(duration);
+            self
+        }
+
+        /// Prefix the Redis keys with `prefix`, instead of `retry:`
+        pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+            self.prefix = prefix.into();
+            self
+        }
+    }
+
+    impl SharedState for RedisState {
+        type Error = RedisError;
+
+        fn withdraw(&self, key: &str) -> impl Future<Output = Result<bool, RedisError>> + Send {
+            let mut connection = self.connection.clone();
+            let script = self.withdraw.clone();
+            let key = format!("{}{key}:spent", self.prefix);
+            let capacity = self.capacity;
+            async move {
+                script
+                    .key(key)
+                    .arg(capacity)
+                    .invoke_async(&mut connection)
+                    .await
+            }
+        }
+
+        fn deposit(&self, key: &str) -> impl Future<Output = Result<(), RedisError>> + Send {
+            let mut connection = self.connection.clone();
+            let script = self.deposit.clone();
+            let key = format!("{}{key}:spent", self.prefix);
+            async move { script.key(key).invoke_async(&mut connection).await }
+        }
+
+        fn is_open(&self, key: &str) -> impl Future<Output = Result<bool, RedisError>> + Send {
+            let mut connection = self.connection.clone();
+            let key = format!("{}{key}:open", self.prefix);
+            async move {
+                redis::cmd("EXISTS")
+                    .arg(key)
+                    .query_async(&mut connection)
+                    .await
+            }
+        }
+
+        fn trip(&self, key: &str) -> impl Future<Output = Result<(), RedisError>> + Send {
+            let mut connection = self.connection.clone();
+            let key = format!("{}{key}:open", self.prefix);
+            let open_for = self.open_for;
+            async move {
+                let Some(open_for) = open_for else {
+                    return Ok(());
+                };
+                // expiring keys need at least a millisecond left
+                let millis = u64::try_from(open_for.as_millis())
+                    .unwrap_or(u64::MAX)
+                    .max(1);
+                redis::cmd("SET")
+                    .arg(key)
+                    .arg(1)
+                    .arg("PX")
+                    .arg(millis)
+                    .query_async(&mut connection)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisState;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{ready, Future},
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{retry_distributed, LocalState, SharedState};
+    use crate::{clock::MockClock, iter::Iter};
+
+    async fn sleep(_: Duration) {}
+
+    /// A store that can't be reached, counting the deposits made anyway
+    #[derive(Default)]
+    struct Unreachable {
+        deposits: AtomicU32,
+    }
+
+    impl SharedState for Unreachable {
+        type Error = ();
+
+        fn withdraw(&self, _: &str) -> impl Future<Output = Result<bool, ()>> + Send {
+            ready(Err(()))
+        }
+
+        fn deposit(&self, _: &str) -> impl Future<Output = Result<(), ()>> + Send {
+            self.deposits.fetch_add(1, Ordering::SeqCst);
+            ready(Err(()))
+        }
+
+        fn is_open(&self, _: &str) -> impl Future<Output = Result<bool, ()>> + Send {
+            ready(Err(()))
+        }
+
+        fn trip(&self, _: &str) -> impl Future<Output = Result<(), ()>> + Send {
+            ready(Err(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn no_refund_without_withdrawal() {
+        let state = Unreachable::default();
+        let policy = Iter::new([Duration::ZERO; 2]);
+
+        let mut attempts = 0;
+        retry_distributed(policy, sleep, &state, "dep", || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(attempts, 3);
+        assert_eq!(state.deposits.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn shares_budget_between_calls() {
+        let state = LocalState::new(3);
+        let policy = || Iter::new(std::iter::repeat(Duration::ZERO));
+
+        let mut attempts = 0;
+        retry_distributed(policy(), sleep, &state, "dep", || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(attempts, 4);
+
+        // the budget is used up for this key...
+        let mut attempts = 0;
+        retry_distributed(policy(), sleep, &state, "dep", || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(attempts, 1);
+
+        // ...but not others
+        let mut attempts = 0;
+        retry_distributed(policy(), sleep, &state, "other", || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(attempts, 4);
+    }
+
+    #[tokio::test]
+    async fn giving_up_trips_the_circuit() {
+        let clock = MockClock::new();
+        let state = Arc::new(
+            LocalState::new(100)
+                .open_for(Duration::from_secs(30))
+                .with_clock(clock.clone()),
+        );
+        let policy = || Iter::new([Duration::ZERO; 2]);
+
+        let mut attempts = 0;
+        retry_distributed(policy(), sleep, &*state, "dep", || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(attempts, 3);
+
+        // the circuit is open, so only first attempts are made, even from other tasks
+        let spawned = tokio::spawn({
+            let state = state.clone();
+            async move {
+                retry_distributed(policy(), sleep, &*state, "dep", || async { None::<()> }).await
+            }
+        });
+        assert_eq!(spawned.await.unwrap(), None);
+        let mut attempts = 0;
+        retry_distributed(policy(), sleep, &*state, "dep", || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(attempts, 1);
+
+        clock.advance(Duration::from_secs(30));
+        let mut attempts = 0;
+        retry_distributed(policy(), sleep, &*state, "dep", || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(attempts, 3);
+    }
+}
@@ -0,0 +1,256 @@
+//! Draining retries during a graceful shutdown.
+//!
+//! When a service shuts down, in-flight work should finish, but there's no point starting retries
+//! that the service won't be around for. Once a [`Drain`] has been [started](Drain::start),
+//! [`retry_draining`] lets the running attempt complete, but returns
+//! [`ShuttingDown`](Drained::ShuttingDown) instead of retrying it, and cuts short any sleep
+//! before a retry.
+
+use std::{
+    collections::HashMap,
+    future::{poll_fn, Future},
+    ops::ControlFlow,
+    pin::pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use crate::{RetryPolicy, ShouldRetry};
+
+#[derive(Debug, Default)]
+struct State {
+    draining: bool,
+    next_waiter: u64,
+    wakers: HashMap<u64, Waker>,
+}
+
+/// A shutdown signal for retry loops, shared between clones
+#[derive(Debug, Clone, Default)]
+pub struct Drain {
+    state: Arc<Mutex<State>>,
+}
+
+impl Drain {
+    /// Create a signal that isn't draining yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start draining. All retry loops using this signal stop retrying.
+    pub fn start(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.draining = true;
+        for (_, waker) in state.wakers.drain() {
+            waker.wake();
+        }
+    }
+
+    /// Whether draining has started
+    pub fn is_draining(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .draining
+    }
+
+    fn waiter(&self) -> Waiter<'_> {
+        Waiter {
+            drain: self,
+            key: None,
+        }
+    }
+}
+
+/// Waits for a [`Drain`] to start, keeping its waker registered only while it's alive
+struct Waiter<'a> {
+    drain: &'a Drain,
+    key: Option<u64>,
+}
+
+impl Waiter<'_> {
+    fn poll_draining(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.drain.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.draining {
+            return Poll::Ready(());
+        }
+        let key = *self.key.get_or_insert_with(|| {
+            state.next_waiter += 1;
+            state.next_waiter
+        });
+        match state.wakers.get_mut(&key) {
+            Some(waker) => waker.clone_from(cx.waker()),
+            None => {
+                state.wakers.insert(key, cx.waker().clone());
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.drain.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.wakers.remove(&key);
+        }
+    }
+}
+
+/// The result of [`retry_draining`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Drained<R> {
+    /// The policy stopped retrying
+    Finished(R),
+    /// Draining stopped the retries.
+    ///
+    /// This holds the result of the last attempt if it would have been retried. It's `None` if
+    /// draining started while waiting to retry, as that result had already been given to the policy.
+    ShuttingDown(Option<R>),
+}
+
+impl<R> Drained<R> {
+    /// Whether the retries were stopped by draining
+    pub fn is_shutting_down(&self) -> bool {
+        matches!(self, Drained::ShuttingDown(_))
+    }
+
+    /// Get the last result, if there is one
+    pub fn into_inner(self) -> Option<R> {
+        match self {
+            Drained::Finished(res) => Some(res),
+            Drained::ShuttingDown(res) => res,
+        }
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// until `drain` is started.
+///
+/// Results are only checked against the drain once their attempt has completed, and only if
+/// they [should be retried](ShouldRetry), so finished work is never thrown away.
+///
+/// ```
+/// use futures_retry_policies::{drain::{retry_draining, Drain, Drained}, iter::Iter};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let drain = Drain::new();
+///
+///     let task = tokio::spawn({
+///         let drain = drain.clone();
+///         async move {
+///             let policy = Iter::new(std::iter::repeat(Duration::from_secs(60)));
+///             retry_draining(&drain, policy, tokio::time::sleep, || async { None::<()> }).await
+///         }
+///     });
+///
+///     // on SIGTERM
+///     tokio::time::sleep(Duration::from_millis(10)).await;
+///     drain.start();
+///     assert_eq!(task.await.unwrap(), Drained::ShuttingDown(None));
+/// }
+/// ```
+pub async fn retry_draining<Policy, Sleeper, Sleep, Futures, Fut>(
+    drain: &Drain,
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+) -> Drained<Fut::Output>
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    Fut::Output: ShouldRetry,
+{
+    let mut attempts = 0u32;
+    loop {
+        let result = futures().await;
+        attempts += 1;
+        if drain.is_draining() && result.should_retry(attempts) {
+            return Drained::ShuttingDown(Some(result));
+        }
+
+        match policy.should_retry(result) {
+            ControlFlow::Continue(delay) => {
+                let mut sleep = pin!(sleeper(delay));
+                let mut waiter = drain.waiter();
+                let interrupted = poll_fn(|cx| match sleep.as_mut().poll(cx) {
+                    Poll::Ready(()) => Poll::Ready(false),
+                    Poll::Pending => waiter.poll_draining(cx).map(|()| true),
+                })
+                .await;
+                if interrupted {
+                    return Drained::ShuttingDown(None);
+                }
+            }
+            ControlFlow::Break(result) => return Drained::Finished(result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{retry_draining, Drain, Drained};
+    use crate::{classified::Retryable, iter::Iter};
+
+    #[tokio::test(start_paused = true)]
+    async fn in_flight_attempt_completes() {
+        let drain = Drain::new();
+        let mut attempts = 0;
+        let res = retry_draining(
+            &drain,
+            Iter::new([Duration::from_secs(1); 5]),
+            tokio::time::sleep,
+            || {
+                attempts += 1;
+                let drain = drain.clone();
+                async move {
+                    // shutdown starts while the second attempt is running
+                    if attempts == 2 {
+                        drain.start();
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    Err::<(), _>(Retryable(attempts))
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(res, Drained::ShuttingDown(Some(Err(Retryable(2)))));
+    }
+
+    #[tokio::test]
+    async fn successes_finish_while_draining() {
+        let drain = Drain::new();
+        drain.start();
+        let res = retry_draining(
+            &drain,
+            Iter::new([Duration::ZERO]),
+            |_| async {},
+            || async { Some(()) },
+        )
+        .await;
+        assert_eq!(res, Drained::Finished(Some(())));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn finished_sleeps_release_their_wakers() {
+        let drain = Drain::new();
+        let res = retry_draining(
+            &drain,
+            Iter::new([Duration::from_secs(1); 3]),
+            tokio::time::sleep,
+            || async { None::<()> },
+        )
+        .await;
+
+        assert_eq!(res, Drained::Finished(None));
+        assert!(drain.state.lock().unwrap().wakers.is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+//! Reporting retries that were given up on.
+//!
+//! [`Escalate`] sends an [`ExhaustionReport`] to a channel whenever its inner policy gives up on a
+//! result that was still worth retrying, so one task can collect them all for alerting, rather than
+//! every call site logging its own failures.
+//!
+//! ```
+//! use futures_retry_policies::{classified::Retryable, escalate::Escalate, iter::Iter, tokio::RetryFutureExt};
+//! use std::{sync::mpsc, time::Duration};
+//!
+//! async fn make_request() -> Result<(), Retryable<&'static str>> {
+//!     // make a request
+//!     Err(Retryable("connection refused"))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     // shared by the whole app, and drained by an alerting task
+//!     let (reports, alerts) = mpsc::sync_channel(64);
+//!
+//!     let policy = Iter::new([Duration::from_millis(10); 2]);
+//!     let policy = Escalate::new(policy, "fetch-user", reports);
+//!     assert!(make_request.retry(policy).await.is_err());
+//!
+//!     let report = alerts.try_recv().unwrap();
+//!     assert_eq!(report.name, "fetch-user");
+//!     assert_eq!(report.attempts, 3);
+//!     assert_eq!(report.result, r#"Err(Retryable("connection refused"))"#);
+//! }
+//! ```
+
+use std::{fmt::Debug, ops::ControlFlow, sync::mpsc, time::Duration};
+
+use crate::{
+    clock::{Clock, StdClock},
+    Classify, RetryPolicy, UseShouldRetry,
+};
+
+/// What [`Escalate`] sends when its retries are exhausted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExhaustionReport {
+    /// The name given to the [`Escalate`] policy
+    pub name: &'static str,
+    /// How many attempts were made, including the first
+    pub attempts: u32,
+    /// How long passed between the first and last attempt finishing
+    pub elapsed: Duration,
+    /// The `Debug` output of the final result
+    pub result: String,
+}
+
+/// Somewhere to send [`ExhaustionReport`]s without blocking.
+///
+/// Reports that can't be sent straight away, as the channel is full or closed, are dropped.
+pub trait ReportSink {
+    /// Send the report if possible
+    fn try_report(&self, report: ExhaustionReport);
+}
+
+impl ReportSink for mpsc::SyncSender<ExhaustionReport> {
+    fn try_report(&self, report: ExhaustionReport) {
+        let _ = self.try_send(report);
+    }
+}
+
+impl ReportSink for mpsc::Sender<ExhaustionReport> {
+    fn try_report(&self, report: ExhaustionReport) {
+        let _ = self.send(report);
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+impl ReportSink for ::tokio::sync::mpsc::Sender<ExhaustionReport> {
+    fn try_report(&self, report: ExhaustionReport) {
+        let _ = self.try_send(report);
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+impl ReportSink for ::tokio::sync::mpsc::UnboundedSender<ExhaustionReport> {
+    fn try_report(&self, report: ExhaustionReport) {
+        let _ = self.send(report);
+    }
+}
+
+/// A [`RetryPolicy`] that sends an [`ExhaustionReport`] to `sink` when the inner policy gives up.
+///
+/// Giving up means stopping on a result that [should be retried](crate::ShouldRetry), or that
+/// the classifier set with [`retry_if`](Escalate::retry_if) accepts. Successes and fatal errors
+/// aren't reported.
+#[derive(Debug, Clone)]
+pub struct Escalate<P, S, K = StdClock, C = UseShouldRetry> {
+    policy: P,
+    classifier: C,
+    name: &'static str,
+    sink: S,
+    clock: K,
+    started: Option<Duration>,
+    attempts: u32,
+}
+
+impl<P, S: ReportSink> Escalate<P, S> {
+    /// Report to `sink` once `policy` gives up, naming the reports `name`
+    pub fn new(policy: P, name: &'static str, sink: S) -> Self {
+        Self {
+            policy,
+            classifier: UseShouldRetry,
+            name,
+            sink,
+            clock: StdClock,
+            started: None,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, S, K, C> Escalate<P, S, K, C> {
+    /// Measure the elapsed time with `clock`
+    pub fn with_clock<K2: Clock>(self, clock: K2) -> Escalate<P, S, K2, C> {
+        Escalate {
+            policy: self.policy,
+            classifier: self.classifier,
+            name: self.name,
+            sink: self.sink,
+            clock,
+            started: None,
+            attempts: self.attempts,
+        }
+    }
+
+    /// Decide which final results are reported with `classifier`
+    pub fn retry_if<F>(self, classifier: F) -> Escalate<P, S, K, F> {
+        Escalate {
+            policy: self.policy,
+            classifier,
+            name: self.name,
+            sink: self.sink,
+            clock: self.clock,
+            started: self.started,
+            attempts: self.attempts,
+        }
+    }
+}
+
+impl<P, S, K, C, R> RetryPolicy<R> for Escalate<P, S, K, C>
+where
+    P: RetryPolicy<R>,
+    S: ReportSink,
+    K: Clock,
+    C: Classify<R>,
+    R: Debug,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let now = self.clock.now();
+        let started = *self.started.get_or_insert(now);
+        self.attempts += 1;
+
+        let flow = self.policy.should_retry(result);
+        if let ControlFlow::Break(result) = &flow {
+            if self.classifier.classify(result, self.attempts) {
+                self.sink.try_report(ExhaustionReport {
+                    name: self.name,
+                    attempts: self.attempts,
+                    elapsed: now.saturating_sub(started),
+                    result: format!("{result:?}"),
+                });
+            }
+        }
+        flow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::Escalate;
+    use crate::{clock::MockClock, iter::Iter, RetryPolicy, RetryPolicyBuilder};
+
+    #[test]
+    fn reports_exhaustion() {
+        let clock = MockClock::new();
+        let (tx, rx) = mpsc::sync_channel(1);
+        let mut policy = Escalate::new(Iter::new([Duration::from_secs(1)]), "test", tx)
+            .with_clock(clock.clone());
+
+        assert!(policy.should_retry(None::<()>).is_continue());
+        clock.advance(Duration::from_secs(2));
+        assert!(policy.should_retry(None::<()>).is_break());
+
+        let report = rx.try_recv().unwrap();
+        assert_eq!(report.attempts, 2);
+        assert_eq!(report.elapsed, Duration::from_secs(2));
+        assert_eq!(report.result, "None");
+    }
+
+    #[test]
+    fn ignores_success_and_full_channels() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let policy = || Escalate::new(Iter::new([]), "test", tx.clone());
+
+        assert!(policy().should_retry(Some(())).is_break());
+        assert!(rx.try_recv().is_err());
+
+        // the second report is dropped, rather than blocking
+        assert!(policy().should_retry(None::<()>).is_break());
+        assert!(policy().should_retry(None::<()>).is_break());
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn classifier_decides_reports() {
+        let (tx, rx) = mpsc::sync_channel(2);
+        // the policy retries anything, leaving the decision to report to the classifier
+        let policy = || {
+            let policy = RetryPolicyBuilder::fixed(Duration::from_secs(1))
+                .retry_if(|_: &Result<(), u16>| true)
+                .max_retries(0)
+                .build();
+            Escalate::new(policy, "test", tx.clone())
+                .retry_if(|res: &Result<(), u16>| res.as_ref().is_err_and(|&status| status >= 500))
+        };
+
+        assert!(policy().should_retry(Err(404)).is_break());
+        assert!(rx.try_recv().is_err());
+
+        assert!(policy().should_retry(Err(503)).is_break());
+        assert_eq!(rx.try_recv().unwrap().result, "Err(503)");
+    }
+}
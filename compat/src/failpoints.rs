@@ -0,0 +1,292 @@
+#![cfg(feature = "failpoints")]
+#![cfg_attr(docsrs, doc(cfg(feature = "failpoints")))]
+//! Failpoints around the attempt boundary, for chaos testing.
+//!
+//! [`retry_with_failpoints`] runs a retry loop that checks a shared set of [`FailPoints`] before
+//! every attempt and before every sleep. Tests can configure an action at either point to inject
+//! failures, skip backoff or add latency, and then check deterministically how budgets and other
+//! shared limits behave when many retry loops go wrong at once.
+//!
+//! The same points are also [`fail`](::fail) crate failpoints, named by [`Failpoint::name`], so
+//! they can be configured with [`fail::cfg`] or the `FAILPOINTS` environment variable like any
+//! other failpoint in the app. Actions set on the [`FailPoints`] take priority. `return` acts like
+//! [`FailAction::Return`], and `return(ms)` like a [`FailAction::Delay`] of that many
+//! milliseconds, using the retry loop's sleep function. The `fail` crate's own `sleep`, `delay`
+//! and `pause` actions block the thread, so avoid them in async code.
+//!
+//! ```
+//! use futures_retry_policies::{
+//!     budget::{Budgeted, RetryBudget},
+//!     failpoints::{retry_with_failpoints, FailAction, FailPoints, Failpoint},
+//!     iter::Iter,
+//! };
+//! use std::time::Duration;
+//!
+//! async fn sleep(_: Duration) {}
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let budget = RetryBudget::new(3);
+//!     let failpoints = FailPoints::new();
+//!     // every attempt fails, and nobody backs off
+//!     failpoints.set(Failpoint::BeforeAttempt, FailAction::Return);
+//!     failpoints.set(Failpoint::BeforeSleep, FailAction::Return);
+//!
+//!     for _ in 0..5 {
+//!         let policy = Budgeted::new(Iter::new([Duration::from_secs(1); 10]), &budget);
+//!         let result = retry_with_failpoints(
+//!             &failpoints,
+//!             policy,
+//!             sleep,
+//!             || async { Some("ok") },
+//!             || None,
+//!         )
+//!         .await;
+//!         assert_eq!(result, None);
+//!     }
+//!
+//!     // the budget stopped the storm after 3 retries in total
+//!     assert_eq!(failpoints.hits(Failpoint::BeforeAttempt), 8);
+//!     assert_eq!(failpoints.hits(Failpoint::BeforeSleep), 3);
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::RetryPolicy;
+
+/// A point in the retry loop of [`retry_with_failpoints`] where a [`FailAction`] can trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Failpoint {
+    /// Before every attempt, including the first
+    BeforeAttempt,
+    /// After the policy decides to retry, before sleeping
+    BeforeSleep,
+}
+
+impl Failpoint {
+    /// The name of the [`fail`](::fail) crate failpoint at this point
+    pub const fn name(self) -> &'static str {
+        match self {
+            Failpoint::BeforeAttempt => "futures_retry_policies::before_attempt",
+            Failpoint::BeforeSleep => "futures_retry_policies::before_sleep",
+        }
+    }
+
+    /// The action configured for this failpoint with the [`fail`](::fail) crate
+    fn fail_crate_action(self) -> FailAction {
+        fail::fail_point!(self.name(), |delay: Option<String>| {
+            match delay.and_then(|ms| ms.parse().ok()) {
+                Some(ms) => FailAction::Delay(Duration::from_millis(ms)),
+                None => FailAction::Return,
+            }
+        });
+        FailAction::Off
+    }
+}
+
+/// What happens when a [`Failpoint`] is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailAction {
+    /// Nothing
+    #[default]
+    Off,
+    /// Return early. Before an attempt, the attempt is replaced by an injected failure.
+    /// Before a sleep, the sleep is skipped.
+    Return,
+    /// Wait for this long first, using the retry loop's sleep function
+    Delay(Duration),
+    /// Panic
+    Panic,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    actions: HashMap<Failpoint, (FailAction, Option<u32>)>,
+    hits: HashMap<Failpoint, u32>,
+}
+
+/// The actions configured for each [`Failpoint`], shared between clones
+#[derive(Debug, Clone, Default)]
+pub struct FailPoints {
+    state: Arc<Mutex<State>>,
+}
+
+impl FailPoints {
+    /// Create a set of failpoints that are all [off](FailAction::Off)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trigger `action` every time `point` is reached
+    pub fn set(&self, point: Failpoint, action: FailAction) {
+        self.lock().actions.insert(point, (action, None));
+    }
+
+    /// Trigger `action` the next `times` times `point` is reached, then turn it off
+    pub fn set_times(&self, point: Failpoint, times: u32, action: FailAction) {
+        self.lock().actions.insert(point, (action, Some(times)));
+    }
+
+    /// Turn every failpoint off. Hit counts are kept.
+    pub fn clear(&self) {
+        self.lock().actions.clear();
+    }
+
+    /// How many times an action has triggered at `point`
+    pub fn hits(&self, point: Failpoint) -> u32 {
+        self.lock().hits.get(&point).copied().unwrap_or(0)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn eval(&self, point: Failpoint) -> FailAction {
+        let mut state = self.lock();
+        let action = match state.actions.get_mut(&point) {
+            None | Some((FailAction::Off, _)) => return point.fail_crate_action(),
+            Some((_, Some(0))) => {
+                state.actions.remove(&point);
+                return point.fail_crate_action();
+            }
+            Some((action, times)) => {
+                if let Some(times) = times {
+                    *times -= 1;
+                }
+                *action
+            }
+        };
+        *state.hits.entry(point).or_default() += 1;
+        action
+    }
+}
+
+/// Retry the futures using the given [retry policy](`RetryPolicy`) and sleep function,
+/// checking `failpoints` before every attempt and every sleep.
+///
+/// When [`FailAction::Return`] triggers before an attempt, the attempt isn't run, and the result
+/// of `inject` is given to the policy instead. Points without an action on `failpoints` fall back
+/// to their [`fail`](::fail) crate configuration.
+///
+/// # Panics
+///
+/// When [`FailAction::Panic`] triggers.
+pub async fn retry_with_failpoints<Policy, Sleeper, Sleep, Futures, Fut, Inject>(
+    failpoints: &FailPoints,
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+    mut inject: Inject,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    Inject: FnMut() -> Fut::Output,
+{
+    loop {
+        let result = match failpoints.eval(Failpoint::BeforeAttempt) {
+            FailAction::Off => futures().await,
+            FailAction::Return => inject(),
+            FailAction::Delay(delay) => {
+                sleeper(delay).await;
+                futures().await
+            }
+            FailAction::Panic => panic!("failpoint {:?} triggered", Failpoint::BeforeAttempt),
+        };
+        match policy.should_retry(result) {
+            ControlFlow::Break(result) => break result,
+            ControlFlow::Continue(delay) => match failpoints.eval(Failpoint::BeforeSleep) {
+                FailAction::Off => sleeper(delay).await,
+                FailAction::Return => {}
+                FailAction::Delay(extra) => sleeper(delay.saturating_add(extra)).await,
+                FailAction::Panic => panic!("failpoint {:?} triggered", Failpoint::BeforeSleep),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::{retry_with_failpoints, FailAction, FailPoints, Failpoint};
+    use crate::iter::Iter;
+
+    #[tokio::test]
+    async fn limited_failures_then_recovers() {
+        // keeps `fail_crate_config` from configuring the same points meanwhile
+        let _scenario = fail::FailScenario::setup();
+        let failpoints = FailPoints::new();
+        failpoints.set_times(Failpoint::BeforeAttempt, 2, FailAction::Return);
+        failpoints.set(
+            Failpoint::BeforeSleep,
+            FailAction::Delay(Duration::from_secs(1)),
+        );
+
+        let slept = Cell::new(Duration::ZERO);
+        let attempts = Cell::new(0);
+        let result = retry_with_failpoints(
+            &failpoints,
+            Iter::new([Duration::from_secs(2); 5]),
+            |delay| {
+                slept.set(slept.get() + delay);
+                async {}
+            },
+            || {
+                attempts.set(attempts.get() + 1);
+                async { Some(1) }
+            },
+            || None,
+        )
+        .await;
+
+        assert_eq!(result, Some(1));
+        assert_eq!(attempts.get(), 1);
+        assert_eq!(slept.get(), Duration::from_secs(6));
+        assert_eq!(failpoints.hits(Failpoint::BeforeAttempt), 2);
+        assert_eq!(failpoints.hits(Failpoint::BeforeSleep), 2);
+    }
+
+    #[tokio::test]
+    async fn fail_crate_config() {
+        // `fail`'s registry is global, and the scenario holds it until it's torn down
+        let scenario = fail::FailScenario::setup();
+        fail::cfg(Failpoint::BeforeAttempt.name(), "1*return").unwrap();
+        fail::cfg(Failpoint::BeforeSleep.name(), "return(3000)").unwrap();
+
+        let failpoints = FailPoints::new();
+        let slept = Cell::new(Duration::ZERO);
+        let attempts = Cell::new(0);
+        let result = retry_with_failpoints(
+            &failpoints,
+            Iter::new([Duration::from_secs(2); 5]),
+            |delay| {
+                slept.set(slept.get() + delay);
+                async {}
+            },
+            || {
+                attempts.set(attempts.get() + 1);
+                async { Some(1) }
+            },
+            || None,
+        )
+        .await;
+        scenario.teardown();
+
+        assert_eq!(result, Some(1));
+        assert_eq!(attempts.get(), 1);
+        assert_eq!(slept.get(), Duration::from_secs(5));
+        // only actions set on the `FailPoints` are counted
+        assert_eq!(failpoints.hits(Failpoint::BeforeAttempt), 0);
+    }
+}
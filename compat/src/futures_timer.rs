@@ -0,0 +1,106 @@
+#![cfg(feature = "futures-timer")]
+#![cfg_attr(docsrs, doc(cfg(feature = "futures-timer")))]
+//! Runtime-agnostic retry features using [`futures-timer`](futures_timer)
+
+use std::{future::Future, time::Duration};
+
+use futures_timer::Delay;
+
+use crate::RetryPolicy;
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and a [`Delay`] as the sleep.
+///
+/// ```
+/// use futures_retry_policies::{futures_timer::retry, RetryPolicy};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// pub struct Retries(usize);
+/// impl RetryPolicy<Result<(), &'static str>> for Retries {
+///     fn should_retry(&mut self, result: Result<(), &'static str>) -> ControlFlow<Result<(), &'static str>, Duration> {
+///         if self.0 > 0 && result.is_err() {
+///             self.0 -= 1;
+///             // continue to retry on error
+///             ControlFlow::Continue(Duration::from_millis(100))
+///         } else {
+///             // We've got a success, or we've exhausted our retries, so break
+///             ControlFlow::Break(result)
+///         }
+///     }
+/// }
+///
+/// async fn make_request() -> Result<(), &'static str>  {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err("fail") } else { Ok(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), &'static str> {
+///     retry(Retries(3), make_request).await
+/// }
+/// ```
+pub fn retry<Policy, Futures, Fut>(
+    backoff: Policy,
+    futures: Futures,
+) -> RetryFuture<Policy, Futures, Fut>
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    super::retry(backoff, Delay::new, futures)
+}
+
+pub type RetryFuture<Policy, Futures, Fut> =
+    crate::RetryFuture<Policy, fn(Duration) -> Delay, Delay, Futures, Fut>;
+
+/// Easy helper trait to retry futures
+///
+/// ```
+/// use futures_retry_policies::{futures_timer::RetryFutureExt, RetryPolicy};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// pub struct Attempts(usize);
+/// impl RetryPolicy<Result<(), &'static str>> for Attempts {
+///     fn should_retry(&mut self, result: Result<(), &'static str>) -> ControlFlow<Result<(), &'static str>, Duration> {
+///         self.0 -= 1;
+///         if self.0 > 0 && result.is_err() {
+///             // continue to retry on error
+///             ControlFlow::Continue(Duration::from_millis(100))
+///         } else {
+///             // We've got a success, or we've exhausted our retries, so break
+///             ControlFlow::Break(result)
+///         }
+///     }
+/// }
+///
+/// async fn make_request() -> Result<(), &'static str>  {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err("fail") } else { Ok(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), &'static str> {
+///     make_request.retry(Attempts(3)).await
+/// }
+/// ```
+pub trait RetryFutureExt<Fut>
+where
+    Fut: Future,
+{
+    fn retry<Policy>(self, policy: Policy) -> RetryFuture<Policy, Self, Fut>
+    where
+        Policy: RetryPolicy<Fut::Output>,
+        Self: FnMut() -> Fut + Sized,
+    {
+        retry(policy, self)
+    }
+}
+
+impl<Futures, Fut> RetryFutureExt<Fut> for Futures
+where
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+}
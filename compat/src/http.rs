@@ -0,0 +1,461 @@
+#![cfg(feature = "http")]
+#![cfg_attr(docsrs, doc(cfg(feature = "http")))]
+//! Retry helpers for [`http`](::http) requests and responses
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    ops::ControlFlow,
+    time::Duration,
+};
+
+use http::{header::HeaderName, Extensions, HeaderValue, Request, Response, StatusCode};
+
+use crate::{Classify, RetryPolicy, ShouldRetry};
+
+/// Overrides the retry policy for a single request.
+///
+/// Insert this into the extensions of a request, and have the integration retrying that request
+/// wrap its policy with [`RetryOverride::apply`]. This lets individual calls opt out of retries that
+/// are configured for the whole client.
+///
+/// ```
+/// use futures_retry_policies::{http::RetryOverride, iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn send(req: &http::Request<()>) -> Option<()> {
+///     // send the request
+///     None
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut req = http::Request::new(());
+///     // this request isn't idempotent, don't retry it
+///     req.extensions_mut().insert(RetryOverride::NoRetry);
+///
+///     // the client's policy, which would normally retry 3 times
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///
+///     let mut attempts = 0;
+///     let policy = RetryOverride::apply(policy, req.extensions());
+///     let res = (|| { attempts += 1; send(&req) }).retry(policy).await;
+///     assert_eq!(res, None);
+///     assert_eq!(attempts, 1);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryOverride {
+    /// Only make a single attempt
+    NoRetry,
+    /// Make at most this many attempts, including the first
+    MaxAttempts(u32),
+}
+
+impl RetryOverride {
+    fn max_attempts(self) -> u32 {
+        match self {
+            RetryOverride::NoRetry => 1,
+            RetryOverride::MaxAttempts(n) => n,
+        }
+    }
+
+    /// Wrap the policy, respecting any [`RetryOverride`] in the extensions
+    pub fn apply<P>(policy: P, extensions: &Extensions) -> Overridden<P> {
+        Overridden {
+            policy,
+            max_attempts: extensions.get::<Self>().map(|o| o.max_attempts()),
+            attempts: 0,
+        }
+    }
+}
+
+/// [`RetryPolicy`] returned by [`RetryOverride::apply`]
+#[derive(Debug, Clone)]
+pub struct Overridden<P> {
+    policy: P,
+    max_attempts: Option<u32>,
+    attempts: u32,
+}
+
+impl<P, R> RetryPolicy<R> for Overridden<P>
+where
+    P: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        match self.max_attempts {
+            Some(max) if self.attempts >= max => ControlFlow::Break(result),
+            _ => self.policy.should_retry(result),
+        }
+    }
+}
+
+/// How a response was retried.
+///
+/// Policies wrapped with [`RetryInfo::record`] insert this into the extensions of the final
+/// response, so that middleware further up, like logging or metrics, can read it without
+/// knowing anything about the retries.
+///
+/// ```
+/// use futures_retry_policies::{http::RetryInfo, iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn send() -> http::Response<()> {
+///     // send the request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # let status = if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { 503 } else { 200 };
+///     # http::Response::builder().status(status).body(()).unwrap()
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = RetryInfo::record(Iter::new([Duration::from_millis(10); 3]));
+///     let res = send.retry(policy).await;
+///
+///     let info = res.extensions().get::<RetryInfo>().unwrap();
+///     assert_eq!(info.attempts, 3);
+///     assert_eq!(info.total_delay, Duration::from_millis(20));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RetryInfo {
+    /// How many attempts were made, including the first
+    pub attempts: u32,
+    /// The sum of the delays waited between attempts
+    pub total_delay: Duration,
+}
+
+impl RetryInfo {
+    /// Wrap the policy, recording a [`RetryInfo`] in the final response's extensions
+    pub fn record<P>(policy: P) -> RecordRetryInfo<P> {
+        RecordRetryInfo {
+            policy,
+            info: RetryInfo::default(),
+        }
+    }
+}
+
+/// [`RetryPolicy`] returned by [`RetryInfo::record`]
+///
+/// Errors that aren't responses have no extensions, so nothing is recorded for them.
+#[derive(Debug, Clone)]
+pub struct RecordRetryInfo<P> {
+    policy: P,
+    info: RetryInfo,
+}
+
+impl<P, B> RetryPolicy<Response<B>> for RecordRetryInfo<P>
+where
+    P: RetryPolicy<Response<B>>,
+{
+    fn should_retry(&mut self, result: Response<B>) -> ControlFlow<Response<B>, Duration> {
+        self.info.attempts = self.info.attempts.saturating_add(1);
+        match self.policy.should_retry(result) {
+            ControlFlow::Continue(delay) => {
+                self.info.total_delay = self.info.total_delay.saturating_add(delay);
+                ControlFlow::Continue(delay)
+            }
+            ControlFlow::Break(mut response) => {
+                response.extensions_mut().insert(self.info);
+                ControlFlow::Break(response)
+            }
+        }
+    }
+}
+
+impl<P, B, E> RetryPolicy<Result<Response<B>, E>> for RecordRetryInfo<P>
+where
+    P: RetryPolicy<Result<Response<B>, E>>,
+{
+    fn should_retry(
+        &mut self,
+        result: Result<Response<B>, E>,
+    ) -> ControlFlow<Result<Response<B>, E>, Duration> {
+        self.info.attempts = self.info.attempts.saturating_add(1);
+        match self.policy.should_retry(result) {
+            ControlFlow::Continue(delay) => {
+                self.info.total_delay = self.info.total_delay.saturating_add(delay);
+                ControlFlow::Continue(delay)
+            }
+            ControlFlow::Break(mut result) => {
+                if let Ok(response) = &mut result {
+                    response.extensions_mut().insert(self.info);
+                }
+                ControlFlow::Break(result)
+            }
+        }
+    }
+}
+
+/// The `Idempotency-Key` header
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// An `Idempotency-Key` for one logical call, to be sent with every attempt.
+///
+/// Servers that support idempotency keys only process a request once per key, which makes
+/// retrying non-idempotent requests, like a `POST`, safe. Create one key per logical call, outside
+/// of the retry loop, then [`apply`](IdempotencyKey::apply) it to the request of every attempt.
+///
+/// ```
+/// use futures_retry_policies::{http::{IdempotencyKey, IDEMPOTENCY_KEY}, iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn send(req: http::Request<()>) -> Option<http::HeaderValue> {
+///     // send the request, failing the first time
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 1 { return None }
+///     req.headers().get(IDEMPOTENCY_KEY).cloned()
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let key = IdempotencyKey::generate();
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///
+///     let sent_with = (|| {
+///         let mut req = http::Request::post("https://example.com/payments").body(()).unwrap();
+///         key.apply(&mut req);
+///         send(req)
+///     })
+///     .retry(policy)
+///     .await;
+///     assert_eq!(sent_with.as_ref(), Some(key.value()));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey(HeaderValue);
+
+impl IdempotencyKey {
+    /// Use a key made by your own generator, eg. a UUID
+    pub fn new(value: HeaderValue) -> Self {
+        Self(value)
+    }
+
+    /// Generate a 128-bit key, hex encoded.
+    ///
+    /// With the `rand` feature, the key is read from the operating system's random number
+    /// generator. Otherwise it's derived from the random keys of the standard library's
+    /// [`RandomState`], which makes it unique per process, but not unpredictable. Use
+    /// [`new`](IdempotencyKey::new) with a UUID if the keys need to be unpredictable.
+    pub fn generate() -> Self {
+        let key = format!("{:032x}", Self::random());
+        Self(HeaderValue::try_from(key).expect("hex is a valid header value"))
+    }
+
+    fn random() -> u128 {
+        #[cfg(feature = "rand")]
+        {
+            use rand::TryRngCore;
+            let mut bytes = [0; 16];
+            if rand::rngs::OsRng.try_fill_bytes(&mut bytes).is_ok() {
+                return u128::from_ne_bytes(bytes);
+            }
+        }
+        let random = || u128::from(RandomState::new().build_hasher().finish());
+        random() << 64 | random()
+    }
+
+    /// The key
+    pub fn value(&self) -> &HeaderValue {
+        &self.0
+    }
+
+    /// Set the `Idempotency-Key` header of `req`, unless it already has one
+    pub fn apply<B>(&self, req: &mut Request<B>) {
+        req.headers_mut()
+            .entry(IDEMPOTENCY_KEY)
+            .or_insert_with(|| self.0.clone());
+    }
+}
+
+/// Classifies which [`StatusCode`]s should be retried.
+///
+/// Use it with any policy that takes a [`Classify`], like the [`RetryPolicyBuilder`](crate::RetryPolicyBuilder).
+///
+/// ```
+/// use futures_retry_policies::{http::RetryableStatus, tokio::RetryFutureExt, RetryPolicyBuilder};
+/// use std::time::Duration;
+///
+/// async fn send() -> http::Response<()> {
+///     // send a POST request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # let status = if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { 503 } else { 201 };
+///     # http::Response::builder().status(status).body(()).unwrap()
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = RetryPolicyBuilder::exponential(Duration::from_millis(10))
+///         .max_retries(3)
+///         .retry_if(RetryableStatus::idempotent_safe())
+///         .build();
+///     let res = send.retry(policy).await;
+///     assert_eq!(res.status(), http::StatusCode::CREATED);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryableStatus {
+    server_errors: bool,
+    too_many_requests: bool,
+    request_timeout: bool,
+}
+
+impl RetryableStatus {
+    /// Statuses that are worth retrying for idempotent requests:
+    /// `408`, `429`, `500`, `502`, `503` and `504`.
+    ///
+    /// This is also what the [`ShouldRetry`] impl for [`StatusCode`] uses.
+    pub const fn idempotent() -> Self {
+        Self {
+            server_errors: true,
+            too_many_requests: true,
+            request_timeout: true,
+        }
+    }
+
+    /// Statuses where the server didn't process the request, so it's safe to retry
+    /// even if the request isn't idempotent: `429` and `503`.
+    pub const fn idempotent_safe() -> Self {
+        Self {
+            server_errors: false,
+            too_many_requests: true,
+            request_timeout: false,
+        }
+    }
+
+    /// Whether to retry `429 Too Many Requests`
+    pub const fn include_429(mut self, include: bool) -> Self {
+        self.too_many_requests = include;
+        self
+    }
+
+    /// Whether to retry `408 Request Timeout`
+    pub const fn include_408(mut self, include: bool) -> Self {
+        self.request_timeout = include;
+        self
+    }
+
+    /// Whether the status should be retried
+    pub fn is_retryable(&self, status: StatusCode) -> bool {
+        match status {
+            StatusCode::SERVICE_UNAVAILABLE => true,
+            StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::GATEWAY_TIMEOUT => self.server_errors,
+            StatusCode::TOO_MANY_REQUESTS => self.too_many_requests,
+            StatusCode::REQUEST_TIMEOUT => self.request_timeout,
+            _ => false,
+        }
+    }
+}
+
+impl Default for RetryableStatus {
+    fn default() -> Self {
+        Self::idempotent()
+    }
+}
+
+impl Classify<StatusCode> for RetryableStatus {
+    fn classify(&mut self, status: &StatusCode, _: u32) -> bool {
+        self.is_retryable(*status)
+    }
+}
+
+impl<B> Classify<Response<B>> for RetryableStatus {
+    fn classify(&mut self, response: &Response<B>, _: u32) -> bool {
+        self.is_retryable(response.status())
+    }
+}
+
+impl<B, E: ShouldRetry> Classify<Result<Response<B>, E>> for RetryableStatus {
+    /// Classifies responses by their status, and errors by their [`ShouldRetry`] impl
+    fn classify(&mut self, result: &Result<Response<B>, E>, attempts: u32) -> bool {
+        match result {
+            Ok(response) => self.is_retryable(response.status()),
+            Err(err) => err.should_retry(attempts),
+        }
+    }
+}
+
+impl ShouldRetry for StatusCode {
+    /// Should retry if the status is one of [`RetryableStatus::idempotent`]
+    fn should_retry(&self, _: u32) -> bool {
+        RetryableStatus::idempotent().is_retryable(*self)
+    }
+}
+
+impl<B> ShouldRetry for Response<B> {
+    /// Should retry if the status should retry
+    fn should_retry(&self, attempts: u32) -> bool {
+        self.status().should_retry(attempts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::{IdempotencyKey, RetryInfo, RetryableStatus, IDEMPOTENCY_KEY};
+    use crate::{iter::Iter, RetryPolicy, ShouldRetry};
+
+    #[test]
+    fn idempotent() {
+        let statuses = RetryableStatus::idempotent();
+        for status in [408, 429, 500, 502, 503, 504] {
+            assert!(statuses.is_retryable(StatusCode::from_u16(status).unwrap()));
+        }
+        for status in [200, 400, 404, 501] {
+            assert!(!statuses.is_retryable(StatusCode::from_u16(status).unwrap()));
+        }
+        assert!(StatusCode::BAD_GATEWAY.should_retry(1));
+    }
+
+    #[test]
+    fn idempotent_safe() {
+        let statuses = RetryableStatus::idempotent_safe();
+        assert!(statuses.is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(statuses.is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!statuses.is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!statuses.is_retryable(StatusCode::REQUEST_TIMEOUT));
+
+        let statuses = statuses.include_429(false).include_408(true);
+        assert!(!statuses.is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(statuses.is_retryable(StatusCode::REQUEST_TIMEOUT));
+    }
+
+    #[test]
+    fn records_retry_info() {
+        let mut policy = RetryInfo::record(Iter::new([Duration::from_secs(1); 2]));
+        let response = |status: u16| http::Response::builder().status(status).body(()).unwrap();
+
+        assert!(policy.should_retry(response(503)).is_continue());
+        let ControlFlow::Break(res) = policy.should_retry(response(200)) else {
+            panic!("should not retry a success")
+        };
+        assert_eq!(
+            res.extensions().get::<RetryInfo>(),
+            Some(&RetryInfo {
+                attempts: 2,
+                total_delay: Duration::from_secs(1),
+            })
+        );
+    }
+
+    #[test]
+    fn idempotency_key() {
+        let key = IdempotencyKey::generate();
+        assert_eq!(key.value().len(), 32);
+        assert_ne!(key, IdempotencyKey::generate());
+
+        let mut req = http::Request::new(());
+        key.apply(&mut req);
+        assert_eq!(req.headers().get(IDEMPOTENCY_KEY), Some(key.value()));
+
+        // an existing key is kept
+        IdempotencyKey::generate().apply(&mut req);
+        assert_eq!(req.headers().get(IDEMPOTENCY_KEY), Some(key.value()));
+    }
+}
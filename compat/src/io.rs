@@ -0,0 +1,375 @@
+#![cfg(feature = "tokio")]
+#![cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+//! Resumable reads.
+//!
+//! Long transfers, like downloads, can fail part way through. Rather than retrying the whole
+//! transfer, [`ResumableReader`] reopens the source at the offset it had reached, and picks up
+//! where it left off.
+
+use std::{
+    future::Future,
+    io,
+    ops::ControlFlow,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::RetryPolicy;
+
+/// Whether an I/O error is likely to go away if the source is reopened.
+///
+/// This covers dropped and timed out connections, and sources that ended early.
+/// Use it as a classifier, eg. with [`RetryPolicyBuilder::retry_if`](crate::RetryPolicyBuilder::retry_if).
+pub fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// An [`AsyncRead`] that reopens its source when reading fails, according to a [retry policy](`RetryPolicy`).
+///
+/// The source is opened with `open(offset)`, where `offset` is how many bytes have been read so far.
+/// Use it to seek, or to send a `Range: bytes={offset}-` header. Errors from both opening and
+/// reading are passed to the policy.
+///
+/// A reader created with [`new`](ResumableReader::new) uses one policy for the whole transfer, so
+/// a limit like [`max_retries`](crate::RetryPolicyBuilder::max_retries) counts every failure
+/// along the way. Use [`resetting`](ResumableReader::resetting) to start with a fresh policy
+/// each time a reopened source makes progress.
+///
+/// Once the policy gives up, its error is returned and the reader can't be used any more.
+///
+/// ```
+/// use futures_retry_policies::{io::{is_transient, ResumableReader}, RetryPolicyBuilder};
+/// use std::{io::SeekFrom, time::Duration};
+/// use tokio::io::{AsyncReadExt, AsyncSeekExt};
+///
+/// #[tokio::main]
+/// async fn main() -> std::io::Result<()> {
+///     let policy = RetryPolicyBuilder::exponential(Duration::from_millis(10))
+///         .max_retries(5)
+///         .retry_if(is_transient)
+///         .build();
+///
+///     let mut reader = ResumableReader::new(policy, tokio::time::sleep, |offset| async move {
+///         let mut file = tokio::fs::File::open("Cargo.toml").await?;
+///         file.seek(SeekFrom::Start(offset)).await?;
+///         Ok(file)
+///     });
+///
+///     let mut manifest = String::new();
+///     reader.read_to_string(&mut manifest).await?;
+///     assert!(manifest.contains("futures-retry-policies"));
+///     Ok(())
+/// }
+/// ```
+pub struct ResumableReader<Policy, Sleeper, Sleep, Open, Fut, R, Reset = fn() -> Policy> {
+    policy: Policy,
+    reset: Option<Reset>,
+    retried: bool,
+    sleeper: Sleeper,
+    open: Open,
+    offset: u64,
+    state: ReaderState<Sleep, Fut, R>,
+}
+
+enum ReaderState<Sleep, Fut, R> {
+    Opening(Pin<Box<Fut>>),
+    Reading(R),
+    Sleeping(Pin<Box<Sleep>>),
+    GaveUp,
+}
+
+impl<Policy, Sleeper, Sleep, Open, Fut, R> ResumableReader<Policy, Sleeper, Sleep, Open, Fut, R>
+where
+    Policy: RetryPolicy<io::Error>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Open: FnMut(u64) -> Fut,
+    Fut: Future<Output = io::Result<R>>,
+    R: AsyncRead + Unpin,
+{
+    /// Read from the source opened by `open`, starting at offset 0.
+    ///
+    /// `policy` is never reset, so it decides on every failure in the transfer, however far
+    /// apart they are.
+    pub fn new(policy: Policy, sleeper: Sleeper, open: Open) -> Self {
+        Self::with_reset(policy, None, sleeper, open)
+    }
+}
+
+impl<Policy, Sleeper, Sleep, Open, Fut, R, Reset>
+    ResumableReader<Policy, Sleeper, Sleep, Open, Fut, R, Reset>
+where
+    Policy: RetryPolicy<io::Error>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Open: FnMut(u64) -> Fut,
+    Fut: Future<Output = io::Result<R>>,
+    R: AsyncRead + Unpin,
+    Reset: FnMut() -> Policy,
+{
+    /// Read from the source opened by `open`, starting at offset 0, with a policy from
+    /// `make_policy`.
+    ///
+    /// Once a reopened source returns data, the policy is replaced with a new one from
+    /// `make_policy`, so its limits apply to each run of failures rather than the whole transfer.
+    ///
+    /// ```
+    /// use futures_retry_policies::{io::{is_transient, ResumableReader}, RetryPolicyBuilder};
+    /// use std::time::Duration;
+    ///
+    /// let policy = RetryPolicyBuilder::exponential(Duration::from_millis(10))
+    ///     .max_retries(5)
+    ///     .retry_if(is_transient)
+    ///     .build();
+    /// let reader = ResumableReader::resetting(
+    ///     move || policy.clone(),
+    ///     tokio::time::sleep,
+    ///     |offset| async move { tokio::fs::File::open("Cargo.toml").await },
+    /// );
+    /// ```
+    pub fn resetting(mut make_policy: Reset, sleeper: Sleeper, open: Open) -> Self {
+        Self::with_reset(make_policy(), Some(make_policy), sleeper, open)
+    }
+
+    fn with_reset(policy: Policy, reset: Option<Reset>, sleeper: Sleeper, mut open: Open) -> Self {
+        let state = ReaderState::Opening(Box::pin(open(0)));
+        Self {
+            policy,
+            reset,
+            retried: false,
+            sleeper,
+            open,
+            offset: 0,
+            state,
+        }
+    }
+
+    /// How many bytes have been read so far
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Ask the policy whether to reopen the source after `err`
+    fn retry(&mut self, err: io::Error) -> Result<(), io::Error> {
+        match self.policy.should_retry(err) {
+            ControlFlow::Continue(delay) => {
+                self.retried = true;
+                self.state = ReaderState::Sleeping(Box::pin((self.sleeper)(delay)));
+                Ok(())
+            }
+            ControlFlow::Break(err) => {
+                self.state = ReaderState::GaveUp;
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<Policy, Sleeper, Sleep, Open, Fut, R, Reset> AsyncRead
+    for ResumableReader<Policy, Sleeper, Sleep, Open, Fut, R, Reset>
+where
+    Policy: RetryPolicy<io::Error> + Unpin,
+    Sleeper: FnMut(Duration) -> Sleep + Unpin,
+    Sleep: Future<Output = ()>,
+    Open: FnMut(u64) -> Fut + Unpin,
+    Fut: Future<Output = io::Result<R>>,
+    R: AsyncRead + Unpin,
+    Reset: FnMut() -> Policy + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.state {
+                ReaderState::Opening(fut) => match ready!(fut.as_mut().poll(cx)) {
+                    Ok(reader) => this.state = ReaderState::Reading(reader),
+                    Err(err) => this.retry(err)?,
+                },
+                ReaderState::Reading(reader) => {
+                    let before = buf.filled().len();
+                    match ready!(Pin::new(reader).poll_read(cx, buf)) {
+                        Ok(()) => {
+                            let read = buf.filled().len() - before;
+                            if read > 0 && this.retried {
+                                // the source has recovered, so start afresh for the next failure
+                                if let Some(reset) = &mut this.reset {
+                                    this.policy = reset();
+                                }
+                                this.retried = false;
+                            }
+                            this.offset += read as u64;
+                            return Poll::Ready(Ok(()));
+                        }
+                        Err(err) => this.retry(err)?,
+                    }
+                }
+                ReaderState::Sleeping(sleep) => {
+                    ready!(sleep.as_mut().poll(cx));
+                    this.state = ReaderState::Opening(Box::pin((this.open)(this.offset)));
+                }
+                ReaderState::GaveUp => {
+                    return Poll::Ready(Err(io::Error::other(
+                        "the retry policy gave up on this reader",
+                    )))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+    use super::{is_transient, ResumableReader};
+    use crate::RetryPolicyBuilder;
+
+    const DATA: &[u8] = b"the quick brown fox jumps over the lazy dog";
+
+    /// Reads `DATA` from an offset, a few bytes at a time, failing after `fail_after` bytes
+    struct Flaky {
+        pos: usize,
+        fail_after: usize,
+    }
+
+    impl AsyncRead for Flaky {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if self.fail_after == 0 {
+                return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+            }
+            let n = 4.min(self.fail_after).min(DATA.len() - self.pos);
+            buf.put_slice(&DATA[self.pos..self.pos + n]);
+            self.pos += n;
+            self.fail_after -= n;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn resumes_from_offset() {
+        let policy = RetryPolicyBuilder::fixed(Duration::ZERO)
+            .retry_if(is_transient)
+            .build();
+        let mut offsets = vec![];
+        let mut reader = ResumableReader::new(policy, sleep, |offset| {
+            offsets.push(offset);
+            async move {
+                Ok(Flaky {
+                    pos: offset as usize,
+                    fail_after: 10,
+                })
+            }
+        });
+
+        let mut out = vec![];
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, DATA);
+        assert_eq!(reader.offset(), DATA.len() as u64);
+        drop(reader);
+        assert_eq!(offsets, [0, 10, 20, 30, 40]);
+    }
+
+    #[tokio::test]
+    async fn gives_up() {
+        let policy = RetryPolicyBuilder::fixed(Duration::ZERO)
+            .max_retries(2)
+            .retry_if(is_transient)
+            .build();
+        let mut opened = 0;
+        let mut reader = ResumableReader::new(policy, sleep, |_| {
+            opened += 1;
+            async {
+                Ok(Flaky {
+                    pos: 0,
+                    fail_after: 0,
+                })
+            }
+        });
+
+        let err = reader.read_to_end(&mut vec![]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert!(reader.read_to_end(&mut vec![]).await.is_err());
+        drop(reader);
+        assert_eq!(opened, 3);
+    }
+
+    #[tokio::test]
+    async fn open_errors_are_retried() {
+        let policy = RetryPolicyBuilder::fixed(Duration::ZERO)
+            .max_retries(2)
+            .retry_if(is_transient)
+            .build();
+        let mut opened = 0;
+        let mut reader = ResumableReader::new(policy, sleep, |offset| {
+            opened += 1;
+            let attempt = opened;
+            async move {
+                if attempt < 2 {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                Ok(Flaky {
+                    pos: offset as usize,
+                    fail_after: usize::MAX,
+                })
+            }
+        });
+
+        let mut out = vec![];
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, DATA);
+    }
+
+    #[tokio::test]
+    async fn resetting_policy() {
+        let policy = RetryPolicyBuilder::fixed(Duration::ZERO)
+            .max_retries(1)
+            .retry_if(is_transient)
+            .build();
+        let open = |offset: u64| async move {
+            Ok(Flaky {
+                pos: offset as usize,
+                fail_after: 10,
+            })
+        };
+
+        // one retry covers the whole transfer
+        let mut reader = ResumableReader::new(policy.clone(), sleep, open);
+        let err = reader.read_to_end(&mut vec![]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(reader.offset(), 20);
+
+        // one retry for each failure, as the source makes progress in between
+        let mut reader = ResumableReader::resetting(|| policy.clone(), sleep, open);
+        let mut out = vec![];
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, DATA);
+    }
+}
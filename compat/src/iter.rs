@@ -26,6 +26,7 @@ use crate::{RetryPolicy, ShouldRetry};
 ///         .unwrap()
 /// }
 /// ```
+#[derive(Debug, Clone)]
 pub struct Iter<I> {
     iter: I,
     amount: u32,
@@ -38,6 +39,23 @@ impl<I: Iterator> Iter<I> {
             amount: 0,
         }
     }
+
+    /// The delays planned for up to the next `n` retries, without changing the policy.
+    ///
+    /// ```
+    /// use futures_retry_policies::iter::Iter;
+    /// use std::time::Duration;
+    ///
+    /// let policy = Iter::new((1..=3).map(Duration::from_secs));
+    /// let schedule: Vec<_> = policy.preview(5).collect();
+    /// assert_eq!(schedule, [1, 2, 3].map(Duration::from_secs));
+    /// ```
+    pub fn preview(&self, n: usize) -> std::iter::Take<I>
+    where
+        I: Clone,
+    {
+        self.iter.clone().take(n)
+    }
 }
 
 impl<R, I> RetryPolicy<R> for Iter<I>
@@ -47,10 +65,11 @@ where
 {
     fn should_retry(&mut self, result: R) -> std::ops::ControlFlow<R, Duration> {
         self.amount += 1;
-        match self.iter.next() {
+        let flow = match self.iter.next() {
             Some(duration) if result.should_retry(self.amount) => ControlFlow::Continue(duration),
             _ => ControlFlow::Break(result),
-        }
+        };
+        crate::verbose::observe("Iter", self.amount, flow)
     }
 }
 
@@ -0,0 +1,302 @@
+//! Independent policy state per destination.
+//!
+//! A client talking to many hosts usually wants each host to back off on its own,
+//! so that one struggling host doesn't slow down requests to the others. [`Keyed`]
+//! keeps a policy per key, creating them on demand and evicting the least recently
+//! used once it's full.
+//!
+//! Concurrent calls to the same key share the policy, so they back off together. Whenever the
+//! policy gives up or a call succeeds, it starts again from its initial state, so the next call
+//! to that key gets its full set of retries.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    ops::ControlFlow,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use crate::RetryPolicy;
+
+/// A map of policies, one per key.
+///
+/// ```
+/// use futures_retry_policies::{keyed::Keyed, RetryPolicyBuilder, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// # #[derive(Debug)]
+/// # struct Error;
+/// # impl futures_retry_policies::ShouldRetry for Error {
+/// #     fn should_retry(&self, _: u32) -> bool { true }
+/// # }
+/// async fn make_request(host: &str) -> Result<(), Error> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err(Error) } else { Ok(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let policies = Keyed::new(100, |_host: &String| {
+///         RetryPolicyBuilder::exponential(Duration::from_millis(10)).max_retries(3).build()
+///     });
+///
+///     let host = "example.com".to_owned();
+///     let policy = policies.policy_for(host.clone());
+///     (|| make_request(&host)).retry(policy).await
+/// }
+/// ```
+pub struct Keyed<K, P, F> {
+    capacity: usize,
+    new_policy: F,
+    state: Mutex<State<K, P>>,
+}
+
+struct State<K, P> {
+    policies: HashMap<K, Entry<P>>,
+    /// The keys by when they were last used, so the least recently used is first
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+struct Entry<P> {
+    policy: Shared<P>,
+    last_used: u64,
+}
+
+impl<K, P, F> Keyed<K, P, F>
+where
+    K: Hash + Eq + Clone,
+    P: Clone,
+    F: Fn(&K) -> P,
+{
+    /// Keep up to `capacity` policies, creating them with `new_policy` when a key is first seen
+    pub fn new(capacity: usize, new_policy: F) -> Self {
+        Self {
+            capacity,
+            new_policy,
+            state: Mutex::new(State {
+                policies: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Get the policy for `key`, creating it if needed.
+    ///
+    /// If the map is full, the least recently used policy is dropped to make room.
+    /// Calls that are still using it keep their handle, but later calls for that key
+    /// start again with a new policy.
+    pub fn policy_for(&self, key: K) -> Shared<P> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.tick += 1;
+        let tick = state.tick;
+
+        let state = &mut *state;
+        if let Some(entry) = state.policies.get_mut(&key) {
+            let last_used = std::mem::replace(&mut entry.last_used, tick);
+            let key = state.recency.remove(&last_used).unwrap_or(key);
+            state.recency.insert(tick, key);
+            return entry.policy.clone();
+        }
+
+        if state.policies.len() >= self.capacity {
+            if let Some((_, oldest)) = state.recency.pop_first() {
+                state.policies.remove(&oldest);
+            }
+        }
+
+        let policy = Shared::new((self.new_policy)(&key));
+        if self.capacity > 0 {
+            state.recency.insert(tick, key.clone());
+            state.policies.insert(
+                key,
+                Entry {
+                    policy: policy.clone(),
+                    last_used: tick,
+                },
+            );
+        }
+        policy
+    }
+
+    /// Drop the policy for `key`, so the next call starts with a new one
+    pub fn remove(&self, key: &K) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(entry) = state.policies.remove(key) {
+            state.recency.remove(&entry.last_used);
+        }
+    }
+
+    /// The number of policies currently kept
+    pub fn len(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.policies.len()
+    }
+
+    /// Whether no policies are currently kept
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A handle to a policy shared between calls, returned by [`Keyed::policy_for`].
+///
+/// All clones of a handle share the same policy state. Once the policy breaks, it is reset to
+/// the state it was shared in, ready for the next call.
+#[derive(Debug, Default)]
+pub struct Shared<P> {
+    policy: Arc<Mutex<Reset<P>>>,
+}
+
+#[derive(Debug, Default)]
+struct Reset<P> {
+    current: P,
+    initial: P,
+}
+
+impl<P: Clone> Shared<P> {
+    /// Share `policy` between calls
+    pub fn new(policy: P) -> Self {
+        Self {
+            policy: Arc::new(Mutex::new(Reset {
+                current: policy.clone(),
+                initial: policy,
+            })),
+        }
+    }
+}
+
+impl<P> Clone for Shared<P> {
+    fn clone(&self) -> Self {
+        Self {
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+impl<P, R> RetryPolicy<R> for Shared<P>
+where
+    P: RetryPolicy<R> + Clone,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let mut policy = self.policy.lock().unwrap_or_else(PoisonError::into_inner);
+        let flow = policy.current.should_retry(result);
+        if flow.is_break() {
+            policy.current = policy.initial.clone();
+        }
+        flow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::Keyed;
+    use crate::{backoff::Backoff, RetryPolicy, RetryPolicyBuilder};
+
+    fn keyed() -> Keyed<&'static str, Backoff, fn(&&'static str) -> Backoff> {
+        Keyed::new(2, |_| {
+            RetryPolicyBuilder::exponential(Duration::from_secs(1)).build()
+        })
+    }
+
+    #[test]
+    fn independent_state() {
+        let policies = keyed();
+        let mut a = policies.policy_for("a");
+        let mut b = policies.policy_for("b");
+
+        assert_eq!(
+            a.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(1))
+        );
+        assert_eq!(
+            a.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(2))
+        );
+        assert_eq!(
+            b.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(1))
+        );
+
+        // state is kept between concurrent calls for the same key
+        let mut a = policies.policy_for("a");
+        assert_eq!(
+            a.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(4))
+        );
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let policies = keyed();
+        let mut a = policies.policy_for("a");
+        assert_eq!(
+            a.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(1))
+        );
+        let mut b = policies.policy_for("b");
+        assert_eq!(
+            b.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(1))
+        );
+
+        policies.policy_for("a");
+        policies.policy_for("c");
+        assert_eq!(policies.len(), 2);
+
+        let mut a = policies.policy_for("a");
+        assert_eq!(
+            a.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(2))
+        );
+        let mut b = policies.policy_for("b");
+        assert_eq!(
+            b.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn removed_keys_are_not_evicted() {
+        let policies = keyed();
+        let mut a = policies.policy_for("a");
+        assert!(a.should_retry(None::<()>).is_continue());
+        policies.policy_for("b");
+        policies.remove(&"b");
+
+        // there's room for "c" without evicting "a"
+        policies.policy_for("c");
+        assert_eq!(policies.len(), 2);
+        let mut a = policies.policy_for("a");
+        assert_eq!(
+            a.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_secs(2))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sequential_calls_get_fresh_retries() {
+        let policies = Keyed::new(2, |_: &&str| {
+            RetryPolicyBuilder::fixed(Duration::from_secs(1))
+                .max_retries(2)
+                .build()
+        });
+
+        for _ in 0..2 {
+            let mut attempts = 0;
+            let policy = policies.policy_for("a");
+            let res = crate::retry(policy, tokio::time::sleep, || {
+                attempts += 1;
+                std::future::ready(None::<()>)
+            })
+            .await;
+            assert_eq!(res, None);
+            assert_eq!(attempts, 3);
+        }
+    }
+}
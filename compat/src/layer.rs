@@ -0,0 +1,414 @@
+//! A batteries-included retry middleware.
+//!
+//! Most services want the same things from retries: a classifier deciding which responses are
+//! worth retrying, a [budget](crate::budget) so retries can't snowball during an outage, and
+//! [stats](crate::stats) to see how often it all happens. [`RetryLayer`] bundles those together
+//! with a policy and a sleep function, and wraps services with them in one line. For metrics in
+//! another system, wrap the policy itself, eg. with `opentelemetry::Metered` from the
+//! `opentelemetry` feature.
+//!
+//! With the `tower` feature, [`RetryLayer`] is a `tower::Layer` and [`RetryService`] a
+//! `tower::Service`, so the layer drops into a `ServiceBuilder`. Without it, a service is any
+//! `FnMut(Req) -> Fut`, called with [`RetryService::call`].
+//!
+//! ```
+//! use futures_retry_policies::{
+//!     budget::RetryBudget, layer::RetryLayer, stats::RetryStats, RetryPolicyBuilder,
+//! };
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum Error { Unavailable, BadRequest }
+//! # impl futures_retry_policies::ShouldRetry for Error { fn should_retry(&self, _: u32) -> bool { true } }
+//!
+//! async fn handle(request: u32) -> Result<u32, Error> {
+//!     // send the request to the backend
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 1 { return Err(Error::Unavailable) }
+//!     Ok(request * 2)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let stats = RetryStats::new(Duration::from_secs(60), 10);
+//!     let policy = RetryPolicyBuilder::exponential(Duration::from_millis(10)).build();
+//!     let layer = RetryLayer::new(policy, tokio::time::sleep)
+//!         .classify(|res: &Result<u32, Error>| res == &Err(Error::Unavailable))
+//!         .budget(RetryBudget::new(100))
+//!         .stats(&stats);
+//!
+//!     let mut service = layer.layer(handle);
+//!     assert_eq!(service.call(21).await, Ok(42));
+//!     assert_eq!(stats.snapshot().total.attempts, 2);
+//! }
+//! ```
+
+use std::{future::Future, ops::ControlFlow, time::Duration};
+
+use crate::{
+    budget::{Budgeted, RetryBudget},
+    retry_with_sleeper,
+    stats::{RetryStats, Stats},
+    Classify, RetryPolicy, RetrySleeper, UseShouldRetry,
+};
+
+/// Retry configuration shared by every [`RetryService`] it [wraps](RetryLayer::layer)
+///
+/// The policy and sleeper are cloned for every call, while the budget and stats are shared.
+#[derive(Debug, Clone)]
+pub struct RetryLayer<P, Z, C = UseShouldRetry> {
+    policy: P,
+    sleeper: Z,
+    classifier: C,
+    budget: Option<RetryBudget>,
+    stats: Option<RetryStats>,
+}
+
+impl<P, Z> RetryLayer<P, Z> {
+    /// Retry with clones of `policy`, sleeping with `sleeper`, for results that
+    /// [should be retried](crate::ShouldRetry)
+    pub fn new(policy: P, sleeper: Z) -> Self {
+        Self {
+            policy,
+            sleeper,
+            classifier: UseShouldRetry,
+            budget: None,
+            stats: None,
+        }
+    }
+}
+
+impl<P, Z, C> RetryLayer<P, Z, C> {
+    /// Only retry results that `classifier` accepts, before consulting the policy
+    pub fn classify<C2>(self, classifier: C2) -> RetryLayer<P, Z, C2> {
+        RetryLayer {
+            policy: self.policy,
+            sleeper: self.sleeper,
+            classifier,
+            budget: self.budget,
+            stats: self.stats,
+        }
+    }
+
+    /// Only retry while `budget` has tokens left. Results that aren't retried refill it.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Record every decision into `stats`
+    pub fn stats(mut self, stats: &RetryStats) -> Self {
+        self.stats = Some(stats.clone());
+        self
+    }
+
+    /// Wrap `service` with retries
+    pub fn layer<S>(&self, service: S) -> RetryService<S, P, Z, C>
+    where
+        P: Clone,
+        Z: Clone,
+        C: Clone,
+    {
+        RetryService {
+            service,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service wrapped with retries by a [`RetryLayer`]
+#[derive(Debug, Clone)]
+pub struct RetryService<S, P, Z, C = UseShouldRetry> {
+    service: S,
+    layer: RetryLayer<P, Z, C>,
+}
+
+impl<S, P, Z, C> RetryService<S, P, Z, C> {
+    /// Call the service with clones of `request` until the result shouldn't be retried, or the
+    /// policy or budget gives up.
+    pub async fn call<Req, Fut>(&mut self, request: Req) -> Fut::Output
+    where
+        S: FnMut(Req) -> Fut,
+        Fut: Future,
+        Req: Clone,
+        P: RetryPolicy<Fut::Output> + Clone,
+        Z: RetrySleeper + Clone,
+        C: Classify<Fut::Output> + Clone,
+    {
+        let policy = Bundled::new(self.layer.clone());
+        let service = &mut self.service;
+        retry_with_sleeper(policy, self.layer.sleeper.clone(), || {
+            service(request.clone())
+        })
+        .await
+    }
+
+    /// The wrapped service
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+/// The policy of one call: the layer's policy behind its budget, recorded into its stats
+enum Bundled<P, C> {
+    Recorded(Stats<Budgeted<P, Option<RetryBudget>, C>, C>),
+    Unrecorded(Budgeted<P, Option<RetryBudget>, C>),
+}
+
+impl<P, C: Clone> Bundled<P, C> {
+    fn new<Z>(layer: RetryLayer<P, Z, C>) -> Self {
+        let budgeted = Budgeted::new(layer.policy, layer.budget).retry_if(layer.classifier.clone());
+        match layer.stats {
+            Some(stats) => Self::Recorded(Stats::new(budgeted, &stats).retry_if(layer.classifier)),
+            None => Self::Unrecorded(budgeted),
+        }
+    }
+}
+
+impl<P, C, R> RetryPolicy<R> for Bundled<P, C>
+where
+    P: RetryPolicy<R>,
+    C: Classify<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        match self {
+            Self::Recorded(policy) => policy.should_retry(result),
+            Self::Unrecorded(policy) => policy.should_retry(result),
+        }
+    }
+}
+
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+mod tower {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{ready, Context, Poll},
+    };
+
+    use pin_project::pin_project;
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    use super::{Bundled, RetryLayer, RetryService};
+    use crate::{Classify, RetryDriver, RetryPolicy, RetrySleeper, Step};
+
+    impl<S, P: Clone, Z: Clone, C: Clone> Layer<S> for RetryLayer<P, Z, C> {
+        type Service = RetryService<S, P, Z, C>;
+
+        fn layer(&self, service: S) -> Self::Service {
+            RetryLayer::layer(self, service)
+        }
+    }
+
+    /// Calls the inner service with clones of the request until the result shouldn't be retried,
+    /// or the policy or budget gives up. Errors from `poll_ready` end the retries straight away.
+    impl<S, Req, P, Z, C> Service<Req> for RetryService<S, P, Z, C>
+    where
+        S: Service<Req> + Clone,
+        Req: Clone,
+        P: RetryPolicy<Result<S::Response, S::Error>> + Clone,
+        Z: RetrySleeper + Clone,
+        C: Classify<Result<S::Response, S::Error>> + Clone,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = ResponseFuture<S, Req, P, Z, C>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+            self.service.poll_ready(cx)
+        }
+
+        fn call(&mut self, request: Req) -> Self::Future {
+            // the service that was polled ready makes the first attempt
+            let clone = self.service.clone();
+            let mut service = std::mem::replace(&mut self.service, clone);
+            let attempt = service.call(request.clone());
+            ResponseFuture {
+                service,
+                request,
+                driver: RetryDriver::new(Bundled::new(self.layer.clone())),
+                sleeper: self.layer.sleeper.clone(),
+                state: State::Attempting(attempt),
+            }
+        }
+    }
+
+    /// [`Future`] returned by [`RetryService`] as a `tower::Service`
+    #[pin_project]
+    pub struct ResponseFuture<S: Service<Req>, Req, P, Z: RetrySleeper, C> {
+        service: S,
+        request: Req,
+        driver: RetryDriver<Bundled<P, C>>,
+        sleeper: Z,
+        #[pin]
+        state: State<S::Future, Z::Sleep>,
+    }
+
+    impl<S: Service<Req>, Req, P, Z: RetrySleeper, C> std::fmt::Debug
+        for ResponseFuture<S, Req, P, Z, C>
+    {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("ResponseFuture")
+                .field("attempts", &self.driver.attempts())
+                .finish_non_exhaustive()
+        }
+    }
+
+    #[pin_project(project = StateProj)]
+    enum State<Fut, Sleep> {
+        Attempting(#[pin] Fut),
+        Sleeping(#[pin] Sleep),
+        Waiting,
+    }
+
+    impl<S, Req, P, Z, C> Future for ResponseFuture<S, Req, P, Z, C>
+    where
+        S: Service<Req>,
+        Req: Clone,
+        P: RetryPolicy<Result<S::Response, S::Error>>,
+        Z: RetrySleeper,
+        C: Classify<Result<S::Response, S::Error>>,
+    {
+        type Output = Result<S::Response, S::Error>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let mut this = self.project();
+            loop {
+                match this.state.as_mut().project() {
+                    StateProj::Attempting(fut) => {
+                        let result = ready!(fut.poll(cx));
+                        match this.driver.on_result(result) {
+                            Step::Sleep(delay) => {
+                                let sleep = this.sleeper.sleep(delay, this.driver.attempts());
+                                this.state.set(State::Sleeping(sleep));
+                            }
+                            Step::Finished(result) => return Poll::Ready(result),
+                        }
+                    }
+                    StateProj::Sleeping(sleep) => {
+                        ready!(sleep.poll(cx));
+                        this.state.set(State::Waiting);
+                    }
+                    StateProj::Waiting => {
+                        ready!(this.service.poll_ready(cx))?;
+                        let attempt = this.service.call(this.request.clone());
+                        this.state.set(State::Attempting(attempt));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tower")]
+pub use self::tower::ResponseFuture;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryLayer;
+    use crate::{budget::RetryBudget, iter::Iter, stats::RetryStats, RetryPolicyBuilder};
+
+    #[tokio::test(start_paused = true)]
+    async fn budget_is_shared_between_services() {
+        let budget = RetryBudget::new(2);
+        let stats = RetryStats::new(Duration::from_secs(60), 1);
+        let layer = RetryLayer::new(Iter::new([Duration::from_secs(1); 5]), tokio::time::sleep)
+            .budget(budget.clone())
+            .stats(&stats);
+
+        let mut a = layer.layer(|_: ()| async { None::<()> });
+        let mut b = layer.layer(|_: ()| async { None::<()> });
+        assert_eq!(a.call(()).await, None);
+        assert_eq!(b.call(()).await, None);
+
+        assert_eq!(budget.available(), 0);
+        let total = stats.snapshot().total;
+        assert_eq!(total.attempts, 4);
+        assert_eq!(total.exhaustions, 2);
+        assert_eq!(total.total_delay, Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn classifier_decides_refills() {
+        let budget = RetryBudget::new(2);
+        // the policy retries anything, leaving the decision to the layer's classifier
+        let policy = RetryPolicyBuilder::fixed(Duration::from_secs(1))
+            .retry_if(|_: &Option<u32>| true)
+            .build();
+        let layer = RetryLayer::new(policy, tokio::time::sleep)
+            .classify(|res: &Option<u32>| res == &Some(1))
+            .budget(budget.clone());
+
+        let mut attempts = 0;
+        let mut service = layer.layer(|_: ()| {
+            attempts += 1;
+            async move { Some(attempts) }
+        });
+        assert_eq!(service.call(()).await, Some(2));
+        // one token was taken for the retry, and the final attempt put it back
+        assert_eq!(budget.available(), 2);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test(start_paused = true)]
+    async fn tower_service() {
+        use std::{
+            future::{poll_fn, ready, Ready},
+            sync::{
+                atomic::{AtomicU32, Ordering},
+                Arc,
+            },
+            task::{Context, Poll},
+        };
+
+        use tower_layer::Layer;
+        use tower_service::Service;
+
+        /// Fails the first two calls, and counts how often it was polled ready
+        #[derive(Clone, Default)]
+        struct Flaky {
+            calls: Arc<AtomicU32>,
+            readied: Arc<AtomicU32>,
+        }
+
+        impl Service<u32> for Flaky {
+            type Response = u32;
+            type Error = Option<()>;
+            type Future = Ready<Result<u32, Option<()>>>;
+
+            fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Option<()>>> {
+                self.readied.fetch_add(1, Ordering::SeqCst);
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, request: u32) -> Self::Future {
+                let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+                ready(if calls < 3 {
+                    Err(None)
+                } else {
+                    Ok(request * 2)
+                })
+            }
+        }
+
+        let stats = RetryStats::new(Duration::from_secs(60), 1);
+        let layer = RetryLayer::new(Iter::new([Duration::from_secs(1); 5]), tokio::time::sleep)
+            .stats(&stats);
+        let inner = Flaky::default();
+        let mut service = Layer::layer(&layer, inner.clone());
+
+        let start = tokio::time::Instant::now();
+        poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+        assert_eq!(Service::call(&mut service, 21).await, Ok(42));
+
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+        // once by the caller, then before each retry
+        assert_eq!(inner.readied.load(Ordering::SeqCst), 3);
+        assert_eq!(stats.snapshot().total.attempts, 3);
+    }
+}
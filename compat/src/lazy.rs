@@ -0,0 +1,162 @@
+#![cfg(feature = "tokio")]
+#![cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+//! Lazily initialised values, with retries.
+//!
+//! Clients are often built lazily on first use, by an initialisation that can fail, like fetching
+//! credentials or connecting. [`RetryingLazy`] retries that initialisation with a policy, keeps the
+//! value once it succeeds, and makes concurrent callers wait for the same initialisation instead
+//! of each running their own.
+
+use std::{fmt, future::Future, time::Duration};
+
+use tokio::sync::OnceCell;
+
+use crate::{retry, RetryPolicy};
+
+/// An async lazy cell, initialised by a retried fallible future.
+///
+/// Only one initialisation runs at a time. If it gives up with an error, that caller gets the
+/// error, and the next caller waiting, if any, starts a fresh initialisation.
+///
+/// ```
+/// use futures_retry_policies::{classified::Retryable, iter::Iter, lazy::RetryingLazy};
+/// use std::time::Duration;
+///
+/// struct Client;
+///
+/// async fn connect() -> Option<Client> {
+///     // connect to the service, which might not be up yet
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return None }
+///     Some(Client)
+/// }
+///
+/// async fn client(cell: &RetryingLazy<Client>) -> Option<&Client> {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let connect = || async { connect().await.ok_or(Retryable("not up yet")) };
+///     cell.get_or_try_init(policy, tokio::time::sleep, connect)
+///         .await
+///         .ok()
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let cell = RetryingLazy::new();
+///     // both callers share the one connection attempt
+///     let (a, b) = tokio::join!(client(&cell), client(&cell));
+///     assert!(a.is_some() && b.is_some());
+/// }
+/// ```
+pub struct RetryingLazy<T> {
+    cell: OnceCell<T>,
+}
+
+impl<T> RetryingLazy<T> {
+    /// Create an uninitialised cell
+    pub fn new() -> Self {
+        Self {
+            cell: OnceCell::new(),
+        }
+    }
+
+    /// Get the value, if it has been initialised
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+
+    /// Get the value, initialising it with `init` retried by `policy` if needed.
+    ///
+    /// The policy and sleeper are only used if this call runs the initialisation.
+    pub async fn get_or_try_init<Policy, Sleeper, Sleep, Init, Fut, E>(
+        &self,
+        policy: Policy,
+        sleeper: Sleeper,
+        init: Init,
+    ) -> Result<&T, E>
+    where
+        Policy: RetryPolicy<Result<T, E>>,
+        Sleeper: FnMut(Duration) -> Sleep,
+        Sleep: Future<Output = ()>,
+        Init: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.cell
+            .get_or_try_init(|| retry(policy, sleeper, init))
+            .await
+    }
+
+    /// Take the value out, leaving the cell uninitialised
+    pub fn take(&mut self) -> Option<T> {
+        self.cell.take()
+    }
+}
+
+impl<T> Default for RetryingLazy<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RetryingLazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryingLazy")
+            .field("value", &self.get())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use super::RetryingLazy;
+    use crate::{classified::Retryable, iter::Iter};
+
+    #[tokio::test(start_paused = true)]
+    async fn coalesces_callers() {
+        let cell = RetryingLazy::new();
+        let attempts = AtomicU32::new(0);
+        let get = || {
+            cell.get_or_try_init(
+                Iter::new([Duration::from_secs(1); 3]),
+                tokio::time::sleep,
+                || async {
+                    match attempts.fetch_add(1, Ordering::SeqCst) {
+                        0 | 1 => Err(Retryable(())),
+                        n => Ok(n),
+                    }
+                },
+            )
+        };
+
+        let (a, b, c) = tokio::join!(get(), get(), get());
+        assert_eq!((a, b, c), (Ok(&2), Ok(&2), Ok(&2)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // memoized from now on
+        assert_eq!(get().await, Ok(&2));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_again_after_giving_up() {
+        let cell = RetryingLazy::<u32>::new();
+        let policy = || Iter::new([Duration::ZERO]);
+        let sleep = |_| async {};
+
+        assert_eq!(
+            cell.get_or_try_init(policy(), sleep, || async { Err(Retryable("down")) })
+                .await,
+            Err(Retryable("down"))
+        );
+        assert_eq!(cell.get(), None);
+        assert_eq!(
+            cell.get_or_try_init(policy(), sleep, || async { Ok::<_, Retryable<&str>>(1) })
+                .await,
+            Ok(&1)
+        );
+    }
+}
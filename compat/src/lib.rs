@@ -44,6 +44,8 @@
 //! Add the `tracing` feature and you can use the `Traced` RetryPolicy to automatically
 //! log your retries
 //!
+//! If you use the [`log`](::log) crate instead, add the `log` feature for the equivalent `Logged` RetryPolicy
+//!
 //! ## Tokio
 //!
 //! Add the `tokio` feature and you can use the convenience tokio retry methods to skip specifying
@@ -77,6 +79,11 @@
 //! }
 //! ```
 //!
+//! ## futures-timer
+//!
+//! If you're not using tokio, add the `futures-timer` feature and use the [`futures_timer`] module
+//! for the same convenience methods, backed by a runtime-agnostic timer.
+//!
 //! ## retry-policies
 //!
 //! This crate has first class support for the [`retry-policies crate`](::retry_policies)
@@ -104,14 +111,83 @@
 //! }
 //! ```
 
+pub mod absolute;
+pub mod adaptive_limit;
+pub mod attempt;
+pub mod backoff;
+pub mod blocking;
+pub mod budget;
+pub mod bulkhead;
+pub mod classified;
+pub mod clock;
+pub mod combine;
+pub mod consumer;
+pub mod context;
+pub mod cost;
+pub mod distributed;
+pub mod drain;
+pub mod escalate;
+pub mod failpoints;
 pub mod futures_retry;
+pub mod futures_timer;
+pub mod http;
+pub mod io;
 pub mod iter;
+pub mod keyed;
+pub mod layer;
+pub mod lazy;
+pub mod limits;
+pub mod log;
+pub mod map;
+pub mod multi;
+pub mod nested;
+pub mod opentelemetry;
+pub mod outcome;
+pub mod panic;
+pub mod poll;
+pub mod presets;
+pub mod prewarm;
+pub mod process;
+pub mod proptest;
+pub mod quorum;
+pub mod race;
+pub mod rate;
+pub mod readiness;
+pub mod recorder;
+pub mod reload;
+pub mod repair;
+pub mod resolve;
+pub mod resource;
 pub mod retry_policies;
+pub mod retry_policies_0_5;
+pub mod sample;
+pub mod scoped;
+pub mod serialized;
+pub mod shadow;
+pub mod sim;
+pub mod singleflight;
+pub mod slo;
+pub mod slow_start;
+pub mod spec;
+pub mod split;
+pub mod staged;
+pub mod stats;
+pub mod success_rate;
 pub mod sync;
+pub mod timer;
 pub mod tokio;
 pub mod tracing;
+pub mod tryhard;
+pub mod variants;
+pub mod verbose;
+pub mod wait;
+pub mod weighted;
 
-pub use futures_retry_policies_core::{retry, RetryFuture, RetryPolicy};
+pub use backoff::RetryPolicyBuilder;
+pub use futures_retry_policies_core::{
+    retry, retry_into, retry_with_sleeper, RetryDriver, RetryFuture, RetryPolicy, RetrySleeper,
+    Schedule, Step,
+};
 
 /// A simpler form of [`RetryPolicy`] that returns whether
 /// the value can be retried.
@@ -139,3 +215,217 @@ impl<T> ShouldRetry for Option<T> {
         self.is_none()
     }
 }
+
+impl<T> ShouldRetry for std::task::Poll<T> {
+    /// Should retry if Pending
+    fn should_retry(&self, _: u32) -> bool {
+        self.is_pending()
+    }
+}
+
+impl<G> ShouldRetry for std::sync::TryLockError<G> {
+    /// Should retry if the lock was held, but not if it was poisoned
+    fn should_retry(&self, _: u32) -> bool {
+        matches!(self, std::sync::TryLockError::WouldBlock)
+    }
+}
+
+impl<T> ShouldRetry for std::sync::mpsc::TrySendError<T> {
+    /// Should retry if the channel was full, but not if it was disconnected
+    fn should_retry(&self, _: u32) -> bool {
+        matches!(self, std::sync::mpsc::TrySendError::Full(_))
+    }
+}
+
+impl ShouldRetry for std::sync::mpsc::TryRecvError {
+    /// Should retry if the channel was empty, but not if it was disconnected
+    fn should_retry(&self, _: u32) -> bool {
+        matches!(self, std::sync::mpsc::TryRecvError::Empty)
+    }
+}
+
+/// Decides whether a result should be retried, separately from the result itself.
+///
+/// This is implemented for closures taking a reference to the result, and for
+/// [`UseShouldRetry`] which defers to the result's [`ShouldRetry`] impl.
+pub trait Classify<R> {
+    /// Whether the result should be re-attempted.
+    /// `attempts` denotes how many prior attempts have been made (starts at 1).
+    fn classify(&mut self, result: &R, attempts: u32) -> bool;
+}
+
+impl<R, F: FnMut(&R) -> bool> Classify<R> for F {
+    fn classify(&mut self, result: &R, _: u32) -> bool {
+        self(result)
+    }
+}
+
+/// A [`Classify`] made from a closure that also takes the attempt number.
+///
+/// Plain closures taking `&R` already classify results. `ClassifyFn` is for classifiers that
+/// also care about the attempt, or keep state between attempts.
+///
+/// ```
+/// use futures_retry_policies::{tokio::RetryFutureExt, ClassifyFn, RetryPolicyBuilder};
+/// use std::time::Duration;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Error { Timeout, Overloaded }
+///
+/// async fn make_request() -> Result<(), Error> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return Err(Error::Timeout) }
+///     Ok(())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // retry overloads for a while, but give up after two timeouts in a row
+///     let mut timeouts = 0;
+///     let classifier = ClassifyFn::new(move |res: &Result<(), Error>, attempt| match res {
+///         Ok(()) => false,
+///         Err(Error::Timeout) => {
+///             timeouts += 1;
+///             timeouts < 2
+///         }
+///         Err(Error::Overloaded) => {
+///             timeouts = 0;
+///             attempt < 10
+///         }
+///     });
+///
+///     let policy = RetryPolicyBuilder::fixed(Duration::from_millis(10))
+///         .retry_if(classifier)
+///         .build();
+///     assert_eq!(make_request.retry(policy).await, Err(Error::Timeout));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ClassifyFn<F>(F);
+
+impl<F> ClassifyFn<F> {
+    /// Classify results with `f(result, attempts)`, where `attempts` starts at 1
+    pub fn new<R>(f: F) -> Self
+    where
+        F: FnMut(&R, u32) -> bool,
+    {
+        Self(f)
+    }
+}
+
+impl<R, F: FnMut(&R, u32) -> bool> Classify<R> for ClassifyFn<F> {
+    fn classify(&mut self, result: &R, attempts: u32) -> bool {
+        (self.0)(result, attempts)
+    }
+}
+
+/// A [`Classify`] that retries according to the result's [`ShouldRetry`] impl.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UseShouldRetry;
+
+impl<R: ShouldRetry> Classify<R> for UseShouldRetry {
+    fn classify(&mut self, result: &R, attempts: u32) -> bool {
+        result.should_retry(attempts)
+    }
+}
+
+/// A [`Classify`] for polling-style APIs returning `Result<Option<T>, E>`, where `Ok(None)`
+/// means "not ready yet".
+///
+/// `Ok(None)` is retried, `Ok(Some(_))` is not, and errors are retried according to their
+/// [`ShouldRetry`] impl.
+///
+/// ```
+/// use futures_retry_policies::{tokio::RetryFutureExt, NotReady, RetryPolicyBuilder};
+/// use std::time::Duration;
+///
+/// #[derive(Debug)]
+/// struct Error;
+/// impl futures_retry_policies::ShouldRetry for Error {
+///     fn should_retry(&self, _: u32) -> bool { false }
+/// }
+///
+/// /// Returns the job's output once it has finished
+/// async fn job_output() -> Result<Option<String>, Error> {
+///     // check the job status
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return Ok(None) }
+///     Ok(Some("done".to_owned()))
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = RetryPolicyBuilder::fixed(Duration::from_millis(10))
+///         .max_retries(5)
+///         .retry_if(NotReady)
+///         .build();
+///     let output = job_output.retry(policy).await.unwrap();
+///     assert_eq!(output.as_deref(), Some("done"));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotReady;
+
+impl<T, E: ShouldRetry> Classify<Result<Option<T>, E>> for NotReady {
+    fn classify(&mut self, result: &Result<Option<T>, E>, attempts: u32) -> bool {
+        match result {
+            Ok(value) => value.is_none(),
+            Err(err) => err.should_retry(attempts),
+        }
+    }
+}
+
+impl<T, E: ShouldRetry> Classify<std::task::Poll<Result<T, E>>> for NotReady {
+    /// Retries `Pending`, and errors according to their [`ShouldRetry`] impl
+    fn classify(&mut self, result: &std::task::Poll<Result<T, E>>, attempts: u32) -> bool {
+        match result {
+            std::task::Poll::Pending => true,
+            std::task::Poll::Ready(result) => result.should_retry(attempts),
+        }
+    }
+}
+
+/// Create a [`Backoff`](backoff::Backoff) policy, checking its settings at compile time.
+///
+/// The settings are the same as the [`RetryPolicyBuilder`]'s, and must be constants:
+/// `base` (required), `factor`, `max_delay`, `max_attempts` and `max_elapsed`. Obviously broken
+/// settings, like a `max_delay` below the `base` delay or no attempts at all, fail to compile.
+/// The policy can be used to initialise a `const`.
+///
+/// ```
+/// use futures_retry_policies::{backoff::Backoff, static_policy, RetryPolicy};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// const POLICY: Backoff = static_policy! {
+///     base: Duration::from_millis(100),
+///     factor: 3.0,
+///     max_delay: Duration::from_secs(1),
+///     max_attempts: 4,
+/// };
+///
+/// let delays: Vec<_> = POLICY.preview(10).collect();
+/// assert_eq!(delays, [100, 300, 900].map(Duration::from_millis));
+/// ```
+///
+/// ```compile_fail
+/// # use futures_retry_policies::{backoff::Backoff, static_policy};
+/// # use std::time::Duration;
+/// // max_delay is below the base delay
+/// const POLICY: Backoff = static_policy! {
+///     base: Duration::from_secs(10),
+///     max_delay: Duration::from_secs(1),
+/// };
+/// ```
+#[macro_export]
+macro_rules! static_policy {
+    ($($field:ident : $value:expr),* $(,)?) => {{
+        const CONFIG: $crate::backoff::StaticConfig = {
+            #[allow(unused_mut)]
+            let mut config = $crate::backoff::StaticConfig::EMPTY;
+            $(config.$field = ::core::option::Option::Some($value);)*
+            config.validate()
+        };
+        CONFIG.build()
+    }};
+}
@@ -0,0 +1,233 @@
+//! Defensive limits that can be wrapped around any policy.
+//!
+//! These are useful when the inner policy comes from configuration or third-party code,
+//! and can't be fully trusted to behave.
+
+use std::{
+    ops::ControlFlow,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    clock::{SystemClock, WallClock},
+    RetryPolicy,
+};
+
+/// A [`RetryPolicy`] that stops after a maximum number of attempts, whatever the inner policy says.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, limits::MaxAttempts, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     None
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // this policy never stops retrying on its own
+///     let forever = Iter::new(std::iter::repeat(Duration::from_millis(1)));
+///     assert_eq!(make_request.retry(MaxAttempts::new(forever, 3)).await, None);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MaxAttempts<P> {
+    policy: P,
+    max_attempts: u32,
+    attempts: u32,
+}
+
+impl<P> MaxAttempts<P> {
+    /// Make at most `max_attempts` attempts, including the first
+    pub fn new(policy: P, max_attempts: u32) -> Self {
+        Self {
+            policy,
+            max_attempts,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, R> RetryPolicy<R> for MaxAttempts<P>
+where
+    P: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        if self.attempts >= self.max_attempts {
+            return ControlFlow::Break(result);
+        }
+        self.policy.should_retry(result)
+    }
+}
+
+/// A [`RetryPolicy`] that keeps the inner policy's delays within bounds.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, limits::Clamp, RetryPolicy};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// // delays from configuration, which may be far too small or large
+/// let configured = Iter::new([Duration::ZERO, Duration::from_secs(3600)]);
+/// let mut policy = Clamp::new(configured, Duration::from_millis(50), Duration::from_secs(30));
+///
+/// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Continue(Duration::from_millis(50)));
+/// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Continue(Duration::from_secs(30)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Clamp<P> {
+    policy: P,
+    min: Duration,
+    max: Duration,
+}
+
+impl<P> Clamp<P> {
+    /// Never wait less than `min`, or more than `max` between retries.
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn new(policy: P, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "Clamp minimum must not exceed the maximum");
+        Self { policy, min, max }
+    }
+}
+
+impl<P, R> RetryPolicy<R> for Clamp<P>
+where
+    P: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let delay = self.policy.should_retry(result)?;
+        ControlFlow::Continue(delay.clamp(self.min, self.max))
+    }
+}
+
+/// A [`RetryPolicy`] that stops retrying once a wall-clock deadline has passed.
+///
+/// This is for deadlines on the calendar, like the end of a maintenance window. For deadlines
+/// relative to the first attempt, use [`max_elapsed`](crate::RetryPolicyBuilder::max_elapsed),
+/// which isn't affected by changes to the system time.
+///
+/// The deadline is checked as each attempt finishes, so a retry scheduled shortly before the
+/// deadline can still start after it. Wrap the inner policy in a [`Clamp`] to bound how late that is.
+/// The deadline can be anything that converts into a [`SystemTime`], including a `chrono::DateTime`.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, limits::Until, RetryPolicy};
+/// use std::{ops::ControlFlow, time::{Duration, SystemTime}};
+///
+/// let policy = Iter::new(std::iter::repeat(Duration::from_secs(60)));
+/// let window_ends = SystemTime::now() + Duration::from_secs(90);
+/// let mut policy = Until::new(policy, window_ends);
+///
+/// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Continue(Duration::from_secs(60)));
+/// # let mut policy = Until::new(Iter::new(std::iter::repeat(Duration::from_secs(60))), SystemTime::now());
+/// // ...once the window has ended
+/// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Until<P, K = SystemClock> {
+    policy: P,
+    deadline: SystemTime,
+    clock: K,
+}
+
+impl<P> Until<P> {
+    /// Retry with `policy` until `deadline`
+    pub fn new(policy: P, deadline: impl Into<SystemTime>) -> Self {
+        Self {
+            policy,
+            deadline: deadline.into(),
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<P, K> Until<P, K> {
+    /// Read the time from `clock`
+    pub fn with_clock<K2: WallClock>(self, clock: K2) -> Until<P, K2> {
+        Until {
+            policy: self.policy,
+            deadline: self.deadline,
+            clock,
+        }
+    }
+}
+
+impl<P, K, R> RetryPolicy<R> for Until<P, K>
+where
+    P: RetryPolicy<R>,
+    K: WallClock,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        if self.clock.now() >= self.deadline {
+            return ControlFlow::Break(result);
+        }
+        self.policy.should_retry(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::{Clamp, MaxAttempts, Until};
+    use crate::{
+        clock::{MockClock, WallClock},
+        iter::Iter,
+        RetryPolicy,
+    };
+
+    #[test]
+    fn caps_attempts() {
+        let forever = Iter::new(std::iter::repeat(Duration::ZERO));
+        let mut policy = MaxAttempts::new(forever, 3);
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+    }
+
+    #[test]
+    fn inner_policy_can_stop_first() {
+        let mut policy = MaxAttempts::new(Iter::new([Duration::ZERO]), 3);
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+    }
+
+    #[test]
+    fn clamps_delays() {
+        let delays = [1, 100, 10_000].map(Duration::from_millis);
+        let mut policy = Clamp::new(
+            Iter::new(delays),
+            Duration::from_millis(50),
+            Duration::from_secs(1),
+        );
+        let mut clamped = vec![];
+        while let ControlFlow::Continue(delay) = policy.should_retry(None::<()>) {
+            clamped.push(delay);
+        }
+        assert_eq!(clamped, [50, 100, 1000].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn until_wall_clock_deadline() {
+        let clock = MockClock::new();
+        let deadline = WallClock::now(&clock) + Duration::from_secs(10);
+        let forever = Iter::new(std::iter::repeat(Duration::from_secs(4)));
+        let mut policy = Until::new(forever, deadline).with_clock(clock.clone());
+
+        let mut retries = 0;
+        while policy.should_retry(None::<()>).is_continue() {
+            retries += 1;
+            clock.advance(Duration::from_secs(4));
+        }
+        // retries at 0s, 4s and 8s, but gives up at 12s
+        assert_eq!(retries, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn min_above_max() {
+        Clamp::new((), Duration::from_secs(2), Duration::from_secs(1));
+    }
+}
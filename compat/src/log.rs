@@ -0,0 +1,289 @@
+#![cfg(feature = "log")]
+#![cfg_attr(docsrs, doc(cfg(feature = "log")))]
+//! Retry features for [`log`](::log) support
+
+use std::{fmt::Debug, ops::ControlFlow, time::Duration};
+
+use log::Level;
+
+use crate::{Classify, RetryPolicy, UseShouldRetry};
+
+/// A [`RetryPolicy`] that logs each retry, and how the retries ended.
+///
+/// Giving up on a result that [should be retried](crate::ShouldRetry), or that the classifier
+/// set with [`retry_if`](Logged::retry_if) accepts, is logged as an exhaustion at the retry
+/// level. Any other final result is logged at the [finished level](Logged::finished_level), if
+/// any retries were made. Results are only formatted if the line would be logged.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, log::Logged, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Logged::new(Iter::new([Duration::from_millis(10); 3]))
+///         .target("my_app::client")
+///         .level(log::Level::Info);
+///     make_request.retry(policy).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Logged<P, C = UseShouldRetry> {
+    policy: P,
+    classifier: C,
+    target: &'static str,
+    level: Level,
+    finished_level: Level,
+    attempts: u32,
+}
+
+impl<P> Logged<P> {
+    /// Log retries made by `policy` and exhaustions as warnings, and other final results as debug
+    pub fn new(policy: P) -> Self {
+        Self {
+            policy,
+            classifier: UseShouldRetry,
+            target: module_path!(),
+            level: Level::Warn,
+            finished_level: Level::Debug,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, C> Logged<P, C> {
+    /// Set the target to log with
+    pub fn target(mut self, target: &'static str) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Set the level to log retries and exhaustions at
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Set the level to log the final result at, once the policy stops retrying on a result that
+    /// shouldn't be retried
+    pub fn finished_level(mut self, level: Level) -> Self {
+        self.finished_level = level;
+        self
+    }
+
+    /// Decide which final results count as exhaustions with `classifier`
+    pub fn retry_if<F>(self, classifier: F) -> Logged<P, F> {
+        Logged {
+            policy: self.policy,
+            classifier,
+            target: self.target,
+            level: self.level,
+            finished_level: self.finished_level,
+            attempts: self.attempts,
+        }
+    }
+}
+
+impl<P, C, R> RetryPolicy<R> for Logged<P, C>
+where
+    P: RetryPolicy<R>,
+    C: Classify<R>,
+    R: Debug,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        let retryable = self.classifier.classify(&result, self.attempts);
+
+        // format the result before the policy takes it, but only if it'd be logged
+        let level = if retryable {
+            Some(self.level)
+        } else {
+            (self.attempts > 1).then_some(self.finished_level)
+        };
+        let res = level
+            .filter(|&level| log::log_enabled!(target: self.target, level))
+            .map(|_| format!("{result:?}"));
+
+        let flow = self.policy.should_retry(result);
+        let Some(res) = res else {
+            return flow;
+        };
+        match &flow {
+            ControlFlow::Continue(duration) => log::log!(
+                target: self.target,
+                self.level,
+                "waiting {duration:?} to retry request after attempt {}: {res}",
+                self.attempts
+            ),
+            ControlFlow::Break(_) if retryable => log::log!(
+                target: self.target,
+                self.level,
+                "gave up retrying request after {} attempts: {res}",
+                self.attempts
+            ),
+            ControlFlow::Break(_) => log::log!(
+                target: self.target,
+                self.finished_level,
+                "request finished after {} attempts: {res}",
+                self.attempts
+            ),
+        }
+        flow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Mutex, Once,
+        },
+        time::Duration,
+    };
+
+    use log::{Level, Log, Metadata, Record};
+
+    use super::Logged;
+    use crate::{iter::Iter, RetryPolicy, RetryPolicyBuilder, ShouldRetry};
+
+    /// Keeps every line logged to a target starting with `test::`, and ignores the rest
+    struct TestLogger(Mutex<Vec<(String, Level, String)>>);
+
+    impl Log for TestLogger {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target().starts_with("test::")
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push((
+                    record.target().to_owned(),
+                    record.level(),
+                    record.args().to_string(),
+                ));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+
+    /// The lines logged to `target`, which should be unique to the test
+    fn logged(target: &str) -> Vec<(Level, String)> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+        LOGGER
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(t, ..)| t == target)
+            .map(|(_, level, line)| (*level, line.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn logs_exhaustion() {
+        logged("test::exhaustion");
+        let mut policy =
+            Logged::new(Iter::new([Duration::from_secs(1)])).target("test::exhaustion");
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert!(policy.should_retry(None::<()>).is_break());
+
+        assert_eq!(
+            logged("test::exhaustion"),
+            [
+                (
+                    Level::Warn,
+                    "waiting 1s to retry request after attempt 1: None".to_owned()
+                ),
+                (
+                    Level::Warn,
+                    "gave up retrying request after 2 attempts: None".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn logs_success_after_retries() {
+        logged("test::success");
+        let policy = || {
+            Logged::new(Iter::new([Duration::from_secs(1)]))
+                .target("test::success")
+                .finished_level(Level::Info)
+        };
+
+        // nothing to say about a first attempt that succeeds
+        assert!(policy().should_retry(Some(())).is_break());
+        assert_eq!(logged("test::success"), []);
+
+        let mut policy = policy();
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert!(policy.should_retry(Some(())).is_break());
+        assert_eq!(
+            logged("test::success")[1],
+            (
+                Level::Info,
+                "request finished after 2 attempts: Some(())".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn classifies_exhaustion() {
+        logged("test::classified");
+        // the policy retries anything, leaving exhaustion to the classifier
+        let policy = RetryPolicyBuilder::fixed(Duration::from_secs(1))
+            .retry_if(|_: &Result<(), &str>| true)
+            .max_retries(1)
+            .build();
+        let mut policy = Logged::new(policy)
+            .target("test::classified")
+            .retry_if(|res: &Result<(), &str>| res.is_err());
+        assert!(policy.should_retry(Err("busy")).is_continue());
+        assert!(policy.should_retry(Err("busy")).is_break());
+        assert_eq!(
+            logged("test::classified")[1],
+            (
+                Level::Warn,
+                "gave up retrying request after 2 attempts: Err(\"busy\")".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn formats_only_when_enabled() {
+        struct Counted<'a>(&'a AtomicU32);
+        impl fmt::Debug for Counted<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                f.write_str("Counted")
+            }
+        }
+        impl ShouldRetry for Counted<'_> {
+            fn should_retry(&self, _: u32) -> bool {
+                true
+            }
+        }
+
+        logged("disabled");
+        let formatted = AtomicU32::new(0);
+        let mut policy = Logged::new(Iter::new([Duration::from_secs(1)])).target("disabled");
+        assert!(policy.should_retry(Counted(&formatted)).is_continue());
+        assert!(policy.should_retry(Counted(&formatted)).is_break());
+        assert_eq!(formatted.load(Ordering::SeqCst), 0);
+    }
+}
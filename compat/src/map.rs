@@ -0,0 +1,110 @@
+//! Transform the final result of a policy before [`retry`](crate::retry) returns it.
+//!
+//! This is handy for enriching errors with details only the retry loop knows, like how many
+//! attempts were made, without matching on the result at every call site.
+//!
+//! ```
+//! use futures_retry_policies::{map::map_break, tokio::RetryFutureExt, RetryPolicyBuilder};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, PartialEq)]
+//! struct Error {
+//!     attempts: u32,
+//! }
+//!
+//! async fn make_request() -> Result<(), Error> {
+//!     // make a request
+//!     Err(Error { attempts: 0 })
+//! }
+//!
+//! #[tokio::main(flavor = "current_thread", start_paused = true)]
+//! async fn main() {
+//!     let backoff = RetryPolicyBuilder::fixed(Duration::from_millis(10))
+//!         .max_retries(2)
+//!         .retry_if(|res: &Result<(), Error>| res.is_err())
+//!         .build();
+//!     let policy = map_break(backoff, |res: Result<(), Error>, attempts| {
+//!         res.map_err(|_| Error { attempts })
+//!     });
+//!
+//!     assert_eq!(make_request.retry(policy).await, Err(Error { attempts: 3 }));
+//! }
+//! ```
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// A [`RetryPolicy`] that transforms the result the inner policy gives up on.
+///
+/// Created by [`map_break`].
+#[derive(Debug, Clone)]
+pub struct MapBreak<P, F> {
+    policy: P,
+    f: F,
+    attempts: u32,
+}
+
+/// Wrap `policy` so that `f` is applied to the final result, along with the number of
+/// attempts made (starting at 1).
+///
+/// `f` is called exactly once, whether the final result was a success or the policy gave up.
+pub fn map_break<P, F, R>(policy: P, f: F) -> MapBreak<P, F>
+where
+    P: RetryPolicy<R>,
+    F: FnMut(R, u32) -> R,
+{
+    MapBreak {
+        policy,
+        f,
+        attempts: 0,
+    }
+}
+
+impl<P, F> MapBreak<P, F> {
+    /// Get the inner policy back
+    pub fn into_inner(self) -> P {
+        self.policy
+    }
+}
+
+impl<P, F, R> RetryPolicy<R> for MapBreak<P, F>
+where
+    P: RetryPolicy<R>,
+    F: FnMut(R, u32) -> R,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts = self.attempts.saturating_add(1);
+        match self.policy.should_retry(result) {
+            ControlFlow::Continue(delay) => ControlFlow::Continue(delay),
+            ControlFlow::Break(result) => ControlFlow::Break((self.f)(result, self.attempts)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::map_break;
+    use crate::{iter::Iter, RetryPolicy};
+
+    #[test]
+    fn maps_only_the_final_result() {
+        let mut policy = map_break(
+            Iter::new([Duration::ZERO, Duration::ZERO]),
+            |res: Option<u32>, attempts| res.or(Some(attempts)),
+        );
+        assert!(policy.should_retry(None).is_continue());
+        assert!(policy.should_retry(None).is_continue());
+        assert_eq!(policy.should_retry(None), ControlFlow::Break(Some(3)));
+    }
+
+    #[test]
+    fn maps_success() {
+        let mut policy = map_break(Iter::new([Duration::ZERO]), |res: Option<u32>, attempts| {
+            res.map(|n| n + attempts)
+        });
+        assert_eq!(policy.should_retry(Some(10)), ControlFlow::Break(Some(11)));
+    }
+}
@@ -0,0 +1,134 @@
+//! Classification of outputs that carry multiple errors, as returned by many batch APIs.
+//!
+//! `Vec<E>` retries if *any* of the errors should be retried. Wrap the errors in [`Any`] or
+//! [`All`] to be explicit about it, or to classify other collections.
+//!
+//! ```
+//! use futures_retry_policies::{multi::All, iter::Iter, tokio::RetryFutureExt, ShouldRetry};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! enum Error { Timeout, Invalid }
+//! impl ShouldRetry for Error {
+//!     fn should_retry(&self, _: u32) -> bool { matches!(self, Error::Timeout) }
+//! }
+//!
+//! async fn write_batch() -> Result<(), All<Vec<Error>>> {
+//!     // write a batch of records
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return Err(All(vec![Error::Timeout, Error::Timeout])) }
+//!     Ok(())
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     // only retry if every record in the batch failed with a retryable error
+//!     write_batch.retry(Iter::new([Duration::from_millis(10); 3])).await.unwrap();
+//! }
+//! ```
+
+use crate::ShouldRetry;
+
+/// Retries if any of the contained values should be retried.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Any<C>(pub C);
+
+/// Retries if all of the contained values should be retried.
+///
+/// An empty collection is not retried.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct All<C>(pub C);
+
+impl<C, E> ShouldRetry for Any<C>
+where
+    for<'a> &'a C: IntoIterator<Item = &'a E>,
+    E: ShouldRetry,
+{
+    fn should_retry(&self, attempts: u32) -> bool {
+        self.0.into_iter().any(|e| e.should_retry(attempts))
+    }
+}
+
+impl<C, E> ShouldRetry for All<C>
+where
+    for<'a> &'a C: IntoIterator<Item = &'a E>,
+    E: ShouldRetry,
+{
+    fn should_retry(&self, attempts: u32) -> bool {
+        let mut errors = self.0.into_iter().peekable();
+        errors.peek().is_some() && errors.all(|e| e.should_retry(attempts))
+    }
+}
+
+impl<E: ShouldRetry> ShouldRetry for Vec<E> {
+    /// Should retry if any of the errors should retry
+    fn should_retry(&self, attempts: u32) -> bool {
+        self.iter().any(|e| e.should_retry(attempts))
+    }
+}
+
+/// Iterates over only the errors that should be retried.
+///
+/// Useful for deciding which parts of a batch to submit in the next attempt.
+pub fn retryable<'a, E: ShouldRetry + 'a>(
+    errors: impl IntoIterator<Item = &'a E>,
+    attempts: u32,
+) -> impl Iterator<Item = &'a E> {
+    errors.into_iter().filter(move |e| e.should_retry(attempts))
+}
+
+/// Splits the errors into those that should be retried, and those that should not.
+pub fn partition<E: ShouldRetry>(
+    errors: impl IntoIterator<Item = E>,
+    attempts: u32,
+) -> (Vec<E>, Vec<E>) {
+    errors.into_iter().partition(|e| e.should_retry(attempts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{partition, retryable, All, Any};
+    use crate::ShouldRetry;
+
+    #[derive(Debug, PartialEq)]
+    struct Error(bool);
+    impl ShouldRetry for Error {
+        fn should_retry(&self, _: u32) -> bool {
+            self.0
+        }
+    }
+
+    #[test]
+    fn any() {
+        assert!(Any(vec![Error(false), Error(true)]).should_retry(1));
+        assert!(!Any(vec![Error(false), Error(false)]).should_retry(1));
+        assert!(!Any(Vec::<Error>::new()).should_retry(1));
+        assert!(vec![Error(false), Error(true)].should_retry(1));
+    }
+
+    #[test]
+    fn all() {
+        assert!(All([Error(true), Error(true)]).should_retry(1));
+        assert!(!All([Error(false), Error(true)]).should_retry(1));
+        assert!(!All(Vec::<Error>::new()).should_retry(1));
+    }
+
+    #[test]
+    fn result_of_errors() {
+        let res: Result<(), Vec<Error>> = Err(vec![Error(false), Error(true)]);
+        assert!(res.should_retry(1));
+
+        let res: Result<(), All<Vec<Error>>> = Err(All(vec![Error(false), Error(true)]));
+        assert!(!res.should_retry(1));
+    }
+
+    #[test]
+    fn filtering() {
+        let errors = vec![Error(false), Error(true), Error(true)];
+        assert_eq!(retryable(&errors, 1).count(), 2);
+
+        let (retry, fatal) = partition(errors, 1);
+        assert_eq!(retry, [Error(true), Error(true)]);
+        assert_eq!(fatal, [Error(false)]);
+    }
+}
@@ -0,0 +1,176 @@
+//! Guarding against retry loops inside retry loops.
+//!
+//! When a retried call is itself made up of retried calls, the retries multiply: 5 outer
+//! attempts of 5 inner attempts each is 25 requests to a dependency that is probably
+//! already struggling. [`retry_guarded`] marks its attempts, so that guarded retry loops
+//! nested inside them can notice, and warn or stop retrying.
+//!
+//! The marker is only set while the attempt is being polled on the current thread, so
+//! work that an attempt spawns onto other tasks isn't considered nested.
+
+use std::{cell::Cell, future::Future, ops::ControlFlow, pin::pin, task::Poll, time::Duration};
+
+use crate::RetryPolicy;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Marks the current thread as running a guarded attempt, until dropped
+struct Enter;
+
+impl Enter {
+    fn new() -> Self {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Enter
+    }
+}
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Whether this is being polled inside an attempt of a guarded retry loop
+pub fn is_nested() -> bool {
+    DEPTH.with(Cell::get) > 0
+}
+
+/// What a guarded retry loop does when it's nested inside another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnNested {
+    /// Log a warning with [`tracing`](::tracing), if enabled, and retry as normal
+    #[default]
+    Warn,
+    /// Log a warning with [`tracing`](::tracing), if enabled, and only make a single attempt,
+    /// leaving the retries to the outer loop
+    AttemptOnce,
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// checking whether it's nested inside another guarded retry loop.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, nested::{retry_guarded, OnNested}};
+/// use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+///
+/// static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+///
+/// async fn make_request() -> Option<()> {
+///     REQUESTS.fetch_add(1, Ordering::SeqCst);
+///     None
+/// }
+///
+/// async fn client_call() -> Option<()> {
+///     let policy = Iter::new([Duration::from_millis(10); 2]);
+///     retry_guarded(OnNested::AttemptOnce, policy, tokio::time::sleep, make_request).await
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 2]);
+///     retry_guarded(OnNested::AttemptOnce, policy, tokio::time::sleep, client_call).await;
+///     // the inner loop didn't retry, so 3 requests were made rather than 9
+///     assert_eq!(REQUESTS.load(Ordering::SeqCst), 3);
+/// }
+/// ```
+pub async fn retry_guarded<Policy, Sleeper, Sleep, Futures, Fut>(
+    on_nested: OnNested,
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    if is_nested() {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("retry loop is nested inside another retry loop");
+        if on_nested == OnNested::AttemptOnce {
+            return guarded(futures()).await;
+        }
+    }
+
+    loop {
+        match policy.should_retry(guarded(futures()).await) {
+            ControlFlow::Continue(duration) => sleeper(duration).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+/// Shorthand for [`retry_guarded`] with [`OnNested::AttemptOnce`].
+///
+/// Only the outermost guarded retry loop retries, so the number of attempts is bounded by
+/// that loop's policy alone.
+pub async fn retry_outermost_only<Policy, Sleeper, Sleep, Futures, Fut>(
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    retry_guarded(OnNested::AttemptOnce, policy, sleeper, futures).await
+}
+
+/// Poll `fut`, marking the thread as inside a guarded attempt while doing so
+async fn guarded<Fut: Future>(fut: Fut) -> Fut::Output {
+    let mut fut = pin!(fut);
+    std::future::poll_fn(|cx| -> Poll<Fut::Output> {
+        let _enter = Enter::new();
+        fut.as_mut().poll(cx)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::{is_nested, retry_guarded, retry_outermost_only, OnNested};
+    use crate::iter::Iter;
+
+    async fn sleep(_: Duration) {}
+
+    fn policy() -> Iter<std::array::IntoIter<Duration, 2>> {
+        Iter::new([Duration::from_secs(1); 2])
+    }
+
+    #[tokio::test]
+    async fn warn_still_retries() {
+        let requests = Cell::new(0);
+        retry_guarded(OnNested::Warn, policy(), sleep, || {
+            retry_guarded(OnNested::Warn, policy(), sleep, || async {
+                requests.set(requests.get() + 1);
+                None::<()>
+            })
+        })
+        .await;
+        assert_eq!(requests.get(), 9);
+    }
+
+    #[tokio::test]
+    async fn outermost_only() {
+        let requests = Cell::new(0);
+        retry_outermost_only(policy(), sleep, || {
+            retry_outermost_only(policy(), sleep, || async {
+                assert!(is_nested());
+                requests.set(requests.get() + 1);
+                None::<()>
+            })
+        })
+        .await;
+        assert_eq!(requests.get(), 3);
+        assert!(!is_nested());
+    }
+}
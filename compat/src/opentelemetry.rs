@@ -0,0 +1,202 @@
+#![cfg(feature = "opentelemetry")]
+#![cfg_attr(docsrs, doc(cfg(feature = "opentelemetry")))]
+//! Retry features for [`opentelemetry`](::opentelemetry) metrics
+
+use std::{ops::ControlFlow, time::Duration};
+
+use opentelemetry::{
+    metrics::{Counter, Histogram, Meter},
+    KeyValue,
+};
+
+use crate::RetryPolicy;
+
+/// A [`RetryPolicy`] that records its retries with OpenTelemetry instruments.
+///
+/// Three instruments are created from the [`Meter`], named after the prefix:
+///
+/// * `{prefix}.retries`, a counter of the retries made.
+/// * `{prefix}.retry_delay`, a histogram of the delays before each retry, in seconds.
+/// * `{prefix}.attempts`, a histogram of how many attempts each call took, recorded once the policy stops retrying.
+///
+/// [`Metered::new`] uses the `retry` prefix. HTTP clients following the semantic conventions can use
+/// `http.client.request` to get `http.client.request.retries` and so on.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, opentelemetry::Metered, tokio::RetryFutureExt};
+/// use opentelemetry::KeyValue;
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let meter = opentelemetry::global::meter("my_app");
+///     let policy = Metered::with_prefix(Iter::new([Duration::from_millis(10); 3]), &meter, "http.client.request")
+///         .attributes([KeyValue::new("server.address", "example.com")]);
+///     make_request.retry(policy).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Metered<P> {
+    policy: P,
+    retries: Counter<u64>,
+    delay: Histogram<f64>,
+    attempts: Histogram<u64>,
+    attributes: Vec<KeyValue>,
+    count: u64,
+}
+
+impl<P> Metered<P> {
+    /// Record the retries made by `policy` with instruments prefixed by `retry`
+    pub fn new(policy: P, meter: &Meter) -> Self {
+        Self::with_prefix(policy, meter, "retry")
+    }
+
+    /// Record the retries made by `policy` with instruments named after `prefix`
+    pub fn with_prefix(policy: P, meter: &Meter, prefix: &str) -> Self {
+        Self {
+            policy,
+            retries: meter
+                .u64_counter(format!("{prefix}.retries"))
+                .with_description("Number of retries made")
+                .with_unit("{retry}")
+                .build(),
+            delay: meter
+                .f64_histogram(format!("{prefix}.retry_delay"))
+                .with_description("Time waited before each retry")
+                .with_unit("s")
+                .build(),
+            attempts: meter
+                .u64_histogram(format!("{prefix}.attempts"))
+                .with_description("Number of attempts made per call")
+                .with_unit("{attempt}")
+                .build(),
+            attributes: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Set the attributes to record with every measurement
+    pub fn attributes(mut self, attributes: impl IntoIterator<Item = KeyValue>) -> Self {
+        self.attributes = attributes.into_iter().collect();
+        self
+    }
+}
+
+impl<P, R> RetryPolicy<R> for Metered<P>
+where
+    P: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.count += 1;
+        match self.policy.should_retry(result) {
+            ControlFlow::Continue(duration) => {
+                self.retries.add(1, &self.attributes);
+                self.delay.record(duration.as_secs_f64(), &self.attributes);
+                ControlFlow::Continue(duration)
+            }
+            ControlFlow::Break(result) => {
+                self.attempts.record(self.count, &self.attributes);
+                ControlFlow::Break(result)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use opentelemetry::{metrics::MeterProvider, KeyValue};
+    use opentelemetry_sdk::metrics::{
+        data::{AggregatedMetrics, MetricData, ResourceMetrics},
+        InMemoryMetricExporter, PeriodicReader, SdkMeterProvider,
+    };
+
+    use super::Metered;
+    use crate::{iter::Iter, retry, RetryPolicy};
+
+    /// The metrics exported so far
+    fn collect(
+        provider: &SdkMeterProvider,
+        exporter: &InMemoryMetricExporter,
+    ) -> Vec<ResourceMetrics> {
+        provider.force_flush().unwrap();
+        exporter.get_finished_metrics().unwrap()
+    }
+
+    #[test]
+    fn records_semantic_convention_metrics() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let meter = provider.meter("test");
+        let server = KeyValue::new("server.address", "example.com");
+
+        let mut policy = Metered::with_prefix(
+            Iter::new([Duration::from_millis(500); 2]),
+            &meter,
+            "http.client.request",
+        )
+        .attributes([server.clone()]);
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+
+        let metrics = collect(&provider, &exporter);
+        let metric = |name: &str| {
+            metrics
+                .iter()
+                .flat_map(|resource| resource.scope_metrics())
+                .flat_map(|scope| scope.metrics())
+                .find(|metric| metric.name() == name)
+                .unwrap_or_else(|| panic!("{name} wasn't recorded"))
+        };
+
+        let retries = metric("http.client.request.retries");
+        assert_eq!(retries.unit(), "{retry}");
+        let AggregatedMetrics::U64(MetricData::Sum(retries)) = retries.data() else {
+            panic!("retries should be a u64 counter");
+        };
+        let point = retries.data_points().next().unwrap();
+        assert_eq!(point.value(), 2);
+        assert_eq!(point.attributes().collect::<Vec<_>>(), [&server]);
+
+        let delay = metric("http.client.request.retry_delay");
+        assert_eq!(delay.unit(), "s");
+        let AggregatedMetrics::F64(MetricData::Histogram(delay)) = delay.data() else {
+            panic!("retry_delay should be a f64 histogram");
+        };
+        let point = delay.data_points().next().unwrap();
+        assert_eq!(point.count(), 2);
+        assert_eq!(point.sum(), 1.0);
+        assert_eq!(point.attributes().collect::<Vec<_>>(), [&server]);
+
+        let attempts = metric("http.client.request.attempts");
+        assert_eq!(attempts.unit(), "{attempt}");
+        let AggregatedMetrics::U64(MetricData::Histogram(attempts)) = attempts.data() else {
+            panic!("attempts should be a u64 histogram");
+        };
+        let point = attempts.data_points().next().unwrap();
+        assert_eq!(point.count(), 1);
+        assert_eq!(point.sum(), 3);
+        assert_eq!(point.attributes().collect::<Vec<_>>(), [&server]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn passes_through() {
+        let meter = opentelemetry::global::meter("test");
+        let policy = Metered::new(Iter::new([Duration::from_secs(1); 2]), &meter);
+
+        let start = tokio::time::Instant::now();
+        let res = retry(policy, tokio::time::sleep, || async { None::<()> }).await;
+        assert_eq!(res, None);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,498 @@
+//! Retries that report whether they ran out of attempts.
+//!
+//! [`retry`](crate::retry) returns the last result, whatever the reason that the policy stopped.
+//! [`try_retry`] also tells you whether the policy gave up on a result that still wanted to be retried,
+//! which is useful to alert on retry exhaustion specifically.
+//!
+//! ```
+//! use futures_retry_policies::{iter::Iter, outcome::{try_retry, RetryOutcome}, ShouldRetry};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! struct Unavailable;
+//! impl ShouldRetry for Unavailable {
+//!     fn should_retry(&self, _: u32) -> bool { true }
+//! }
+//!
+//! async fn make_request() -> Result<(), Unavailable>  {
+//!     // make a request
+//!     Err(Unavailable)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let policy = Iter::new([Duration::from_millis(10); 3]);
+//!     match try_retry(policy, tokio::time::sleep, make_request).await {
+//!         RetryOutcome::Succeeded(res) => println!("finished with {res:?}"),
+//!         RetryOutcome::GaveUp { last, attempts } => {
+//!             println!("gave up after {attempts} attempts, last saw {last:?}");
+//!             # assert_eq!(attempts, 4);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`retry_or_exhausted`] instead returns the last error as a [`RetryError`], which implements
+//! [`Error`], for code that propagates errors with `?`.
+
+use std::{error::Error, fmt, future::Future, ops::ControlFlow, time::Duration};
+
+use crate::{
+    clock::{Clock, StdClock},
+    retry, RetryPolicy, ShouldRetry,
+};
+
+/// The result of [`try_retry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryOutcome<R> {
+    /// The policy stopped on a result that did not need retrying.
+    ///
+    /// This includes errors that [should not be retried](ShouldRetry).
+    Succeeded(R),
+    /// The policy stopped on a result that should still have been retried.
+    GaveUp {
+        /// The result of the last attempt
+        last: R,
+        /// How many attempts were made
+        attempts: u32,
+    },
+}
+
+impl<R> RetryOutcome<R> {
+    /// Whether the policy gave up on a retryable result
+    pub fn gave_up(&self) -> bool {
+        matches!(self, RetryOutcome::GaveUp { .. })
+    }
+
+    /// Get the result of the last attempt
+    pub fn into_inner(self) -> R {
+        match self {
+            RetryOutcome::Succeeded(res) => res,
+            RetryOutcome::GaveUp { last, .. } => last,
+        }
+    }
+}
+
+/// Counts the attempts made under the inner policy
+struct Counted<P> {
+    policy: P,
+    attempts: u32,
+}
+
+impl<P: RetryPolicy<R>, R> RetryPolicy<R> for Counted<P> {
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        self.policy.should_retry(result)
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// reporting whether the policy gave up.
+///
+/// Whether the last result still wanted to be retried is decided by its [`ShouldRetry`] impl.
+pub async fn try_retry<Policy, Sleeper, Sleep, Futures, Fut>(
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> RetryOutcome<Fut::Output>
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    Fut::Output: ShouldRetry,
+{
+    let mut policy = Counted {
+        policy,
+        attempts: 0,
+    };
+    let last = retry(&mut policy, sleeper, futures).await;
+    if last.should_retry(policy.attempts) {
+        RetryOutcome::GaveUp {
+            last,
+            attempts: policy.attempts,
+        }
+    } else {
+        RetryOutcome::Succeeded(last)
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// calling `on_give_up` if the policy gives up on a result that should still be retried.
+///
+/// This is useful to send exhausted operations to a dead-letter store, or to raise an alert,
+/// as part of the retry itself. The last result is returned once the hook completes.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, outcome::retry_on_give_up};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     None
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let res = retry_on_give_up(policy, tokio::time::sleep, make_request, |_, attempts| async move {
+///         // send an alert
+///         eprintln!("request failed after {attempts} attempts");
+///     })
+///     .await;
+///     assert_eq!(res, None);
+/// }
+/// ```
+pub async fn retry_on_give_up<Policy, Sleeper, Sleep, Futures, Fut, GiveUp, GiveUpFut>(
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+    on_give_up: GiveUp,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    Fut::Output: ShouldRetry,
+    GiveUp: FnOnce(&Fut::Output, u32) -> GiveUpFut,
+    GiveUpFut: Future<Output = ()>,
+{
+    match try_retry(policy, sleeper, futures).await {
+        RetryOutcome::Succeeded(res) => res,
+        RetryOutcome::GaveUp { last, attempts } => {
+            on_give_up(&last, attempts).await;
+            last
+        }
+    }
+}
+
+/// The error returned by [`retry_or_exhausted`] when the policy gave up, wrapping the last error seen.
+///
+/// ```
+/// use futures_retry_policies::outcome::RetriesExhausted;
+/// use std::time::Duration;
+///
+/// let err = RetriesExhausted::new("connection refused", 5, Duration::from_millis(12_300));
+/// assert_eq!(err.to_string(), "gave up after 5 attempts over 12.3s");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetriesExhausted<E> {
+    error: E,
+    attempts: u32,
+    elapsed: Duration,
+}
+
+impl<E> RetriesExhausted<E> {
+    /// Record that `attempts` attempts were made over `elapsed`, the last failing with `error`
+    pub fn new(error: E, attempts: u32, elapsed: Duration) -> Self {
+        Self {
+            error,
+            attempts,
+            elapsed,
+        }
+    }
+
+    /// The error of the last attempt
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Get the error of the last attempt
+    pub fn into_error(self) -> E {
+        self.error
+    }
+
+    /// How many attempts were made
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// How long passed between starting the first attempt and the last attempt failing
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+impl<E> fmt::Display for RetriesExhausted<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.attempts == 1 { "" } else { "s" };
+        write!(
+            f,
+            "gave up after {} attempt{plural} over {:.1?}",
+            self.attempts, self.elapsed
+        )
+    }
+}
+
+impl<E: Error + 'static> Error for RetriesExhausted<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The error returned by [`retry_or_exhausted`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryError<E> {
+    /// The policy gave up on an error that [should still have been retried](ShouldRetry)
+    Exhausted(RetriesExhausted<E>),
+    /// The last error [shouldn't be retried](ShouldRetry), so the policy stopped on it
+    Fatal(E),
+}
+
+impl<E> RetryError<E> {
+    /// Whether the policy gave up on a retryable error
+    pub fn is_exhausted(&self) -> bool {
+        matches!(self, RetryError::Exhausted(_))
+    }
+
+    /// The error of the last attempt
+    pub fn error(&self) -> &E {
+        match self {
+            RetryError::Exhausted(err) => err.error(),
+            RetryError::Fatal(err) => err,
+        }
+    }
+
+    /// Get the error of the last attempt
+    pub fn into_error(self) -> E {
+        match self {
+            RetryError::Exhausted(err) => err.into_error(),
+            RetryError::Fatal(err) => err,
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetryError::Exhausted(err) => err.fmt(f),
+            RetryError::Fatal(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for RetryError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RetryError::Exhausted(err) => err.source(),
+            RetryError::Fatal(err) => err.source(),
+        }
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// returning the last error as a [`RetryError`].
+///
+/// Errors that [should still be retried](ShouldRetry) are returned as
+/// [`Exhausted`](RetryError::Exhausted), with how many attempts were made. Errors that shouldn't
+/// be retried are returned as they are, as [`Fatal`](RetryError::Fatal).
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, outcome::retry_or_exhausted};
+/// use std::time::Duration;
+///
+/// #[derive(Debug)]
+/// struct Unavailable;
+/// # impl std::fmt::Display for Unavailable {
+/// #     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { f.write_str("unavailable") }
+/// # }
+/// impl std::error::Error for Unavailable {}
+/// # impl futures_retry_policies::ShouldRetry for Unavailable {
+/// #     fn should_retry(&self, _: u32) -> bool { true }
+/// # }
+///
+/// async fn make_request() -> Result<(), Unavailable> {
+///     // make a request
+///     Err(Unavailable)
+/// }
+///
+/// async fn run() -> Result<(), Box<dyn std::error::Error>> {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     retry_or_exhausted(policy, tokio::time::sleep, make_request).await?;
+///     Ok(())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let err = run().await.unwrap_err();
+///     assert!(err.to_string().starts_with("gave up after 4 attempts"));
+/// }
+/// ```
+pub async fn retry_or_exhausted<Policy, Sleeper, Sleep, Futures, Fut, T, E>(
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> Result<T, RetryError<E>>
+where
+    Policy: RetryPolicy<Result<T, E>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: ShouldRetry,
+{
+    retry_or_exhausted_with_clock(StdClock, policy, sleeper, futures).await
+}
+
+/// Like [`retry_or_exhausted`], but measures the time elapsed with `clock`
+pub async fn retry_or_exhausted_with_clock<K, Policy, Sleeper, Sleep, Futures, Fut, T, E>(
+    clock: K,
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> Result<T, RetryError<E>>
+where
+    K: Clock,
+    Policy: RetryPolicy<Result<T, E>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: ShouldRetry,
+{
+    let start = clock.now();
+    let mut policy = Counted {
+        policy,
+        attempts: 0,
+    };
+    retry(&mut policy, sleeper, futures).await.map_err(|error| {
+        if error.should_retry(policy.attempts) {
+            let elapsed = clock.now().saturating_sub(start);
+            RetryError::Exhausted(RetriesExhausted::new(error, policy.attempts, elapsed))
+        } else {
+            RetryError::Fatal(error)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        retry_on_give_up, retry_or_exhausted, retry_or_exhausted_with_clock, try_retry,
+        RetriesExhausted, RetryError, RetryOutcome,
+    };
+    use crate::{
+        clock::{Clock, MockClock},
+        iter::Iter,
+        ShouldRetry,
+    };
+
+    #[derive(Debug, PartialEq)]
+    enum Error {
+        Retry,
+        Fatal,
+    }
+    impl ShouldRetry for Error {
+        fn should_retry(&self, _: u32) -> bool {
+            matches!(self, Error::Retry)
+        }
+    }
+
+    async fn sleep(_: Duration) {}
+
+    fn policy() -> Iter<std::array::IntoIter<Duration, 2>> {
+        Iter::new([Duration::ZERO; 2])
+    }
+
+    #[tokio::test]
+    async fn gave_up() {
+        let outcome = try_retry(policy(), sleep, || async { Err::<(), _>(Error::Retry) }).await;
+        assert_eq!(
+            outcome,
+            RetryOutcome::GaveUp {
+                last: Err(Error::Retry),
+                attempts: 3
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn succeeded() {
+        let mut attempts = 0;
+        let outcome = try_retry(policy(), sleep, || {
+            attempts += 1;
+            let res = if attempts < 2 {
+                Err(Error::Retry)
+            } else {
+                Ok(())
+            };
+            async move { res }
+        })
+        .await;
+        assert_eq!(outcome, RetryOutcome::Succeeded(Ok(())));
+    }
+
+    #[tokio::test]
+    async fn fatal_is_not_giving_up() {
+        let outcome = try_retry(policy(), sleep, || async { Err::<(), _>(Error::Fatal) }).await;
+        assert!(!outcome.gave_up());
+        assert_eq!(outcome.into_inner(), Err(Error::Fatal));
+    }
+
+    #[tokio::test]
+    async fn give_up_hook() {
+        let mut gave_up = None;
+        let res = retry_on_give_up(
+            policy(),
+            sleep,
+            || async { Err::<(), _>(Error::Retry) },
+            |_, attempts| {
+                gave_up = Some(attempts);
+                async {}
+            },
+        )
+        .await;
+        assert_eq!(res, Err(Error::Retry));
+        assert_eq!(gave_up, Some(3));
+
+        let mut gave_up = false;
+        retry_on_give_up(
+            policy(),
+            sleep,
+            || async { Err::<(), _>(Error::Fatal) },
+            |_, _| {
+                gave_up = true;
+                async {}
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(!gave_up);
+    }
+
+    #[tokio::test]
+    async fn exhausted() {
+        let clock = MockClock::new();
+        let err = retry_or_exhausted_with_clock(
+            clock.clone(),
+            Iter::new([Duration::from_secs(1); 2]),
+            |delay| {
+                clock.advance(delay);
+                async {}
+            },
+            || async { Err::<(), _>(Error::Retry) },
+        )
+        .await
+        .unwrap_err();
+        let RetryError::Exhausted(err) = err else {
+            panic!("expected the retries to be exhausted, got {err:?}");
+        };
+        assert_eq!(err.attempts(), 3);
+        assert_eq!(err.elapsed(), Duration::from_secs(2));
+        assert_eq!(clock.now(), Duration::from_secs(2));
+        assert_eq!(err.into_error(), Error::Retry);
+
+        let err = retry_or_exhausted(policy(), sleep, || async { Err::<(), _>(Error::Fatal) })
+            .await
+            .unwrap_err();
+        assert_eq!(err, RetryError::Fatal(Error::Fatal));
+
+        let err = RetriesExhausted::new(Error::Fatal, 1, Duration::from_millis(250));
+        assert_eq!(err.to_string(), "gave up after 1 attempt over 250.0ms");
+    }
+}
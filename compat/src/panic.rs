@@ -0,0 +1,183 @@
+//! Retrying attempts that panic.
+//!
+//! Some operations, often ones backed by FFI, occasionally panic rather than return an error.
+//! Wrapping the attempts with [`catch_unwind`] turns those panics into a [`Panicked`] error,
+//! which is retried like any other error instead of unwinding through the retry loop.
+//!
+//! No task is spawned, so this works on any executor. See
+//! [`retry_spawned`](crate::tokio::retry_spawned) to isolate each attempt in its own tokio task instead.
+
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+
+use crate::ShouldRetry;
+
+/// An attempt panicked.
+///
+/// This is always retried by [`ShouldRetry`].
+pub struct Panicked {
+    payload: Box<dyn Any + Send>,
+}
+
+impl Panicked {
+    /// The panic message, if the panic was given one
+    pub fn message(&self) -> Option<&str> {
+        if let Some(message) = self.payload.downcast_ref::<&'static str>() {
+            Some(message)
+        } else {
+            self.payload.downcast_ref::<String>().map(String::as_str)
+        }
+    }
+
+    /// Get the panic payload, eg. to [resume](std::panic::resume_unwind) the panic
+    pub fn into_payload(self) -> Box<dyn Any + Send> {
+        self.payload
+    }
+}
+
+impl fmt::Debug for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Panicked")
+            .field("message", &self.message())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "attempt panicked: {message}"),
+            None => f.write_str("attempt panicked"),
+        }
+    }
+}
+
+impl std::error::Error for Panicked {}
+
+impl ShouldRetry for Panicked {
+    fn should_retry(&self, _: u32) -> bool {
+        true
+    }
+}
+
+/// [`Future`] that catches panics from the future it wraps, returned by [`catch_unwind`]
+#[pin_project]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct CatchUnwind<Fut> {
+    #[pin]
+    fut: Fut,
+}
+
+impl<Fut> CatchUnwind<Fut> {
+    /// Catch panics from `fut`.
+    ///
+    /// The future is assumed to be [unwind safe](std::panic::UnwindSafe). It is dropped after a
+    /// panic, and the next attempt starts afresh, but any state shared between attempts must still
+    /// be left consistent if an attempt panics.
+    pub fn new(fut: Fut) -> Self {
+        Self { fut }
+    }
+}
+
+impl<Fut: Future> Future for CatchUnwind<Fut> {
+    type Output = Result<Fut::Output, Panicked>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let fut = self.project().fut;
+        match panic::catch_unwind(AssertUnwindSafe(|| fut.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(Panicked { payload })),
+        }
+    }
+}
+
+/// Wrap each attempt made by `futures` in a [`CatchUnwind`], so that panics become [`Panicked`] errors.
+///
+/// The panic hook still runs, so panics are reported as usual, but the retry loop carries on.
+/// Creating the future isn't covered, only polling it.
+///
+/// ```
+/// use futures_retry_policies::{panic::catch_unwind, tokio::RetryFutureExt, RetryPolicyBuilder};
+/// use std::time::Duration;
+///
+/// async fn flaky_ffi_call() -> u32 {
+///     // call into a library that occasionally panics
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { panic!("oops") }
+///     42
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = RetryPolicyBuilder::fixed(Duration::from_millis(10))
+///         .max_retries(3)
+///         .build();
+///
+///     let res = catch_unwind(flaky_ffi_call).retry(policy).await;
+///     assert_eq!(res.unwrap(), 42);
+/// }
+/// ```
+pub fn catch_unwind<Futures, Fut>(mut futures: Futures) -> impl FnMut() -> CatchUnwind<Fut>
+where
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    move || CatchUnwind::new(futures())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::catch_unwind;
+    use crate::{iter::Iter, retry};
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn panics_are_retried() {
+        let mut attempts = 0;
+        let res = retry(
+            Iter::new([Duration::ZERO; 3]),
+            sleep,
+            catch_unwind(|| {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    tokio::task::yield_now().await;
+                    if attempt < 3 {
+                        panic!("attempt {attempt} failed");
+                    }
+                    attempt
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(res.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_with_the_panic() {
+        let res: Result<(), _> = retry(
+            Iter::new([Duration::ZERO]),
+            sleep,
+            catch_unwind(|| async { panic!("always") }),
+        )
+        .await;
+
+        let err = res.unwrap_err();
+        assert_eq!(err.message(), Some("always"));
+        assert_eq!(err.to_string(), "attempt panicked: always");
+    }
+}
@@ -0,0 +1,144 @@
+//! Polling until something is ready.
+//!
+//! Waiting for a job to finish or a resource to reach some state is a retry loop too, but one
+//! that retries on a successful "not yet" rather than on an error. [`poll_until`] keeps that
+//! separate from error handling: the check returns a [`Poll`], and is repeated while it is
+//! [`Pending`](Poll::Pending).
+
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    ops::ControlFlow,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use crate::{outcome::RetriesExhausted, RetryPolicy};
+
+/// The value that [`poll_until`] was waiting for, along with how long it took to become ready
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Polled<T> {
+    /// The ready value
+    pub value: T,
+    /// How many times the check was made, including the one that was ready
+    pub polls: u32,
+    /// How long passed between starting the first check and the value being ready
+    pub elapsed: Duration,
+}
+
+/// The error when [`poll_until`] gave up while the check was still pending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct StillPending;
+
+impl fmt::Display for StillPending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("still pending")
+    }
+}
+
+impl Error for StillPending {}
+
+/// Repeat `check` until it is ready, waiting between checks according to the given
+/// [retry policy](`RetryPolicy`) and sleep function.
+///
+/// The policy sees each [`Poll`], which retries when [`Pending`](Poll::Pending) by its
+/// [`ShouldRetry`](crate::ShouldRetry) impl. Checks that can also fail can return a
+/// `Poll<Result<T, E>>`, and use [`NotReady`](crate::NotReady) to retry some errors too.
+///
+/// ```
+/// use futures_retry_policies::{poll::poll_until, RetryPolicyBuilder};
+/// use std::{task::Poll, time::Duration};
+///
+/// async fn job_status() -> Poll<&'static str> {
+///     // check the job's status
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return Poll::Pending }
+///     Poll::Ready("succeeded")
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = RetryPolicyBuilder::fixed(Duration::from_millis(10))
+///         .max_elapsed(Duration::from_secs(60))
+///         .build();
+///
+///     let status = poll_until(policy, tokio::time::sleep, job_status).await.unwrap();
+///     assert_eq!(status.value, "succeeded");
+///     assert_eq!(status.polls, 3);
+/// }
+/// ```
+pub async fn poll_until<Policy, Sleeper, Sleep, Check, Fut, T>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut check: Check,
+) -> Result<Polled<T>, RetriesExhausted<StillPending>>
+where
+    Policy: RetryPolicy<Poll<T>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Check: FnMut() -> Fut,
+    Fut: Future<Output = Poll<T>>,
+{
+    let start = Instant::now();
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        match policy.should_retry(check().await) {
+            ControlFlow::Continue(delay) => sleeper(delay).await,
+            ControlFlow::Break(Poll::Ready(value)) => {
+                break Ok(Polled {
+                    value,
+                    polls,
+                    elapsed: start.elapsed(),
+                })
+            }
+            ControlFlow::Break(Poll::Pending) => {
+                break Err(RetriesExhausted::new(StillPending, polls, start.elapsed()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{task::Poll, time::Duration};
+
+    use super::{poll_until, StillPending};
+    use crate::iter::Iter;
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn ready() {
+        let mut polls = 0;
+        let polled = poll_until(Iter::new([Duration::ZERO; 5]), sleep, || {
+            polls += 1;
+            let ready = polls >= 3;
+            async move {
+                if ready {
+                    Poll::Ready(polls)
+                } else {
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(polled.value, 3);
+        assert_eq!(polled.polls, 3);
+    }
+
+    #[tokio::test]
+    async fn still_pending() {
+        let err = poll_until(Iter::new([Duration::ZERO; 2]), sleep, || async {
+            Poll::<()>::Pending
+        })
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.error(), &StillPending);
+        assert_eq!(err.attempts(), 3);
+    }
+}
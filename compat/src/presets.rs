@@ -0,0 +1,136 @@
+//! Ready-made policies for common situations.
+//!
+//! Each preset is a [`Backoff`] with exponential delays, jitter and limits that suit its use.
+//! They're a good starting point when you don't want to tune a policy yourself. If you need to
+//! tweak one, the docs of each preset show the [`RetryPolicyBuilder`] settings it uses.
+//!
+//! ```
+//! use futures_retry_policies::{presets, tokio::RetryFutureExt};
+//!
+//! #[derive(Debug)]
+//! struct Error;
+//! # impl futures_retry_policies::ShouldRetry for Error {
+//! #     fn should_retry(&self, _: u32) -> bool { true }
+//! # }
+//!
+//! async fn make_request() -> Result<(), Error> {
+//!     // make a request
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 1 { Err(Error) } else { Ok(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     make_request.retry(presets::http_client()).await
+//! }
+//! ```
+
+use std::time::Duration;
+
+use crate::{
+    backoff::{Backoff, Jitter},
+    RetryPolicyBuilder,
+};
+
+/// For requests to other services over HTTP, or similar RPC protocols.
+///
+/// A few quick retries, with full jitter so that many clients don't retry in lockstep:
+///
+/// ```
+/// # use futures_retry_policies::RetryPolicyBuilder;
+/// # use std::time::Duration;
+/// RetryPolicyBuilder::exponential(Duration::from_millis(100))
+///     .max_delay(Duration::from_secs(5))
+///     .jitter_full()
+///     .max_retries(3)
+///     .max_elapsed(Duration::from_secs(30))
+///     .build()
+/// # ;
+/// ```
+pub fn http_client() -> Backoff {
+    RetryPolicyBuilder::exponential(Duration::from_millis(100))
+        .max_delay(Duration::from_secs(5))
+        .jitter_full()
+        .max_retries(3)
+        .max_elapsed(Duration::from_secs(30))
+        .build()
+}
+
+/// For database queries and transactions, eg. after a deadlock or serialization failure.
+///
+/// Conflicts usually clear quickly, so this starts with short delays, with equal jitter
+/// so that conflicting transactions are spread out:
+///
+/// ```
+/// # use futures_retry_policies::{backoff::Jitter, RetryPolicyBuilder};
+/// # use std::time::Duration;
+/// RetryPolicyBuilder::exponential(Duration::from_millis(20))
+///     .max_delay(Duration::from_secs(1))
+///     .jitter(Jitter::Equal)
+///     .max_retries(5)
+///     .max_elapsed(Duration::from_secs(10))
+///     .build()
+/// # ;
+/// ```
+pub fn database() -> Backoff {
+    RetryPolicyBuilder::exponential(Duration::from_millis(20))
+        .max_delay(Duration::from_secs(1))
+        .jitter(Jitter::Equal)
+        .max_retries(5)
+        .max_elapsed(Duration::from_secs(10))
+        .build()
+}
+
+/// For waiting on a dependency to become available when starting up.
+///
+/// Dependencies that are starting at the same time can take a while, so this keeps
+/// polling every few seconds for up to five minutes:
+///
+/// ```
+/// # use futures_retry_policies::{backoff::Jitter, RetryPolicyBuilder};
+/// # use std::time::Duration;
+/// RetryPolicyBuilder::exponential(Duration::from_millis(250))
+///     .factor(1.5)
+///     .max_delay(Duration::from_secs(5))
+///     .jitter(Jitter::Equal)
+///     .max_elapsed(Duration::from_secs(5 * 60))
+///     .build()
+/// # ;
+/// ```
+pub fn startup_dependency_wait() -> Backoff {
+    RetryPolicyBuilder::exponential(Duration::from_millis(250))
+        .factor(1.5)
+        .max_delay(Duration::from_secs(5))
+        .jitter(Jitter::Equal)
+        .max_elapsed(Duration::from_secs(5 * 60))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{database, http_client, startup_dependency_wait};
+
+    #[test]
+    fn schedules() {
+        let millis = |ms: &[u64]| {
+            ms.iter()
+                .copied()
+                .map(Duration::from_millis)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            http_client().preview(10).collect::<Vec<_>>(),
+            millis(&[100, 200, 400])
+        );
+        assert_eq!(
+            database().preview(10).collect::<Vec<_>>(),
+            millis(&[20, 40, 80, 160, 320])
+        );
+        assert!(startup_dependency_wait()
+            .preview(100)
+            .all(|delay| delay <= Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,142 @@
+//! Preparing the next attempt while waiting to retry.
+//!
+//! Some of the work in an attempt doesn't depend on the previous attempt, like opening a
+//! connection or refreshing DNS. [`retry_prewarmed`] does that work during the backoff sleep,
+//! so the next attempt can start as soon as the sleep is over.
+
+use std::{
+    future::{poll_fn, Future},
+    ops::ControlFlow,
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+use crate::RetryPolicy;
+
+/// Retry `attempt` using the given [retry policy](`RetryPolicy`) and sleep function, running
+/// `prepare` concurrently with each sleep.
+///
+/// Every attempt is given the output of its own call to `prepare`. The first attempt is prepared
+/// straight away, and later ones while sleeping. If preparing takes longer than the sleep, the next
+/// attempt waits for it to finish.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, prewarm::retry_prewarmed};
+/// use std::time::Duration;
+///
+/// struct Connection;
+///
+/// async fn connect() -> Connection {
+///     // open a connection
+///     Connection
+/// }
+///
+/// async fn make_request(conn: Connection) -> Option<()> {
+///     // make a request on the connection
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     retry_prewarmed(policy, tokio::time::sleep, connect, make_request).await.unwrap();
+/// }
+/// ```
+pub async fn retry_prewarmed<Policy, Sleeper, Sleep, Prepare, PrepareFut, Attempt, Fut>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut prepare: Prepare,
+    mut attempt: Attempt,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Prepare: FnMut() -> PrepareFut,
+    PrepareFut: Future,
+    Attempt: FnMut(PrepareFut::Output) -> Fut,
+    Fut: Future,
+{
+    let mut prepared = prepare().await;
+    loop {
+        match policy.should_retry(attempt(prepared).await) {
+            ControlFlow::Continue(delay) => {
+                let ((), next) = join(sleeper(delay), prepare()).await;
+                prepared = next;
+            }
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+/// Wait for both futures to complete
+async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    let (mut a, mut b) = (pin!(a), pin!(b));
+    let (mut a_out, mut b_out) = (None, None);
+    poll_fn(|cx| {
+        if a_out.is_none() {
+            if let Poll::Ready(out) = a.as_mut().poll(cx) {
+                a_out = Some(out);
+            }
+        }
+        if b_out.is_none() {
+            if let Poll::Ready(out) = b.as_mut().poll(cx) {
+                b_out = Some(out);
+            }
+        }
+        if a_out.is_some() && b_out.is_some() {
+            Poll::Ready((a_out.take().unwrap(), b_out.take().unwrap()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_prewarmed;
+    use crate::iter::Iter;
+
+    #[tokio::test(start_paused = true)]
+    async fn prepares_during_sleep() {
+        let start = tokio::time::Instant::now();
+        let mut prepared = 0;
+        let res = retry_prewarmed(
+            Iter::new([Duration::from_secs(2); 3]),
+            tokio::time::sleep,
+            || {
+                prepared += 1;
+                let n = prepared;
+                async move {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    n
+                }
+            },
+            |n| async move { (n == 3).then_some(n) },
+        )
+        .await;
+
+        assert_eq!(res, Some(3));
+        // 1s to prepare the first attempt, then preparing overlaps with both 2s sleeps
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 2 + 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_slow_preparation() {
+        let start = tokio::time::Instant::now();
+        retry_prewarmed(
+            Iter::new([Duration::from_secs(1)]),
+            tokio::time::sleep,
+            || tokio::time::sleep(Duration::from_secs(3)),
+            |()| async { None::<()> },
+        )
+        .await;
+
+        assert_eq!(start.elapsed(), Duration::from_secs(3 + 3));
+    }
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "process")]
+#![cfg_attr(docsrs, doc(cfg(feature = "process")))]
+//! Retry features for child processes
+
+use std::{future::Future, io, ops::ControlFlow, process::Output, time::Duration};
+
+use tokio::process::Command;
+
+use crate::{classified::Classified, RetryPolicy};
+
+/// Run a command until its output passes `succeeded`, using the given [retry policy](`RetryPolicy`)
+/// and sleep function.
+///
+/// A new [`Command`] is built for every attempt. Each attempt is handed to the policy as a
+/// [`Classified`] result, marked as retryable when the command ran but its output didn't pass.
+/// Commands that fail to start at all aren't retried.
+///
+/// Returns the output of the last attempt.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, process::retry_command};
+/// use std::time::Duration;
+/// use tokio::process::Command;
+///
+/// #[tokio::main]
+/// async fn main() -> std::io::Result<()> {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let output = retry_command(
+///         policy,
+///         tokio::time::sleep,
+///         || Command::new("true"),
+///         |output| output.status.success(),
+///     )
+///     .await?;
+///     assert!(output.status.success());
+///     Ok(())
+/// }
+/// ```
+pub async fn retry_command<Policy, Sleeper, Sleep, Commands, Succeeded>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut commands: Commands,
+    mut succeeded: Succeeded,
+) -> io::Result<Output>
+where
+    Policy: RetryPolicy<Classified<io::Result<Output>>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Commands: FnMut() -> Command,
+    Succeeded: FnMut(&Output) -> bool,
+{
+    loop {
+        let result = match commands().output().await {
+            Ok(output) => {
+                let retryable = !succeeded(&output);
+                Classified::new(Ok(output), retryable)
+            }
+            Err(err) => Classified::new(Err(err), false),
+        };
+        match policy.should_retry(result) {
+            ControlFlow::Continue(duration) => sleeper(duration).await,
+            ControlFlow::Break(result) => break result.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::process::Command;
+
+    use super::retry_command;
+    use crate::iter::Iter;
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn retries_failed_commands() {
+        let mut attempts = 0;
+        let output = retry_command(
+            Iter::new([Duration::from_secs(1); 2]),
+            sleep,
+            || {
+                attempts += 1;
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c").arg("echo hello; exit 1");
+                cmd
+            },
+            |output| output.status.success(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(attempts, 3);
+        assert!(!output.status.success());
+        assert_eq!(output.stdout, b"hello\n");
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_spawn_errors() {
+        let mut attempts = 0;
+        let res = retry_command(
+            Iter::new([Duration::from_secs(1); 2]),
+            sleep,
+            || {
+                attempts += 1;
+                Command::new("this-command-does-not-exist")
+            },
+            |output| output.status.success(),
+        )
+        .await;
+
+        assert_eq!(attempts, 1);
+        assert!(res.is_err());
+    }
+}
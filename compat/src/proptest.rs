@@ -0,0 +1,153 @@
+#![cfg(feature = "proptest")]
+#![cfg_attr(docsrs, doc(cfg(feature = "proptest")))]
+//! [`proptest`](::proptest) strategies for the built-in policies, and for sequences of results.
+//!
+//! Use these to check that retry handling holds up against randomised schedules and failures.
+//!
+//! ```
+//! use futures_retry_policies::{proptest::{backoff, classified}, RetryPolicy};
+//! use proptest::prelude::*;
+//! use std::ops::ControlFlow;
+//!
+//! proptest!(|(mut policy in backoff(), results in classified(any::<u8>(), 1..20))| {
+//!     for result in results {
+//!         let retryable = result.retryable;
+//!         match policy.should_retry(result) {
+//!             ControlFlow::Continue(_) => prop_assert!(retryable),
+//!             ControlFlow::Break(_) => break,
+//!         }
+//!     }
+//! });
+//! ```
+
+use std::{ops::Range, time::Duration};
+
+use proptest::prelude::*;
+
+use crate::{
+    backoff::{Backoff, Jitter},
+    classified::Classified,
+    iter::Iter,
+    RetryPolicyBuilder,
+};
+
+/// Durations between 1ms and 10s
+pub fn delay() -> impl Strategy<Value = Duration> {
+    (1u64..10_000).prop_map(Duration::from_millis)
+}
+
+/// Any of the [`Jitter`] modes
+pub fn jitter() -> impl Strategy<Value = Jitter> {
+    prop_oneof![Just(Jitter::None), Just(Jitter::Full), Just(Jitter::Equal)]
+}
+
+impl Arbitrary for Jitter {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        jitter().boxed()
+    }
+}
+
+/// [`Backoff`] policies with random delays, jitter and limits of at most 10 retries
+pub fn backoff() -> impl Strategy<Value = Backoff> {
+    (
+        delay(),
+        1.0f64..4.0,
+        delay(),
+        jitter(),
+        0u32..10,
+        proptest::option::of(delay()),
+    )
+        .prop_map(
+            |(base, factor, max_delay, jitter, max_retries, max_elapsed)| {
+                let builder = RetryPolicyBuilder::exponential(base)
+                    .factor(factor)
+                    .max_delay(max_delay)
+                    .jitter(jitter)
+                    .max_retries(max_retries);
+                match max_elapsed {
+                    Some(max_elapsed) => builder.max_elapsed(max_elapsed),
+                    None => builder,
+                }
+                .build()
+            },
+        )
+}
+
+/// [`Iter`] policies over at most `len` random delays
+pub fn iter(len: Range<usize>) -> impl Strategy<Value = Iter<std::vec::IntoIter<Duration>>> {
+    proptest::collection::vec(delay(), len).prop_map(Iter::new)
+}
+
+/// Sequences of [`Classified`] results, randomly retryable, with some delay hints
+pub fn classified<T: std::fmt::Debug>(
+    value: impl Strategy<Value = T>,
+    len: Range<usize>,
+) -> impl Strategy<Value = Vec<Classified<T>>> {
+    let result = (value, any::<bool>(), proptest::option::of(delay())).prop_map(
+        |(value, retryable, delay_hint)| Classified {
+            value,
+            retryable,
+            delay_hint,
+        },
+    );
+    proptest::collection::vec(result, len)
+}
+
+/// Sequences of results, where errors are retryable and successes end the sequence.
+///
+/// Each sequence is some number of errors, which may be followed by a single success.
+pub fn results<T: std::fmt::Debug, E: std::fmt::Debug>(
+    ok: impl Strategy<Value = T>,
+    err: impl Strategy<Value = E> + Clone,
+    len: Range<usize>,
+) -> impl Strategy<Value = Vec<Result<T, E>>> {
+    (
+        proptest::collection::vec(err, len),
+        proptest::option::of(ok),
+    )
+        .prop_map(|(errors, ok)| {
+            let mut results: Vec<_> = errors.into_iter().map(Err).collect();
+            results.extend(ok.map(Ok));
+            results
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use proptest::prelude::*;
+
+    use super::{backoff, iter, results};
+    use crate::RetryPolicy;
+
+    proptest! {
+        #[test]
+        fn backoff_limits_retries(mut policy in backoff()) {
+            prop_assert_eq!(policy.should_retry(Some(())), ControlFlow::Break(Some(())));
+
+            let mut retries = 0;
+            while let ControlFlow::Continue(_) = policy.should_retry(None::<()>) {
+                retries += 1;
+            }
+            prop_assert!(retries <= 10);
+        }
+
+        #[test]
+        fn iter_retries_errors(
+            mut policy in iter(0..10),
+            results in results(any::<u8>(), Just(None::<()>), 0..10),
+        ) {
+            for result in results {
+                let is_err = result.is_err();
+                match policy.should_retry(result) {
+                    ControlFlow::Continue(_) => prop_assert!(is_err),
+                    ControlFlow::Break(_) => break,
+                }
+            }
+        }
+    }
+}
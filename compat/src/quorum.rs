@@ -0,0 +1,112 @@
+//! Retrying until enough results have been collected.
+//!
+//! Distributed systems clients often need to keep going until a condition over all the results
+//! so far holds, like a quorum of acknowledgements, rather than until a single attempt succeeds.
+//! [`retry_until`] collects the result of every round, and stops once a predicate over all of
+//! them is satisfied.
+
+use std::{
+    future::Future,
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+
+use crate::{outcome::RetriesExhausted, RetryPolicy, ShouldRetry};
+
+/// What the policy of [`retry_until`] decides on: the collected results don't satisfy the
+/// predicate yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Unsatisfied;
+
+impl ShouldRetry for Unsatisfied {
+    /// Always retry
+    fn should_retry(&self, _: u32) -> bool {
+        true
+    }
+}
+
+/// Run rounds of `make_fut`, collecting every result, until `satisfied` holds for the collected
+/// results, waiting between rounds according to the given [retry policy](`RetryPolicy`) and
+/// sleep function.
+///
+/// Returns the collected results once satisfied. If the policy gives up first, the results
+/// collected so far are returned as the error.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, quorum::retry_until};
+/// use std::time::Duration;
+///
+/// async fn replicate() -> Vec<&'static str> {
+///     // send a write to any replicas that haven't acknowledged it, returning the new acknowledgements
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # match COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) { 0 => vec!["a"], _ => vec!["b", "c"] }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 5]);
+///     let quorum = |acks: &[Vec<&str>]| acks.iter().flatten().count() >= 2;
+///
+///     let rounds = retry_until(policy, tokio::time::sleep, replicate, quorum).await.unwrap();
+///     assert_eq!(rounds, [vec!["a"], vec!["b", "c"]]);
+/// }
+/// ```
+pub async fn retry_until<Policy, Sleeper, Sleep, MakeFut, Fut, Satisfied>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut make_fut: MakeFut,
+    mut satisfied: Satisfied,
+) -> Result<Vec<Fut::Output>, RetriesExhausted<Vec<Fut::Output>>>
+where
+    Policy: RetryPolicy<Unsatisfied>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    MakeFut: FnMut() -> Fut,
+    Fut: Future,
+    Satisfied: FnMut(&[Fut::Output]) -> bool,
+{
+    let start = Instant::now();
+    let mut results = Vec::new();
+    loop {
+        results.push(make_fut().await);
+        if satisfied(&results) {
+            break Ok(results);
+        }
+        match policy.should_retry(Unsatisfied) {
+            ControlFlow::Continue(delay) => sleeper(delay).await,
+            ControlFlow::Break(Unsatisfied) => {
+                let rounds = results.len() as u32;
+                break Err(RetriesExhausted::new(results, rounds, start.elapsed()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_until;
+    use crate::iter::Iter;
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn gives_up_with_partial_results() {
+        let mut round = 0;
+        let err = retry_until(
+            Iter::new([Duration::ZERO; 2]),
+            sleep,
+            || {
+                round += 1;
+                std::future::ready(round)
+            },
+            |rounds: &[u32]| rounds.iter().sum::<u32>() > 10,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.attempts(), 3);
+        assert_eq!(err.into_error(), [1, 2, 3]);
+    }
+}
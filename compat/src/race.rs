@@ -0,0 +1,456 @@
+//! Retries that race against other ways of getting the answer.
+//!
+//! When multiple strategies run concurrently, eg. a cache lookup alongside a retried fetch from the
+//! origin, the retries should stop as soon as another strategy has the answer.
+//! [`retry_until_resolved`] aborts the retry loop, including any attempt or sleep in progress,
+//! once a shared signal resolves.
+//!
+//! [`race_replicas`] races attempts against each other instead, hedging requests across
+//! a set of [`Replicas`] that serve the same data.
+
+use std::{
+    future::{poll_fn, Future},
+    ops::ControlFlow,
+    pin::{pin, Pin},
+    task::Poll,
+    time::Duration,
+};
+
+use crate::{
+    clock::{Clock, StdClock},
+    retry, RetryPolicy, ShouldRetry,
+};
+
+/// The result of [`retry_until_resolved`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Race<R, T> {
+    /// The retries finished first
+    Retried(R),
+    /// The signal resolved first
+    Resolved(T),
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// until `resolved` completes.
+///
+/// `resolved` is typically the receiving half of a one-shot channel, shared by all the strategies.
+/// Note that some receivers, like [`tokio::sync::oneshot::Receiver`](::tokio::sync::oneshot::Receiver),
+/// also complete if the sender is dropped without sending a value.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, race::{retry_until_resolved, Race}};
+/// use std::time::Duration;
+/// use tokio::sync::oneshot;
+///
+/// async fn fetch_from_cache() -> Option<&'static str> {
+///     tokio::time::sleep(Duration::from_millis(20)).await;
+///     Some("cached")
+/// }
+///
+/// async fn fetch_from_origin() -> Option<&'static str> {
+///     // the origin is unavailable
+///     None
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (tx, rx) = oneshot::channel();
+///     tokio::spawn(async move {
+///         if let Some(value) = fetch_from_cache().await {
+///             let _ = tx.send(value);
+///         }
+///     });
+///
+///     let policy = Iter::new([Duration::from_secs(1); 3]);
+///     match retry_until_resolved(rx, policy, tokio::time::sleep, fetch_from_origin).await {
+///         Race::Retried(value) => println!("origin gave {value:?}"),
+///         Race::Resolved(value) => println!("another strategy gave {value:?}"),
+///     }
+/// }
+/// ```
+pub async fn retry_until_resolved<Resolved, Policy, Sleeper, Sleep, Futures, Fut>(
+    resolved: Resolved,
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> Race<Fut::Output, Resolved::Output>
+where
+    Resolved: Future,
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    let mut resolved = pin!(resolved);
+    let mut retried = pin!(retry(policy, sleeper, futures));
+    poll_fn(|cx| {
+        if let Poll::Ready(value) = resolved.as_mut().poll(cx) {
+            return Poll::Ready(Race::Resolved(value));
+        }
+        retried.as_mut().poll(cx).map(Race::Retried)
+    })
+    .await
+}
+
+/// What has been learned about a replica from previous attempts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReplicaStats {
+    /// A moving average of how long finished attempts took, if any have finished
+    pub latency: Option<Duration>,
+    /// How many attempts in a row have failed
+    pub consecutive_failures: u32,
+}
+
+impl ReplicaStats {
+    fn record_latency(&mut self, elapsed: Duration) {
+        self.consecutive_failures = 0;
+        self.latency = Some(match self.latency {
+            Some(latency) => latency.mul_f64(0.8) + elapsed.mul_f64(0.2),
+            None => elapsed,
+        });
+    }
+}
+
+/// A set of replicas that can serve the same requests, used by [`race_replicas`].
+///
+/// Keep the same `Replicas` between calls, so that what was learned about each replica
+/// carries over.
+#[derive(Debug, Clone)]
+pub struct Replicas<T, K = StdClock> {
+    replicas: Vec<(T, ReplicaStats)>,
+    clock: K,
+}
+
+impl<T> Replicas<T> {
+    /// Track the given replicas, with nothing known about them yet
+    pub fn new(replicas: impl IntoIterator<Item = T>) -> Self {
+        Self::with_clock(replicas, StdClock)
+    }
+}
+
+impl<T, K> Replicas<T, K> {
+    /// Track the given replicas, measuring latencies with `clock`
+    pub fn with_clock(replicas: impl IntoIterator<Item = T>, clock: K) -> Self {
+        Self {
+            replicas: replicas
+                .into_iter()
+                .map(|replica| (replica, ReplicaStats::default()))
+                .collect(),
+            clock,
+        }
+    }
+
+    /// The replicas, with what has been learned about them
+    pub fn iter(&self) -> impl Iterator<Item = (&T, &ReplicaStats)> {
+        self.replicas
+            .iter()
+            .map(|(replica, stats)| (replica, stats))
+    }
+
+    /// The number of replicas
+    pub fn len(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Whether there are no replicas
+    pub fn is_empty(&self) -> bool {
+        self.replicas.is_empty()
+    }
+
+    /// Replica indices, healthiest and fastest first, then those without a known latency
+    fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.replicas.len()).collect();
+        order.sort_by_key(|&i| {
+            let stats = &self.replicas[i].1;
+            (
+                stats.consecutive_failures,
+                stats.latency.is_none(),
+                stats.latency,
+            )
+        });
+        order
+    }
+}
+
+/// Hedge an attempt across `replicas`, retrying failures using the given
+/// [retry policy](`RetryPolicy`) and sleep function.
+///
+/// The first attempt goes to the healthiest, fastest replica. If it hasn't finished after
+/// `hedge_after`, another attempt is started on the next replica, and so on, until every replica
+/// has an attempt in flight. A replica never has more than one. When an attempt fails, it's handed
+/// to the policy, and the next free replica is tried after the policy's delay. As soon as
+/// one attempt finishes with a result that shouldn't be retried, the other attempts are cancelled,
+/// by dropping them.
+///
+/// Each finished attempt updates the replica's [`ReplicaStats`], which decide the order of
+/// future calls.
+///
+/// # Panics
+///
+/// If there are no replicas.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, race::{race_replicas, Replicas}};
+/// use std::time::Duration;
+///
+/// async fn fetch(replica: &str) -> Option<String> {
+///     // make a request
+///     # Some(replica.to_owned())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut replicas = Replicas::new(["replica-1:8080", "replica-2:8080", "replica-3:8080"]);
+///
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let value = race_replicas(
+///         &mut replicas,
+///         Duration::from_millis(50),
+///         policy,
+///         tokio::time::sleep,
+///         |replica| fetch(replica),
+///     )
+///     .await;
+///     assert!(value.is_some());
+/// }
+/// ```
+pub async fn race_replicas<T, K, Policy, Sleeper, Sleep, Attempt, Fut>(
+    replicas: &mut Replicas<T, K>,
+    hedge_after: Duration,
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut attempt: Attempt,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    K: Clock,
+    Attempt: FnMut(&T) -> Fut,
+    Fut: Future,
+    Fut::Output: ShouldRetry,
+{
+    assert!(!replicas.is_empty(), "no replicas to race");
+
+    let order = replicas.order();
+    let mut next = 0;
+    let mut in_flight: Vec<(usize, Duration, Pin<Box<Fut>>)> = Vec::new();
+    let mut gave_up = None;
+    let mut attempts = 0;
+
+    // starts an attempt on the next replica without one in flight, if there is any
+    let mut launch = |replicas: &Replicas<T, K>, in_flight: &mut Vec<(usize, _, _)>| {
+        let Some(index) = (next..next + order.len())
+            .map(|i| order[i % order.len()])
+            .find(|&index| in_flight.iter().all(|(i, ..)| *i != index))
+        else {
+            return false;
+        };
+        next += 1;
+        in_flight.push((
+            index,
+            replicas.clock.now(),
+            Box::pin(attempt(&replicas.replicas[index].0)),
+        ));
+        true
+    };
+
+    launch(replicas, &mut in_flight);
+    let mut timer: Option<Pin<Box<Sleep>>> =
+        (order.len() > 1).then(|| Box::pin(sleeper(hedge_after)));
+
+    poll_fn(|cx| loop {
+        let mut i = 0;
+        while i < in_flight.len() {
+            let Poll::Ready(result) = in_flight[i].2.as_mut().poll(cx) else {
+                i += 1;
+                continue;
+            };
+            let (index, started, _) = in_flight.swap_remove(i);
+            let elapsed = replicas.clock.now().saturating_sub(started);
+            let stats = &mut replicas.replicas[index].1;
+            attempts += 1;
+
+            if !result.should_retry(attempts) {
+                stats.record_latency(elapsed);
+                return Poll::Ready(result);
+            }
+            stats.consecutive_failures = stats.consecutive_failures.saturating_add(1);
+
+            if gave_up.is_some() {
+                gave_up = Some(result);
+                continue;
+            }
+            match policy.should_retry(result) {
+                ControlFlow::Continue(duration) => timer = Some(Box::pin(sleeper(duration))),
+                ControlFlow::Break(result) => {
+                    gave_up = Some(result);
+                    timer = None;
+                }
+            }
+        }
+
+        if in_flight.is_empty() {
+            if let Some(result) = gave_up.take() {
+                return Poll::Ready(result);
+            }
+        }
+
+        let fired = match &mut timer {
+            Some(sleep) => sleep.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if !fired {
+            return Poll::Pending;
+        }
+        // every replica has an attempt in flight, so there's nothing left to hedge to
+        timer = (launch(replicas, &mut in_flight) && in_flight.len() < order.len())
+            .then(|| Box::pin(sleeper(hedge_after)));
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{race_replicas, retry_until_resolved, Race, Replicas};
+    use crate::iter::Iter;
+
+    #[tokio::test(start_paused = true)]
+    async fn aborts_sleep() {
+        let start = tokio::time::Instant::now();
+        let mut attempts = 0;
+        let res = retry_until_resolved(
+            tokio::time::sleep(Duration::from_secs(5)),
+            Iter::new([Duration::from_secs(60); 3]),
+            tokio::time::sleep,
+            || {
+                attempts += 1;
+                async { None::<()> }
+            },
+        )
+        .await;
+        assert_eq!(res, Race::Resolved(()));
+        assert_eq!(attempts, 1);
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_finish_first() {
+        let res = retry_until_resolved(
+            std::future::pending::<()>(),
+            Iter::new([Duration::from_secs(1); 3]),
+            tokio::time::sleep,
+            || async { None::<()> },
+        )
+        .await;
+        assert_eq!(res, Race::Retried(None));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn hedges_to_faster_replica() {
+        use crate::clock::TokioClock;
+
+        let mut replicas = Replicas::with_clock([10, 1, 5], TokioClock::new());
+        let fetch = |&secs: &u64| async move {
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            Some(secs)
+        };
+
+        // nothing is known yet, so the first replica is tried, then hedged after 2s
+        let start = tokio::time::Instant::now();
+        let res = race_replicas(
+            &mut replicas,
+            Duration::from_secs(2),
+            Iter::new([Duration::from_secs(1); 3]),
+            tokio::time::sleep,
+            fetch,
+        )
+        .await;
+        assert_eq!(res, Some(1));
+        assert_eq!(start.elapsed(), Duration::from_secs(2 + 1));
+
+        // the fast replica is known now, so it goes first
+        let start = tokio::time::Instant::now();
+        let res = race_replicas(
+            &mut replicas,
+            Duration::from_secs(2),
+            Iter::new([Duration::from_secs(1); 3]),
+            tokio::time::sleep,
+            fetch,
+        )
+        .await;
+        assert_eq!(res, Some(1));
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_failures_on_other_replicas() {
+        let mut replicas = Replicas::new([false, false, true]);
+        let mut tried = vec![];
+
+        let start = tokio::time::Instant::now();
+        let res = race_replicas(
+            &mut replicas,
+            Duration::from_secs(60),
+            Iter::new([Duration::from_secs(1); 3]),
+            tokio::time::sleep,
+            |&healthy: &bool| {
+                tried.push(healthy);
+                async move { healthy.then_some(()) }
+            },
+        )
+        .await;
+
+        assert_eq!(res, Some(()));
+        assert_eq!(tried, [false, false, true]);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        let failures: Vec<_> = replicas
+            .iter()
+            .map(|(_, stats)| stats.consecutive_failures)
+            .collect();
+        assert_eq!(failures, [1, 1, 0]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up() {
+        let mut replicas = Replicas::new([(), ()]);
+        let res = race_replicas(
+            &mut replicas,
+            Duration::from_secs(60),
+            Iter::new([Duration::from_secs(1); 3]),
+            tokio::time::sleep,
+            |_| async { None::<()> },
+        )
+        .await;
+        assert_eq!(res, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hedges_once_per_replica() {
+        let mut replicas = Replicas::new([0, 1, 2]);
+        let mut launched = vec![];
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(60),
+            race_replicas(
+                &mut replicas,
+                Duration::from_secs(1),
+                Iter::new([Duration::from_secs(1); 3]),
+                tokio::time::sleep,
+                |&replica: &usize| {
+                    launched.push(replica);
+                    std::future::pending::<Option<()>>()
+                },
+            ),
+        )
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(launched, [0, 1, 2]);
+    }
+}
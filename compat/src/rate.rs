@@ -0,0 +1,106 @@
+//! Fixed-rate retries, where the delay is measured between attempt starts.
+//!
+//! Policies normally return the delay between the end of one attempt and the start of the next.
+//! Pollers usually want a fixed rate instead: attempts that begin a fixed interval apart, no
+//! matter how long each attempt takes. [`FixedRate`] subtracts the time spent in each attempt from
+//! the delay chosen by the inner policy to achieve this.
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::{
+    clock::{Clock, StdClock},
+    RetryPolicy,
+};
+
+/// A [`RetryPolicy`] that treats the inner policy's delays as the time between attempt starts.
+///
+/// If an attempt takes longer than the delay, the next attempt starts immediately.
+/// The first attempt is assumed to start when the `FixedRate` is created.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, rate::FixedRate, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn poll_job() -> Option<()> {
+///     // check if the job is complete, which takes a while
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # tokio::time::sleep(Duration::from_millis(5)).await;
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // start checking every 20ms
+///     let policy = FixedRate::new(Iter::new([Duration::from_millis(20); 5]));
+///     poll_job.retry(policy).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FixedRate<P, K = StdClock> {
+    policy: P,
+    clock: K,
+    attempt_started: Duration,
+}
+
+impl<P> FixedRate<P> {
+    /// Space out the starts of attempts by the delays of `policy`, starting from now
+    pub fn new(policy: P) -> Self {
+        Self::with_clock(policy, StdClock)
+    }
+}
+
+impl<P, K: Clock> FixedRate<P, K> {
+    /// Measure the time spent in attempts with `clock`
+    pub fn with_clock(policy: P, clock: K) -> Self {
+        Self {
+            policy,
+            attempt_started: clock.now(),
+            clock,
+        }
+    }
+}
+
+impl<P, K, R> RetryPolicy<R> for FixedRate<P, K>
+where
+    P: RetryPolicy<R>,
+    K: Clock,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let duration = self.policy.should_retry(result)?;
+        let now = self.clock.now();
+        let spent = now.saturating_sub(self.attempt_started);
+        let duration = duration.saturating_sub(spent);
+        self.attempt_started = now + duration;
+        ControlFlow::Continue(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::FixedRate;
+    use crate::{clock::MockClock, iter::Iter, RetryPolicy};
+
+    #[test]
+    fn subtracts_attempt_time() {
+        let clock = MockClock::new();
+        let mut policy =
+            FixedRate::with_clock(Iter::new([Duration::from_millis(100); 2]), clock.clone());
+
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(
+            policy.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::from_millis(80))
+        );
+
+        // the attempt took longer than the interval
+        clock.advance(Duration::from_millis(80 + 150));
+        assert_eq!(
+            policy.should_retry(None::<()>),
+            ControlFlow::Continue(Duration::ZERO)
+        );
+
+        assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+    }
+}
@@ -0,0 +1,184 @@
+//! Surfacing retry health as a readiness signal.
+//!
+//! When a dependency is down and every call is being retried into oblivion, the service is better
+//! off reporting itself as not ready, so that load balancers and orchestrators route around it,
+//! e.g. with a Kubernetes readiness probe. [`Readiness`] combines the shared state that policies
+//! already keep, like a [`SuccessRate`], into a single `is_ready()` signal.
+
+use std::fmt;
+
+use crate::{slow_start::SlowStart, success_rate::SuccessRate};
+
+type Check = Box<dyn Fn() -> bool + Send + Sync>;
+
+/// A readiness signal, ready only while all of its checks pass.
+///
+/// With the `tokio` feature, changes can also be followed on a [`watch`](::tokio::sync::watch)
+/// channel, see [`Readiness::subscribe`].
+///
+/// ```
+/// use futures_retry_policies::{readiness::Readiness, success_rate::SuccessRate};
+///
+/// // shared with the FailFast policies calling the dependency
+/// let success_rate = SuccessRate::new(0.5);
+/// let readiness = Readiness::new().success_rate(&success_rate, 0.2);
+/// assert!(readiness.is_ready());
+///
+/// for _ in 0..3 {
+///     success_rate.record(false);
+/// }
+/// assert!(!readiness.is_ready());
+/// ```
+pub struct Readiness {
+    checks: Vec<Check>,
+    #[cfg(feature = "tokio")]
+    tx: ::tokio::sync::watch::Sender<bool>,
+}
+
+impl fmt::Debug for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Readiness")
+            .field("checks", &self.checks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Readiness {
+    /// A signal with no checks, which is always ready
+    pub fn new() -> Self {
+        Self {
+            checks: Vec::new(),
+            #[cfg(feature = "tokio")]
+            tx: ::tokio::sync::watch::channel(true).0,
+        }
+    }
+
+    /// Only be ready while `check()` returns true
+    pub fn check(mut self, check: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    /// Only be ready while `success_rate` is at or above `threshold`
+    pub fn success_rate(self, success_rate: &SuccessRate, threshold: f64) -> Self {
+        let success_rate = success_rate.clone();
+        self.check(move || success_rate.rate() >= threshold)
+    }
+
+    /// Only be ready while `slow_start` isn't ramping back up after an outage
+    pub fn slow_start(self, slow_start: &SlowStart) -> Self {
+        let slow_start = slow_start.clone();
+        self.check(move || !slow_start.is_ramping())
+    }
+
+    /// Only be ready while `limit` has permits available
+    #[cfg(feature = "adaptive-limit")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "adaptive-limit")))]
+    pub fn adaptive_limit(self, limit: &crate::adaptive_limit::AdaptiveLimit) -> Self {
+        let limit = limit.clone();
+        self.check(move || !limit.is_overloaded())
+    }
+
+    /// Whether every check passes
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|check| check())
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+impl Readiness {
+    /// Follow the signal, as updated by [`refresh`](Readiness::refresh)
+    pub fn subscribe(&self) -> ::tokio::sync::watch::Receiver<bool> {
+        self.tx.subscribe()
+    }
+
+    /// Run the checks, and notify subscribers if the signal has changed. Returns the signal.
+    pub fn refresh(&self) -> bool {
+        let ready = self.is_ready();
+        self.tx.send_if_modified(|current| {
+            let changed = *current != ready;
+            *current = ready;
+            changed
+        });
+        ready
+    }
+
+    /// [Refresh](Readiness::refresh) the signal every `interval`, forever
+    ///
+    /// ```
+    /// use futures_retry_policies::{readiness::Readiness, success_rate::SuccessRate};
+    /// use std::{sync::Arc, time::Duration};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let success_rate = SuccessRate::default();
+    ///     let readiness = Arc::new(Readiness::new().success_rate(&success_rate, 0.2));
+    ///     let mut ready = readiness.subscribe();
+    ///
+    ///     tokio::spawn({
+    ///         let readiness = readiness.clone();
+    ///         async move { readiness.refresh_every(Duration::from_secs(1)).await }
+    ///     });
+    ///
+    ///     // eg. in the readiness probe handler
+    ///     assert!(*ready.borrow_and_update());
+    /// }
+    /// ```
+    pub async fn refresh_every(&self, interval: std::time::Duration) {
+        let mut interval = ::tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(::tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.refresh();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::Readiness;
+
+    #[test]
+    fn ready_only_while_all_checks_pass() {
+        let up = Arc::new(AtomicBool::new(true));
+        let readiness = Readiness::new().check({
+            let up = up.clone();
+            move || up.load(Ordering::Relaxed)
+        });
+        assert!(readiness.is_ready());
+
+        up.store(false, Ordering::Relaxed);
+        assert!(!readiness.is_ready());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn subscribers_see_changes() {
+        let up = Arc::new(AtomicBool::new(true));
+        let readiness = Readiness::new().check({
+            let up = up.clone();
+            move || up.load(Ordering::Relaxed)
+        });
+        let mut ready = readiness.subscribe();
+
+        assert!(readiness.refresh());
+        assert!(!ready.has_changed().unwrap());
+
+        up.store(false, Ordering::Relaxed);
+        assert!(!readiness.refresh());
+        assert!(ready.has_changed().unwrap());
+        assert!(!*ready.borrow_and_update());
+    }
+}
@@ -0,0 +1,251 @@
+//! Recording recent retries for post-mortems.
+//!
+//! When a process misbehaves, it helps to know what it has been retrying recently.
+//! A [`FlightRecorder`] keeps the last few retry decisions in a fixed-size ring buffer,
+//! which can be [dumped](FlightRecorder::dump) at any time, eg. from a debug endpoint.
+//!
+//! Recording never blocks or allocates. Events are dropped, rather than waited on,
+//! in the rare case that many threads record into the same slot at once.
+
+use std::{
+    ops::ControlFlow,
+    sync::{
+        atomic::{fence, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::RetryPolicy;
+
+/// A retry decision, as stored by a [`FlightRecorder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryEvent {
+    /// When the decision was made
+    pub at: SystemTime,
+    /// The attempt that was classified, starting at 1
+    pub attempt: u32,
+    /// How long the policy decided to wait before retrying, or `None` if it stopped
+    pub delay: Option<Duration>,
+}
+
+/// A lock-free ring buffer of the most recent [`RetryEvent`]s, shared between clones.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, recorder::{FlightRecorder, Recorded}, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let recorder = FlightRecorder::new(128);
+///
+///     let policy = Recorded::new(Iter::new([Duration::from_millis(10); 3]), &recorder);
+///     make_request.retry(policy).await.unwrap();
+///
+///     // later, when something looks wrong
+///     for event in recorder.dump() {
+///         println!("{:?}: attempt {} -> {:?}", event.at, event.attempt, event.delay);
+///     }
+///     # assert_eq!(recorder.dump().len(), 3);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FlightRecorder {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    next: AtomicU64,
+    slots: Box<[Slot]>,
+}
+
+/// A slot in the ring buffer, guarded by a sequence lock.
+///
+/// `seq` is odd while an event is being written. Once written, it holds `2 * (index + 1)`,
+/// where `index` counts every event recorded, so readers can order the events and
+/// detect slots that were overwritten while being read.
+#[derive(Debug, Default)]
+struct Slot {
+    seq: AtomicU64,
+    at: AtomicU64,
+    attempt: AtomicU32,
+    delay: AtomicU64,
+}
+
+const NO_DELAY: u64 = u64::MAX;
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(NO_DELAY - 1)
+}
+
+impl FlightRecorder {
+    /// Keep the last `capacity` events
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "a flight recorder needs room for at least one event"
+        );
+        Self {
+            inner: Arc::new(Inner {
+                next: AtomicU64::new(0),
+                slots: (0..capacity).map(|_| Slot::default()).collect(),
+            }),
+        }
+    }
+
+    /// Record an event, overwriting the oldest if the buffer is full
+    pub fn record(&self, event: RetryEvent) {
+        let index = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.inner.slots[(index % self.inner.slots.len() as u64) as usize];
+        let done = 2 * (index + 1);
+
+        let seq = slot.seq.load(Ordering::Relaxed);
+        // skip if another thread is writing this slot, or already wrote a newer event to it
+        if seq % 2 == 1 || seq >= done {
+            return;
+        }
+        if slot
+            .seq
+            .compare_exchange(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        // make sure readers that see any of the new fields also see the slot as being written
+        fence(Ordering::Release);
+
+        let at = event.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        slot.at.store(nanos(at), Ordering::Relaxed);
+        slot.attempt.store(event.attempt, Ordering::Relaxed);
+        slot.delay
+            .store(event.delay.map_or(NO_DELAY, nanos), Ordering::Relaxed);
+        slot.seq.store(done, Ordering::Release);
+    }
+
+    /// The recorded events, oldest first
+    pub fn dump(&self) -> Vec<RetryEvent> {
+        let mut events: Vec<(u64, RetryEvent)> = self
+            .inner
+            .slots
+            .iter()
+            .filter_map(|slot| {
+                let seq = slot.seq.load(Ordering::Acquire);
+                if seq == 0 || seq % 2 == 1 {
+                    return None;
+                }
+                let at = slot.at.load(Ordering::Relaxed);
+                let attempt = slot.attempt.load(Ordering::Relaxed);
+                let delay = slot.delay.load(Ordering::Relaxed);
+                fence(Ordering::Acquire);
+                if slot.seq.load(Ordering::Relaxed) != seq {
+                    // overwritten while reading
+                    return None;
+                }
+                let event = RetryEvent {
+                    at: UNIX_EPOCH + Duration::from_nanos(at),
+                    attempt,
+                    delay: (delay != NO_DELAY).then(|| Duration::from_nanos(delay)),
+                };
+                Some((seq, event))
+            })
+            .collect();
+        events.sort_by_key(|(seq, _)| *seq);
+        events.into_iter().map(|(_, event)| event).collect()
+    }
+}
+
+/// A [`RetryPolicy`] that records each of its decisions into a [`FlightRecorder`]
+#[derive(Debug, Clone)]
+pub struct Recorded<P> {
+    policy: P,
+    recorder: FlightRecorder,
+    attempts: u32,
+}
+
+impl<P> Recorded<P> {
+    /// Record the decisions made by `policy`
+    pub fn new(policy: P, recorder: &FlightRecorder) -> Self {
+        Self {
+            policy,
+            recorder: recorder.clone(),
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, R> RetryPolicy<R> for Recorded<P>
+where
+    P: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts = self.attempts.saturating_add(1);
+        let decision = self.policy.should_retry(result);
+        self.recorder.record(RetryEvent {
+            at: SystemTime::now(),
+            attempt: self.attempts,
+            delay: match &decision {
+                ControlFlow::Continue(delay) => Some(*delay),
+                ControlFlow::Break(_) => None,
+            },
+        });
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{FlightRecorder, RetryEvent};
+
+    fn event(attempt: u32) -> RetryEvent {
+        RetryEvent {
+            at: UNIX_EPOCH + Duration::from_secs(attempt.into()),
+            attempt,
+            delay: attempt
+                .is_multiple_of(2)
+                .then(|| Duration::from_millis(attempt.into())),
+        }
+    }
+
+    #[test]
+    fn keeps_most_recent() {
+        let recorder = FlightRecorder::new(3);
+        assert!(recorder.dump().is_empty());
+
+        for attempt in 1..=5 {
+            recorder.record(event(attempt));
+        }
+        assert_eq!(recorder.dump(), [3, 4, 5].map(event));
+    }
+
+    #[test]
+    fn concurrent_records() {
+        let recorder = FlightRecorder::new(16);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for attempt in 0..1000 {
+                        recorder.record(event(attempt));
+                    }
+                });
+            }
+        });
+
+        let events = recorder.dump();
+        assert!(!events.is_empty() && events.len() <= 16);
+        // every event is whole, never mixed from different writes
+        assert!(events.iter().all(|e| *e == event(e.attempt)));
+    }
+}
@@ -0,0 +1,217 @@
+//! Hot-reloading retry settings at runtime.
+//!
+//! Services often want to tune their retries from a config system, without a restart. A
+//! [`ConfigHandle`] holds the current settings and publishes new ones, and a [`HotReload`] policy
+//! picks them up at its next decision, so even retry loops that are already running follow the
+//! new settings from their next retry on.
+//!
+//! The settings are kept behind an [`RwLock`](std::sync::RwLock), which a running policy only
+//! reads after new settings were published. With the `arc-swap` feature, they're kept in an [`ArcSwap`](arc_swap::ArcSwap)
+//! instead, so neither reading nor publishing ever blocks.
+
+use std::{
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{backoff::Backoff, RetryPolicy, RetryPolicyBuilder};
+
+/// A policy that can take new settings while it is in use
+pub trait Reconfigure<C> {
+    /// Use `config` from now on, keeping any progress so far, like the number of retries
+    fn reconfigure(&mut self, config: &C);
+}
+
+/// Keeps the retries so far, and the time elapsed since the first attempt
+impl<C: Clone, K: Clone> Reconfigure<RetryPolicyBuilder<C, K>> for Backoff<C, K> {
+    fn reconfigure(&mut self, config: &RetryPolicyBuilder<C, K>) {
+        self.replace_config(config.clone());
+    }
+}
+
+#[derive(Debug)]
+struct Published<C> {
+    config: Slot<C>,
+    version: AtomicU64,
+}
+
+/// Where the current settings are kept
+#[cfg(feature = "arc-swap")]
+#[derive(Debug)]
+struct Slot<C>(arc_swap::ArcSwap<C>);
+
+#[cfg(feature = "arc-swap")]
+impl<C> Slot<C> {
+    fn new(config: C) -> Self {
+        Self(arc_swap::ArcSwap::from_pointee(config))
+    }
+
+    fn store(&self, config: C) {
+        self.0.store(Arc::new(config));
+    }
+
+    fn load(&self) -> Arc<C> {
+        self.0.load_full()
+    }
+}
+
+/// Where the current settings are kept
+#[cfg(not(feature = "arc-swap"))]
+#[derive(Debug)]
+struct Slot<C>(std::sync::RwLock<Arc<C>>);
+
+#[cfg(not(feature = "arc-swap"))]
+impl<C> Slot<C> {
+    fn new(config: C) -> Self {
+        Self(std::sync::RwLock::new(Arc::new(config)))
+    }
+
+    fn store(&self, config: C) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+    }
+
+    fn load(&self) -> Arc<C> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// The current settings for [`HotReload`] policies, shared between clones.
+///
+/// ```
+/// use futures_retry_policies::{reload::ConfigHandle, RetryPolicy, RetryPolicyBuilder};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// let config = ConfigHandle::new(RetryPolicyBuilder::fixed(Duration::from_secs(1)));
+/// let mut policy = config.policy();
+/// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Continue(Duration::from_secs(1)));
+///
+/// // from the config system
+/// config.publish(RetryPolicyBuilder::fixed(Duration::from_secs(5)).max_retries(1));
+/// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+/// ```
+#[derive(Debug)]
+pub struct ConfigHandle<C> {
+    published: Arc<Published<C>>,
+}
+
+impl<C> Clone for ConfigHandle<C> {
+    fn clone(&self) -> Self {
+        Self {
+            published: self.published.clone(),
+        }
+    }
+}
+
+impl<C> ConfigHandle<C> {
+    /// Start with `config`
+    pub fn new(config: C) -> Self {
+        Self {
+            published: Arc::new(Published {
+                config: Slot::new(config),
+                version: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Replace the settings for every [`HotReload`] policy, from their next decision
+    pub fn publish(&self, config: C) {
+        self.published.config.store(config);
+        self.published.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// The current settings
+    pub fn load(&self) -> Arc<C> {
+        self.published.config.load()
+    }
+
+    fn version(&self) -> u64 {
+        self.published.version.load(Ordering::Acquire)
+    }
+}
+
+impl<Cl: Clone, K: Clone> ConfigHandle<RetryPolicyBuilder<Cl, K>> {
+    /// A [`Backoff`] policy built from the current settings, following any that are published later
+    pub fn policy(&self) -> HotReload<Backoff<Cl, K>, RetryPolicyBuilder<Cl, K>> {
+        let version = self.version();
+        let policy = RetryPolicyBuilder::clone(&self.load()).build();
+        HotReload {
+            policy,
+            config: self.clone(),
+            version,
+        }
+    }
+}
+
+/// A [`RetryPolicy`] that [reconfigures](Reconfigure) the inner policy whenever new settings are
+/// published to its [`ConfigHandle`].
+#[derive(Debug, Clone)]
+pub struct HotReload<P, C> {
+    policy: P,
+    config: ConfigHandle<C>,
+    version: u64,
+}
+
+impl<P: Reconfigure<C>, C> HotReload<P, C> {
+    /// Retry with `policy`, reconfiguring it with the settings published to `config` after now
+    pub fn new(policy: P, config: &ConfigHandle<C>) -> Self {
+        Self {
+            policy,
+            config: config.clone(),
+            version: config.version(),
+        }
+    }
+}
+
+impl<P, C, R> RetryPolicy<R> for HotReload<P, C>
+where
+    P: RetryPolicy<R> + Reconfigure<C>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let version = self.config.version();
+        if version != self.version {
+            self.policy.reconfigure(&self.config.load());
+            self.version = version;
+        }
+        self.policy.should_retry(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::ConfigHandle;
+    use crate::{RetryPolicy, RetryPolicyBuilder};
+
+    #[test]
+    fn keeps_progress_across_reloads() {
+        let secs = Duration::from_secs;
+        let config = ConfigHandle::new(RetryPolicyBuilder::exponential(secs(1)));
+        let mut policy = config.policy();
+
+        assert_eq!(
+            policy.should_retry(None::<()>),
+            ControlFlow::Continue(secs(1))
+        );
+        assert_eq!(
+            policy.should_retry(None::<()>),
+            ControlFlow::Continue(secs(2))
+        );
+
+        // the third retry, with the new base
+        config.publish(RetryPolicyBuilder::exponential(secs(10)));
+        assert_eq!(
+            policy.should_retry(None::<()>),
+            ControlFlow::Continue(secs(40))
+        );
+        // a new loop starts from the beginning
+        assert_eq!(
+            config.policy().should_retry(None::<()>),
+            ControlFlow::Continue(secs(10))
+        );
+    }
+}
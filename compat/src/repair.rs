@@ -0,0 +1,139 @@
+//! Repairing local state before retrying.
+//!
+//! Some failures are caused by local state, like a stale cache entry or an expired session, and
+//! waiting won't fix them. [`retry_with_repair`] runs a repair step for the results a classifier
+//! marks as repairable, like clearing the cache entry or logging in again, and then retries
+//! straight away. Everything else is left to the retry policy as usual.
+
+use std::{future::Future, ops::ControlFlow, time::Duration};
+
+use crate::{Classify, RetryPolicy};
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function, repairing
+/// local state first for [repairable](Classify) results.
+///
+/// When `repairable` classifies a result as repairable, `repair` is run with it. If the repair
+/// succeeds, the next attempt starts immediately, without consulting the policy. If it fails, or
+/// the attempt straight after a repair fails again, the result goes to the policy, which applies
+/// its normal backoff. This way, a repair that doesn't help can't cause a busy loop.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, repair::retry_with_repair};
+/// use std::{cell::Cell, time::Duration};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Error { SessionExpired, Unavailable }
+/// # impl futures_retry_policies::ShouldRetry for Error {
+/// #     fn should_retry(&self, _: u32) -> bool { true }
+/// # }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let session = Cell::new(0);
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///
+///     let res = retry_with_repair(
+///         policy,
+///         tokio::time::sleep,
+///         || async {
+///             // the first session has expired
+///             if session.get() == 0 { Err(Error::SessionExpired) } else { Ok(session.get()) }
+///         },
+///         |res: &Result<u32, Error>| matches!(res, Err(Error::SessionExpired)),
+///         async |_: &Result<u32, Error>| {
+///             // log in again
+///             session.set(session.get() + 1);
+///             true
+///         },
+///     )
+///     .await;
+///     assert_eq!(res, Ok(1));
+/// }
+/// ```
+pub async fn retry_with_repair<Policy, Sleeper, Sleep, Futures, Fut, C, Repair>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+    mut repairable: C,
+    mut repair: Repair,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    C: Classify<Fut::Output>,
+    Repair: AsyncFnMut(&Fut::Output) -> bool,
+{
+    let mut attempts = 0u32;
+    let mut repaired = false;
+    loop {
+        let result = futures().await;
+        attempts += 1;
+
+        let just_repaired = std::mem::take(&mut repaired);
+        if !just_repaired && repairable.classify(&result, attempts) && repair(&result).await {
+            repaired = true;
+            continue;
+        }
+        match policy.should_retry(result) {
+            ControlFlow::Continue(delay) => sleeper(delay).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::retry_with_repair;
+    use crate::iter::Iter;
+
+    #[tokio::test(start_paused = true)]
+    async fn unhelpful_repairs_back_off() {
+        let start = tokio::time::Instant::now();
+        let (attempts, repairs) = (Cell::new(0), Cell::new(0));
+        let res = retry_with_repair(
+            Iter::new([Duration::from_secs(1); 2]),
+            tokio::time::sleep,
+            || async {
+                attempts.set(attempts.get() + 1);
+                None::<()>
+            },
+            |_: &Option<()>| true,
+            async |_: &Option<()>| {
+                repairs.set(repairs.get() + 1);
+                true
+            },
+        )
+        .await;
+
+        assert_eq!(res, None);
+        // repair, retry now, back off, repair, retry now, back off, repair, retry now, give up
+        assert_eq!(attempts.get(), 6);
+        assert_eq!(repairs.get(), 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_repairs_use_the_policy() {
+        let start = tokio::time::Instant::now();
+        let attempts = Cell::new(0);
+        retry_with_repair(
+            Iter::new([Duration::from_secs(1); 2]),
+            tokio::time::sleep,
+            || async {
+                attempts.set(attempts.get() + 1);
+                None::<()>
+            },
+            |_: &Option<()>| true,
+            async |_: &Option<()>| false,
+        )
+        .await;
+
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,132 @@
+//! Re-resolve addresses between connection attempts.
+//!
+//! When a connection keeps failing, it might be because the address it's connecting to
+//! went stale, eg. a Kubernetes service whose endpoints were replaced. [`retry_resolved`]
+//! resolves the address afresh before every attempt, so a retry doesn't just hit the same dead address.
+
+use std::{future::Future, ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// Retry a connection, calling `resolver` to get a fresh address before each attempt.
+///
+/// A failure to resolve is treated like a failed attempt, and goes through the policy too.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, resolve::retry_resolved, ShouldRetry};
+/// use std::{io, net::SocketAddr, time::Duration};
+/// use tokio::net::{lookup_host, TcpStream};
+///
+/// #[derive(Debug)]
+/// struct Error(io::Error);
+/// impl ShouldRetry for Error {
+///     fn should_retry(&self, _: u32) -> bool { true }
+/// }
+///
+/// async fn resolve() -> Result<SocketAddr, Error> {
+///     let mut addrs = lookup_host("localhost:0").await.map_err(Error)?;
+///     addrs.next().ok_or_else(|| Error(io::ErrorKind::NotFound.into()))
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 2]);
+///     let connection = retry_resolved(policy, tokio::time::sleep, resolve, |addr| async move {
+///         TcpStream::connect(addr).await.map_err(Error)
+///     })
+///     .await;
+///     # assert!(connection.is_err());
+/// }
+/// ```
+pub async fn retry_resolved<Policy, Sleeper, Sleep, Resolver, Resolve, Addr, Connect, Fut, T, E>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut resolver: Resolver,
+    mut connect: Connect,
+) -> Result<T, E>
+where
+    Policy: RetryPolicy<Result<T, E>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Resolver: FnMut() -> Resolve,
+    Resolve: Future<Output = Result<Addr, E>>,
+    Connect: FnMut(Addr) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    loop {
+        let result = match resolver().await {
+            Ok(addr) => connect(addr).await,
+            Err(err) => Err(err),
+        };
+        match policy.should_retry(result) {
+            ControlFlow::Continue(dur) => sleeper(dur).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_resolved;
+    use crate::{iter::Iter, ShouldRetry};
+
+    #[derive(Debug, PartialEq)]
+    struct Error;
+    impl ShouldRetry for Error {
+        fn should_retry(&self, _: u32) -> bool {
+            true
+        }
+    }
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn resolves_every_attempt() {
+        let mut resolved = 0;
+        let mut connected = vec![];
+        let res = retry_resolved(
+            Iter::new([Duration::ZERO; 3]),
+            sleep,
+            || {
+                resolved += 1;
+                let addr = resolved;
+                async move { Ok(addr) }
+            },
+            |addr| {
+                connected.push(addr);
+                async move {
+                    if addr < 3 {
+                        Err(Error)
+                    } else {
+                        Ok(addr)
+                    }
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(res, Ok(3));
+        assert_eq!(connected, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn resolve_failures_are_retried() {
+        let mut resolved = 0;
+        let res = retry_resolved(
+            Iter::new([Duration::ZERO; 3]),
+            sleep,
+            || {
+                resolved += 1;
+                let res = if resolved < 2 { Err(Error) } else { Ok(()) };
+                async move { res }
+            },
+            |()| async { Ok(()) },
+        )
+        .await;
+
+        assert_eq!(res, Ok(()));
+        assert_eq!(resolved, 2);
+    }
+}
@@ -0,0 +1,159 @@
+//! Acquiring a fresh resource for every attempt.
+//!
+//! When an attempt fails, the connection, permit or file handle it used may be the reason, eg.
+//! a connection that was reset, or a handle left in a bad state. [`retry_with_resource`] acquires
+//! a new resource for each attempt and releases it straight after, so nothing carries over into
+//! the next attempt. [`retry_with_resource_release`] also runs an async release hook, eg. to
+//! return a connection to a pool or shut it down cleanly.
+//!
+//! Failing to acquire a resource counts as a failed attempt, and is retried like any other error.
+
+use std::{future::Future, ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// Retry an attempt with a freshly acquired resource each time, using the given
+/// [retry policy](`RetryPolicy`) and sleep function.
+///
+/// The resource is dropped after each attempt.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, resource::retry_with_resource};
+/// use std::time::Duration;
+///
+/// struct Connection;
+/// # #[derive(Debug)]
+/// # struct Error;
+/// # impl futures_retry_policies::ShouldRetry for Error {
+/// #     fn should_retry(&self, _: u32) -> bool { true }
+/// # }
+///
+/// async fn connect() -> Result<Connection, Error> {
+///     // open a new connection
+///     Ok(Connection)
+/// }
+///
+/// async fn query(conn: &mut Connection) -> Result<u32, Error> {
+///     // run a query, which may fail and leave the connection broken
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return Err(Error) }
+///     Ok(42)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let res = retry_with_resource(policy, tokio::time::sleep, connect, query).await;
+///     assert_eq!(res.unwrap(), 42);
+/// }
+/// ```
+pub async fn retry_with_resource<Policy, Sleeper, Sleep, Acquire, Res, Attempt, T, E>(
+    policy: Policy,
+    sleeper: Sleeper,
+    acquire: Acquire,
+    attempt: Attempt,
+) -> Result<T, E>
+where
+    Policy: RetryPolicy<Result<T, E>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Acquire: AsyncFnMut() -> Result<Res, E>,
+    Attempt: AsyncFnMut(&mut Res) -> Result<T, E>,
+{
+    retry_with_resource_release(policy, sleeper, acquire, attempt, async |_| {}).await
+}
+
+/// Retry an attempt with a freshly acquired resource each time, using the given
+/// [retry policy](`RetryPolicy`) and sleep function.
+///
+/// After each attempt, the resource is passed to `release`, which is awaited before the policy
+/// decides whether to retry. If the retry future is dropped mid-attempt, the resource is dropped
+/// without being released.
+pub async fn retry_with_resource_release<
+    Policy,
+    Sleeper,
+    Sleep,
+    Acquire,
+    Res,
+    Attempt,
+    Release,
+    T,
+    E,
+>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut acquire: Acquire,
+    mut attempt: Attempt,
+    mut release: Release,
+) -> Result<T, E>
+where
+    Policy: RetryPolicy<Result<T, E>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Acquire: AsyncFnMut() -> Result<Res, E>,
+    Attempt: AsyncFnMut(&mut Res) -> Result<T, E>,
+    Release: AsyncFnMut(Res),
+{
+    loop {
+        let result = match acquire().await {
+            Ok(mut resource) => {
+                let result = attempt(&mut resource).await;
+                release(resource).await;
+                result
+            }
+            Err(err) => Err(err),
+        };
+        match policy.should_retry(result) {
+            ControlFlow::Continue(delay) => sleeper(delay).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, time::Duration};
+
+    use super::retry_with_resource_release;
+    use crate::{classified::Retryable, iter::Iter};
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn fresh_resource_per_attempt() {
+        let log = RefCell::new(vec![]);
+        let mut next = 0;
+        let res = retry_with_resource_release(
+            Iter::new([Duration::ZERO; 3]),
+            sleep,
+            async || {
+                next += 1;
+                log.borrow_mut().push(format!("acquire {next}"));
+                // the second resource can't be acquired
+                if next == 2 {
+                    Err(Retryable("unavailable"))
+                } else {
+                    Ok(next)
+                }
+            },
+            async |resource: &mut u32| match *resource {
+                1 => Err(Retryable("broken")),
+                n => Ok(n),
+            },
+            async |resource| log.borrow_mut().push(format!("release {resource}")),
+        )
+        .await;
+
+        assert_eq!(res, Ok(3));
+        assert_eq!(
+            log.into_inner(),
+            [
+                "acquire 1",
+                "release 1",
+                "acquire 2",
+                "acquire 3",
+                "release 3"
+            ]
+        );
+    }
+}
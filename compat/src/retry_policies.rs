@@ -1,6 +1,11 @@
 #![cfg(feature = "retry-policies")]
 #![cfg_attr(docsrs, doc(cfg(feature = "retry-policies")))]
 //! Polyfills for the [`retry_policies`] crate
+//!
+//! This targets the 0.2 releases of `retry_policies`, where the [`RetryDecision`] is made from the
+//! number of past retries alone. The 0.5 releases are supported by
+//! [`retry_policies_0_5`](crate::retry_policies_0_5), with the `retry-policies-0_5` feature.
+//!
 //! Retry a future using the given [backoff policy](`RetryPolicy`) and sleep function.
 //!
 //! ```
@@ -25,38 +30,259 @@
 //!     retry(policy, tokio::time::sleep, make_request).await
 //! }
 //! ```
-use std::{mem, ops::ControlFlow, time::Duration};
+use std::{borrow::Borrow, marker::PhantomData, mem, ops::ControlFlow, sync::Arc, time::Duration};
 
-use chrono::Utc;
-use retry_policies::RetryDecision;
+use chrono::{DateTime, Utc};
+use retry_policies::{policies::ExponentialBackoff, RetryDecision};
 
 use crate::RetryPolicy;
 
 // exported for backwards compatability
 pub use super::ShouldRetry;
 
-pub struct RetryPolicies<P> {
+pub use retry_policies::Jitter;
+
+/// [`RetryPolicy`] adapter for a [`retry_policies::RetryPolicy`].
+///
+/// The backoff policy `B` can be owned, or shared through `P`, for when configuration
+/// exposes it behind a reference, an [`Arc`] or a trait object.
+///
+/// ```
+/// use futures_retry_policies::{retry, retry_policies::RetryPolicies};
+/// use retry_policies::policies::ExponentialBackoff;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let backoff: Box<dyn retry_policies::RetryPolicy + Send + Sync> =
+///         Box::new(ExponentialBackoff::builder().build_with_max_retries(3));
+///
+///     let policy = RetryPolicies::from_box(backoff);
+///     retry(policy, tokio::time::sleep, make_request).await.unwrap();
+/// }
+/// ```
+pub struct RetryPolicies<P, B: ?Sized = P, N = fn() -> DateTime<Utc>> {
     policy: P,
     amount: u32,
+    now: N,
+    backoff: PhantomData<fn(&B)>,
 }
 
 impl<P> RetryPolicies<P> {
     pub fn new(policy: P) -> Self {
-        Self { policy, amount: 0 }
+        Self::from_borrowed(policy)
+    }
+}
+
+impl RetryPolicies<ExponentialBackoff> {
+    /// Configure an [`ExponentialBackoff`] and adapt it in one go.
+    ///
+    /// ```
+    /// use futures_retry_policies::retry_policies::{Jitter, RetryPolicies};
+    /// use std::time::Duration;
+    ///
+    /// let policy = RetryPolicies::builder()
+    ///     .retry_bounds(Duration::from_millis(100), Duration::from_secs(10))
+    ///     .jitter(Jitter::Bounded)
+    ///     .build_with_max_retries(5);
+    /// ```
+    pub fn builder() -> RetryPoliciesBuilder {
+        RetryPoliciesBuilder {
+            backoff: ExponentialBackoff::builder(),
+        }
+    }
+}
+
+/// Builder for a [`RetryPolicies`] using an [`ExponentialBackoff`], created with [`RetryPolicies::builder`].
+///
+/// This passes the settings through to [`retry_policies`' own builder](retry_policies::policies::ExponentialBackoffBuilder).
+#[must_use]
+pub struct RetryPoliciesBuilder {
+    backoff: retry_policies::policies::ExponentialBackoffBuilder,
+}
+
+impl RetryPoliciesBuilder {
+    /// Set the smallest and largest delays between retries. Defaults to between 1s and 30m.
+    ///
+    /// Panics if `min_retry_interval` is greater than `max_retry_interval`.
+    pub fn retry_bounds(self, min_retry_interval: Duration, max_retry_interval: Duration) -> Self {
+        Self {
+            backoff: self
+                .backoff
+                .retry_bounds(min_retry_interval, max_retry_interval),
+        }
+    }
+
+    /// Choose how the delays are randomised
+    pub fn jitter(self, jitter: Jitter) -> Self {
+        Self {
+            backoff: self.backoff.jitter(jitter),
+        }
+    }
+
+    /// Create the policy, giving up after `max_retries` retries
+    pub fn build_with_max_retries(self, max_retries: u32) -> RetryPolicies<ExponentialBackoff> {
+        RetryPolicies::new(self.backoff.build_with_max_retries(max_retries))
+    }
+}
+
+impl<P, N: Fn() -> DateTime<Utc>> RetryPolicies<P, P, N> {
+    /// Use `now` to get the current time, instead of [`Utc::now`].
+    ///
+    /// Backoff policies decide when the next retry should execute, and that time is turned into
+    /// a delay using `now`. This lets tests and simulations control time, as long as the backoff
+    /// policy uses the same time source.
+    ///
+    /// ```
+    /// use chrono::{DateTime, TimeZone, Utc};
+    /// use futures_retry_policies::{retry_policies::RetryPolicies, RetryPolicy};
+    /// use retry_policies::RetryDecision;
+    /// use std::{ops::ControlFlow, time::Duration};
+    ///
+    /// fn frozen() -> DateTime<Utc> {
+    ///     Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+    /// }
+    ///
+    /// /// Retries one second after the frozen time
+    /// struct OneSecond;
+    /// impl retry_policies::RetryPolicy for OneSecond {
+    ///     fn should_retry(&self, _: u32) -> RetryDecision {
+    ///         RetryDecision::Retry { execute_after: frozen() + chrono::Duration::seconds(1) }
+    ///     }
+    /// }
+    ///
+    /// let mut policy = RetryPolicies::new_with_clock(OneSecond, frozen);
+    /// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Continue(Duration::from_secs(1)));
+    /// ```
+    pub fn new_with_clock(policy: P, now: N) -> Self {
+        RetryPolicies::new(policy).with_clock(now)
+    }
+}
+
+impl<'a, B: ?Sized> RetryPolicies<&'a B, B> {
+    /// Use a backoff policy by reference
+    pub fn from_ref(policy: &'a B) -> Self {
+        Self::from_borrowed(policy)
     }
 }
 
-impl<P, R> RetryPolicy<R> for RetryPolicies<P>
+impl<B: ?Sized> RetryPolicies<Arc<B>, B> {
+    /// Use a shared backoff policy
+    pub fn from_arc(policy: Arc<B>) -> Self {
+        Self::from_borrowed(policy)
+    }
+}
+
+impl<B: ?Sized> RetryPolicies<Box<B>, B> {
+    /// Use a boxed backoff policy, such as a `Box<dyn retry_policies::RetryPolicy + Send + Sync>`
+    pub fn from_box(policy: Box<B>) -> Self {
+        Self::from_borrowed(policy)
+    }
+}
+
+impl<P, B: ?Sized> RetryPolicies<P, B> {
+    fn from_borrowed(policy: P) -> Self {
+        Self {
+            policy,
+            amount: 0,
+            now: Utc::now,
+            backoff: PhantomData,
+        }
+    }
+}
+
+impl<P, B: ?Sized, N> RetryPolicies<P, B, N> {
+    /// Use `now` to get the current time, instead of [`Utc::now`].
+    ///
+    /// See [`new_with_clock`](RetryPolicies::new_with_clock).
+    pub fn with_clock<N2: Fn() -> DateTime<Utc>>(self, now: N2) -> RetryPolicies<P, B, N2> {
+        RetryPolicies {
+            policy: self.policy,
+            amount: self.amount,
+            now,
+            backoff: PhantomData,
+        }
+    }
+
+    /// The delays planned for up to the next `n` retries, without changing the policy.
+    ///
+    /// If the backoff policy uses jitter, then each preview will differ from the delays
+    /// that end up being used.
+    ///
+    /// ```
+    /// use futures_retry_policies::retry_policies::RetryPolicies;
+    /// use retry_policies::{policies::ExponentialBackoff, Jitter};
+    /// use std::time::Duration;
+    ///
+    /// let backoff = ExponentialBackoff::builder()
+    ///     .retry_bounds(Duration::from_secs(1), Duration::from_secs(60))
+    ///     .jitter(Jitter::None)
+    ///     .build_with_max_retries(3);
+    /// let policy = RetryPolicies::new(backoff);
+    /// // allow for the clock moving on while previewing
+    /// let schedule: Vec<_> = policy.preview(5).map(|d| d.as_secs_f64().round()).collect();
+    /// assert_eq!(schedule, [1.0, 2.0, 4.0]);
+    /// ```
+    pub fn preview(&self, n: usize) -> impl Iterator<Item = Duration> + '_
+    where
+        P: Borrow<B>,
+        B: retry_policies::RetryPolicy,
+        N: Fn() -> DateTime<Utc>,
+    {
+        (self.amount..).take(n).map_while(|n_past_retries| {
+            match self.policy.borrow().should_retry(n_past_retries) {
+                RetryDecision::Retry { execute_after } => Some(self.delay_until(execute_after)),
+                RetryDecision::DoNotRetry => None,
+            }
+        })
+    }
+}
+
+impl<P, B, N, R> RetryPolicy<R> for RetryPolicies<P, B, N>
 where
-    P: retry_policies::RetryPolicy,
+    P: Borrow<B>,
+    B: retry_policies::RetryPolicy + ?Sized,
+    N: Fn() -> DateTime<Utc>,
     R: ShouldRetry,
 {
     fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let flow = match self.next_retry(result) {
+            ControlFlow::Continue(execute_after) => {
+                ControlFlow::Continue(self.delay_until(execute_after))
+            }
+            ControlFlow::Break(result) => ControlFlow::Break(result),
+        };
+        crate::verbose::observe("RetryPolicies", self.amount, flow)
+    }
+}
+
+impl<P, B: ?Sized, N: Fn() -> DateTime<Utc>> RetryPolicies<P, B, N> {
+    /// How long from now until `execute_after`
+    pub(crate) fn delay_until(&self, execute_after: DateTime<Utc>) -> Duration {
+        (execute_after - (self.now)()).to_std().unwrap_or_default()
+    }
+}
+
+impl<P, B, N> RetryPolicies<P, B, N>
+where
+    P: Borrow<B>,
+    B: retry_policies::RetryPolicy + ?Sized,
+{
+    /// Decide when the next retry should execute, if at all
+    pub(crate) fn next_retry<R: ShouldRetry>(
+        &mut self,
+        result: R,
+    ) -> ControlFlow<R, DateTime<Utc>> {
         let attempts = self.amount + 1;
         let n_past_retries = mem::replace(&mut self.amount, attempts);
-        match self.policy.should_retry(n_past_retries) {
+        match self.policy.borrow().should_retry(n_past_retries) {
             RetryDecision::Retry { execute_after } if result.should_retry(attempts) => {
-                ControlFlow::Continue((execute_after - Utc::now()).to_std().unwrap_or_default())
+                ControlFlow::Continue(execute_after)
             }
             _ => ControlFlow::Break(result),
         }
@@ -158,4 +384,36 @@ mod tests {
         assert_eq!(counter, 2);
         assert_eq!(policy.amount, 2);
     }
+
+    #[tokio::test]
+    async fn shared_backoff() {
+        let backoff = ExponentialBackoff::builder().build_with_max_retries(3);
+
+        let mut policy = RetryPolicies::from_ref(&backoff);
+        let _: AlwaysRetry = retry(&mut policy, sleep, || async { AlwaysRetry }).await;
+        assert_eq!(policy.amount, 4);
+
+        let mut policy = RetryPolicies::from_arc(std::sync::Arc::new(backoff));
+        let _: AlwaysRetry = retry(&mut policy, sleep, || async { AlwaysRetry }).await;
+        assert_eq!(policy.amount, 4);
+
+        let backoff: Box<dyn retry_policies::RetryPolicy + Send + Sync> =
+            Box::new(ExponentialBackoff::builder().build_with_max_retries(3));
+        let mut policy = RetryPolicies::from_box(backoff);
+        let _: AlwaysRetry = retry(&mut policy, sleep, || async { AlwaysRetry }).await;
+        assert_eq!(policy.amount, 4);
+    }
+
+    #[test]
+    fn builder() {
+        let policy = RetryPolicies::builder()
+            .retry_bounds(Duration::from_secs(2), Duration::from_secs(8))
+            .jitter(super::Jitter::None)
+            .build_with_max_retries(4);
+        let schedule: Vec<_> = policy
+            .preview(10)
+            .map(|d| d.as_secs_f64().round())
+            .collect();
+        assert_eq!(schedule, [2.0, 4.0, 8.0, 8.0]);
+    }
 }
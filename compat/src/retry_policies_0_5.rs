@@ -0,0 +1,292 @@
+#![cfg(feature = "retry-policies-0_5")]
+#![cfg_attr(docsrs, doc(cfg(feature = "retry-policies-0_5")))]
+//! Polyfills for the 0.5 releases of the [`retry_policies`](retry_policies_0_5) crate
+//!
+//! From 0.4 on, `retry_policies` works with [`SystemTime`] instead of `chrono`, and a
+//! [`RetryDecision`] is also given the time the first attempt started, so policies can give up
+//! after a total duration. The 0.2 releases are supported by [`retry_policies`](crate::retry_policies).
+//!
+//! With Cargo's renaming, both can be used at once:
+//!
+//! ```toml
+//! retry-policies = { package = "retry-policies", version = "0.5" }
+//! futures-retry-policies = { version = "0.3", features = ["retry-policies-0_5"] }
+//! ```
+//!
+//! ```
+//! use futures_retry_policies::{retry, retry_policies_0_5::RetryPolicies};
+//! use retry_policies_0_5::policies::ExponentialBackoff;
+//! use std::time::Duration;
+//!
+//! async fn make_request() -> Option<()> {
+//!     // make a request
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let backoff = ExponentialBackoff::builder()
+//!         .retry_bounds(Duration::from_millis(10), Duration::from_secs(1))
+//!         .build_with_total_retry_duration(Duration::from_secs(60));
+//!     let policy = RetryPolicies::new(backoff);
+//!     retry(policy, tokio::time::sleep, make_request).await.unwrap();
+//! }
+//! ```
+use std::{
+    borrow::Borrow,
+    marker::PhantomData,
+    mem,
+    ops::ControlFlow,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use retry_policies_0_5::{policies::ExponentialBackoff, RetryDecision};
+
+use crate::{RetryPolicy, ShouldRetry};
+
+pub use retry_policies_0_5::Jitter;
+
+/// [`RetryPolicy`] adapter for a 0.5 [`retry_policies::RetryPolicy`](retry_policies_0_5::RetryPolicy).
+///
+/// The backoff policy `B` can be owned, or shared through `P`, for when configuration
+/// exposes it behind a reference, an [`Arc`] or a trait object.
+///
+/// The backoff policy is told that the first attempt started when the adapter was created, so
+/// create it right before retrying.
+pub struct RetryPolicies<P, B: ?Sized = P, N = fn() -> SystemTime> {
+    policy: P,
+    amount: u32,
+    started: SystemTime,
+    now: N,
+    backoff: PhantomData<fn(&B)>,
+}
+
+impl<P> RetryPolicies<P> {
+    /// Adapt `policy`, which is owned
+    pub fn new(policy: P) -> Self {
+        Self::from_borrowed(policy)
+    }
+}
+
+impl RetryPolicies<ExponentialBackoff> {
+    /// Configure an [`ExponentialBackoff`] and adapt it in one go.
+    ///
+    /// ```
+    /// use futures_retry_policies::retry_policies_0_5::{Jitter, RetryPolicies};
+    /// use std::time::Duration;
+    ///
+    /// let policy = RetryPolicies::builder()
+    ///     .retry_bounds(Duration::from_millis(100), Duration::from_secs(10))
+    ///     .jitter(Jitter::Bounded)
+    ///     .base(3)
+    ///     .build_with_max_retries(5);
+    /// ```
+    pub fn builder() -> RetryPoliciesBuilder {
+        RetryPoliciesBuilder {
+            backoff: ExponentialBackoff::builder(),
+        }
+    }
+}
+
+/// Builder for a [`RetryPolicies`] using an [`ExponentialBackoff`], created with [`RetryPolicies::builder`].
+///
+/// This passes the settings through to
+/// [`retry_policies`' own builder](retry_policies_0_5::policies::ExponentialBackoffBuilder).
+#[must_use]
+pub struct RetryPoliciesBuilder {
+    backoff: retry_policies_0_5::policies::ExponentialBackoffBuilder,
+}
+
+impl RetryPoliciesBuilder {
+    /// Set the smallest and largest delays between retries. Defaults to between 1s and 30m.
+    ///
+    /// Panics if `min_retry_interval` is greater than `max_retry_interval`.
+    pub fn retry_bounds(self, min_retry_interval: Duration, max_retry_interval: Duration) -> Self {
+        Self {
+            backoff: self
+                .backoff
+                .retry_bounds(min_retry_interval, max_retry_interval),
+        }
+    }
+
+    /// Choose how the delays are randomised
+    pub fn jitter(self, jitter: Jitter) -> Self {
+        Self {
+            backoff: self.backoff.jitter(jitter),
+        }
+    }
+
+    /// Multiply the delay by `base` after each retry. Defaults to 2.
+    pub fn base(self, base: u32) -> Self {
+        Self {
+            backoff: self.backoff.base(base),
+        }
+    }
+
+    /// Create the policy, giving up after `max_retries` retries
+    pub fn build_with_max_retries(self, max_retries: u32) -> RetryPolicies<ExponentialBackoff> {
+        RetryPolicies::new(self.backoff.build_with_max_retries(max_retries))
+    }
+}
+
+impl<'a, B: ?Sized> RetryPolicies<&'a B, B> {
+    /// Use a backoff policy by reference
+    pub fn from_ref(policy: &'a B) -> Self {
+        Self::from_borrowed(policy)
+    }
+}
+
+impl<B: ?Sized> RetryPolicies<Arc<B>, B> {
+    /// Use a shared backoff policy
+    pub fn from_arc(policy: Arc<B>) -> Self {
+        Self::from_borrowed(policy)
+    }
+}
+
+impl<B: ?Sized> RetryPolicies<Box<B>, B> {
+    /// Use a boxed backoff policy, such as a `Box<dyn retry_policies::RetryPolicy + Send + Sync>`
+    pub fn from_box(policy: Box<B>) -> Self {
+        Self::from_borrowed(policy)
+    }
+}
+
+impl<P, B: ?Sized> RetryPolicies<P, B> {
+    fn from_borrowed(policy: P) -> Self {
+        Self {
+            policy,
+            amount: 0,
+            started: SystemTime::now(),
+            now: SystemTime::now,
+            backoff: PhantomData,
+        }
+    }
+}
+
+impl<P, B: ?Sized, N> RetryPolicies<P, B, N> {
+    /// Use `now` to get the current time, instead of [`SystemTime::now`].
+    ///
+    /// Backoff policies decide when the next retry should execute, and that time is turned into
+    /// a delay using `now`. The first attempt is counted as starting at the current time of `now`.
+    ///
+    /// ```
+    /// use futures_retry_policies::{retry_policies_0_5::RetryPolicies, RetryPolicy};
+    /// use retry_policies_0_5::RetryDecision;
+    /// use std::{ops::ControlFlow, time::{Duration, SystemTime}};
+    ///
+    /// fn frozen() -> SystemTime {
+    ///     SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    /// }
+    ///
+    /// /// Retries one second after the first attempt started
+    /// struct OneSecond;
+    /// impl retry_policies_0_5::RetryPolicy for OneSecond {
+    ///     fn should_retry(&self, started: SystemTime, _: u32) -> RetryDecision {
+    ///         RetryDecision::Retry { execute_after: started + Duration::from_secs(1) }
+    ///     }
+    /// }
+    ///
+    /// let mut policy = RetryPolicies::new(OneSecond).with_clock(frozen);
+    /// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Continue(Duration::from_secs(1)));
+    /// ```
+    pub fn with_clock<N2: Fn() -> SystemTime>(self, now: N2) -> RetryPolicies<P, B, N2> {
+        RetryPolicies {
+            policy: self.policy,
+            amount: self.amount,
+            started: now(),
+            now,
+            backoff: PhantomData,
+        }
+    }
+}
+
+impl<P, B, N, R> RetryPolicy<R> for RetryPolicies<P, B, N>
+where
+    P: Borrow<B>,
+    B: retry_policies_0_5::RetryPolicy + ?Sized,
+    N: Fn() -> SystemTime,
+    R: ShouldRetry,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let attempts = self.amount + 1;
+        let n_past_retries = mem::replace(&mut self.amount, attempts);
+        let flow = match self
+            .policy
+            .borrow()
+            .should_retry(self.started, n_past_retries)
+        {
+            RetryDecision::Retry { execute_after } if result.should_retry(attempts) => {
+                let delay = execute_after
+                    .duration_since((self.now)())
+                    .unwrap_or_default();
+                ControlFlow::Continue(delay)
+            }
+            _ => ControlFlow::Break(result),
+        };
+        crate::verbose::observe("RetryPolicies", self.amount, flow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        ops::ControlFlow,
+        time::{Duration, SystemTime},
+    };
+
+    use retry_policies_0_5::RetryDecision;
+
+    use super::{Jitter, RetryPolicies};
+    use crate::RetryPolicy;
+
+    thread_local! {
+        static NOW: Cell<SystemTime> = const { Cell::new(SystemTime::UNIX_EPOCH) };
+    }
+
+    fn now() -> SystemTime {
+        NOW.get()
+    }
+
+    /// Retries every second, for 3 seconds after the first attempt started
+    struct Timed;
+    impl retry_policies_0_5::RetryPolicy for Timed {
+        fn should_retry(&self, started: SystemTime, _: u32) -> RetryDecision {
+            if now() >= started + Duration::from_secs(3) {
+                return RetryDecision::DoNotRetry;
+            }
+            RetryDecision::Retry {
+                execute_after: now() + Duration::from_secs(1),
+            }
+        }
+    }
+
+    #[test]
+    fn passes_start_time() {
+        NOW.set(SystemTime::UNIX_EPOCH + Duration::from_secs(100));
+        let mut policy = RetryPolicies::new(Timed).with_clock(now);
+
+        let mut retries = 0;
+        while let ControlFlow::Continue(delay) = policy.should_retry(None::<()>) {
+            assert_eq!(delay, Duration::from_secs(1));
+            NOW.set(NOW.get() + delay);
+            retries += 1;
+        }
+        assert_eq!(retries, 3);
+    }
+
+    #[test]
+    fn builder() {
+        let mut policy = RetryPolicies::builder()
+            .retry_bounds(Duration::from_secs(2), Duration::from_secs(8))
+            .jitter(Jitter::None)
+            .build_with_max_retries(4);
+        // allow for the clock moving on while deciding
+        let schedule: Vec<_> =
+            std::iter::from_fn(|| policy.should_retry(None::<()>).continue_value())
+                .map(|d| d.as_secs_f64().round())
+                .collect();
+        assert_eq!(schedule, [2.0, 4.0, 8.0, 8.0]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Observing only a sample of retry sequences.
+//!
+//! In very high throughput clients, logging or tracing every retry can cost more than the
+//! retries themselves. [`Sampler::sample`] decides once per retry sequence whether to wrap the
+//! policy with an observing decorator, like [`Verbose`](crate::verbose::Verbose) or
+//! [`Recorded`](crate::recorder::Recorded), so only a bounded fraction of sequences pay for it.
+//!
+//! ```
+//! use futures_retry_policies::{iter::Iter, sample::Sampler, verbose::Verbose, tokio::RetryFutureExt};
+//! use std::time::Duration;
+//!
+//! async fn make_request() -> Option<()> {
+//!     // make a request
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     // shared by the whole client
+//!     let sampler = Sampler::one_in(100);
+//!
+//!     let policy = sampler.sample(Iter::new([Duration::from_millis(10); 3]), |policy| {
+//!         Verbose::new(policy, "my_app::client")
+//!     });
+//!     make_request.retry(policy).await.unwrap();
+//! }
+//! ```
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::RetryPolicy;
+
+/// Decides which retry sequences are observed, shared between clones
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    mode: Mode,
+}
+
+#[derive(Debug, Clone)]
+enum Mode {
+    OneIn { n: u64, count: Arc<AtomicU64> },
+    Random { fraction: f64 },
+}
+
+impl Sampler {
+    /// Observe every `n`th sequence, starting with the first. `0` observes nothing.
+    pub fn one_in(n: u64) -> Self {
+        Self {
+            mode: Mode::OneIn {
+                n,
+                count: Arc::new(AtomicU64::new(0)),
+            },
+        }
+    }
+
+    /// Observe each sequence with probability `fraction`, between `0.0` and `1.0`
+    pub fn random(fraction: f64) -> Self {
+        Self {
+            mode: Mode::Random {
+                fraction: fraction.clamp(0.0, 1.0),
+            },
+        }
+    }
+
+    /// Whether the next sequence should be observed
+    pub fn should_sample(&self) -> bool {
+        match &self.mode {
+            Mode::OneIn { n: 0, .. } => false,
+            Mode::OneIn { n, count } => count.fetch_add(1, Ordering::Relaxed).is_multiple_of(*n),
+            Mode::Random { fraction } => {
+                let bits = RandomState::new().build_hasher().finish();
+                ((bits >> 11) as f64 / (1u64 << 53) as f64) < *fraction
+            }
+        }
+    }
+
+    /// Wrap `policy` with `observe` if this sequence is sampled
+    pub fn sample<P, O>(&self, policy: P, observe: impl FnOnce(P) -> O) -> Sampled<P, O> {
+        if self.should_sample() {
+            Sampled::Observed(observe(policy))
+        } else {
+            Sampled::Unobserved(policy)
+        }
+    }
+}
+
+/// A [`RetryPolicy`] that may or may not be observed, returned by [`Sampler::sample`]
+#[derive(Debug, Clone)]
+pub enum Sampled<P, O> {
+    /// This sequence was sampled, and is observed
+    Observed(O),
+    /// This sequence wasn't sampled
+    Unobserved(P),
+}
+
+impl<P, O, R> RetryPolicy<R> for Sampled<P, O>
+where
+    P: RetryPolicy<R>,
+    O: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        match self {
+            Sampled::Observed(policy) => policy.should_retry(result),
+            Sampled::Unobserved(policy) => policy.should_retry(result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sampled, Sampler};
+
+    #[test]
+    fn one_in() {
+        let sampler = Sampler::one_in(3);
+        let sampled: Vec<_> = (0..7).map(|_| sampler.clone().should_sample()).collect();
+        assert_eq!(sampled, [true, false, false, true, false, false, true]);
+
+        assert!(!Sampler::one_in(0).should_sample());
+    }
+
+    #[test]
+    fn random_extremes() {
+        assert!((0..100).all(|_| Sampler::random(1.0).should_sample()));
+        assert!((0..100).all(|_| !Sampler::random(0.0).should_sample()));
+    }
+
+    #[test]
+    fn wraps_when_sampled() {
+        let sampler = Sampler::one_in(2);
+        assert!(matches!(sampler.sample((), |()| 1), Sampled::Observed(1)));
+        assert!(matches!(
+            sampler.sample((), |()| 1),
+            Sampled::Unobserved(())
+        ));
+    }
+}
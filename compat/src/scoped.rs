@@ -0,0 +1,154 @@
+//! Retries that stop once the component that wanted them is gone.
+//!
+//! A retry loop running in a spawned task can easily outlive the component that asked for the work.
+//! [`retry_scoped`] checks an [`Owner`] guard, like a [`Weak`](std::sync::Weak) reference to the
+//! component, and stops scheduling new attempts once it has been dropped.
+
+use std::{future::Future, ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// Something that can report whether the owner of a retry loop still exists
+pub trait Owner {
+    /// Whether the owner is still around to receive the result
+    fn is_alive(&self) -> bool;
+}
+
+impl<T: ?Sized> Owner for std::sync::Weak<T> {
+    fn is_alive(&self) -> bool {
+        self.strong_count() > 0
+    }
+}
+
+impl<T: ?Sized> Owner for std::rc::Weak<T> {
+    fn is_alive(&self) -> bool {
+        self.strong_count() > 0
+    }
+}
+
+/// The result of [`retry_scoped`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scoped<R> {
+    /// The policy stopped retrying
+    Finished(R),
+    /// The owner was dropped before the policy stopped retrying
+    Cancelled,
+}
+
+impl<R> Scoped<R> {
+    /// Whether the retries were cancelled
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Scoped::Cancelled)
+    }
+
+    /// Get the final result, if the retries weren't cancelled
+    pub fn finished(self) -> Option<R> {
+        match self {
+            Scoped::Finished(res) => Some(res),
+            Scoped::Cancelled => None,
+        }
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// for as long as the `owner` is alive.
+///
+/// The owner is checked before each sleep, and again before each new attempt.
+/// Attempts that are already running are allowed to complete.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, scoped::{retry_scoped, Scoped}};
+/// use std::{sync::Arc, time::Duration};
+///
+/// struct Component;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let component = Arc::new(Component);
+///     let owner = Arc::downgrade(&component);
+///
+///     let task = tokio::spawn(async move {
+///         let policy = Iter::new(std::iter::repeat(Duration::from_millis(10)));
+///         // retries forever, until the component shuts down
+///         retry_scoped(owner, policy, tokio::time::sleep, || async { None::<()> }).await
+///     });
+///
+///     tokio::time::sleep(Duration::from_millis(50)).await;
+///     drop(component);
+///     assert!(task.await.unwrap().is_cancelled());
+/// }
+/// ```
+pub async fn retry_scoped<O, Policy, Sleeper, Sleep, Futures, Fut>(
+    owner: O,
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+) -> Scoped<Fut::Output>
+where
+    O: Owner,
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    loop {
+        let dur = match policy.should_retry(futures().await) {
+            ControlFlow::Continue(dur) => dur,
+            ControlFlow::Break(result) => break Scoped::Finished(result),
+        };
+        if !owner.is_alive() {
+            break Scoped::Cancelled;
+        }
+        sleeper(dur).await;
+        if !owner.is_alive() {
+            break Scoped::Cancelled;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::{retry_scoped, Scoped};
+    use crate::iter::Iter;
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn finishes_while_alive() {
+        let owner = Rc::new(());
+        let mut attempts = 0;
+        let res = retry_scoped(
+            Rc::downgrade(&owner),
+            Iter::new([Duration::ZERO; 3]),
+            sleep,
+            || {
+                attempts += 1;
+                let res = (attempts == 2).then_some(attempts);
+                async move { res }
+            },
+        )
+        .await;
+        assert_eq!(res, Scoped::Finished(Some(2)));
+    }
+
+    #[tokio::test]
+    async fn cancelled_when_dropped() {
+        let mut owner = Some(Rc::new(()));
+        let weak = Rc::downgrade(owner.as_ref().unwrap());
+        let mut attempts = 0;
+        let res = retry_scoped(weak, Iter::new([Duration::ZERO; 5]), sleep, || {
+            attempts += 1;
+            if attempts == 2 {
+                owner = None;
+            }
+            async { None::<()> }
+        })
+        .await;
+        assert!(res.is_cancelled());
+        assert_eq!(res.finished(), None);
+        assert_eq!(attempts, 2);
+    }
+}
@@ -0,0 +1,182 @@
+//! Retry a queue of operations strictly in order.
+//!
+//! For things like ordered event publishing, a later operation must not run before an
+//! earlier one has either succeeded, or been given up on. [`SerializedRetry`] owns a queue
+//! of operations and retries each one in turn, handing the ones that never succeed to a
+//! dead-letter callback before moving on.
+
+use std::{collections::VecDeque, future::Future, time::Duration};
+
+use crate::{retry, RetryPolicy};
+
+/// A queue of operations that are retried one at a time, in order.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, serialized::SerializedRetry, ShouldRetry};
+/// use std::time::Duration;
+///
+/// #[derive(Debug)]
+/// struct PublishError(&'static str);
+/// impl ShouldRetry for PublishError {
+///     fn should_retry(&self, _: u32) -> bool { true }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut dead = vec![];
+///     let mut queue = SerializedRetry::new(
+///         || Iter::new([Duration::from_millis(10); 2]),
+///         tokio::time::sleep,
+///         |_op, PublishError(event)| dead.push(event),
+///     );
+///
+///     for event in ["created", "poisoned", "deleted"] {
+///         queue.push(move || async move {
+///             // publish the event
+///             if event == "poisoned" { Err(PublishError(event)) } else { Ok(event) }
+///         });
+///     }
+///
+///     # let mut published = vec![];
+///     while let Some(event) = queue.next().await {
+///         println!("published {event}");
+///         # published.push(event);
+///     }
+///     # drop(queue);
+///     # assert_eq!(published, ["created", "deleted"]);
+///     # assert_eq!(dead, ["poisoned"]);
+/// }
+/// ```
+pub struct SerializedRetry<Op, NewPolicy, Sleeper, DeadLetter> {
+    pending: VecDeque<Op>,
+    new_policy: NewPolicy,
+    sleeper: Sleeper,
+    dead_letter: DeadLetter,
+}
+
+impl<Op, NewPolicy, Sleeper, DeadLetter> SerializedRetry<Op, NewPolicy, Sleeper, DeadLetter> {
+    /// Create an empty queue.
+    ///
+    /// `new_policy` creates a fresh policy for each operation, and `dead_letter` is given
+    /// each operation that the policy gave up on, along with its last error.
+    pub fn new(new_policy: NewPolicy, sleeper: Sleeper, dead_letter: DeadLetter) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            new_policy,
+            sleeper,
+            dead_letter,
+        }
+    }
+
+    /// Add an operation to the back of the queue
+    pub fn push(&mut self, op: Op) {
+        self.pending.push_back(op);
+    }
+
+    /// How many operations are yet to be processed
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether there are no operations left to process
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Process operations in order until one succeeds, and return its value.
+    ///
+    /// Operations that fail are passed to the dead-letter callback. Returns `None` once the queue is empty.
+    ///
+    /// If this future is dropped, the operation being retried stays at the front of the queue.
+    pub async fn next<Policy, Sleep, Fut, T, E>(&mut self) -> Option<T>
+    where
+        Op: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        NewPolicy: FnMut() -> Policy,
+        Policy: RetryPolicy<Result<T, E>>,
+        Sleeper: FnMut(Duration) -> Sleep,
+        Sleep: Future<Output = ()>,
+        DeadLetter: FnMut(Op, E),
+    {
+        loop {
+            let op = self.pending.front_mut()?;
+            let result = retry((self.new_policy)(), &mut self.sleeper, op).await;
+            let op = self.pending.pop_front()?;
+            match result {
+                Ok(value) => return Some(value),
+                Err(err) => (self.dead_letter)(op, err),
+            }
+        }
+    }
+
+    /// Process every operation in the queue, discarding the successful values.
+    pub async fn drain<Policy, Sleep, Fut, T, E>(&mut self)
+    where
+        Op: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        NewPolicy: FnMut() -> Policy,
+        Policy: RetryPolicy<Result<T, E>>,
+        Sleeper: FnMut(Duration) -> Sleep,
+        Sleep: Future<Output = ()>,
+        DeadLetter: FnMut(Op, E),
+    {
+        while self.next().await.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, time::Duration};
+
+    use super::SerializedRetry;
+    use crate::{iter::Iter, ShouldRetry};
+
+    #[derive(Debug)]
+    struct Error(u32);
+    impl ShouldRetry for Error {
+        fn should_retry(&self, _: u32) -> bool {
+            true
+        }
+    }
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn in_order() {
+        let log = RefCell::new(vec![]);
+        let mut dead = vec![];
+        let mut queue = SerializedRetry::new(
+            || Iter::new([Duration::ZERO; 2]),
+            sleep,
+            |_, Error(id)| dead.push(id),
+        );
+
+        for id in 0..3 {
+            let log = &log;
+            let mut attempts = 0;
+            queue.push(move || {
+                attempts += 1;
+                log.borrow_mut().push((id, attempts));
+                // the middle operation always fails, the others succeed on their second attempt
+                let res = if id == 1 || attempts < 2 {
+                    Err(Error(id))
+                } else {
+                    Ok(id)
+                };
+                async move { res }
+            });
+        }
+
+        assert_eq!(queue.next().await, Some(0));
+        assert_eq!(queue.len(), 2);
+        queue.drain().await;
+        assert!(queue.is_empty());
+        drop(queue);
+
+        assert_eq!(
+            log.into_inner(),
+            [(0, 1), (0, 2), (1, 1), (1, 2), (1, 3), (2, 1), (2, 2)]
+        );
+        assert_eq!(dead, [1]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Comparing a new retry policy against the current one, before switching to it.
+//!
+//! A [`Shadow`] makes every decision with the primary policy, but also asks a shadow policy what
+//! it would have done with the same result, and reports whenever the two disagree. Running it
+//! against production traffic shows how a rollout would change retries, without the risk.
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// A decision where the shadow policy disagreed with the primary policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// The attempt that was classified, starting at 1
+    pub attempt: u32,
+    /// How long the primary policy waited before retrying, or `None` if it stopped
+    pub primary: Option<Duration>,
+    /// How long the shadow policy would have waited, or `None` if it would have stopped
+    pub shadow: Option<Duration>,
+}
+
+/// A [`RetryPolicy`] that retries with `P`, and reports where `Q` would have decided differently.
+///
+/// The shadow policy is given a clone of every result. Once it has stopped, it isn't asked again,
+/// and every further retry of the primary policy is a divergence.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, shadow::{Divergence, Shadow}, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let mut divergences = vec![];
+/// let mut policy = Shadow::new(
+///     Iter::new([Duration::from_secs(1); 3]),
+///     Iter::new([Duration::from_secs(2); 1]),
+///     |divergence| divergences.push(divergence),
+/// );
+///
+/// assert_eq!(policy.should_retry(None::<()>).continue_value(), Some(Duration::from_secs(1)));
+/// assert_eq!(policy.should_retry(None::<()>).continue_value(), Some(Duration::from_secs(1)));
+/// drop(policy);
+///
+/// assert_eq!(divergences, [
+///     Divergence { attempt: 1, primary: Some(Duration::from_secs(1)), shadow: Some(Duration::from_secs(2)) },
+///     Divergence { attempt: 2, primary: Some(Duration::from_secs(1)), shadow: None },
+/// ]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Shadow<P, Q, F> {
+    primary: P,
+    shadow: Option<Q>,
+    on_divergence: F,
+    tolerance: Duration,
+    attempts: u32,
+}
+
+impl<P, Q, F: FnMut(Divergence)> Shadow<P, Q, F> {
+    /// Retry with `primary`, calling `on_divergence` whenever `shadow` decides differently
+    pub fn new(primary: P, shadow: Q, on_divergence: F) -> Self {
+        Self {
+            primary,
+            shadow: Some(shadow),
+            on_divergence,
+            tolerance: Duration::ZERO,
+            attempts: 0,
+        }
+    }
+
+    /// Treat delays that differ by at most `tolerance` as the same, eg. to allow for jitter
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl<P, Q, F, R> RetryPolicy<R> for Shadow<P, Q, F>
+where
+    P: RetryPolicy<R>,
+    Q: RetryPolicy<R>,
+    F: FnMut(Divergence),
+    R: Clone,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        let shadow = self
+            .shadow
+            .as_mut()
+            .and_then(|shadow| shadow.should_retry(result.clone()).continue_value());
+        if shadow.is_none() {
+            self.shadow = None;
+        }
+
+        let flow = self.primary.should_retry(result);
+        let primary = flow.clone().continue_value();
+        let agrees = match (primary, shadow) {
+            (Some(primary), Some(shadow)) => primary.abs_diff(shadow) <= self.tolerance,
+            (primary, shadow) => primary.is_none() && shadow.is_none(),
+        };
+        if !agrees {
+            (self.on_divergence)(Divergence {
+                attempt: self.attempts,
+                primary,
+                shadow,
+            });
+        }
+        flow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Shadow;
+    use crate::{iter::Iter, RetryPolicy};
+
+    #[test]
+    fn agreeing_within_tolerance() {
+        let mut divergences = 0;
+        let mut policy = Shadow::new(
+            Iter::new([Duration::from_millis(100); 2]),
+            Iter::new([Duration::from_millis(110); 2]),
+            |_| divergences += 1,
+        )
+        .tolerance(Duration::from_millis(10));
+
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert!(policy.should_retry(None::<()>).is_break());
+        assert!(policy.should_retry(Some(())).is_break());
+        drop(policy);
+        assert_eq!(divergences, 0);
+    }
+}
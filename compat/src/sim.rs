@@ -0,0 +1,329 @@
+#![cfg(feature = "sim")]
+#![cfg_attr(docsrs, doc(cfg(feature = "sim")))]
+//! Deterministic simulations of retry loops, for testing policies.
+//!
+//! A [`Sim`] runs a retry loop against a scripted sequence of results, on a virtual clock.
+//! No real time passes, so tests can assert the exact timeline of attempts that a policy
+//! produces, including how long each attempt took and how long was waited in between.
+//!
+//! ```
+//! use futures_retry_policies::{
+//!     classified::{Classified, Hinted},
+//!     iter::Iter,
+//!     sim::{Sim, Step},
+//! };
+//! use std::time::Duration;
+//!
+//! let secs = Duration::from_secs;
+//! let sim = Sim::new();
+//! let timeline = sim.run(
+//!     Hinted(Iter::new([secs(1); 5])),
+//!     [
+//!         // fail
+//!         Step::new(Classified::new("unavailable", true)),
+//!         // fail, with the server asking to wait 2s, after taking 3s to respond
+//!         Step::new(Classified::new("slow down", true).with_delay_hint(secs(2))).taking(secs(3)),
+//!         // ok
+//!         Step::new(Classified::new("ok", false)),
+//!     ],
+//! );
+//!
+//! assert_eq!(timeline.result.into_inner(), "ok");
+//! assert_eq!(timeline.start_times(), [secs(0), secs(1), secs(6)]);
+//! assert_eq!(timeline.elapsed, secs(6));
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::{ready, Future, Ready},
+    ops::ControlFlow,
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use crate::{
+    clock::{Clock, MockClock},
+    retry_with_sleeper, RetryPolicy, RetrySleeper,
+};
+
+/// One scripted attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step<R> {
+    result: R,
+    duration: Duration,
+}
+
+impl<R> Step<R> {
+    /// An attempt that immediately returns `result`
+    pub fn new(result: R) -> Self {
+        Self {
+            result,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Take `duration` of virtual time before returning the result
+    pub fn taking(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+/// A record of one attempt in a [`Timeline`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttemptRecord {
+    /// When the attempt started, on the virtual clock
+    pub started: Duration,
+    /// When the attempt finished, on the virtual clock
+    pub finished: Duration,
+    /// How long the policy waited after this attempt, if it retried
+    pub delay: Option<Duration>,
+}
+
+/// Everything that happened during a [`Sim::run`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline<R> {
+    /// Each attempt that was made, in order
+    pub attempts: Vec<AttemptRecord>,
+    /// The result the retry loop returned
+    pub result: R,
+    /// How much virtual time passed from the first attempt starting to the result being returned
+    pub elapsed: Duration,
+}
+
+impl<R> Timeline<R> {
+    /// When each attempt started
+    pub fn start_times(&self) -> Vec<Duration> {
+        self.attempts.iter().map(|a| a.started).collect()
+    }
+
+    /// The delays waited between attempts
+    pub fn delays(&self) -> Vec<Duration> {
+        self.attempts.iter().filter_map(|a| a.delay).collect()
+    }
+}
+
+/// One decision made by a policy in [`simulate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision<R> {
+    /// The attempt that was classified, starting at 1
+    pub attempt: u32,
+    /// When the decision was made, on the virtual clock
+    pub at: Duration,
+    /// What the policy decided
+    pub flow: ControlFlow<R, Duration>,
+}
+
+/// A deterministic harness to run retry loops on a virtual clock.
+///
+/// Policies that read the time, like [`Backoff`](crate::backoff::Backoff) or
+/// [`FixedRate`](crate::rate::FixedRate), can be given [`Sim::clock`] to follow the virtual time.
+#[derive(Debug, Clone, Default)]
+pub struct Sim {
+    clock: MockClock,
+}
+
+impl Sim {
+    /// Create a simulation, starting at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The virtual clock of this simulation
+    pub fn clock(&self) -> MockClock {
+        self.clock.clone()
+    }
+
+    /// Run `policy` against the scripted results, returning every decision it made.
+    ///
+    /// Unlike [`Sim::run`], this doesn't run a retry loop at all, so attempts take no time, and
+    /// it stops early rather than panicking if the policy wants more results than were given.
+    pub fn simulate<P, R>(
+        &self,
+        mut policy: P,
+        results: impl IntoIterator<Item = R>,
+    ) -> Vec<Decision<R>>
+    where
+        P: RetryPolicy<R>,
+    {
+        let start = self.clock.now();
+        let mut decisions = Vec::new();
+        for (attempt, result) in (1..).zip(results) {
+            let at = self.clock.now() - start;
+            let flow = policy.should_retry(result);
+            let delay = match flow {
+                ControlFlow::Continue(delay) => Some(delay),
+                ControlFlow::Break(_) => None,
+            };
+            decisions.push(Decision { attempt, at, flow });
+            match delay {
+                Some(delay) => self.clock.advance(delay),
+                None => break,
+            }
+        }
+        decisions
+    }
+
+    /// Run `policy` against the scripted results, returning the timeline of attempts.
+    ///
+    /// # Panics
+    ///
+    /// If the policy makes more attempts than there are steps in the script.
+    pub fn run<P, R>(&self, policy: P, script: impl IntoIterator<Item = Step<R>>) -> Timeline<R>
+    where
+        P: RetryPolicy<R>,
+    {
+        let script: RefCell<VecDeque<Step<R>>> = RefCell::new(script.into_iter().collect());
+        let attempts = RefCell::new(Vec::new());
+        let start = self.clock.now();
+
+        let fut = retry_with_sleeper(
+            policy,
+            SimSleeper {
+                clock: &self.clock,
+                attempts: &attempts,
+            },
+            || {
+                let step = script.borrow_mut().pop_front();
+                let Some(step) = step else {
+                    panic!("script ran out after {} attempts", attempts.borrow().len());
+                };
+                let started = self.clock.now();
+                self.clock.advance(step.duration);
+                attempts.borrow_mut().push(AttemptRecord {
+                    started: started - start,
+                    finished: self.clock.now() - start,
+                    delay: None,
+                });
+                ready(step.result)
+            },
+        );
+
+        let Poll::Ready(result) = pin!(fut).poll(&mut Context::from_waker(Waker::noop())) else {
+            unreachable!("simulated attempts and sleeps are always ready");
+        };
+
+        Timeline {
+            attempts: attempts.into_inner(),
+            result,
+            elapsed: self.clock.now() - start,
+        }
+    }
+}
+
+/// Run `policy` against the scripted results on a fresh [`Sim`], returning its decisions.
+///
+/// See [`Sim::simulate`].
+///
+/// ```
+/// use futures_retry_policies::{sim::simulate, RetryPolicyBuilder};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1)).max_retries(3).build();
+/// let decisions = simulate(policy, [None, None, Some("ok")]);
+///
+/// let delays: Vec<_> = decisions.iter().map(|d| (d.at, d.flow.clone())).collect();
+/// assert_eq!(delays, [
+///     (Duration::ZERO, ControlFlow::Continue(Duration::from_secs(1))),
+///     (Duration::from_secs(1), ControlFlow::Continue(Duration::from_secs(2))),
+///     (Duration::from_secs(3), ControlFlow::Break(Some("ok"))),
+/// ]);
+/// ```
+pub fn simulate<P, R>(policy: P, results: impl IntoIterator<Item = R>) -> Vec<Decision<R>>
+where
+    P: RetryPolicy<R>,
+{
+    Sim::new().simulate(policy, results)
+}
+
+/// Sleeps by moving the virtual clock forward
+struct SimSleeper<'a> {
+    clock: &'a MockClock,
+    attempts: &'a RefCell<Vec<AttemptRecord>>,
+}
+
+impl RetrySleeper for SimSleeper<'_> {
+    type Sleep = Ready<()>;
+
+    fn sleep(&mut self, delay: Duration, _: u32) -> Self::Sleep {
+        if let Some(attempt) = self.attempts.borrow_mut().last_mut() {
+            attempt.delay = Some(delay);
+        }
+        self.clock.advance(delay);
+        ready(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Sim, Step};
+    use crate::{clock::Clock, rate::FixedRate, RetryPolicyBuilder};
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Error;
+    impl crate::ShouldRetry for Error {
+        fn should_retry(&self, _: u32) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn simulate_stops_when_results_run_out() {
+        let sim = Sim::new();
+        let policy = RetryPolicyBuilder::fixed(Duration::from_secs(1)).build();
+        let decisions = sim.simulate(policy, [Err::<(), _>(Error); 2]);
+
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[1].attempt, 2);
+        assert!(decisions.iter().all(|d| d.flow.is_continue()));
+        assert_eq!(sim.clock().now(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn exponential_timeline() {
+        let sim = Sim::new();
+        let policy = RetryPolicyBuilder::exponential(Duration::from_secs(1))
+            .max_retries(3)
+            .build();
+        let timeline = sim.run(policy, [Err::<(), _>(Error); 4].map(Step::new));
+
+        assert_eq!(timeline.result, Err(Error));
+        assert_eq!(timeline.delays(), [1, 2, 4].map(Duration::from_secs));
+        assert_eq!(timeline.elapsed, Duration::from_secs(7));
+    }
+
+    #[test]
+    fn clock_follows_attempts() {
+        let sim = Sim::new();
+        let policy = FixedRate::with_clock(
+            RetryPolicyBuilder::fixed(Duration::from_secs(5)).build(),
+            sim.clock(),
+        );
+        let secs = Duration::from_secs;
+        let timeline = sim.run(
+            policy,
+            [
+                Step::new(Err::<(), _>(Error)).taking(secs(2)),
+                Step::new(Err(Error)).taking(secs(1)),
+                Step::new(Ok(())),
+            ],
+        );
+
+        // attempts start every 5s, however long they take
+        assert_eq!(timeline.start_times(), [secs(0), secs(5), secs(10)]);
+        assert_eq!(timeline.result, Ok(()));
+    }
+
+    #[test]
+    #[should_panic = "script ran out after 2 attempts"]
+    fn script_runs_out() {
+        Sim::new().run(
+            RetryPolicyBuilder::fixed(Duration::from_secs(1)).build(),
+            [Err::<(), _>(Error); 2].map(Step::new),
+        );
+    }
+}
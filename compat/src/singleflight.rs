@@ -0,0 +1,206 @@
+#![cfg(feature = "tokio")]
+#![cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+//! Sharing one retry loop between concurrent identical requests.
+//!
+//! During an incident, many callers tend to retry the same request at once, like fetching the
+//! same configuration or the same hot key. A [`SingleFlight`] runs only one retry loop per key:
+//! the first caller for a key runs it, and everyone else asking for that key in the meantime waits
+//! for its result instead of adding their own attempts.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::watch;
+
+use crate::{retry, RetryPolicy};
+
+type Flights<K, T> = Mutex<HashMap<K, watch::Receiver<Option<T>>>>;
+
+/// Retry loops in flight by key, shared between clones.
+///
+/// If the caller running a key's retry loop is cancelled, one of the callers waiting on it takes
+/// over, starting a fresh loop with its own policy.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, singleflight::SingleFlight};
+/// use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+///
+/// static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+///
+/// async fn fetch(key: &str) -> Option<String> {
+///     // fetch the value from an overloaded backend
+///     ATTEMPTS.fetch_add(1, Ordering::SeqCst);
+///     tokio::time::sleep(Duration::from_millis(10)).await;
+///     Some(format!("value of {key}"))
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let flights = SingleFlight::new();
+///     let get = || {
+///         let policy = Iter::new([Duration::from_millis(10); 3]);
+///         flights.retry("hot-key", policy, tokio::time::sleep, || fetch("hot-key"))
+///     };
+///
+///     let (a, b, c) = tokio::join!(get(), get(), get());
+///     assert_eq!(a.as_deref(), Some("value of hot-key"));
+///     assert_eq!(a, b);
+///     assert_eq!(b, c);
+///     assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 1);
+/// }
+/// ```
+pub struct SingleFlight<K, T> {
+    flights: Arc<Flights<K, T>>,
+}
+
+impl<K, T> Clone for SingleFlight<K, T> {
+    fn clone(&self) -> Self {
+        Self {
+            flights: self.flights.clone(),
+        }
+    }
+}
+
+impl<K, T> Default for SingleFlight<K, T> {
+    fn default() -> Self {
+        Self {
+            flights: Arc::default(),
+        }
+    }
+}
+
+impl<K, T> fmt::Debug for SingleFlight<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SingleFlight")
+            .field("in_flight", &self.lock().len())
+            .finish()
+    }
+}
+
+impl<K, T> SingleFlight<K, T> {
+    /// Create an empty set of flights
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, watch::Receiver<Option<T>>>> {
+        self.flights.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// How many keys have a retry loop running
+    pub fn in_flight(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+impl<K: Hash + Eq + Clone, T: Clone> SingleFlight<K, T> {
+    /// Retry the futures for `key` using the given [retry policy](`RetryPolicy`) and sleep
+    /// function, unless a retry loop for `key` is already running, in which case its result is
+    /// returned instead.
+    pub async fn retry<Policy, Sleeper, Sleep, Futures, Fut>(
+        &self,
+        key: K,
+        policy: Policy,
+        sleeper: Sleeper,
+        futures: Futures,
+    ) -> T
+    where
+        Policy: RetryPolicy<T>,
+        Sleeper: FnMut(Duration) -> Sleep,
+        Sleep: Future<Output = ()>,
+        Futures: FnMut() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        loop {
+            let joined = {
+                let mut flights = self.lock();
+                match flights.get(&key) {
+                    Some(flight) => Joined::Waiting(flight.clone()),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        flights.insert(key.clone(), rx);
+                        Joined::Leading(tx)
+                    }
+                }
+            };
+            let mut flight = match joined {
+                Joined::Waiting(flight) => flight,
+                Joined::Leading(tx) => {
+                    let _landing = Landing {
+                        flights: &self.flights,
+                        key: &key,
+                    };
+                    let result = retry(policy, sleeper, futures).await;
+                    let _ = tx.send(Some(result.clone()));
+                    return result;
+                }
+            };
+            // an error means the caller running the loop was cancelled, so take over
+            let result = flight
+                .wait_for(Option::is_some)
+                .await
+                .map(|result| result.clone());
+            if let Ok(Some(result)) = result {
+                return result;
+            }
+        }
+    }
+}
+
+enum Joined<T> {
+    Waiting(watch::Receiver<Option<T>>),
+    Leading(watch::Sender<Option<T>>),
+}
+
+/// Removes the flight once its retry loop finishes or is cancelled
+struct Landing<'a, K: Hash + Eq, T> {
+    flights: &'a Flights<K, T>,
+    key: &'a K,
+}
+
+impl<K: Hash + Eq, T> Drop for Landing<'_, K, T> {
+    fn drop(&mut self) {
+        let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+        flights.remove(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::SingleFlight;
+    use crate::iter::Iter;
+
+    #[tokio::test(start_paused = true)]
+    async fn waiter_takes_over_when_leader_cancelled() {
+        let flights = SingleFlight::<&str, Option<u32>>::new();
+        let attempts = Cell::new(0);
+        let attempt = || {
+            attempts.set(attempts.get() + 1);
+            async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                Some(1)
+            }
+        };
+        let policy = || Iter::new([Duration::ZERO; 1]);
+
+        let leader = tokio::time::timeout(
+            Duration::from_millis(500),
+            flights.retry("key", policy(), tokio::time::sleep, attempt),
+        );
+        let waiter = flights.retry("key", policy(), tokio::time::sleep, attempt);
+        let (leader, waiter) = tokio::join!(leader, waiter);
+
+        assert!(leader.is_err());
+        assert_eq!(waiter, Some(1));
+        assert_eq!(attempts.get(), 2);
+        assert_eq!(flights.in_flight(), 0);
+    }
+}
@@ -0,0 +1,132 @@
+//! Throttling retries as an SLO error budget runs out.
+//!
+//! Services with a service level objective have an error budget, like 0.1% of requests over a
+//! month, that is spent by every failure. Retrying helps while there is plenty of budget left,
+//! but as it runs out, retries that pile onto a struggling dependency start to cost more than
+//! they save. [`ErrorBudgeted`] asks an [`ErrorBudget`] how much is left, and retries less and
+//! more slowly as it is consumed.
+
+use std::{ops::ControlFlow, sync::Arc, time::Duration};
+
+use crate::RetryPolicy;
+
+/// An SLO error budget, as tracked by the application.
+///
+/// This is implemented for closures returning the fraction, so a budget tracked elsewhere can
+/// be read with eg. `|| tracker.remaining()`.
+pub trait ErrorBudget {
+    /// The fraction of the budget that is left, from `1.0` when none has been spent, to `0.0`
+    /// when it is exhausted. Values outside of that range are clamped.
+    fn remaining_fraction(&self) -> f64;
+}
+
+impl<B: ErrorBudget + ?Sized> ErrorBudget for Arc<B> {
+    fn remaining_fraction(&self) -> f64 {
+        B::remaining_fraction(self)
+    }
+}
+
+impl<F: Fn() -> f64> ErrorBudget for F {
+    fn remaining_fraction(&self) -> f64 {
+        self()
+    }
+}
+
+/// A [`RetryPolicy`] that scales retries down with the [`ErrorBudget`] that is left.
+///
+/// With a full budget, this makes up to `max_attempts` attempts with the inner policy's delays.
+/// With half the budget left, it makes half as many attempts, and waits twice as long between
+/// them. Once the budget is exhausted, it makes no retries at all. The inner policy can still give
+/// up sooner.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, slo::ErrorBudgeted, RetryPolicy};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// // a quarter of this month's error budget is left
+/// let budget = || 0.25;
+///
+/// let policy = Iter::new(std::iter::repeat(Duration::from_secs(1)));
+/// let mut policy = ErrorBudgeted::new(policy, budget, 8);
+///
+/// assert_eq!(policy.should_retry(None::<()>), ControlFlow::Continue(Duration::from_secs(4)));
+/// assert!(policy.should_retry(None::<()>).is_break());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ErrorBudgeted<P, B> {
+    policy: P,
+    budget: B,
+    max_attempts: u32,
+    attempts: u32,
+}
+
+impl<P, B: ErrorBudget> ErrorBudgeted<P, B> {
+    /// Make up to `max_attempts` attempts with `policy` while `budget` is full
+    pub fn new(policy: P, budget: B, max_attempts: u32) -> Self {
+        Self {
+            policy,
+            budget,
+            max_attempts,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, B, R> RetryPolicy<R> for ErrorBudgeted<P, B>
+where
+    P: RetryPolicy<R>,
+    B: ErrorBudget,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        let remaining = self.budget.remaining_fraction().clamp(0.0, 1.0);
+        let max_attempts = (f64::from(self.max_attempts) * remaining).ceil() as u32;
+        if self.attempts >= max_attempts.max(1) {
+            return ControlFlow::Break(result);
+        }
+
+        let delay = self.policy.should_retry(result)?;
+        let scaled = Duration::try_from_secs_f64(delay.as_secs_f64() / remaining);
+        ControlFlow::Continue(scaled.unwrap_or(Duration::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    };
+
+    use super::ErrorBudgeted;
+    use crate::{iter::Iter, RetryPolicy};
+
+    fn attempts<P: RetryPolicy<Option<()>>>(mut policy: P) -> usize {
+        (1..)
+            .find(|_| policy.should_retry(None).is_break())
+            .unwrap()
+    }
+
+    #[test]
+    fn scales_with_budget() {
+        let remaining = AtomicU64::new(1.0f64.to_bits());
+        let budget = || f64::from_bits(remaining.load(Ordering::Relaxed));
+        let policy = || {
+            ErrorBudgeted::new(
+                Iter::new(std::iter::repeat(Duration::from_secs(1))),
+                &budget,
+                6,
+            )
+        };
+
+        assert_eq!(attempts(policy()), 6);
+        remaining.store(0.5f64.to_bits(), Ordering::Relaxed);
+        assert_eq!(attempts(policy()), 3);
+        assert_eq!(
+            policy().should_retry(None::<()>),
+            std::ops::ControlFlow::Continue(Duration::from_secs(2))
+        );
+        remaining.store(0.0f64.to_bits(), Ordering::Relaxed);
+        assert_eq!(attempts(policy()), 1);
+    }
+}
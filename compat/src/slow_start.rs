@@ -0,0 +1,204 @@
+//! Ramping retries back up after a dependency recovers.
+//!
+//! Once a dependency comes back after an outage, every caller that was failing fast starts
+//! retrying at once, which can knock it straight back over. [`SlowStart`] only lets through a
+//! ramping number of retries after it has been [tripped](SlowStart::trip): 1 in the first
+//! interval, then 2, then 4, and so on until the ramp is complete.
+//!
+//! [`FailFast`](crate::success_rate::FailFast) trips and ramps a [`SlowStart`] by itself, with
+//! [`FailFast::slow_start`](crate::success_rate::FailFast::slow_start).
+
+use std::{
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    clock::{Clock, StdClock},
+    RetryPolicy, ShouldRetry,
+};
+
+#[derive(Debug, Default)]
+struct State {
+    tripped: bool,
+    ramp: Option<Ramp>,
+}
+
+#[derive(Debug)]
+struct Ramp {
+    start: Duration,
+    interval: u32,
+    used: u32,
+}
+
+/// A ramp of permitted retries, shared between clones.
+///
+/// ```
+/// use futures_retry_policies::{clock::MockClock, slow_start::SlowStart};
+/// use std::time::Duration;
+///
+/// let clock = MockClock::new();
+/// let slow_start = SlowStart::new(Duration::from_secs(1), 4).with_clock(clock.clone());
+///
+/// // the dependency went down, and has just come back
+/// slow_start.trip();
+/// assert!(slow_start.try_acquire());
+/// assert!(!slow_start.try_acquire());
+///
+/// clock.advance(Duration::from_secs(1));
+/// assert!(slow_start.try_acquire());
+/// assert!(slow_start.try_acquire());
+/// assert!(!slow_start.try_acquire());
+/// ```
+#[derive(Clone)]
+pub struct SlowStart {
+    state: Arc<Mutex<State>>,
+    interval: Duration,
+    full: u32,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl std::fmt::Debug for SlowStart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlowStart")
+            .field("state", &self.state)
+            .field("interval", &self.interval)
+            .field("full", &self.full)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SlowStart {
+    /// Double the permitted retries every `interval`, until `full` retries are permitted in an interval.
+    pub fn new(interval: Duration, full: u32) -> Self {
+        Self {
+            state: Arc::default(),
+            interval,
+            full,
+            clock: Arc::new(StdClock),
+        }
+    }
+
+    /// Read the time from the given clock
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Mark the dependency as unavailable. The next [`try_acquire`](SlowStart::try_acquire) starts the ramp.
+    pub fn trip(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tripped = true;
+        state.ramp = None;
+    }
+
+    /// Whether the ramp is in progress
+    pub fn is_ramping(&self) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tripped || state.ramp.is_some()
+    }
+
+    /// Take one of the retries permitted in the current interval.
+    ///
+    /// Always succeeds when not ramping.
+    pub fn try_acquire(&self) -> bool {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if std::mem::take(&mut state.tripped) {
+            state.ramp = Some(Ramp {
+                start: now,
+                interval: 0,
+                used: 0,
+            });
+        }
+        let Some(ramp) = &mut state.ramp else {
+            return true;
+        };
+
+        let elapsed = now.saturating_sub(ramp.start);
+        let interval = (elapsed.as_nanos() / self.interval.as_nanos().max(1)).min(31) as u32;
+        let permitted = 1u32 << interval;
+        if permitted >= self.full {
+            state.ramp = None;
+            return true;
+        }
+        if interval != ramp.interval {
+            ramp.interval = interval;
+            ramp.used = 0;
+        }
+        if ramp.used < permitted {
+            ramp.used += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A [`RetryPolicy`] that only retries when its [`SlowStart`] permits it
+#[derive(Debug, Clone)]
+pub struct SlowStarted<P> {
+    policy: P,
+    slow_start: SlowStart,
+    attempts: u32,
+}
+
+impl<P> SlowStarted<P> {
+    /// Retry with `policy`, as far as `slow_start` permits
+    pub fn new(policy: P, slow_start: SlowStart) -> Self {
+        Self {
+            policy,
+            slow_start,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, R> RetryPolicy<R> for SlowStarted<P>
+where
+    P: RetryPolicy<R>,
+    R: ShouldRetry,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        if result.should_retry(self.attempts) && !self.slow_start.try_acquire() {
+            return ControlFlow::Break(result);
+        }
+        self.policy.should_retry(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::{SlowStart, SlowStarted};
+    use crate::{clock::MockClock, iter::Iter, RetryPolicy};
+
+    #[test]
+    fn doubles_each_interval() {
+        let clock = MockClock::new();
+        let slow_start = SlowStart::new(Duration::from_secs(1), 8).with_clock(clock.clone());
+        assert!(!slow_start.is_ramping());
+
+        slow_start.trip();
+        assert!(slow_start.is_ramping());
+        let mut permitted = vec![];
+        for _ in 0..4 {
+            permitted.push((0..10).filter(|_| slow_start.try_acquire()).count());
+            clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(permitted, [1, 2, 4, 10]);
+        assert!(!slow_start.is_ramping());
+    }
+
+    #[test]
+    fn policy_gives_up_when_not_permitted() {
+        let slow_start = SlowStart::new(Duration::from_secs(60), 8).with_clock(MockClock::new());
+        slow_start.trip();
+        let mut policy = SlowStarted::new(Iter::new([Duration::ZERO; 3]), slow_start);
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert_eq!(policy.should_retry(None::<()>), ControlFlow::Break(None));
+    }
+}
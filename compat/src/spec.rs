@@ -0,0 +1,212 @@
+//! Parsing policies from compact strings.
+//!
+//! [`Backoff`] and [`RetryPolicyBuilder`] can be parsed from short specs like
+//! `"exponential:100ms..30s,x2,jitter,max=8"`, so retries can be configured from CLI flags or
+//! environment variables without pulling in serde.
+//!
+//! A spec starts with the kind of backoff, followed by comma separated options:
+//!
+//! * `exponential:<base>` or `exponential:<base>..<max delay>` doubles the delay after every retry.
+//! * `fixed:<delay>` always waits the same delay.
+//! * `x<factor>` multiplies the delay by `factor` after every retry, eg. `x1.5`.
+//! * `jitter` adds full jitter. `jitter=full`, `jitter=equal` and `jitter=none` are also accepted.
+//! * `max=<n>` gives up after `n` retries.
+//! * `elapsed=<duration>` gives up on retries that would start after `duration`.
+//!
+//! Durations are a number followed by a unit of `ns`, `us`, `ms`, `s`, `m` or `h`, like `250ms` or `1.5s`.
+//! A [`Backoff`] can also be parsed from the name of a [preset](crate::presets), like `http_client`.
+//!
+//! ```
+//! use futures_retry_policies::{backoff::Backoff, RetryPolicyBuilder};
+//! use std::time::Duration;
+//!
+//! let policy: Backoff = "exponential:100ms..30s,x2,jitter,max=8".parse().unwrap();
+//! let expected = RetryPolicyBuilder::exponential(Duration::from_millis(100))
+//!     .max_delay(Duration::from_secs(30))
+//!     .factor(2.0)
+//!     .jitter_full()
+//!     .max_retries(8)
+//!     .build();
+//! assert!(policy.preview(10).eq(expected.preview(10)));
+//!
+//! let err = "exponential:100,max=8".parse::<Backoff>().unwrap_err();
+//! assert_eq!(err.to_string(), "invalid retry policy spec: missing unit in duration `100`");
+//! ```
+
+use std::{error::Error, fmt, str::FromStr, time::Duration};
+
+use crate::{
+    backoff::{Backoff, Jitter},
+    presets, RetryPolicyBuilder,
+};
+
+/// The error returned when a policy spec can't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSpecError {
+    message: String,
+}
+
+impl ParseSpecError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid retry policy spec: {}", self.message)
+    }
+}
+
+impl Error for ParseSpecError {}
+
+fn parse_duration(s: &str) -> Result<Duration, ParseSpecError> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .ok_or_else(|| ParseSpecError::new(format!("missing unit in duration `{s}`")))?;
+    let (value, unit) = s.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| ParseSpecError::new(format!("invalid duration `{s}`")))?;
+    let secs = match unit {
+        "ns" => 1e-9,
+        "us" => 1e-6,
+        "ms" => 1e-3,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => {
+            return Err(ParseSpecError::new(format!(
+                "unknown unit in duration `{s}`"
+            )))
+        }
+    };
+    Duration::try_from_secs_f64(value * secs)
+        .map_err(|_| ParseSpecError::new(format!("duration `{s}` is out of range")))
+}
+
+impl FromStr for RetryPolicyBuilder {
+    type Err = ParseSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = s.split(',').map(str::trim);
+        let kind = options.next().unwrap_or_default();
+        let mut builder = match kind.split_once(':') {
+            Some(("exponential", delays)) => match delays.split_once("..") {
+                Some((base, max)) => RetryPolicyBuilder::exponential(parse_duration(base)?)
+                    .max_delay(parse_duration(max)?),
+                None => RetryPolicyBuilder::exponential(parse_duration(delays)?),
+            },
+            Some(("fixed", delay)) => RetryPolicyBuilder::fixed(parse_duration(delay)?),
+            _ => {
+                return Err(ParseSpecError::new(format!(
+                    "expected `exponential:<base>` or `fixed:<delay>`, found `{kind}`"
+                )))
+            }
+        };
+
+        for option in options {
+            builder = match option.split_once('=') {
+                None if option == "jitter" => builder.jitter_full(),
+                None if option.starts_with('x') => match option[1..].parse::<f64>() {
+                    Ok(factor) if factor >= 1.0 => builder.factor(factor),
+                    _ => return Err(ParseSpecError::new(format!("invalid factor `{option}`"))),
+                },
+                Some(("jitter", jitter)) => builder.jitter(match jitter {
+                    "full" => Jitter::Full,
+                    "equal" => Jitter::Equal,
+                    "none" => Jitter::None,
+                    _ => return Err(ParseSpecError::new(format!("unknown jitter `{jitter}`"))),
+                }),
+                Some(("max", max)) => match max.parse() {
+                    Ok(max) => builder.max_retries(max),
+                    Err(_) => return Err(ParseSpecError::new(format!("invalid retries `{max}`"))),
+                },
+                Some(("elapsed", elapsed)) => builder.max_elapsed(parse_duration(elapsed)?),
+                _ => return Err(ParseSpecError::new(format!("unknown option `{option}`"))),
+            };
+        }
+        Ok(builder)
+    }
+}
+
+impl TryFrom<&str> for RetryPolicyBuilder {
+    type Error = ParseSpecError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl FromStr for Backoff {
+    type Err = ParseSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "http_client" => Ok(presets::http_client()),
+            "database" => Ok(presets::database()),
+            "startup_dependency_wait" => Ok(presets::startup_dependency_wait()),
+            spec => Ok(spec.parse::<RetryPolicyBuilder>()?.build()),
+        }
+    }
+}
+
+impl TryFrom<&str> for Backoff {
+    type Error = ParseSpecError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::parse_duration;
+    use crate::backoff::Backoff;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("5 days").is_err());
+        assert!(parse_duration("ms").is_err());
+    }
+
+    #[test]
+    fn specs() {
+        let delays = |spec: &str| {
+            spec.parse::<Backoff>()
+                .unwrap()
+                .preview(5)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays("fixed:1s,max=3"), [1, 1, 1].map(Duration::from_secs));
+        assert_eq!(
+            delays("exponential:1s..5s,x3"),
+            [1, 3, 5, 5, 5].map(Duration::from_secs)
+        );
+        assert_eq!(
+            delays("exponential:100ms, jitter=equal, elapsed=30s"),
+            [100, 200, 400, 800, 1600].map(Duration::from_millis)
+        );
+        assert_eq!(delays("http_client").len(), 3);
+    }
+
+    #[test]
+    fn invalid_specs() {
+        for spec in [
+            "",
+            "linear:1s",
+            "fixed:1s,x0.5",
+            "fixed:1s,max=-1",
+            "fixed:1s,retry",
+        ] {
+            assert!(spec.parse::<Backoff>().is_err(), "{spec}");
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Controlling a retry loop from outside of it.
+//!
+//! [`retry_split`] returns the retry loop as a [`SplitRetry`] future, along with a
+//! [`RetryController`] that other tasks, or other branches of a `select!` loop, can use to watch
+//! its progress and cut a sleep short. Neither part relies on being polled to completion: the
+//! driver can be polled by reference in a `select!` loop, see
+//! [`RetryFuture`](crate::RetryFuture#cancellation), and the controller's methods never block.
+//!
+//! ```
+//! use futures_retry_policies::{iter::Iter, split::retry_split};
+//! use std::time::Duration;
+//!
+//! async fn make_request() -> Option<()> {
+//!     // make a request
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let policy = Iter::new([Duration::from_secs(60); 3]);
+//!     let (driver, controller) = retry_split(policy, tokio::time::sleep, make_request);
+//!     let mut driver = std::pin::pin!(driver);
+//!     let mut network_up = tokio::time::interval(Duration::from_millis(10));
+//!
+//!     let res = loop {
+//!         tokio::select! {
+//!             res = &mut driver => break res,
+//!             // when the network comes back, don't wait out the rest of the backoff
+//!             _ = network_up.tick() => controller.retry_now(),
+//!         }
+//!     };
+//!     assert_eq!(res, Some(()));
+//!     assert_eq!(controller.attempts(), 3);
+//! }
+//! ```
+
+use std::{
+    future::Future,
+    ops::ControlFlow,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use pin_project::pin_project;
+
+use crate::RetryPolicy;
+
+/// Lock the shared state, clearing the waker as the driver is running
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    let mut shared = shared.lock().unwrap_or_else(|e| e.into_inner());
+    shared.waker = None;
+    shared
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    attempts: u32,
+    sleeping: bool,
+    finished: bool,
+    retry_now: bool,
+    waker: Option<Waker>,
+}
+
+/// Watches and steers a [`SplitRetry`], returned by [`retry_split`]
+#[derive(Debug, Clone)]
+pub struct RetryController {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl RetryController {
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// How many attempts have been started
+    pub fn attempts(&self) -> u32 {
+        self.lock().attempts
+    }
+
+    /// Whether the driver is waiting to retry
+    pub fn is_sleeping(&self) -> bool {
+        self.lock().sleeping
+    }
+
+    /// Whether the driver has returned its final result
+    pub fn is_finished(&self) -> bool {
+        self.lock().finished
+    }
+
+    /// End the current sleep early, so the next attempt starts straight away.
+    ///
+    /// This does nothing unless the driver is sleeping.
+    pub fn retry_now(&self) {
+        let mut shared = self.lock();
+        if shared.sleeping {
+            shared.retry_now = true;
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Create a retry loop as a [`SplitRetry`] future and a [`RetryController`] for it, using the
+/// given [retry policy](`RetryPolicy`) and sleep function.
+pub fn retry_split<Policy, Sleeper, Sleep, Futures, Fut>(
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> (
+    SplitRetry<Policy, Sleeper, Sleep, Futures, Fut>,
+    RetryController,
+)
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let driver = SplitRetry {
+        policy,
+        sleeper,
+        futures,
+        shared: shared.clone(),
+        state: DriverState::Idle,
+    };
+    (driver, RetryController { shared })
+}
+
+/// [`Future`] running the retry loop, returned by [`retry_split`]
+///
+/// Dropping it behaves the same as dropping a [`RetryFuture`](crate::RetryFuture#cancellation).
+#[pin_project]
+pub struct SplitRetry<Policy, Sleeper, Sleep, Futures, Fut> {
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+    shared: Arc<Mutex<Shared>>,
+    #[pin]
+    state: DriverState<Sleep, Fut>,
+}
+
+impl<Policy, Sleeper, Sleep, Futures, Fut> std::fmt::Debug
+    for SplitRetry<Policy, Sleeper, Sleep, Futures, Fut>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("SplitRetry")
+            .field("attempts", &shared.attempts)
+            .field("sleeping", &shared.sleeping)
+            .field("finished", &shared.finished)
+            .finish_non_exhaustive()
+    }
+}
+
+#[pin_project(project = DriverStateProj)]
+enum DriverState<Sleep, Fut> {
+    Idle,
+    Sleeping(#[pin] Sleep),
+    Attempting(#[pin] Fut),
+}
+
+impl<Policy, Sleeper, Sleep, Futures, Fut> Future
+    for SplitRetry<Policy, Sleeper, Sleep, Futures, Fut>
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            match this.state.as_mut().project() {
+                DriverStateProj::Idle => {
+                    this.state.set(DriverState::Attempting((this.futures)()));
+                    lock(this.shared).attempts += 1;
+                }
+                DriverStateProj::Attempting(fut) => {
+                    let result = std::task::ready!(fut.poll(cx));
+                    match this.policy.should_retry(result) {
+                        ControlFlow::Continue(delay) => {
+                            this.state.set(DriverState::Sleeping((this.sleeper)(delay)));
+                            let mut shared = lock(this.shared);
+                            shared.sleeping = true;
+                            shared.retry_now = false;
+                        }
+                        ControlFlow::Break(result) => {
+                            lock(this.shared).finished = true;
+                            return Poll::Ready(result);
+                        }
+                    }
+                }
+                DriverStateProj::Sleeping(sleep) => {
+                    let slept = sleep.poll(cx).is_ready();
+                    let mut shared = lock(this.shared);
+                    if !slept && !shared.retry_now {
+                        shared.waker = Some(cx.waker().clone());
+                        return Poll::Pending;
+                    }
+                    shared.sleeping = false;
+                    shared.retry_now = false;
+                    shared.attempts += 1;
+                    drop(shared);
+                    this.state.set(DriverState::Attempting((this.futures)()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_split;
+    use crate::iter::Iter;
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_now_cuts_sleep_short() {
+        let start = tokio::time::Instant::now();
+        let (driver, controller) = retry_split(
+            Iter::new([Duration::from_secs(60)]),
+            tokio::time::sleep,
+            || async { None::<()> },
+        );
+        let mut driver = std::pin::pin!(driver);
+
+        tokio::select! {
+            _ = &mut driver => panic!("should still be sleeping"),
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+        assert!(controller.is_sleeping());
+        assert_eq!(controller.attempts(), 1);
+
+        controller.retry_now();
+        assert_eq!(driver.await, None);
+        assert!(controller.is_finished());
+        assert_eq!(controller.attempts(), 2);
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sleeps_normally() {
+        let start = tokio::time::Instant::now();
+        let (driver, controller) = retry_split(
+            Iter::new([Duration::from_secs(2); 2]),
+            tokio::time::sleep,
+            || async { None::<()> },
+        );
+        // no effect outside of a sleep
+        controller.retry_now();
+        assert_eq!(driver.await, None);
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+    }
+}
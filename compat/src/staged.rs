@@ -0,0 +1,131 @@
+//! Different policies for different phases of retrying.
+//!
+//! Many clients want to retry quickly a few times, to ride out a blip, and only then back off
+//! more slowly. [`Staged`] hands the first few retries to one policy, and the rest to another.
+//!
+//! ```
+//! use futures_retry_policies::{staged::Staged, tokio::RetryFutureExt, RetryPolicyBuilder};
+//! use std::time::Duration;
+//!
+//! async fn connect() -> Result<(), &'static str> {
+//!     // connect to the server
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 4 { Err("refused") } else { Ok(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let retryable = |res: &Result<(), &str>| res.is_err();
+//!     // reconnect straight away three times
+//!     let fast = RetryPolicyBuilder::fixed(Duration::ZERO).retry_if(retryable).build();
+//!     // then back off
+//!     let slow = RetryPolicyBuilder::exponential(Duration::from_millis(10))
+//!         .max_retries(5)
+//!         .retry_if(retryable)
+//!         .build();
+//!
+//!     let policy = Staged::first(3, fast).then(slow);
+//!     connect.retry(policy).await.unwrap();
+//! }
+//! ```
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// A [`RetryPolicy`] that uses one policy for the first retries, then switches to another.
+///
+/// Created with [`Staged::first`]. The second policy only starts seeing results once the first
+/// stage is over, so its own counters and limits start from zero at that point. If the first policy
+/// gives up during its stage, that is final.
+#[derive(Debug, Clone)]
+pub struct Staged<A, B> {
+    first: A,
+    then: B,
+    retries: u32,
+    stage_retries: u32,
+}
+
+impl Staged<(), ()> {
+    /// Use `policy` for the first `retries` retries.
+    pub fn first<A>(retries: u32, policy: A) -> StagedBuilder<A> {
+        StagedBuilder { retries, policy }
+    }
+}
+
+/// Builder for a [`Staged`] policy, created by [`Staged::first`]
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct StagedBuilder<A> {
+    retries: u32,
+    policy: A,
+}
+
+impl<A> StagedBuilder<A> {
+    /// Use `policy` for every retry after the first stage
+    pub fn then<B>(self, policy: B) -> Staged<A, B> {
+        Staged {
+            first: self.policy,
+            then: policy,
+            retries: 0,
+            stage_retries: self.retries,
+        }
+    }
+}
+
+impl<A, B, R> RetryPolicy<R> for Staged<A, B>
+where
+    A: RetryPolicy<R>,
+    B: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let delay = if self.retries < self.stage_retries {
+            self.first.should_retry(result)?
+        } else {
+            self.then.should_retry(result)?
+        };
+        self.retries = self.retries.saturating_add(1);
+        ControlFlow::Continue(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::Staged;
+    use crate::{iter::Iter, RetryPolicy};
+
+    fn delays<P: RetryPolicy<Option<()>>>(mut policy: P) -> Vec<Duration> {
+        let mut delays = vec![];
+        while let ControlFlow::Continue(delay) = policy.should_retry(None) {
+            delays.push(delay);
+        }
+        delays
+    }
+
+    #[test]
+    fn switches_stage() {
+        let fast = Iter::new(std::iter::repeat(Duration::ZERO));
+        let slow = Iter::new([1, 2, 4].map(Duration::from_secs));
+        assert_eq!(
+            delays(Staged::first(3, fast).then(slow)),
+            [0, 0, 0, 1, 2, 4].map(Duration::from_secs)
+        );
+    }
+
+    #[test]
+    fn first_stage_can_give_up() {
+        let fast = Iter::new([Duration::ZERO]);
+        let slow = Iter::new(std::iter::repeat(Duration::from_secs(1)));
+        assert_eq!(delays(Staged::first(3, fast).then(slow)), [Duration::ZERO]);
+    }
+
+    #[test]
+    fn stops_on_success() {
+        let mut policy =
+            Staged::first(1, Iter::new([Duration::ZERO])).then(Iter::new([Duration::from_secs(1)]));
+        assert!(policy.should_retry(None::<()>).is_continue());
+        assert_eq!(policy.should_retry(Some(())), ControlFlow::Break(Some(())));
+    }
+}
@@ -0,0 +1,262 @@
+//! Retry statistics over time, without a metrics library.
+//!
+//! [`RetryStats`] counts attempts, successes, exhaustions and the total delay in fixed-width time
+//! buckets, keeping the most recent few. A [`snapshot`](RetryStats::snapshot) returns plain
+//! structs, ready to be shown on a debug endpoint. [`Stats`] records into it from any policy.
+
+use std::{
+    collections::VecDeque,
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    clock::{Clock, StdClock},
+    Classify, RetryPolicy, UseShouldRetry,
+};
+
+/// The counts for one bucket of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BucketStats {
+    /// When the bucket started, as read from the clock
+    pub start: Duration,
+    /// How many attempts finished
+    pub attempts: u64,
+    /// How many attempts finished with a result that shouldn't be retried
+    pub successes: u64,
+    /// How many times a policy gave up on a result that should be retried
+    pub exhaustions: u64,
+    /// The total of all delays chosen before retrying
+    pub total_delay: Duration,
+}
+
+impl BucketStats {
+    fn add(&mut self, other: &BucketStats) {
+        self.attempts += other.attempts;
+        self.successes += other.successes;
+        self.exhaustions += other.exhaustions;
+        self.total_delay = self.total_delay.saturating_add(other.total_delay);
+    }
+}
+
+/// A snapshot of [`RetryStats`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StatsSnapshot {
+    /// The buckets with any activity, oldest first
+    pub buckets: Vec<BucketStats>,
+    /// The sum of all the buckets. Its `start` is that of the oldest bucket.
+    pub total: BucketStats,
+}
+
+/// Time-bucketed retry statistics, shared between clones.
+///
+/// ```
+/// use futures_retry_policies::{clock::MockClock, iter::Iter, stats::{RetryStats, Stats}, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let clock = MockClock::new();
+/// // keep the last 60 one-minute buckets
+/// let stats = RetryStats::new(Duration::from_secs(60), 60).with_clock(clock.clone());
+///
+/// let mut policy = Stats::new(Iter::new([Duration::from_secs(1); 3]), &stats);
+/// assert!(policy.should_retry(None::<()>).is_continue());
+/// assert!(policy.should_retry(Some(())).is_break());
+///
+/// let snapshot = stats.snapshot();
+/// assert_eq!(snapshot.total.attempts, 2);
+/// assert_eq!(snapshot.total.successes, 1);
+/// assert_eq!(snapshot.total.total_delay, Duration::from_secs(1));
+/// ```
+#[derive(Clone)]
+pub struct RetryStats {
+    buckets: Arc<Mutex<VecDeque<BucketStats>>>,
+    width: Duration,
+    keep: usize,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl std::fmt::Debug for RetryStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryStats")
+            .field("width", &self.width)
+            .field("keep", &self.keep)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryStats {
+    /// Count in buckets `width` long, keeping the most recent `keep` of them
+    pub fn new(width: Duration, keep: usize) -> Self {
+        Self {
+            buckets: Arc::default(),
+            width,
+            keep: keep.max(1),
+            clock: Arc::new(StdClock),
+        }
+    }
+
+    /// Read the time from `clock`
+    pub fn with_clock(mut self, clock: impl Clock + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// How far back the buckets before the current one reach
+    fn window(&self) -> Duration {
+        let older = u32::try_from(self.keep - 1).unwrap_or(u32::MAX);
+        self.width.saturating_mul(older)
+    }
+
+    pub(crate) fn record(&self, f: impl FnOnce(&mut BucketStats)) {
+        let now = self.clock.now();
+        let width = self.width.as_nanos().max(1);
+        let start = Duration::from_nanos((now.as_nanos() / width * width) as u64);
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.back().is_none_or(|bucket| bucket.start != start) {
+            buckets.push_back(BucketStats {
+                start,
+                ..BucketStats::default()
+            });
+        }
+        let oldest = now.saturating_sub(self.window());
+        while buckets
+            .front()
+            .is_some_and(|bucket| bucket.start.saturating_add(self.width) <= oldest)
+        {
+            buckets.pop_front();
+        }
+        f(buckets.back_mut().expect("bucket was just pushed"));
+    }
+
+    /// The counts in each recent bucket, and their total
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let oldest = self.clock.now().saturating_sub(self.window());
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let buckets: Vec<_> = buckets
+            .iter()
+            .filter(|bucket| bucket.start.saturating_add(self.width) > oldest)
+            .copied()
+            .collect();
+
+        let mut total = BucketStats {
+            start: buckets
+                .first()
+                .map_or(Duration::ZERO, |bucket| bucket.start),
+            ..BucketStats::default()
+        };
+        for bucket in &buckets {
+            total.add(bucket);
+        }
+        StatsSnapshot { buckets, total }
+    }
+}
+
+/// A [`RetryPolicy`] that records every decision of the inner policy into a [`RetryStats`].
+///
+/// Results count as successes when they [shouldn't be retried](crate::ShouldRetry), and as
+/// exhaustions when the inner policy gives up on them anyway. Which results should be retried can
+/// be changed with [`retry_if`](Stats::retry_if).
+#[derive(Debug, Clone)]
+pub struct Stats<P, C = UseShouldRetry> {
+    policy: P,
+    stats: RetryStats,
+    classifier: C,
+    attempts: u32,
+}
+
+impl<P> Stats<P> {
+    /// Record the decisions of `policy` into `stats`
+    pub fn new(policy: P, stats: &RetryStats) -> Self {
+        Self {
+            policy,
+            stats: stats.clone(),
+            classifier: UseShouldRetry,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, C> Stats<P, C> {
+    /// Decide which results count as successes with `classifier`
+    pub fn retry_if<F>(self, classifier: F) -> Stats<P, F> {
+        Stats {
+            policy: self.policy,
+            stats: self.stats,
+            classifier,
+            attempts: self.attempts,
+        }
+    }
+}
+
+impl<P, C, R> RetryPolicy<R> for Stats<P, C>
+where
+    P: RetryPolicy<R>,
+    C: Classify<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        let retryable = self.classifier.classify(&result, self.attempts);
+        let flow = self.policy.should_retry(result);
+        self.stats.record(|bucket| {
+            bucket.attempts += 1;
+            match &flow {
+                ControlFlow::Continue(delay) => {
+                    bucket.total_delay = bucket.total_delay.saturating_add(*delay)
+                }
+                ControlFlow::Break(_) if retryable => bucket.exhaustions += 1,
+                ControlFlow::Break(_) => bucket.successes += 1,
+            }
+        });
+        flow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{RetryStats, Stats};
+    use crate::{clock::MockClock, iter::Iter, RetryPolicy};
+
+    #[test]
+    fn buckets_expire() {
+        let clock = MockClock::new();
+        let stats = RetryStats::new(Duration::from_secs(10), 2).with_clock(clock.clone());
+        let policy = || Stats::new(Iter::new([Duration::from_secs(1)]), &stats);
+
+        let mut first = policy();
+        assert!(first.should_retry(None::<()>).is_continue());
+        assert!(first.should_retry(None::<()>).is_break());
+
+        clock.advance(Duration::from_secs(15));
+        assert!(policy().should_retry(Some(())).is_break());
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.buckets.len(), 2);
+        assert_eq!(snapshot.buckets[0].attempts, 2);
+        assert_eq!(snapshot.buckets[0].exhaustions, 1);
+        assert_eq!(snapshot.buckets[1].start, Duration::from_secs(10));
+        assert_eq!(snapshot.buckets[1].successes, 1);
+        assert_eq!(snapshot.total.attempts, 3);
+
+        // the first bucket falls out of the window
+        clock.advance(Duration::from_secs(10));
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.buckets.len(), 1);
+        assert_eq!(snapshot.total.attempts, 1);
+        assert_eq!(snapshot.total.start, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn huge_windows_saturate() {
+        let clock = MockClock::new();
+        let stats = RetryStats::new(Duration::MAX, usize::MAX).with_clock(clock.clone());
+        let mut policy = Stats::new(Iter::new([Duration::from_secs(1)]), &stats);
+        assert!(policy.should_retry(None::<()>).is_continue());
+
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(stats.snapshot().total.attempts, 1);
+    }
+}
@@ -0,0 +1,313 @@
+//! Failing fast when a dependency is clearly down.
+//!
+//! Retrying against a dependency that is failing almost every request only adds load
+//! and latency. [`SuccessRate`] tracks an exponentially weighted moving average of
+//! attempt outcomes, shared between calls, and [`FailFast`] stops retrying while it
+//! is below a threshold. This is a lighter alternative to a full circuit breaker, as
+//! first attempts are always made.
+//!
+//! Some results are neither a success nor worth retrying, like a response served from a
+//! fallback or a stale cache. With a [`Triage`], [`FailFast`] can treat those as
+//! [soft failures](Verdict::SoftFailure): they end the retry loop, but still count against the
+//! success rate, so later calls back off.
+
+use std::{
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{slow_start::SlowStart, RetryPolicy, ShouldRetry, UseShouldRetry};
+
+/// A moving average of the success rate of attempts, shared between clones.
+///
+/// Recent attempts are weighted by `alpha`, so a higher `alpha` reacts faster. The rate
+/// starts at `1.0`, so a new dependency is assumed to be healthy.
+#[derive(Debug, Clone)]
+pub struct SuccessRate {
+    rate: Arc<AtomicU64>,
+    alpha: f64,
+}
+
+impl SuccessRate {
+    /// Track the success rate, weighting each new attempt by `alpha`, between `0.0` and `1.0`
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            rate: Arc::new(AtomicU64::new(1.0f64.to_bits())),
+            alpha: alpha.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Record the outcome of an attempt
+    pub fn record(&self, success: bool) {
+        let sample = if success { 1.0 } else { 0.0 };
+        let _ = self
+            .rate
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                let rate = f64::from_bits(bits);
+                Some((self.alpha * sample + (1.0 - self.alpha) * rate).to_bits())
+            });
+    }
+
+    /// The current success rate, between `0.0` and `1.0`
+    pub fn rate(&self) -> f64 {
+        f64::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for SuccessRate {
+    /// Weight each new attempt by `0.1`
+    fn default() -> Self {
+        Self::new(0.1)
+    }
+}
+
+/// How an attempt went, as decided by a [`Triage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    /// The attempt succeeded
+    Success,
+    /// The attempt failed, and can be retried
+    RetryableFailure,
+    /// The attempt failed, but its result is good enough to return, like a fallback response.
+    /// It isn't retried, but counts as a failure.
+    SoftFailure,
+}
+
+/// Decides the [`Verdict`] for a result.
+///
+/// This is implemented for closures taking a reference to the result, and for
+/// [`UseShouldRetry`], which only gives [`Success`](Verdict::Success) and
+/// [`RetryableFailure`](Verdict::RetryableFailure) according to the result's [`ShouldRetry`] impl.
+pub trait Triage<R> {
+    /// The verdict for an attempt.
+    /// `attempts` denotes how many prior attempts have been made (starts at 1).
+    fn triage(&mut self, result: &R, attempts: u32) -> Verdict;
+}
+
+impl<R, F: FnMut(&R) -> Verdict> Triage<R> for F {
+    fn triage(&mut self, result: &R, _: u32) -> Verdict {
+        self(result)
+    }
+}
+
+impl<R: ShouldRetry> Triage<R> for UseShouldRetry {
+    fn triage(&mut self, result: &R, attempts: u32) -> Verdict {
+        if result.should_retry(attempts) {
+            Verdict::RetryableFailure
+        } else {
+            Verdict::Success
+        }
+    }
+}
+
+/// A [`RetryPolicy`] that records every attempt into a [`SuccessRate`], and doesn't retry
+/// while that rate is below a threshold.
+///
+/// Attempts are counted as successful when they shouldn't be retried, according to [`ShouldRetry`],
+/// unless another [`Triage`] is given with [`FailFast::triage`].
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, success_rate::{FailFast, SuccessRate}, tokio::RetryFutureExt};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     // shared by all calls to the same dependency
+///     let success_rate = SuccessRate::default();
+///
+///     let policy = FailFast::new(Iter::new([Duration::from_millis(10); 3]), success_rate.clone(), 0.2);
+///     make_request.retry(policy).await.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FailFast<P, C = UseShouldRetry> {
+    policy: P,
+    triage: C,
+    success_rate: SuccessRate,
+    threshold: f64,
+    slow_start: Option<SlowStart>,
+    attempts: u32,
+}
+
+impl<P> FailFast<P> {
+    /// Retry with `policy`, unless `success_rate` is below `threshold`
+    pub fn new(policy: P, success_rate: SuccessRate, threshold: f64) -> Self {
+        Self {
+            policy,
+            triage: UseShouldRetry,
+            success_rate,
+            threshold,
+            slow_start: None,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, C> FailFast<P, C> {
+    /// Decide how each attempt went with `triage`, eg. to record [soft failures](Verdict::SoftFailure)
+    ///
+    /// ```
+    /// use futures_retry_policies::{
+    ///     iter::Iter,
+    ///     success_rate::{FailFast, SuccessRate, Verdict},
+    ///     RetryPolicy,
+    /// };
+    /// use std::time::Duration;
+    ///
+    /// enum Response { Fresh, FromFallback, Unavailable }
+    /// # impl futures_retry_policies::ShouldRetry for Response {
+    /// #     fn should_retry(&self, _: u32) -> bool { matches!(self, Response::Unavailable) }
+    /// # }
+    ///
+    /// let success_rate = SuccessRate::new(0.5);
+    /// let mut policy = FailFast::new(Iter::new([Duration::from_secs(1); 3]), success_rate.clone(), 0.2)
+    ///     .triage(|res: &Response| match res {
+    ///         Response::Fresh => Verdict::Success,
+    ///         Response::FromFallback => Verdict::SoftFailure,
+    ///         Response::Unavailable => Verdict::RetryableFailure,
+    ///     });
+    ///
+    /// // the fallback response is returned, but the dependency looks less healthy
+    /// assert!(policy.should_retry(Response::FromFallback).is_break());
+    /// assert_eq!(success_rate.rate(), 0.5);
+    /// ```
+    pub fn triage<C2>(self, triage: C2) -> FailFast<P, C2> {
+        FailFast {
+            policy: self.policy,
+            triage,
+            success_rate: self.success_rate,
+            threshold: self.threshold,
+            slow_start: self.slow_start,
+            attempts: self.attempts,
+        }
+    }
+
+    /// Trip `slow_start` whenever this fails fast, so retries ramp back up once the success rate recovers
+    pub fn slow_start(mut self, slow_start: SlowStart) -> Self {
+        self.slow_start = Some(slow_start);
+        self
+    }
+}
+
+impl<P, C, R> RetryPolicy<R> for FailFast<P, C>
+where
+    P: RetryPolicy<R>,
+    C: Triage<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts += 1;
+        let verdict = self.triage.triage(&result, self.attempts);
+        self.success_rate.record(verdict == Verdict::Success);
+        if verdict == Verdict::SoftFailure {
+            return ControlFlow::Break(result);
+        }
+
+        let retryable = verdict == Verdict::RetryableFailure;
+
+        if retryable && self.success_rate.rate() < self.threshold {
+            if let Some(slow_start) = &self.slow_start {
+                slow_start.trip();
+            }
+            return ControlFlow::Break(result);
+        }
+        if retryable {
+            if let Some(slow_start) = &self.slow_start {
+                if !slow_start.try_acquire() {
+                    return ControlFlow::Break(result);
+                }
+            }
+        }
+        self.policy.should_retry(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::{FailFast, SuccessRate, Verdict};
+    use crate::{clock::MockClock, iter::Iter, slow_start::SlowStart, RetryPolicy};
+
+    #[test]
+    fn moving_average() {
+        let rate = SuccessRate::new(0.5);
+        assert_eq!(rate.rate(), 1.0);
+        rate.record(false);
+        assert_eq!(rate.rate(), 0.5);
+        rate.record(false);
+        assert_eq!(rate.rate(), 0.25);
+        rate.record(true);
+        assert_eq!(rate.rate(), 0.625);
+    }
+
+    #[test]
+    fn fails_fast_when_down() {
+        let rate = SuccessRate::new(0.5);
+        let policy = || FailFast::new(Iter::new([Duration::from_secs(1); 10]), rate.clone(), 0.2);
+
+        // first call retries while the rate drops: 0.5, 0.25, 0.125
+        let mut first = policy();
+        assert!(first.should_retry(None::<()>).is_continue());
+        assert!(first.should_retry(None::<()>).is_continue());
+        assert_eq!(first.should_retry(None::<()>), ControlFlow::Break(None));
+
+        // later calls fail fast too
+        let mut second = policy();
+        assert_eq!(second.should_retry(None::<()>), ControlFlow::Break(None));
+
+        // and recover once requests succeed again
+        rate.record(true);
+        rate.record(true);
+        let mut third = policy();
+        assert!(third.should_retry(None::<()>).is_continue());
+    }
+
+    #[test]
+    fn slow_starts_after_recovery() {
+        let rate = SuccessRate::new(0.5);
+        let slow_start = SlowStart::new(Duration::from_secs(60), 4).with_clock(MockClock::new());
+        let policy = || {
+            FailFast::new(Iter::new([Duration::from_secs(1); 10]), rate.clone(), 0.2)
+                .slow_start(slow_start.clone())
+        };
+
+        let mut first = policy();
+        while first.should_retry(None::<()>).is_continue() {}
+        assert!(slow_start.is_ramping());
+
+        // recovered, but only one retry is let through in the first interval
+        for _ in 0..4 {
+            rate.record(true);
+        }
+        assert!(policy().should_retry(None::<()>).is_continue());
+        assert_eq!(policy().should_retry(None::<()>), ControlFlow::Break(None));
+        assert!(rate.rate() > 0.2);
+    }
+
+    #[test]
+    fn soft_failures_make_later_calls_fail_fast() {
+        let rate = SuccessRate::new(0.5);
+        let mut soft = FailFast::new(Iter::new([Duration::from_secs(1); 10]), rate.clone(), 0.2)
+            .triage(|res: &Option<&str>| match res {
+                Some("fallback") => Verdict::SoftFailure,
+                Some(_) => Verdict::Success,
+                None => Verdict::RetryableFailure,
+            });
+        for _ in 0..3 {
+            assert!(soft.should_retry(Some("fallback")).is_break());
+        }
+
+        let mut later = FailFast::new(Iter::new([Duration::from_secs(1); 10]), rate.clone(), 0.2);
+        assert_eq!(later.should_retry(None::<()>), ControlFlow::Break(None));
+    }
+}
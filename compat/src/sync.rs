@@ -3,10 +3,12 @@
 //! While this crate is intended for use with futures, there's nothing that stops [`RetryPolicy`]
 //! from working in a sync fashion.
 
-use std::{ops::ControlFlow, thread};
+use std::thread;
 
 use crate::RetryPolicy;
 
+pub use crate::blocking::retry_blocking;
+
 /// Blocking retry function
 ///
 /// ```rust
@@ -37,17 +39,12 @@ use crate::RetryPolicy;
 ///     retry(Attempts(3), make_request)
 /// }
 /// ```
-pub fn retry<Policy, F, Output>(mut policy: Policy, f: F) -> Output
+pub fn retry<Policy, F, Output>(policy: Policy, f: F) -> Output
 where
     Policy: RetryPolicy<Output>,
     F: Fn() -> Output,
 {
-    loop {
-        match policy.should_retry(f()) {
-            ControlFlow::Continue(dur) => thread::sleep(dur),
-            ControlFlow::Break(result) => break result,
-        }
-    }
+    retry_blocking(policy, thread::sleep, f)
 }
 
 /// Easy helper trait to retry functions
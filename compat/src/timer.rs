@@ -0,0 +1,104 @@
+//! Timers for tests.
+//!
+//! The sleeper passed to [`retry_with_sleeper`](crate::retry_with_sleeper) can be any
+//! [`RetrySleeper`], including plain sleep functions like `tokio::time::sleep`. In tests, it's
+//! often clearer to say what the sleeps are for. [`NoopTimer`] skips the delays entirely, and
+//! [`PanicTimer`] fails the test if anything is retried at all.
+
+use std::{
+    future::{ready, Ready},
+    time::Duration,
+};
+
+use crate::RetrySleeper;
+
+/// A [`RetrySleeper`] that returns immediately, ignoring the delay.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, retry_with_sleeper, timer::NoopTimer};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_secs(3600); 3]);
+///     // finishes straight away, despite the hour-long delays
+///     let res = retry_with_sleeper(policy, NoopTimer, || async { None::<()> }).await;
+///     assert_eq!(res, None);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopTimer;
+
+impl RetrySleeper for NoopTimer {
+    type Sleep = Ready<()>;
+
+    fn sleep(&mut self, _: Duration, _: u32) -> Ready<()> {
+        ready(())
+    }
+}
+
+/// A [`RetrySleeper`] that panics if it is ever asked to sleep.
+///
+/// Use it in tests where the first attempt is expected to be final, so an unexpected retry
+/// fails loudly instead of silently waiting.
+///
+/// ```should_panic
+/// use futures_retry_policies::{iter::Iter, retry_with_sleeper, timer::PanicTimer};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10)]);
+///     // panics: "unexpected retry after attempt 1"
+///     retry_with_sleeper(policy, PanicTimer, || async { None::<()> }).await;
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PanicTimer;
+
+impl RetrySleeper for PanicTimer {
+    type Sleep = Ready<()>;
+
+    #[track_caller]
+    fn sleep(&mut self, delay: Duration, attempt: u32) -> Ready<()> {
+        panic!("unexpected retry after attempt {attempt}, with a delay of {delay:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{NoopTimer, PanicTimer};
+    use crate::{iter::Iter, retry_with_sleeper};
+
+    #[tokio::test]
+    async fn noop() {
+        let mut attempts = 0;
+        let res = retry_with_sleeper(Iter::new([Duration::MAX; 2]), NoopTimer, || {
+            attempts += 1;
+            async { None::<()> }
+        })
+        .await;
+        assert_eq!(res, None);
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn panic_timer_allows_first_attempt() {
+        let res = retry_with_sleeper(Iter::new([Duration::ZERO]), PanicTimer, || async {
+            Some(())
+        })
+        .await;
+        assert_eq!(res, Some(()));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "unexpected retry after attempt 1")]
+    async fn panic_timer_panics_on_retry() {
+        retry_with_sleeper(Iter::new([Duration::ZERO]), PanicTimer, || async {
+            None::<()>
+        })
+        .await;
+    }
+}
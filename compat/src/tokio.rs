@@ -2,10 +2,15 @@
 #![cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
 //! Retry features for the [tokio runtime](https://tokio.rs)
 
-use std::{future::Future, time::Duration};
-use tokio::time::{sleep, Sleep};
+use std::{fmt::Debug, future::Future, ops::ControlFlow, time::Duration};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch, TryLockError},
+    task::{JoinError, JoinHandle},
+    time::{error::Elapsed, sleep, Instant, Sleep},
+};
 
-use crate::RetryPolicy;
+use crate::{Classify, RetryPolicy, ShouldRetry, UseShouldRetry};
 
 /// Retry a future using the given [retry policy](`RetryPolicy`) and [tokio's sleep](`sleep`) method.
 ///
@@ -104,6 +109,336 @@ where
 {
 }
 
+/// The progress of a retry loop, as reported by [`retry_with_progress`] and [`retry_with_progress_if`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RetryProgress {
+    /// The current attempt, starting at 1, or 0 before the first attempt has started
+    pub attempt: u32,
+    /// When the next attempt will start, if the loop is waiting to retry
+    pub next_retry_at: Option<Instant>,
+    /// The [`Debug`] output of the last result that is being retried
+    pub last_error: Option<String>,
+    /// Whether the policy has stopped retrying
+    pub finished: bool,
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and [tokio's sleep](`sleep`) method,
+/// reporting the progress on a [`watch`] channel.
+///
+/// This is useful for showing users what is going on, eg. "retrying in 12s (attempt 3)".
+/// Dropping the returned future cancels the retries. Only results that [should be
+/// retried](ShouldRetry) are formatted for [`last_error`](RetryProgress::last_error); use
+/// [`retry_with_progress_if`] to choose them with a classifier instead.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, tokio::retry_with_progress};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let (fut, mut progress) = retry_with_progress(policy, make_request);
+///
+///     tokio::spawn(async move {
+///         while progress.changed().await.is_ok() {
+///             let progress = progress.borrow_and_update();
+///             if let Some(at) = progress.next_retry_at {
+///                 let secs = at.saturating_duration_since(tokio::time::Instant::now()).as_secs();
+///                 println!("retrying in {secs}s (attempt {})", progress.attempt);
+///             }
+///         }
+///     });
+///
+///     fut.await.unwrap();
+/// }
+/// ```
+pub fn retry_with_progress<Policy, Futures, Fut>(
+    policy: Policy,
+    futures: Futures,
+) -> (
+    impl Future<Output = Fut::Output>,
+    watch::Receiver<RetryProgress>,
+)
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    Fut::Output: ShouldRetry + Debug,
+{
+    retry_with_progress_if(policy, UseShouldRetry, futures)
+}
+
+/// Like [`retry_with_progress`], but only reports results that `classifier` would retry.
+///
+/// Results are formatted for [`last_error`](RetryProgress::last_error) before the policy decides,
+/// so `classifier` picks out the results worth formatting, and the rest, like successes, aren't.
+/// Nothing is formatted once every receiver has been dropped.
+pub fn retry_with_progress_if<Policy, C, Futures, Fut>(
+    mut policy: Policy,
+    mut classifier: C,
+    mut futures: Futures,
+) -> (
+    impl Future<Output = Fut::Output>,
+    watch::Receiver<RetryProgress>,
+)
+where
+    Policy: RetryPolicy<Fut::Output>,
+    C: Classify<Fut::Output>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+    Fut::Output: Debug,
+{
+    let (tx, rx) = watch::channel(RetryProgress::default());
+    let fut = async move {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            tx.send_modify(|progress| {
+                progress.attempt = attempts;
+                progress.next_retry_at = None;
+            });
+
+            let result = futures().await;
+            // the policy takes the result, so format it now if it could be reported
+            let res = (!tx.is_closed() && classifier.classify(&result, attempts))
+                .then(|| format!("{result:?}"));
+            match policy.should_retry(result) {
+                ControlFlow::Continue(duration) => {
+                    tx.send_modify(|progress| {
+                        progress.next_retry_at = Some(Instant::now() + duration);
+                        progress.last_error = res;
+                    });
+                    sleep(duration).await;
+                }
+                ControlFlow::Break(result) => {
+                    tx.send_modify(|progress| progress.finished = true);
+                    break result;
+                }
+            }
+        }
+    };
+    (fut, rx)
+}
+
+/// Retry if the channel was full, but not if it was closed
+impl<T> ShouldRetry for mpsc::error::TrySendError<T> {
+    fn should_retry(&self, _: u32) -> bool {
+        matches!(self, mpsc::error::TrySendError::Full(_))
+    }
+}
+
+/// Retry if the channel was empty, but not if it was disconnected
+impl ShouldRetry for mpsc::error::TryRecvError {
+    fn should_retry(&self, _: u32) -> bool {
+        matches!(self, mpsc::error::TryRecvError::Empty)
+    }
+}
+
+/// Always retry, as the lock was only held by someone else
+impl ShouldRetry for TryLockError {
+    fn should_retry(&self, _: u32) -> bool {
+        true
+    }
+}
+
+/// Always retry timeouts
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, tokio::RetryFutureExt};
+/// use std::time::Duration;
+/// use tokio::time::timeout;
+///
+/// async fn make_request() {
+///     // make a request that sometimes hangs
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 1 { std::future::pending::<()>().await }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     let request = || timeout(Duration::from_millis(50), make_request());
+///     request.retry(policy).await.unwrap();
+/// }
+/// ```
+impl ShouldRetry for Elapsed {
+    fn should_retry(&self, _: u32) -> bool {
+        true
+    }
+}
+
+/// Retry panics, but not cancelled tasks
+impl ShouldRetry for JoinError {
+    fn should_retry(&self, _: u32) -> bool {
+        self.is_panic()
+    }
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and [tokio's sleep](`sleep`) method,
+/// running each attempt as its own [spawned](tokio::spawn) task.
+///
+/// A panicking attempt is caught by its [`JoinHandle`], so the policy sees it as a [`JoinError`]
+/// and can retry it rather than the panic tearing down the whole retry loop. [`JoinError`]s are
+/// retried by [`ShouldRetry`] if they were caused by a panic.
+///
+/// The attempts stay owned by the retry loop: dropping the returned future aborts the running attempt.
+///
+/// ```
+/// use futures_retry_policies::{tokio::retry_spawned, RetryPolicyBuilder};
+/// use std::time::Duration;
+/// use tokio::task::JoinError;
+///
+/// async fn flaky() -> Result<(), &'static str> {
+///     // an operation that occasionally panics
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 1 { panic!("oops") }
+///     Ok(())
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = RetryPolicyBuilder::fixed(Duration::from_millis(10))
+///         .max_retries(3)
+///         .retry_if(|res: &Result<Result<(), &str>, JoinError>| match res {
+///             Ok(res) => res.is_err(),
+///             Err(err) => err.is_panic(),
+///         })
+///         .build();
+///
+///     retry_spawned(policy, flaky).await.unwrap().unwrap();
+/// }
+/// ```
+pub async fn retry_spawned<Policy, Futures, Fut>(
+    policy: Policy,
+    futures: Futures,
+) -> Result<Fut::Output, JoinError>
+where
+    Policy: RetryPolicy<Result<Fut::Output, JoinError>>,
+    Futures: FnMut() -> Fut,
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    retry_spawned_on(Handle::current(), policy, futures).await
+}
+
+/// Like [`retry_spawned`], but spawns each attempt onto the runtime of `handle`.
+///
+/// Every attempt is a fresh task, so on a multi-threaded runtime a retry can be picked up by a
+/// different worker than the attempt that failed. This also allows retrying work on a dedicated
+/// runtime from outside of it.
+///
+/// ```
+/// use futures_retry_policies::{tokio::{retry_spawned_on, RetryJoinErrors}, RetryPolicyBuilder};
+/// use std::time::Duration;
+///
+/// async fn work() -> Option<u32> {
+///     // some work that occasionally fails or panics
+///     # Some(42)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+///
+///     let policy = RetryPolicyBuilder::fixed(Duration::from_millis(10))
+///         .max_retries(3)
+///         .retry_if(RetryJoinErrors::new().cancelled(true))
+///         .build();
+///     let res = retry_spawned_on(runtime.handle().clone(), policy, work).await;
+///     assert_eq!(res.unwrap(), Some(42));
+///     # runtime.shutdown_background();
+/// }
+/// ```
+pub async fn retry_spawned_on<Policy, Futures, Fut>(
+    handle: Handle,
+    mut policy: Policy,
+    mut futures: Futures,
+) -> Result<Fut::Output, JoinError>
+where
+    Policy: RetryPolicy<Result<Fut::Output, JoinError>>,
+    Futures: FnMut() -> Fut,
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    loop {
+        let task = AbortOnDrop(handle.spawn(futures()));
+        match policy.should_retry(task.join().await) {
+            ControlFlow::Continue(delay) => sleep(delay).await,
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+/// A [`Classify`] for the results of spawned tasks, choosing which [`JoinError`]s to retry.
+///
+/// Tasks that completed are retried according to their output's [`ShouldRetry`] impl.
+/// By default, panics are retried and cancellations are not, as with [`JoinError`]'s own
+/// [`ShouldRetry`] impl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryJoinErrors {
+    panics: bool,
+    cancelled: bool,
+}
+
+impl Default for RetryJoinErrors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryJoinErrors {
+    /// Retry panics, but not cancellations
+    pub const fn new() -> Self {
+        Self {
+            panics: true,
+            cancelled: false,
+        }
+    }
+
+    /// Whether to retry tasks that panicked
+    pub const fn panics(mut self, retry: bool) -> Self {
+        self.panics = retry;
+        self
+    }
+
+    /// Whether to retry tasks that were cancelled, eg. by a runtime shutting down
+    pub const fn cancelled(mut self, retry: bool) -> Self {
+        self.cancelled = retry;
+        self
+    }
+}
+
+impl<T: ShouldRetry> Classify<Result<T, JoinError>> for RetryJoinErrors {
+    fn classify(&mut self, result: &Result<T, JoinError>, attempts: u32) -> bool {
+        match result {
+            Ok(output) => output.should_retry(attempts),
+            Err(err) if err.is_panic() => self.panics,
+            Err(_) => self.cancelled,
+        }
+    }
+}
+
+/// Aborts the task if the retry loop is dropped while it's running
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> AbortOnDrop<T> {
+    async fn join(mut self) -> Result<T, JoinError> {
+        (&mut self.0).await
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -113,6 +448,7 @@ mod tests {
 
     use crate::{retry_policies::RetryPolicies, tokio::RetryFutureExt, ShouldRetry};
 
+    #[derive(Debug)]
     struct Error(u32);
     impl ShouldRetry for Error {
         fn should_retry(&self, attempts: u32) -> bool {
@@ -165,4 +501,167 @@ mod tests {
         req.retry(policy()).await.unwrap_err();
         assert_eq!(start.elapsed(), Duration::from_secs(1 + 2));
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_local() {
+        let attempts = std::rc::Rc::new(std::cell::Cell::new(0));
+        let local = tokio::task::LocalSet::new();
+
+        let start = tokio::time::Instant::now();
+        let task = local.spawn_local({
+            let attempts = attempts.clone();
+            async move {
+                (|| {
+                    // the `Rc` makes each attempt `!Send`
+                    let attempts = attempts.clone();
+                    async move {
+                        attempts.set(attempts.get() + 1);
+                        tokio::task::yield_now().await;
+                        Err::<(), _>(Error(2))
+                    }
+                })
+                .retry(policy())
+                .await
+            }
+        });
+        local.run_until(task).await.unwrap().unwrap_err();
+
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(1 + 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn progress() {
+        let (fut, progress) = super::retry_with_progress(
+            crate::iter::Iter::new([Duration::from_secs(1); 3]),
+            || async { Err::<(), _>(Error(1)) },
+        );
+        let start = tokio::time::Instant::now();
+        let mut fut = std::pin::pin!(fut);
+
+        // let the first attempt fail
+        assert!(futures_poll(&mut fut).await.is_none());
+        let state = progress.borrow().clone();
+        assert_eq!(state.attempt, 1);
+        assert_eq!(state.next_retry_at, Some(start + Duration::from_secs(1)));
+        assert_eq!(state.last_error.as_deref(), Some("Err(Error(1))"));
+        assert!(!state.finished);
+
+        fut.await.unwrap_err();
+        let state = progress.borrow().clone();
+        assert_eq!(state.attempt, 2);
+        assert_eq!(state.next_retry_at, None);
+        assert!(state.finished);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn progress_formats_retries_only() {
+        struct Counted(u32);
+        impl std::fmt::Debug for Counted {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                FORMATTED.with(|n| n.set(n.get() + 1));
+                write!(f, "Counted({})", self.0)
+            }
+        }
+        thread_local! {
+            static FORMATTED: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+        }
+
+        let mut attempt = 0;
+        let (fut, progress) = super::retry_with_progress_if(
+            crate::RetryPolicyBuilder::fixed(Duration::from_secs(1))
+                .retry_if(|res: &Counted| res.0 < 2)
+                .build(),
+            |res: &Counted| res.0 < 2,
+            || {
+                attempt += 1;
+                std::future::ready(Counted(attempt))
+            },
+        );
+        assert_eq!(fut.await.0, 2);
+        assert_eq!(progress.borrow().last_error.as_deref(), Some("Counted(1)"));
+        assert_eq!(FORMATTED.get(), 1);
+    }
+
+    /// Poll the future once
+    async fn futures_poll<F: std::future::Future + Unpin>(fut: &mut F) -> Option<F::Output> {
+        std::future::poll_fn(|cx| {
+            std::task::Poll::Ready(match std::pin::Pin::new(&mut *fut).poll(cx) {
+                std::task::Poll::Ready(res) => Some(res),
+                std::task::Poll::Pending => None,
+            })
+        })
+        .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawned_panics_are_retried() {
+        let attempts = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let res = super::retry_spawned(crate::iter::Iter::new([Duration::from_secs(1); 3]), || {
+            let attempts = attempts.clone();
+            async move {
+                if attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 {
+                    panic!("flaky");
+                }
+            }
+        })
+        .await;
+
+        assert!(res.is_ok());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_full_channels() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        tx.try_send(0).unwrap();
+
+        let send = super::retry(crate::iter::Iter::new([Duration::from_secs(1); 3]), || {
+            std::future::ready(tx.try_send(1))
+        });
+        let (sent, first) = tokio::join!(send, rx.recv());
+        assert_eq!(first, Some(0));
+        sent.unwrap();
+        assert_eq!(rx.recv().await, Some(1));
+
+        drop(rx);
+        let closed = super::retry(crate::iter::Iter::new([Duration::from_secs(1); 3]), || {
+            std::future::ready(tx.try_send(2))
+        });
+        assert!(matches!(
+            closed.await,
+            Err(tokio::sync::mpsc::error::TrySendError::Closed(2))
+        ));
+    }
+
+    #[tokio::test]
+    async fn classifies_cancelled_tasks() {
+        use crate::Classify;
+
+        let task = tokio::spawn(std::future::pending::<Option<()>>());
+        task.abort();
+        let cancelled = task.await;
+
+        let mut default = super::RetryJoinErrors::new();
+        assert!(!default.classify(&cancelled, 1));
+        assert!(default.classify(&Ok(None::<()>), 1));
+        assert!(super::RetryJoinErrors::new()
+            .cancelled(true)
+            .classify(&cancelled, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawned_attempt_is_aborted_on_drop() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(1);
+        let fut = super::retry_spawned(crate::iter::Iter::new([Duration::ZERO]), move || {
+            let tx = tx.clone();
+            async move {
+                let _tx = tx;
+                std::future::pending::<()>().await
+            }
+        });
+        let _ = tokio::time::timeout(Duration::from_secs(1), fut).await;
+        // the attempt holding the sender was aborted
+        assert!(rx.recv().await.is_none());
+    }
 }
@@ -2,11 +2,14 @@
 #![cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
 //! Retry features for [`tracing`] support
 
-use std::{fmt::Debug, ops::ControlFlow, time::Duration};
+use std::{fmt::Debug, future::Future, ops::ControlFlow, time::Duration};
+
+use tracing::Instrument;
 
 use crate::RetryPolicy;
 
 /// A [`RetryPolicy`] that logs a warning if a retry is being attempted
+#[derive(Debug, Clone)]
 pub struct Traced<P>(pub P);
 
 impl<P, R> RetryPolicy<R> for Traced<P>
@@ -23,3 +26,228 @@ where
         ControlFlow::Continue(duration)
     }
 }
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function,
+/// running each attempt in its own span.
+///
+/// Each attempt gets an `info` level `retry_attempt` span, as a child of the current span,
+/// with the attempt number as `retry.attempt` and the delay that preceded it as `retry.delay`.
+/// Backends can use these to group the attempts of a retried call. With `tracing-opentelemetry`,
+/// the fields are exported as attributes of the attempt spans.
+///
+/// With the `opentelemetry` feature, each attempt also runs with the attempt number as
+/// `retry.attempt` in the [baggage](opentelemetry::baggage) of the current OpenTelemetry
+/// context, so propagators send it along with outgoing requests. This is set on the
+/// OpenTelemetry context directly, rather than through `tracing-opentelemetry`.
+///
+/// ```
+/// use futures_retry_policies::{iter::Iter, tracing::retry_instrumented};
+/// use std::time::Duration;
+///
+/// async fn make_request() -> Option<()> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { None } else { Some(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let policy = Iter::new([Duration::from_millis(10); 3]);
+///     retry_instrumented(policy, tokio::time::sleep, make_request).await.unwrap();
+/// }
+/// ```
+pub async fn retry_instrumented<Policy, Sleeper, Sleep, Futures, Fut>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut futures: Futures,
+) -> Fut::Output
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
+{
+    let mut attempt = 1u32;
+    let mut delay = Duration::ZERO;
+    loop {
+        let span = tracing::info_span!(
+            "retry_attempt",
+            "retry.attempt" = attempt,
+            "retry.delay" = ?delay,
+        );
+        #[cfg(not(feature = "opentelemetry"))]
+        let attempt_fut = futures().instrument(span);
+        #[cfg(feature = "opentelemetry")]
+        let attempt_fut = {
+            // the baggage is set both while creating the attempt and while polling it
+            let cx = attempt_context(attempt);
+            let attempt_fut = {
+                let _guard = cx.clone().attach();
+                futures().instrument(span)
+            };
+            opentelemetry::context::FutureExt::with_context(attempt_fut, cx)
+        };
+        match policy.should_retry(attempt_fut.await) {
+            ControlFlow::Continue(dur) => {
+                sleeper(dur).await;
+                attempt += 1;
+                delay = dur;
+            }
+            ControlFlow::Break(result) => break result,
+        }
+    }
+}
+
+/// The current context, with `attempt` added to its baggage
+#[cfg(feature = "opentelemetry")]
+fn attempt_context(attempt: u32) -> opentelemetry::Context {
+    use opentelemetry::baggage::{Baggage, BaggageExt};
+
+    opentelemetry::Context::map_current(|cx| {
+        let mut baggage: Baggage = cx
+            .baggage()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        baggage.insert("retry.attempt", attempt.to_string());
+        cx.with_baggage(baggage)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt::Debug,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Instrument, Metadata, Subscriber,
+    };
+
+    use super::retry_instrumented;
+    use crate::iter::Iter;
+
+    #[derive(Debug)]
+    struct CapturedSpan {
+        name: &'static str,
+        fields: Vec<(&'static str, String)>,
+        parent: Option<u64>,
+    }
+
+    impl Visit for CapturedSpan {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.fields.push((field.name(), format!("{value:?}")));
+        }
+    }
+
+    /// A subscriber keeping every span it's told about, with its fields and parent
+    #[derive(Clone, Default)]
+    struct Capture {
+        spans: Arc<Mutex<Vec<CapturedSpan>>>,
+        entered: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+            let parent = match attrs.parent() {
+                Some(parent) => Some(parent.into_u64()),
+                None if attrs.is_contextual() => self.entered.lock().unwrap().last().copied(),
+                None => None,
+            };
+            let mut span = CapturedSpan {
+                name: attrs.metadata().name(),
+                fields: Vec::new(),
+                parent,
+            };
+            attrs.record(&mut span);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(span);
+            span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, span: &span::Id) {
+            self.entered.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _: &span::Id) {
+            self.entered.lock().unwrap().pop();
+        }
+    }
+
+    async fn sleep(_: Duration) {}
+
+    #[test]
+    fn span_per_attempt() {
+        let capture = Capture::default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        tracing::subscriber::with_default(capture.clone(), || {
+            let call = tracing::info_span!("call");
+            let res = runtime.block_on(
+                retry_instrumented(Iter::new([Duration::from_secs(1); 2]), sleep, || async {
+                    None::<()>
+                })
+                .instrument(call),
+            );
+            assert_eq!(res, None);
+        });
+
+        let spans = capture.spans.lock().unwrap();
+        assert_eq!(spans.len(), 4);
+        assert_eq!(spans[0].name, "call");
+        for (i, delay) in ["0ns", "1s", "1s"].into_iter().enumerate() {
+            let span = &spans[i + 1];
+            assert_eq!(span.name, "retry_attempt");
+            assert_eq!(span.parent, Some(1));
+            let attempt = (i + 1).to_string();
+            assert_eq!(
+                span.fields,
+                [("retry.attempt", &*attempt), ("retry.delay", delay)]
+                    .map(|(k, v)| (k, v.to_owned()))
+            );
+        }
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    #[tokio::test]
+    async fn attempt_baggage() {
+        use opentelemetry::{baggage::BaggageExt, Context};
+
+        fn attempt() -> Option<String> {
+            let cx = Context::current();
+            cx.baggage().get("retry.attempt").map(|v| v.to_string())
+        }
+
+        let seen = Mutex::new(vec![]);
+        retry_instrumented(Iter::new([Duration::ZERO; 2]), sleep, || {
+            let created = attempt();
+            let seen = &seen;
+            async move {
+                seen.lock().unwrap().push((created, attempt()));
+                None::<()>
+            }
+        })
+        .await;
+
+        let seen = seen.into_inner().unwrap();
+        let expected: Vec<_> = ["1", "2", "3"]
+            .map(|n| (Some(n.to_owned()), Some(n.to_owned())))
+            .into();
+        assert_eq!(seen, expected);
+    }
+}
@@ -0,0 +1,269 @@
+//! Configuration compatible with [`tryhard`](https://docs.rs/tryhard).
+//!
+//! Code migrating from `tryhard` can keep its configuration, swapping `tryhard::retry_fn(f)`
+//! for a [`RetryFutureConfig`] passed to any of the retry functions in this crate.
+//!
+//! ```
+//! use futures_retry_policies::{tokio::RetryFutureExt, tryhard};
+//! use std::time::Duration;
+//!
+//! #[derive(Debug)]
+//! struct Error;
+//!
+//! async fn make_request() -> Result<(), Error> {
+//!     // make a request
+//!     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+//!     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err(Error) } else { Ok(()) }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // was: tryhard::retry_fn(make_request).retries(5).exponential_backoff(..).max_delay(..)
+//!     let config = tryhard::retries(5)
+//!         .exponential_backoff(Duration::from_millis(10))
+//!         .max_delay(Duration::from_secs(1));
+//!     make_request.retry(config).await
+//! }
+//! ```
+//!
+//! Unlike most policies in this crate, and like `tryhard`, every error is retried unless
+//! a [custom backoff](RetryFutureConfig::custom_backoff) decides otherwise.
+
+use std::{ops::ControlFlow, time::Duration};
+
+/// What a backoff strategy decided to do after an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryPolicy {
+    /// Retry straight away
+    Repeat,
+    /// Retry after the delay
+    Delay(Duration),
+    /// Stop retrying
+    Break,
+}
+
+impl From<Duration> for RetryPolicy {
+    fn from(delay: Duration) -> Self {
+        RetryPolicy::Delay(delay)
+    }
+}
+
+/// Decides how long to wait before each retry
+pub trait BackoffStrategy<E> {
+    /// The decision for the given retry, starting at 1
+    fn delay(&mut self, attempt: u32, error: &E) -> RetryPolicy;
+}
+
+/// Retry straight away
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoBackoff;
+
+impl<E> BackoffStrategy<E> for NoBackoff {
+    fn delay(&mut self, _: u32, _: &E) -> RetryPolicy {
+        RetryPolicy::Repeat
+    }
+}
+
+/// Wait the same delay before every retry
+#[derive(Debug, Clone, Copy)]
+pub struct FixedBackoff {
+    delay: Duration,
+}
+
+impl<E> BackoffStrategy<E> for FixedBackoff {
+    fn delay(&mut self, _: u32, _: &E) -> RetryPolicy {
+        RetryPolicy::Delay(self.delay)
+    }
+}
+
+/// Wait a delay that grows linearly with each retry
+#[derive(Debug, Clone, Copy)]
+pub struct LinearBackoff {
+    delay: Duration,
+}
+
+impl<E> BackoffStrategy<E> for LinearBackoff {
+    fn delay(&mut self, attempt: u32, _: &E) -> RetryPolicy {
+        RetryPolicy::Delay(self.delay.saturating_mul(attempt))
+    }
+}
+
+/// Wait a delay that doubles with each retry
+#[derive(Debug, Clone, Copy)]
+pub struct ExponentialBackoff {
+    delay: Duration,
+}
+
+impl<E> BackoffStrategy<E> for ExponentialBackoff {
+    fn delay(&mut self, attempt: u32, _: &E) -> RetryPolicy {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        RetryPolicy::Delay(self.delay.saturating_mul(factor))
+    }
+}
+
+/// Decide each delay with a function
+#[derive(Debug, Clone, Copy)]
+pub struct CustomBackoff<F> {
+    f: F,
+}
+
+impl<E, F, R> BackoffStrategy<E> for CustomBackoff<F>
+where
+    F: FnMut(u32, &E) -> R,
+    R: Into<RetryPolicy>,
+{
+    fn delay(&mut self, attempt: u32, error: &E) -> RetryPolicy {
+        (self.f)(attempt, error).into()
+    }
+}
+
+/// Retry with up to `max_retries` retries, without waiting between them
+pub fn retries(max_retries: u32) -> RetryFutureConfig<NoBackoff> {
+    RetryFutureConfig::new(max_retries)
+}
+
+/// A [`RetryPolicy`](crate::RetryPolicy) configured like `tryhard`'s `RetryFutureConfig`
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct RetryFutureConfig<B> {
+    max_retries: u32,
+    max_delay: Option<Duration>,
+    backoff: B,
+    attempts: u32,
+}
+
+impl RetryFutureConfig<NoBackoff> {
+    /// Retry with up to `max_retries` retries, without waiting between them
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            max_delay: None,
+            backoff: NoBackoff,
+            attempts: 0,
+        }
+    }
+}
+
+impl<B> RetryFutureConfig<B> {
+    /// Never wait more than `max_delay` between retries
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Wait `delay` before every retry
+    pub fn fixed_backoff(self, delay: Duration) -> RetryFutureConfig<FixedBackoff> {
+        self.with_backoff(FixedBackoff { delay })
+    }
+
+    /// Wait `delay` multiplied by the retry number before every retry
+    pub fn linear_backoff(self, delay: Duration) -> RetryFutureConfig<LinearBackoff> {
+        self.with_backoff(LinearBackoff { delay })
+    }
+
+    /// Wait `delay` before the first retry, doubling it for every retry after
+    pub fn exponential_backoff(self, delay: Duration) -> RetryFutureConfig<ExponentialBackoff> {
+        self.with_backoff(ExponentialBackoff { delay })
+    }
+
+    /// Decide what to do after each error with `f`.
+    ///
+    /// `f` is called with the retry number, starting at 1, and the error. It can return a [`Duration`]
+    /// to wait, or a [`RetryPolicy`] to also retry straight away or stop retrying.
+    pub fn custom_backoff<F, E, R>(self, f: F) -> RetryFutureConfig<CustomBackoff<F>>
+    where
+        F: FnMut(u32, &E) -> R,
+        R: Into<RetryPolicy>,
+    {
+        self.with_backoff(CustomBackoff { f })
+    }
+
+    fn with_backoff<B2>(self, backoff: B2) -> RetryFutureConfig<B2> {
+        RetryFutureConfig {
+            max_retries: self.max_retries,
+            max_delay: self.max_delay,
+            backoff,
+            attempts: self.attempts,
+        }
+    }
+}
+
+impl<B, T, E> crate::RetryPolicy<Result<T, E>> for RetryFutureConfig<B>
+where
+    B: BackoffStrategy<E>,
+{
+    fn should_retry(&mut self, result: Result<T, E>) -> ControlFlow<Result<T, E>, Duration> {
+        let Err(error) = &result else {
+            return ControlFlow::Break(result);
+        };
+        if self.attempts >= self.max_retries {
+            return ControlFlow::Break(result);
+        }
+        self.attempts += 1;
+
+        let delay = match self.backoff.delay(self.attempts, error) {
+            RetryPolicy::Repeat => Duration::ZERO,
+            RetryPolicy::Delay(delay) => delay,
+            RetryPolicy::Break => return ControlFlow::Break(result),
+        };
+        ControlFlow::Continue(self.max_delay.map_or(delay, |max| delay.min(max)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::{retries, RetryPolicy};
+
+    fn delays<P: crate::RetryPolicy<Result<(), u32>>>(mut policy: P) -> Vec<Duration> {
+        let mut delays = vec![];
+        let mut error = 0;
+        while let ControlFlow::Continue(delay) = policy.should_retry(Err(error)) {
+            delays.push(delay);
+            error += 1;
+        }
+        delays
+    }
+
+    #[test]
+    fn backoffs() {
+        let secs = |s: [u64; 4]| s.map(Duration::from_secs).to_vec();
+        assert_eq!(delays(retries(4)), secs([0; 4]));
+        assert_eq!(
+            delays(retries(4).fixed_backoff(Duration::from_secs(1))),
+            secs([1; 4])
+        );
+        assert_eq!(
+            delays(retries(4).linear_backoff(Duration::from_secs(1))),
+            secs([1, 2, 3, 4])
+        );
+        assert_eq!(
+            delays(
+                retries(4)
+                    .exponential_backoff(Duration::from_secs(1))
+                    .max_delay(Duration::from_secs(5))
+            ),
+            secs([1, 2, 4, 5])
+        );
+    }
+
+    #[test]
+    fn custom_backoff() {
+        let config = retries(10).custom_backoff(|attempt, error: &u32| match error {
+            0 => RetryPolicy::Repeat,
+            3 => RetryPolicy::Break,
+            _ => RetryPolicy::Delay(Duration::from_secs(attempt.into())),
+        });
+        assert_eq!(delays(config), [0, 2, 3].map(Duration::from_secs).to_vec());
+    }
+
+    #[test]
+    fn ok_is_not_retried() {
+        let mut config = retries(3);
+        assert_eq!(
+            crate::RetryPolicy::should_retry(&mut config, Ok::<_, ()>(())),
+            ControlFlow::Break(Ok(()))
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Retry limits that depend on what went wrong.
+//!
+//! Different failures deserve different patience. A timeout might be worth a couple more
+//! tries, while a `503 Service Unavailable` could be retried for longer. [`PerVariant`] maps
+//! each result to a kind, and limits how many times each kind is retried.
+
+use crate::Classify;
+
+/// A [`Classify`] that limits retries per kind of result.
+///
+/// Each result is mapped to a kind by a function, returning `None` for results
+/// that should not be retried at all. Each kind is then retried up to its own limit,
+/// counting only the retries made for that kind.
+///
+/// ```
+/// use futures_retry_policies::{variants::PerVariant, tokio::RetryFutureExt, RetryPolicyBuilder};
+/// use std::time::Duration;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Kind { Timeout, Unavailable }
+///
+/// #[derive(Debug)]
+/// enum Error { Timeout, Unavailable, Invalid }
+///
+/// async fn make_request() -> Result<(), Error> {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err(Error::Timeout) } else { Ok(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let classifier = PerVariant::new(|res: &Result<(), Error>| match res {
+///         Err(Error::Timeout) => Some(Kind::Timeout),
+///         Err(Error::Unavailable) => Some(Kind::Unavailable),
+///         _ => None,
+///     })
+///     .at_most(Kind::Timeout, 2)
+///     .at_most(Kind::Unavailable, 5);
+///
+///     let policy = RetryPolicyBuilder::exponential(Duration::from_millis(10))
+///         .retry_if(classifier)
+///         .build();
+///     make_request.retry(policy).await
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PerVariant<K, F> {
+    kind: F,
+    limits: Vec<(K, u32)>,
+    default_limit: Option<u32>,
+    retries: Vec<(K, u32)>,
+}
+
+impl<K, F> PerVariant<K, F> {
+    /// Group results into kinds with `kind`.
+    ///
+    /// Kinds without a limit are retried without limit, leaving it up to the policy.
+    pub fn new(kind: F) -> Self {
+        Self {
+            kind,
+            limits: Vec::new(),
+            default_limit: None,
+            retries: Vec::new(),
+        }
+    }
+
+    /// Retry results of the given kind at most `max_retries` times
+    pub fn at_most(mut self, kind: K, max_retries: u32) -> Self {
+        self.limits.push((kind, max_retries));
+        self
+    }
+
+    /// Retry results of kinds without their own limit at most `max_retries` times
+    pub fn default_limit(mut self, max_retries: u32) -> Self {
+        self.default_limit = Some(max_retries);
+        self
+    }
+}
+
+impl<R, K, F> Classify<R> for PerVariant<K, F>
+where
+    K: PartialEq,
+    F: FnMut(&R) -> Option<K>,
+{
+    fn classify(&mut self, result: &R, _: u32) -> bool {
+        let Some(kind) = (self.kind)(result) else {
+            return false;
+        };
+
+        let limit = self
+            .limits
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, limit)| *limit)
+            .or(self.default_limit);
+
+        let retries = match self.retries.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, retries)) => retries,
+            None => {
+                self.retries.push((kind, 0));
+                &mut self.retries.last_mut().unwrap().1
+            }
+        };
+
+        if limit.is_some_and(|limit| *retries >= limit) {
+            return false;
+        }
+        *retries += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PerVariant;
+    use crate::Classify;
+
+    #[derive(Debug, PartialEq)]
+    enum Kind {
+        Timeout,
+        Unavailable,
+        Reset,
+    }
+
+    fn kind(res: &Kind) -> Option<Kind> {
+        match res {
+            Kind::Timeout => Some(Kind::Timeout),
+            Kind::Unavailable => Some(Kind::Unavailable),
+            Kind::Reset => None,
+        }
+    }
+
+    #[test]
+    fn limits_per_kind() {
+        let mut classifier = PerVariant::new(kind)
+            .at_most(Kind::Timeout, 2)
+            .at_most(Kind::Unavailable, 3);
+
+        let results = [
+            Kind::Timeout,
+            Kind::Unavailable,
+            Kind::Timeout,
+            Kind::Unavailable,
+            Kind::Unavailable,
+        ];
+        for (attempt, res) in (1..).zip(&results) {
+            assert!(classifier.classify(res, attempt));
+        }
+        assert!(!classifier.classify(&Kind::Timeout, 6));
+        assert!(!classifier.classify(&Kind::Unavailable, 6));
+        assert!(!classifier.classify(&Kind::Reset, 6));
+    }
+
+    #[test]
+    fn default_limit() {
+        let mut classifier = PerVariant::new(kind)
+            .at_most(Kind::Timeout, 2)
+            .default_limit(1);
+
+        assert!(classifier.classify(&Kind::Unavailable, 1));
+        assert!(!classifier.classify(&Kind::Unavailable, 2));
+        assert!(classifier.classify(&Kind::Timeout, 2));
+    }
+}
@@ -0,0 +1,231 @@
+//! Verbose logging of retry decisions, switched on at runtime.
+//!
+//! When debugging an incident, it helps to see every decision the retry policies are making,
+//! without redeploying with extra logging. Setting the `FUTURES_RETRY_VERBOSE` environment variable
+//! to `1` (or calling [`set_enabled`]) makes the crate's own policies, like
+//! [`Backoff`](crate::backoff::Backoff), [`Iter`](crate::iter::Iter) and
+//! [`RetryPolicies`](crate::retry_policies::RetryPolicies), report each decision. Wrap other
+//! policies in [`Verbose`] to include them too.
+//!
+//! When switched off, the cost is a single atomic load per decision.
+//!
+//! # Output
+//!
+//! Decisions are reported at the info level under the `futures_retry_policies::verbose` target,
+//! with [`tracing`](::tracing) if the `tracing` feature is enabled, or with [`log`](::log) if the
+//! `log` feature is.
+//!
+//! **Without either feature, decisions are printed to stderr** with [`eprintln!`], so verbose
+//! logging still works in a binary that doesn't set up a logger. The output isn't structured, and
+//! can't be filtered or redirected by the application, so enable one of the features if writing
+//! to the process's stderr isn't acceptable.
+
+use std::{
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use crate::RetryPolicy;
+
+/// The environment variable that enables verbose logging, when set to `1`, `true` or `on`
+pub const ENV_VAR: &str = "FUTURES_RETRY_VERBOSE";
+
+const UNSET: u8 = 0;
+const OFF: u8 = 1;
+const ON: u8 = 2;
+
+static STATE: Switch = Switch::new();
+
+/// Whether retry decisions are being logged
+pub fn is_enabled() -> bool {
+    STATE.is_enabled(|| std::env::var(ENV_VAR).ok())
+}
+
+/// Switch verbose logging on or off, overriding the environment variable
+pub fn set_enabled(enabled: bool) {
+    STATE.set(enabled);
+}
+
+/// The on/off state, which defaults to the environment variable until it's set
+struct Switch {
+    state: AtomicU8,
+    from_env: OnceLock<bool>,
+}
+
+impl Switch {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(UNSET),
+            from_env: OnceLock::new(),
+        }
+    }
+
+    fn is_enabled(&self, var: impl FnOnce() -> Option<String>) -> bool {
+        match self.state.load(Ordering::Relaxed) {
+            UNSET => self.read_env(var),
+            state => state == ON,
+        }
+    }
+
+    fn set(&self, enabled: bool) {
+        self.state
+            .store(if enabled { ON } else { OFF }, Ordering::Relaxed);
+    }
+
+    fn read_env(&self, var: impl FnOnce() -> Option<String>) -> bool {
+        *self.from_env.get_or_init(|| {
+            let enabled = var()
+                .is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "1" | "true" | "on"));
+            // only overwrite the state if it wasn't set in the meantime
+            let state = if enabled { ON } else { OFF };
+            let _ = self
+                .state
+                .compare_exchange(UNSET, state, Ordering::Relaxed, Ordering::Relaxed);
+            enabled
+        })
+    }
+}
+
+/// Report the decision `policy` made after `attempt`, if verbose logging is enabled
+pub(crate) fn observe<R>(
+    policy: &'static str,
+    attempt: u32,
+    flow: ControlFlow<R, Duration>,
+) -> ControlFlow<R, Duration> {
+    if is_enabled() {
+        let delay = match &flow {
+            ControlFlow::Continue(delay) => Some(*delay),
+            ControlFlow::Break(_) => None,
+        };
+        report(policy, attempt, delay);
+    }
+    flow
+}
+
+fn report(policy: &'static str, attempt: u32, delay: Option<Duration>) {
+    #[cfg(feature = "tracing")]
+    match delay {
+        Some(delay) => tracing::info!(
+            target: "futures_retry_policies::verbose",
+            policy, attempt, ?delay, "retrying"
+        ),
+        None => tracing::info!(
+            target: "futures_retry_policies::verbose",
+            policy, attempt, "not retrying"
+        ),
+    }
+    #[cfg(all(feature = "log", not(feature = "tracing")))]
+    match delay {
+        Some(delay) => ::log::info!(
+            target: "futures_retry_policies::verbose",
+            "{policy}: retrying attempt {attempt} after {delay:?}"
+        ),
+        None => ::log::info!(
+            target: "futures_retry_policies::verbose",
+            "{policy}: not retrying attempt {attempt}"
+        ),
+    }
+    #[cfg(not(any(feature = "log", feature = "tracing")))]
+    match delay {
+        Some(delay) => eprintln!("{policy}: retrying attempt {attempt} after {delay:?}"),
+        None => eprintln!("{policy}: not retrying attempt {attempt}"),
+    }
+}
+
+/// A [`RetryPolicy`] that reports its decisions while verbose logging is [enabled](is_enabled).
+///
+/// ```
+/// use futures_retry_policies::{verbose::Verbose, RetryPolicy};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// struct Custom;
+/// impl RetryPolicy<Option<()>> for Custom {
+///     fn should_retry(&mut self, result: Option<()>) -> ControlFlow<Option<()>, Duration> {
+///         match result {
+///             None => ControlFlow::Continue(Duration::from_millis(10)),
+///             Some(_) => ControlFlow::Break(result),
+///         }
+///     }
+/// }
+///
+/// let policy = Verbose::new(Custom, "my_app::Custom");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Verbose<P> {
+    policy: P,
+    name: &'static str,
+    attempts: u32,
+}
+
+impl<P> Verbose<P> {
+    /// Report the decisions of `policy` under `name`
+    pub fn new(policy: P, name: &'static str) -> Self {
+        Self {
+            policy,
+            name,
+            attempts: 0,
+        }
+    }
+}
+
+impl<P, R> RetryPolicy<R> for Verbose<P>
+where
+    P: RetryPolicy<R>,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        self.attempts = self.attempts.saturating_add(1);
+        observe(self.name, self.attempts, self.policy.should_retry(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Switch;
+
+    // each test uses its own switch, as the global one is shared with every other test
+
+    #[test]
+    fn from_env() {
+        for (var, enabled) in [
+            (None, false),
+            (Some("1"), true),
+            (Some("TRUE"), true),
+            (Some("on"), true),
+            (Some("0"), false),
+            (Some("yes"), false),
+        ] {
+            let switch = Switch::new();
+            assert_eq!(
+                switch.is_enabled(|| var.map(str::to_owned)),
+                enabled,
+                "{var:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn env_is_read_once() {
+        let switch = Switch::new();
+        assert!(switch.is_enabled(|| Some("1".to_owned())));
+        assert!(switch.is_enabled(|| unreachable!()));
+    }
+
+    #[test]
+    fn overrides_env() {
+        let switch = Switch::new();
+        switch.set(false);
+        assert!(!switch.is_enabled(|| Some("1".to_owned())));
+        switch.set(true);
+        assert!(switch.is_enabled(|| None));
+
+        // setting it after the environment was read still wins
+        let switch = Switch::new();
+        assert!(!switch.is_enabled(|| None));
+        switch.set(true);
+        assert!(switch.is_enabled(|| None));
+    }
+}
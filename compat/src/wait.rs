@@ -0,0 +1,115 @@
+//! Waiting for dependencies to become ready.
+//!
+//! Services often need to wait for a database or another service before they can start.
+//! This is a retry loop, but the intent differs: every failed probe is expected, and the only
+//! question is whether the dependency becomes ready in time. [`wait_for`] retries every failed
+//! probe, logs it, and reports how long it waited if the policy gives up.
+
+use std::{fmt::Debug, future::Future, ops::ControlFlow, time::Duration, time::Instant};
+
+use crate::{classified::Retryable, outcome::RetriesExhausted, RetryPolicy};
+
+/// Wait until `probe` succeeds, using the given [retry policy](`RetryPolicy`) and sleep function.
+///
+/// Every error from the probe is retried, as the policy sees it wrapped in [`Retryable`].
+/// Failed probes are logged with [`tracing`](::tracing) or [`log`](::log), whichever is enabled.
+///
+/// Returns the successful probe's value, or the last error along with how long was waited.
+///
+/// ```
+/// use futures_retry_policies::{presets, wait::wait_for};
+///
+/// async fn health_check() -> Result<(), &'static str> {
+///     // check the dependency
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err("connection refused") } else { Ok(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     match wait_for(presets::startup_dependency_wait(), tokio::time::sleep, health_check).await {
+///         Ok(()) => println!("database is ready"),
+///         Err(err) => panic!("database never became ready: {err}"),
+///     }
+/// }
+/// ```
+pub async fn wait_for<Policy, Sleeper, Sleep, Probe, Fut, T, E>(
+    mut policy: Policy,
+    mut sleeper: Sleeper,
+    mut probe: Probe,
+) -> Result<T, RetriesExhausted<E>>
+where
+    Policy: RetryPolicy<Result<T, Retryable<E>>>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Probe: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Debug,
+{
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = probe().await.map_err(Retryable);
+        if let Err(Retryable(_err)) = &result {
+            #[cfg(feature = "tracing")]
+            tracing::info!(attempt = attempts, error = ?_err, "dependency is not ready");
+            #[cfg(all(feature = "log", not(feature = "tracing")))]
+            ::log::info!("dependency is not ready (attempt {attempts}): {_err:?}");
+        }
+
+        match policy.should_retry(result) {
+            ControlFlow::Continue(duration) => sleeper(duration).await,
+            ControlFlow::Break(result) => {
+                break result.map_err(|Retryable(error)| {
+                    RetriesExhausted::new(error, attempts, start.elapsed())
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::wait_for;
+    use crate::RetryPolicyBuilder;
+
+    async fn sleep(_: Duration) {}
+
+    #[tokio::test]
+    async fn becomes_ready() {
+        let mut probes = 0;
+        let res = wait_for(
+            RetryPolicyBuilder::fixed(Duration::from_secs(1)).build(),
+            sleep,
+            || {
+                probes += 1;
+                let res = if probes < 5 {
+                    Err("not ready")
+                } else {
+                    Ok(probes)
+                };
+                async move { res }
+            },
+        )
+        .await;
+        assert_eq!(res, Ok(5));
+    }
+
+    #[tokio::test]
+    async fn times_out() {
+        let err = wait_for(
+            RetryPolicyBuilder::fixed(Duration::from_secs(1))
+                .max_retries(2)
+                .build(),
+            sleep,
+            || async { Err::<(), _>("not ready") },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.attempts(), 3);
+        assert_eq!(*err.error(), "not ready");
+    }
+}
@@ -0,0 +1,108 @@
+//! Backing off harder after more severe errors.
+//!
+//! [`Weighted`] multiplies the delays of any policy by a weight picked from each result, so a
+//! single policy can back off gently after a blip, and much more after being told to slow down.
+
+use std::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// A [`RetryPolicy`] that scales the inner policy's delays by the severity of each result.
+///
+/// The `severity` function returns the weight for a result, which multiplies the delay chosen by
+/// the inner policy. A weight of 1 keeps the delay as it is. The inner policy still decides
+/// whether to retry at all.
+///
+/// ```
+/// use futures_retry_policies::{weighted::Weighted, RetryPolicy, RetryPolicyBuilder};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Error { TooManyRequests, Io }
+///
+/// let backoff = RetryPolicyBuilder::exponential(Duration::from_millis(100))
+///     .retry_if(|res: &Result<(), Error>| res.is_err())
+///     .build();
+/// let mut policy = Weighted::new(backoff, |res: &Result<(), Error>| match res {
+///     Err(Error::TooManyRequests) => 4,
+///     _ => 1,
+/// });
+///
+/// assert_eq!(policy.should_retry(Err(Error::Io)), ControlFlow::Continue(Duration::from_millis(100)));
+/// assert_eq!(policy.should_retry(Err(Error::TooManyRequests)), ControlFlow::Continue(Duration::from_millis(800)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Weighted<P, F> {
+    policy: P,
+    severity: F,
+    max_delay: Duration,
+}
+
+impl<P, F> Weighted<P, F> {
+    /// Scale the delays of `policy` by the weight `severity` gives each result
+    pub fn new(policy: P, severity: F) -> Self {
+        Self {
+            policy,
+            severity,
+            max_delay: Duration::MAX,
+        }
+    }
+
+    /// Never wait longer than `max_delay` after weighting. Unlimited by default.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+}
+
+impl<P, F, R> RetryPolicy<R> for Weighted<P, F>
+where
+    P: RetryPolicy<R>,
+    F: FnMut(&R) -> u32,
+{
+    fn should_retry(&mut self, result: R) -> ControlFlow<R, Duration> {
+        let weight = (self.severity)(&result);
+        let delay = self.policy.should_retry(result)?;
+        let weighted = delay.checked_mul(weight).unwrap_or(Duration::MAX);
+        ControlFlow::Continue(weighted.min(self.max_delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, time::Duration};
+
+    use super::Weighted;
+    use crate::{classified::Retryable, iter::Iter, RetryPolicy};
+
+    #[test]
+    fn weights_delays() {
+        let delays = Iter::new([Duration::from_secs(1); 2]);
+        let mut policy = Weighted::new(delays, |res: &Result<(), Retryable<u32>>| {
+            res.as_ref().map_or_else(|e| e.0, |_| 1)
+        });
+        assert_eq!(
+            policy.should_retry(Err(Retryable(3))),
+            ControlFlow::Continue(Duration::from_secs(3))
+        );
+        assert_eq!(
+            policy.should_retry(Err(Retryable(1))),
+            ControlFlow::Continue(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.should_retry(Err(Retryable(5))),
+            ControlFlow::Break(Err(Retryable(5)))
+        );
+    }
+
+    #[test]
+    fn caps_weighted_delays() {
+        let delays = Iter::new(std::iter::repeat(Duration::from_secs(10)));
+        let mut policy =
+            Weighted::new(delays, |_: &Option<()>| u32::MAX).max_delay(Duration::from_secs(60));
+        assert_eq!(
+            policy.should_retry(None),
+            ControlFlow::Continue(Duration::from_secs(60))
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Measures the overhead [`retry`] adds on top of awaiting a future directly.
+//!
+//! Run with `cargo bench -p futures-retry-policies-core`.
+
+use std::{
+    future::Future,
+    hint::black_box,
+    ops::ControlFlow,
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures_retry_policies_core::{retry, RetryPolicy};
+
+/// Retries errors n times, without any delay
+struct Retries(usize);
+
+impl RetryPolicy<Result<u64, u64>> for Retries {
+    fn should_retry(
+        &mut self,
+        result: Result<u64, u64>,
+    ) -> ControlFlow<Result<u64, u64>, Duration> {
+        if self.0 > 0 && result.is_err() {
+            self.0 -= 1;
+            ControlFlow::Continue(Duration::ZERO)
+        } else {
+            ControlFlow::Break(result)
+        }
+    }
+}
+
+/// Polls a future that never returns pending to completion, without any runtime overhead
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut cx = Context::from_waker(Waker::noop());
+    match pin!(fut).poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("benchmarked futures never wait"),
+    }
+}
+
+async fn request(n: u64) -> Result<u64, u64> {
+    Ok(black_box(n))
+}
+
+async fn sleep(_: Duration) {}
+
+fn first_attempt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("first_attempt_succeeds");
+    group.bench_function("direct", |b| b.iter(|| block_on(request(1))));
+    group.bench_function("retry", |b| {
+        b.iter(|| block_on(retry(Retries(3), sleep, || request(1))))
+    });
+    group.finish();
+}
+
+fn retried(c: &mut Criterion) {
+    c.bench_function("three_attempts", |b| {
+        b.iter(|| {
+            let mut attempts = 0;
+            block_on(retry(Retries(3), sleep, || {
+                attempts += 1;
+                async move {
+                    if attempts < 3 {
+                        Err(black_box(attempts))
+                    } else {
+                        Ok(black_box(attempts))
+                    }
+                }
+            }))
+        })
+    });
+}
+
+criterion_group!(benches, first_attempt, retried);
+criterion_main!(benches);
@@ -0,0 +1,93 @@
+use core::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// What to do after an attempt, as decided by a [`RetryDriver`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Step<R> {
+    /// Sleep for this long, then make another attempt
+    Sleep(Duration),
+    /// Stop retrying, with this as the final result
+    Finished(R),
+}
+
+/// The bookkeeping of a retry loop, without the loop.
+///
+/// The driver owns the [`RetryPolicy`] and counts the attempts. It is fed each result with
+/// [`on_result`](RetryDriver::on_result), and returns the next [`Step`], leaving the attempts and
+/// sleeps up to the caller. This is what [`RetryFuture`](crate::RetryFuture) is built on, and is
+/// useful for embedding retries into your own futures or state machines, eg. the reconnect logic
+/// of a connection pool.
+///
+/// ```
+/// use futures_retry_policies_core::{RetryDriver, Schedule, Step};
+/// use std::time::Duration;
+///
+/// let mut driver = RetryDriver::new(Schedule::new([Duration::from_millis(10); 2]));
+///
+/// assert_eq!(driver.on_result(Err::<(), _>("fail")), Step::Sleep(Duration::from_millis(10)));
+/// assert_eq!(driver.on_result(Err::<(), _>("fail")), Step::Sleep(Duration::from_millis(10)));
+/// assert_eq!(driver.on_result(Err::<(), _>("fail")), Step::Finished(Err("fail")));
+/// assert_eq!(driver.attempts(), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryDriver<Policy> {
+    policy: Policy,
+    attempts: u32,
+    delay: Option<Duration>,
+}
+
+impl<Policy> RetryDriver<Policy> {
+    /// Create a driver that hasn't seen any attempts yet
+    #[inline]
+    pub const fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            attempts: 0,
+            delay: None,
+        }
+    }
+
+    /// Record the result of an attempt, and decide what to do next
+    #[inline]
+    pub fn on_result<R>(&mut self, result: R) -> Step<R>
+    where
+        Policy: RetryPolicy<R>,
+    {
+        self.attempts = self.attempts.saturating_add(1);
+        match self.policy.should_retry(result) {
+            ControlFlow::Continue(delay) => {
+                self.delay = Some(delay);
+                Step::Sleep(delay)
+            }
+            ControlFlow::Break(result) => {
+                self.delay = None;
+                Step::Finished(result)
+            }
+        }
+    }
+
+    /// How many results have been recorded
+    #[inline]
+    pub const fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The delay before the next attempt, if the last result is being retried
+    #[inline]
+    pub const fn delay(&self) -> Option<Duration> {
+        self.delay
+    }
+
+    /// Get a reference to the policy
+    #[inline]
+    pub const fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Get the policy back
+    #[inline]
+    pub fn into_policy(self) -> Policy {
+        self.policy
+    }
+}
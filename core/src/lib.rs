@@ -40,7 +40,8 @@
 #![no_std]
 
 use core::{
-    future::Future,
+    fmt,
+    future::{Future, IntoFuture},
     ops::ControlFlow,
     pin::Pin,
     task::{ready, Context, Poll},
@@ -49,6 +50,12 @@ use core::{
 
 use pin_project::pin_project;
 
+mod driver;
+mod schedule;
+
+pub use driver::{RetryDriver, Step};
+pub use schedule::Schedule;
+
 /// Policy to decide whether a result should be retried
 pub trait RetryPolicy<Res> {
     /// Determine if the request should be retried under the given policy.
@@ -63,6 +70,69 @@ impl<P: RetryPolicy<R>, R> RetryPolicy<R> for &mut P {
     }
 }
 
+/// Something that can sleep between attempts.
+///
+/// This is implemented for any `FnMut(Duration) -> impl Future<Output = ()>`, like
+/// `tokio::time::sleep`. Custom sleepers can implement it directly to also receive the attempt
+/// number, eg. to prioritise the timers of requests that have already waited a few times.
+///
+/// ```
+/// use futures_retry_policies_core::{retry_with_sleeper, RetryPolicy, RetrySleeper};
+/// use std::{ops::ControlFlow, time::Duration};
+///
+/// /// Logs each sleep before delegating to tokio
+/// struct LoggingSleeper;
+/// impl RetrySleeper for LoggingSleeper {
+///     type Sleep = tokio::time::Sleep;
+///     fn sleep(&mut self, delay: Duration, attempt: u32) -> Self::Sleep {
+///         println!("attempt {attempt} failed, retrying in {delay:?}");
+///         tokio::time::sleep(delay)
+///     }
+/// }
+///
+/// # pub struct Retries(usize);
+/// # impl RetryPolicy<Result<(), &'static str>> for Retries {
+/// #     fn should_retry(&mut self, result: Result<(), &'static str>) -> ControlFlow<Result<(), &'static str>, Duration> {
+/// #         if self.0 > 0 && result.is_err() {
+/// #             self.0 -= 1;
+/// #             ControlFlow::Continue(Duration::from_millis(100))
+/// #         } else {
+/// #             ControlFlow::Break(result)
+/// #         }
+/// #     }
+/// # }
+/// async fn make_request() -> Result<(), &'static str>  {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err("fail") } else { Ok(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), &'static str> {
+///     retry_with_sleeper(Retries(3), LoggingSleeper, make_request).await
+/// }
+/// ```
+pub trait RetrySleeper {
+    /// The future returned by [`sleep`](RetrySleeper::sleep)
+    type Sleep: Future<Output = ()>;
+
+    /// Sleep for `delay` after the given attempt failed. Attempts start at 1.
+    fn sleep(&mut self, delay: Duration, attempt: u32) -> Self::Sleep;
+}
+
+impl<F, Sleep> RetrySleeper for F
+where
+    F: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+{
+    type Sleep = Sleep;
+
+    #[inline]
+    fn sleep(&mut self, delay: Duration, _: u32) -> Sleep {
+        self(delay)
+    }
+}
+
 /// Retry a future using the given [retry policy](`RetryPolicy`) and sleep function.
 ///
 /// ```
@@ -94,6 +164,7 @@ impl<P: RetryPolicy<R>, R> RetryPolicy<R> for &mut P {
 ///     retry(Retries(3), tokio::time::sleep, make_request).await
 /// }
 /// ```
+#[inline]
 pub fn retry<Policy, Sleeper, Sleep, Futures, Fut>(
     policy: Policy,
     sleeper: Sleeper,
@@ -105,19 +176,120 @@ where
     Sleep: Future<Output = ()>,
     Futures: FnMut() -> Fut,
     Fut: Future,
+{
+    retry_with_sleeper(policy, sleeper, futures)
+}
+
+/// Retry a future using the given [retry policy](`RetryPolicy`) and [`RetrySleeper`].
+///
+/// This is the same as [`retry`], but takes any [`RetrySleeper`] rather than only sleep functions.
+#[inline]
+pub fn retry_with_sleeper<Policy, Sleeper, Futures, Fut>(
+    policy: Policy,
+    sleeper: Sleeper,
+    futures: Futures,
+) -> RetryFuture<Policy, Sleeper, Sleeper::Sleep, Futures, Fut>
+where
+    Policy: RetryPolicy<Fut::Output>,
+    Sleeper: RetrySleeper,
+    Futures: FnMut() -> Fut,
+    Fut: Future,
 {
     RetryFuture {
-        policy,
+        driver: RetryDriver::new(policy),
         sleeper,
         futures,
         state: RetryState::Idle,
     }
 }
 
+/// Retry an [`IntoFuture`] value, like a request builder, using the given
+/// [retry policy](`RetryPolicy`) and sleep function.
+///
+/// Each attempt runs a fresh clone of `operation`, so there's no need for a closure that
+/// rebuilds it.
+///
+/// ```
+/// use futures_retry_policies_core::{retry_into, Schedule};
+/// use std::{future::{IntoFuture, Ready}, time::Duration};
+///
+/// /// A request builder, like the ones many HTTP clients provide
+/// #[derive(Clone)]
+/// struct Request { url: &'static str }
+///
+/// impl IntoFuture for Request {
+///     type Output = Result<(), &'static str>;
+///     type IntoFuture = Ready<Self::Output>;
+///     fn into_future(self) -> Self::IntoFuture {
+///         // send the request
+///         # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///         # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { return std::future::ready(Err("fail")) }
+///         std::future::ready(Ok(()))
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), &'static str> {
+///     let request = Request { url: "https://example.com" };
+///     let schedule = Schedule::new([Duration::from_millis(10); 3]);
+///     retry_into(schedule, tokio::time::sleep, request).await
+/// }
+/// ```
+#[inline]
+pub fn retry_into<Policy, Sleeper, Sleep, Operation>(
+    policy: Policy,
+    sleeper: Sleeper,
+    operation: Operation,
+) -> RetryFuture<Policy, Sleeper, Sleep, impl FnMut() -> Operation::IntoFuture, Operation::IntoFuture>
+where
+    Policy: RetryPolicy<Operation::Output>,
+    Sleeper: FnMut(Duration) -> Sleep,
+    Sleep: Future<Output = ()>,
+    Operation: IntoFuture + Clone,
+{
+    retry(policy, sleeper, move || operation.clone().into_future())
+}
+
 /// [`Future`] returned by [`retry`](crate::retry)
+///
+/// The current attempt, or sleep, is stored inline and pinned in place, so the futures
+/// don't need to be [`Unpin`] and are never boxed. The `RetryFuture` itself is [`Unpin`]
+/// if all of its parts are.
+///
+/// Similarly, the `RetryFuture` is [`Send`] exactly when all of its parts are. Nothing here
+/// requires `Send`, so futures holding an `Rc` or other thread-local state can be retried
+/// on single-threaded executors, like tokio's `LocalSet` or in WASM.
+///
+/// # Cancellation
+///
+/// Dropping the `RetryFuture` while an attempt is in flight drops that attempt, with whatever
+/// that means for the attempt itself. Dropping it while sleeping simply means no more attempts
+/// are made. Either way, the policy is dropped without seeing another result.
+///
+/// Polling the `RetryFuture` by reference, eg. as `&mut fut` in a `select!` loop, is cancel safe:
+/// when another branch completes, the current attempt or sleep is kept as is, and carries on
+/// from where it was the next time the `RetryFuture` is polled. Creating a new `RetryFuture` in
+/// every iteration of the loop would instead start again from the first attempt.
+///
+/// # Debugging
+///
+/// The `Debug` and `Display` output shows how far along the retries are, so a stuck
+/// `RetryFuture` in a debugger or task dump can be told apart from a slow attempt:
+///
+/// ```
+/// use futures_retry_policies_core::{retry, Schedule};
+/// use std::{future::Future, pin::pin, task::{Context, Poll, Waker}, time::Duration};
+///
+/// let schedule = Schedule::new([Duration::from_secs(1)]);
+/// let mut fut = pin!(retry(schedule, |_| std::future::pending(), || async { Err::<(), _>("fail") }));
+/// assert_eq!(fut.to_string(), "not started");
+///
+/// let _ = fut.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+/// assert_eq!(fut.to_string(), "attempt 1 failed, sleeping for 1s");
+/// ```
 #[pin_project]
 pub struct RetryFuture<Policy, Sleeper, Sleep, Futures, Fut> {
-    policy: Policy,
+    driver: RetryDriver<Policy>,
     sleeper: Sleeper,
     futures: Futures,
     #[pin]
@@ -135,28 +307,33 @@ impl<Policy, Sleeper, Sleep, Futures, Fut> Future
     for RetryFuture<Policy, Sleeper, Sleep, Futures, Fut>
 where
     Policy: RetryPolicy<Fut::Output>,
-    Sleeper: FnMut(Duration) -> Sleep,
+    Sleeper: RetrySleeper<Sleep = Sleep>,
     Sleep: Future<Output = ()>,
     Futures: FnMut() -> Fut,
     Fut: Future,
 {
     type Output = Fut::Output;
 
+    #[inline]
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         // Retry state machine:
         // 1. State goes from idle to attempting
         // 2. Once the attempt is ready, it checks if a retry is necessary.
         // 3. If a retry is necessary:
         //   i.   We transition to sleeping.
-        //   ii.  And then transition back to idle.
-        //   iii. And then loop back to 1
+        //   ii.  And then transition straight to attempting.
+        //   iii. And then loop back to 2
         //
-        // This is the state-machine version of:
+        // Nothing here allocates, and an attempt that succeeds first time costs
+        // just the attempt itself plus a single call to the policy. The policy
+        // call can't be skipped, as the policy is what decides what a success is.
+        // The decisions are all made by the `RetryDriver`, this only runs the
+        // attempts and sleeps it asks for. This is the state-machine version of:
         // ```
         // loop {
-        //     match self.policy.should_retry((self.futures)().await) {
-        //         ControlFlow::Continue(dur) => (self.sleeper)(dur).await,
-        //         ControlFlow::Break(result) => break result,
+        //     match self.driver.on_result((self.futures)().await) {
+        //         Step::Sleep(dur) => (self.sleeper)(dur).await,
+        //         Step::Finished(result) => break result,
         //     }
         // }
         // ```
@@ -165,18 +342,57 @@ where
             match this.state.as_mut().project() {
                 RetryStateProj::Idle => this.state.set(RetryState::Attempts((this.futures)())),
                 RetryStateProj::Attempts(fut) => {
-                    match this.policy.should_retry(ready!(fut.poll(cx))) {
-                        ControlFlow::Continue(sleep) => {
-                            this.state.set(RetryState::Sleeping((this.sleeper)(sleep)));
+                    let result = ready!(fut.poll(cx));
+                    match this.driver.on_result(result) {
+                        Step::Sleep(delay) => {
+                            let sleep = this.sleeper.sleep(delay, this.driver.attempts());
+                            this.state.set(RetryState::Sleeping(sleep));
                         }
-                        ControlFlow::Break(res) => return Poll::Ready(res),
+                        Step::Finished(res) => return Poll::Ready(res),
                     }
                 }
                 RetryStateProj::Sleeping(sleep) => {
                     ready!(sleep.poll(cx));
-                    this.state.set(RetryState::Idle)
+                    this.state.set(RetryState::Attempts((this.futures)()))
                 }
             }
         }
     }
 }
+
+impl<Sleep, Fut> RetryState<Sleep, Fut> {
+    fn name(&self) -> &'static str {
+        match self {
+            RetryState::Idle => "idle",
+            RetryState::Sleeping(_) => "sleeping",
+            RetryState::Attempts(_) => "attempting",
+        }
+    }
+}
+
+impl<Policy, Sleeper, Sleep, Futures, Fut> fmt::Debug
+    for RetryFuture<Policy, Sleeper, Sleep, Futures, Fut>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryFuture")
+            .field("state", &self.state.name())
+            .field("attempts", &self.driver.attempts())
+            .field("delay", &self.driver.delay())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Policy, Sleeper, Sleep, Futures, Fut> fmt::Display
+    for RetryFuture<Policy, Sleeper, Sleep, Futures, Fut>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attempts = self.driver.attempts();
+        match (&self.state, self.driver.delay()) {
+            (RetryState::Idle, _) => f.write_str("not started"),
+            (RetryState::Sleeping(_), Some(delay)) => {
+                write!(f, "attempt {attempts} failed, sleeping for {delay:?}")
+            }
+            _ => write!(f, "running attempt {}", attempts.saturating_add(1)),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use core::{ops::ControlFlow, time::Duration};
+
+use crate::RetryPolicy;
+
+/// A fixed schedule of delays, stored inline without any allocation.
+///
+/// As a [`RetryPolicy`], it retries every error until the schedule has run out. Successes don't
+/// use up a delay, so a schedule can be reused after one.
+/// It's also an [`Iterator`] of the remaining delays, for use with other policies.
+///
+/// ```
+/// use futures_retry_policies_core::{RetryPolicy, Schedule};
+/// use std::time::Duration;
+///
+/// let mut schedule = Schedule::new([Duration::from_millis(10)]);
+/// assert!(schedule.should_retry(Ok::<_, ()>(())).is_break());
+/// assert_eq!(schedule.remaining(), 1);
+/// ```
+///
+/// ```
+/// use futures_retry_policies_core::{retry, Schedule};
+/// use std::time::Duration;
+///
+/// const SCHEDULE: Schedule<3> = Schedule::new([
+///     Duration::from_millis(10),
+///     Duration::from_millis(50),
+///     Duration::from_millis(100),
+/// ]);
+///
+/// async fn make_request() -> Result<(), &'static str>  {
+///     // make a request
+///     # static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+///     # if COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 { Err("fail") } else { Ok(()) }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), &'static str> {
+///     retry(SCHEDULE, tokio::time::sleep, make_request).await
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Schedule<const N: usize> {
+    delays: [Duration; N],
+    next: usize,
+}
+
+impl<const N: usize> Schedule<N> {
+    /// Wait for each of the `delays` in turn, retrying at most `N` times
+    pub const fn new(delays: [Duration; N]) -> Self {
+        Self { delays, next: 0 }
+    }
+
+    /// How many retries are left in the schedule
+    pub const fn remaining(&self) -> usize {
+        N - self.next
+    }
+}
+
+impl<const N: usize> Iterator for Schedule<N> {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let delay = *self.delays.get(self.next)?;
+        self.next += 1;
+        Some(delay)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining(), Some(self.remaining()))
+    }
+}
+
+impl<const N: usize> ExactSizeIterator for Schedule<N> {}
+
+impl<T, E, const N: usize> RetryPolicy<Result<T, E>> for Schedule<N> {
+    fn should_retry(&mut self, result: Result<T, E>) -> ControlFlow<Result<T, E>, Duration> {
+        if result.is_ok() {
+            return ControlFlow::Break(result);
+        }
+        match self.next() {
+            Some(delay) => ControlFlow::Continue(delay),
+            None => ControlFlow::Break(result),
+        }
+    }
+}
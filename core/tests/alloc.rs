@@ -0,0 +1,108 @@
+//! Checks that the retry future stores attempts inline, without allocating.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    future::Future,
+    ops::ControlFlow,
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures_retry_policies_core::{retry, RetryPolicy};
+
+/// Counts the allocations made on the current thread
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+struct Retries(usize);
+
+impl RetryPolicy<Result<(), ()>> for Retries {
+    fn should_retry(&mut self, result: Result<(), ()>) -> ControlFlow<Result<(), ()>, Duration> {
+        if self.0 > 0 && result.is_err() {
+            self.0 -= 1;
+            ControlFlow::Continue(Duration::ZERO)
+        } else {
+            ControlFlow::Break(result)
+        }
+    }
+}
+
+/// A sleep that is pending once before completing, to exercise re-polling a pinned future
+async fn sleep(_: Duration) {
+    let mut yielded = false;
+    std::future::poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+fn assert_unpin<T: Unpin>(_: &T) {}
+
+#[test]
+fn no_allocations_per_attempt() {
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut attempts = 0;
+
+    let before = allocations();
+    // async blocks are `!Unpin`, so this needs to be pinned to be polled
+    let mut fut = pin!(retry(Retries(5), sleep, || {
+        attempts += 1;
+        let n = attempts;
+        async move {
+            if n < 5 {
+                Err(())
+            } else {
+                Ok(())
+            }
+        }
+    }));
+    let res = loop {
+        if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+            break res;
+        }
+    };
+    let after = allocations();
+
+    res.unwrap();
+    assert_eq!(attempts, 5);
+    assert_eq!(after - before, 0);
+}
+
+#[test]
+fn unpin_when_parts_are_unpin() {
+    let fut = retry(
+        Retries(1),
+        |_| std::future::ready(()),
+        || std::future::ready(Ok(())),
+    );
+    assert_unpin(&fut);
+}
@@ -0,0 +1,70 @@
+//! Checks that `Send` is only required when the caller needs it.
+
+use std::{
+    cell::Cell,
+    future::Future,
+    ops::ControlFlow,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures_retry_policies_core::{retry, RetryPolicy};
+
+struct Retries(usize);
+
+impl RetryPolicy<Result<(), ()>> for Retries {
+    fn should_retry(&mut self, result: Result<(), ()>) -> ControlFlow<Result<(), ()>, Duration> {
+        if self.0 > 0 && result.is_err() {
+            self.0 -= 1;
+            ControlFlow::Continue(Duration::ZERO)
+        } else {
+            ControlFlow::Break(result)
+        }
+    }
+}
+
+fn assert_send<T: Send>(_: &T) {}
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut fut = pin!(fut);
+    loop {
+        if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+            break res;
+        }
+    }
+}
+
+#[test]
+fn send_when_parts_are_send() {
+    let fut = retry(Retries(1), |_| async {}, || async { Err::<(), ()>(()) });
+    assert_send(&fut);
+}
+
+#[test]
+fn retries_non_send_futures() {
+    let attempts = Rc::new(Cell::new(0));
+
+    let res = block_on(retry(
+        Retries(5),
+        |_| async {},
+        || {
+            let attempts = attempts.clone();
+            async move {
+                attempts.set(attempts.get() + 1);
+                // hold the `Rc` across an await point, making the attempt `!Send`
+                std::future::ready(()).await;
+                if attempts.get() < 3 {
+                    Err(())
+                } else {
+                    Ok(())
+                }
+            }
+        },
+    ));
+
+    res.unwrap();
+    assert_eq!(attempts.get(), 3);
+}
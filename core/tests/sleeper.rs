@@ -0,0 +1,54 @@
+//! Checks that custom sleepers see the attempt that failed.
+
+use std::{
+    future::Future,
+    ops::ControlFlow,
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures_retry_policies_core::{retry_with_sleeper, RetryPolicy, RetrySleeper};
+
+struct Retries(usize);
+
+impl RetryPolicy<Result<(), ()>> for Retries {
+    fn should_retry(&mut self, result: Result<(), ()>) -> ControlFlow<Result<(), ()>, Duration> {
+        if self.0 > 0 && result.is_err() {
+            self.0 -= 1;
+            ControlFlow::Continue(Duration::from_secs(self.0 as u64))
+        } else {
+            ControlFlow::Break(result)
+        }
+    }
+}
+
+/// Records each sleep instead of sleeping
+#[derive(Default)]
+struct Recorder(Vec<(Duration, u32)>);
+
+impl RetrySleeper for &mut Recorder {
+    type Sleep = std::future::Ready<()>;
+
+    fn sleep(&mut self, delay: Duration, attempt: u32) -> Self::Sleep {
+        self.0.push((delay, attempt));
+        std::future::ready(())
+    }
+}
+
+#[test]
+fn sleeper_sees_attempts() {
+    let mut recorder = Recorder::default();
+    let fut = pin!(retry_with_sleeper(Retries(3), &mut recorder, || async {
+        Err(())
+    }));
+    let Poll::Ready(res) = fut.poll(&mut Context::from_waker(Waker::noop())) else {
+        panic!("retries should complete without waiting");
+    };
+
+    res.unwrap_err();
+    assert_eq!(
+        recorder.0,
+        [(2, 1), (1, 2), (0, 3)].map(|(secs, attempt)| (Duration::from_secs(secs), attempt))
+    );
+}